@@ -0,0 +1,159 @@
+//! 将频谱分析的结果映射到频段
+//!
+//! 人耳对频率的感知接近对数关系，线性划分的频段会让低频只占很少的几个频段，
+//! 因此支持按对数或者梅尔刻度划分频段，并可以按照 A 计权调整各个频段的响度。
+
+use spectrum_analyzer::FrequencySpectrum;
+use wasm_bindgen::prelude::*;
+
+/// 频段的划分方式
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrequencyScale {
+    /// 按频率线性划分
+    #[default]
+    Linear,
+    /// 按频率的对数划分，每个八度占用相同数量的频段
+    Logarithmic,
+    /// 按梅尔刻度划分，低频接近线性，高频接近对数
+    Mel,
+}
+
+/// 各个频段的响度计权方式
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrequencyWeighting {
+    /// 不进行计权
+    #[default]
+    None,
+    /// A 计权，降低人耳不敏感的低频和极高频的响度
+    A,
+}
+
+/// 对数刻度无法从 0 赫兹开始，最低频率会被限制在此频率以上
+const MIN_LOG_FREQ: f32 = 20.0;
+
+fn hz_to_mel(freq: f32) -> f32 {
+    2595.0 * (1.0 + freq / 700.0).log10()
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (10f32.powf(mel / 2595.0) - 1.0)
+}
+
+impl FrequencyScale {
+    /// 计算频率范围中比例 `t`（0 到 1）处的频率
+    fn freq_at(self, min_freq: f32, max_freq: f32, t: f32) -> f32 {
+        match self {
+            Self::Linear => min_freq + (max_freq - min_freq) * t,
+            Self::Logarithmic => {
+                let min_freq = min_freq.max(MIN_LOG_FREQ).min(max_freq);
+                min_freq * (max_freq / min_freq).powf(t)
+            }
+            Self::Mel => {
+                let (min_mel, max_mel) = (hz_to_mel(min_freq), hz_to_mel(max_freq));
+                mel_to_hz(min_mel + (max_mel - min_mel) * t)
+            }
+        }
+    }
+}
+
+impl FrequencyWeighting {
+    /// 计算频率的线性增益
+    fn gain(self, freq: f32) -> f32 {
+        match self {
+            Self::None => 1.0,
+            Self::A => {
+                let f2 = freq * freq;
+                let ra = 12194.0f32.powi(2) * f2 * f2
+                    / ((f2 + 20.6f32.powi(2))
+                        * ((f2 + 107.7f32.powi(2)) * (f2 + 737.9f32.powi(2))).sqrt()
+                        * (f2 + 12194.0f32.powi(2)));
+                // A 计权在 1000 赫兹处的增益为 0dB，需要补偿约 2dB
+                ra * 1.2589
+            }
+        }
+    }
+}
+
+/// 将频谱映射到各个频段，结果会和 `result` 中原有的值取平均以平滑变化
+///
+/// 线性划分时直接取频段起点处的值，和之前的行为一致；
+/// 其它划分方式下高频的频段会覆盖多个频谱分量，此时取其中的平均值。
+pub fn map_bands(
+    spec: &FrequencySpectrum,
+    scale: FrequencyScale,
+    weighting: FrequencyWeighting,
+    result: &mut [f32],
+) {
+    let freq_min = spec.min_fr().val();
+    let freq_max = spec.max_fr().val();
+    let data = spec.data();
+    let len = result.len() as f32;
+    result.iter_mut().enumerate().for_each(|(i, v)| {
+        let start = scale.freq_at(freq_min, freq_max, i as f32 / len);
+        let start = start.clamp(freq_min, freq_max);
+        let value = if scale == FrequencyScale::Linear {
+            spec.freq_val_exact(start).val()
+        } else {
+            let end = scale
+                .freq_at(freq_min, freq_max, (i + 1) as f32 / len)
+                .clamp(freq_min, freq_max);
+            let from = data.partition_point(|(fr, _)| fr.val() < start);
+            let to = data.partition_point(|(fr, _)| fr.val() < end);
+            if to > from {
+                data[from..to].iter().map(|(_, x)| x.val()).sum::<f32>() / (to - from) as f32
+            } else {
+                // 低频的频段比频谱分量更窄时插值
+                spec.freq_val_exact((start + end) / 2.0).val()
+            }
+        };
+        *v += value * weighting.gain(start.max(1.0));
+        *v /= 2.0;
+    });
+}
+
+#[test]
+fn band_edges_test() {
+    const BANDS: usize = 64;
+    let (min_freq, max_freq) = (0.0, 22050.0);
+    for scale in [
+        FrequencyScale::Linear,
+        FrequencyScale::Logarithmic,
+        FrequencyScale::Mel,
+    ] {
+        let edges: Vec<f32> = (0..=BANDS)
+            .map(|i| scale.freq_at(min_freq, max_freq, i as f32 / BANDS as f32))
+            .collect();
+        // 频段的边界严格递增，并且覆盖整个频率范围
+        assert!(edges.windows(2).all(|x| x[0] < x[1]), "{scale:?}");
+        let lowest = if scale == FrequencyScale::Logarithmic {
+            MIN_LOG_FREQ
+        } else {
+            min_freq
+        };
+        assert!((edges[0] - lowest).abs() < 1e-3, "{scale:?}");
+        assert!(
+            (edges[BANDS] - max_freq).abs() / max_freq < 1e-3,
+            "{scale:?}"
+        );
+    }
+
+    // 对数刻度下相邻边界的比值相同，即每个八度占用相同数量的频段
+    let log = |t: f32| FrequencyScale::Logarithmic.freq_at(min_freq, max_freq, t);
+    let ratio = log(1.0 / BANDS as f32) / log(0.0);
+    assert!((1..BANDS).all(|i| {
+        let r = log((i + 1) as f32 / BANDS as f32) / log(i as f32 / BANDS as f32);
+        (r - ratio).abs() < 1e-3
+    }));
+}
+
+#[test]
+fn a_weighting_test() {
+    let db = |freq: f32| 20.0 * FrequencyWeighting::A.gain(freq).log10();
+    assert!(db(1000.0).abs() < 0.1);
+    // IEC 61672 中 100 赫兹和 10 千赫兹处的 A 计权分别约为 -19.1dB 和 -2.5dB
+    assert!((db(100.0) + 19.1).abs() < 0.2);
+    assert!((db(10000.0) + 2.5).abs() < 0.2);
+    assert_eq!(FrequencyWeighting::None.gain(100.0), 1.0);
+}
@@ -0,0 +1,198 @@
+//! 节拍和 BPM 检测
+//!
+//! 使用基于能量的起音检测：将音频分成短帧计算能量，
+//! 当某一帧的能量明显高于最近约一秒的平均能量时视为一次节拍，
+//! 能量的方差越大（节奏越鲜明）判定阈值越低。
+//! BPM 根据最近若干次节拍的间隔的中位数估计，并折算到常见的节奏范围内。
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+/// 每帧的采样数量，在 44100Hz 下约为 23 毫秒
+const FRAME_SIZE: usize = 1024;
+/// 计算平均能量时使用的历史帧数量，约为一秒
+const HISTORY_SIZE: usize = 43;
+/// 两次节拍之间的最小间隔，单位为秒，对应最高 240 BPM
+const MIN_BEAT_INTERVAL: f64 = 0.25;
+/// 估计 BPM 时使用的节拍间隔数量
+const INTERVAL_HISTORY_SIZE: usize = 16;
+/// 估计出的 BPM 会被折算到此范围内
+const BPM_RANGE: (f32, f32) = (70.0, 180.0);
+/// 能量低于此值的帧视为静音，不会被判定为节拍
+const SILENCE_ENERGY: f32 = 1e-6;
+
+/// 检测到的一次节拍
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beat {
+    /// 节拍所在帧的能量相对于最近平均能量的倍数
+    pub energy: f32,
+    /// 估计的 BPM，节拍数量还不足以估计时为 0
+    #[wasm_bindgen(js_name = "bpmEstimate")]
+    pub bpm_estimate: f32,
+}
+
+pub struct BeatDetector {
+    sample_rate: usize,
+    frame: Vec<f32>,
+    history: VecDeque<f32>,
+    /// 已处理的采样数量，用作检测的时钟
+    position: u64,
+    last_beat: Option<u64>,
+    intervals: VecDeque<f64>,
+    pending: Option<Beat>,
+}
+
+impl BeatDetector {
+    pub fn new(sample_rate: usize) -> Self {
+        Self {
+            sample_rate,
+            frame: Vec::with_capacity(FRAME_SIZE),
+            history: VecDeque::with_capacity(HISTORY_SIZE),
+            position: 0,
+            last_beat: None,
+            intervals: VecDeque::with_capacity(INTERVAL_HISTORY_SIZE),
+            pending: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frame.clear();
+        self.history.clear();
+        self.last_beat = None;
+        self.intervals.clear();
+        self.pending = None;
+    }
+
+    /// 传入单声道的采样数据
+    pub fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            self.frame.push(sample);
+            if self.frame.len() == FRAME_SIZE {
+                self.process_frame();
+                self.frame.clear();
+            }
+        }
+    }
+
+    /// 取出最近一次检测到的节拍，两次读取之间有多次节拍时只保留最后一次
+    pub fn take_beat(&mut self) -> Option<Beat> {
+        self.pending.take()
+    }
+
+    /// 当前估计的 BPM，节拍数量还不足以估计时为 0
+    pub fn bpm(&self) -> f32 {
+        if self.intervals.len() < 4 {
+            return 0.0;
+        }
+        let mut intervals: Vec<f64> = self.intervals.iter().copied().collect();
+        intervals.sort_by(|a, b| a.total_cmp(b));
+        let median = intervals[intervals.len() / 2];
+        let mut bpm = (60.0 / median) as f32;
+        while bpm < BPM_RANGE.0 {
+            bpm *= 2.0;
+        }
+        while bpm > BPM_RANGE.1 {
+            bpm /= 2.0;
+        }
+        bpm
+    }
+
+    fn process_frame(&mut self) {
+        let energy = self.frame.iter().map(|x| x * x).sum::<f32>() / FRAME_SIZE as f32;
+        self.position += FRAME_SIZE as u64;
+        if self.history.len() == HISTORY_SIZE {
+            let average = self.history.iter().sum::<f32>() / HISTORY_SIZE as f32;
+            let variance = self
+                .history
+                .iter()
+                .map(|x| (x - average).powi(2))
+                .sum::<f32>()
+                / HISTORY_SIZE as f32;
+            // 经典的能量检测阈值，方差以平均能量为单位归一化使其和音量无关，再放大到和原公式相近的量级
+            let variance = if average > 0.0 {
+                variance / (average * average)
+            } else {
+                0.0
+            };
+            let threshold = (1.5142857 - 0.0025714 * variance * 100.0).max(1.2);
+            if energy > SILENCE_ENERGY && energy > threshold * average {
+                self.on_onset(energy / average);
+            }
+            self.history.pop_front();
+        }
+        self.history.push_back(energy);
+    }
+
+    fn on_onset(&mut self, energy: f32) {
+        let min_interval = (MIN_BEAT_INTERVAL * self.sample_rate as f64) as u64;
+        if let Some(last_beat) = self.last_beat {
+            let interval = self.position - last_beat;
+            if interval < min_interval {
+                return;
+            }
+            // 间隔过长时通常是停顿或者切歌，不用于估计 BPM
+            let interval = interval as f64 / self.sample_rate as f64;
+            if interval < 60.0 / BPM_RANGE.0 as f64 * 2.0 {
+                if self.intervals.len() == INTERVAL_HISTORY_SIZE {
+                    self.intervals.pop_front();
+                }
+                self.intervals.push_back(interval);
+            }
+        }
+        self.last_beat = Some(self.position);
+        self.pending = Some(Beat {
+            energy,
+            bpm_estimate: self.bpm(),
+        });
+    }
+}
+
+#[cfg(test)]
+fn click_train(sample_rate: usize, bpm: f32, seconds: usize) -> Vec<f32> {
+    let period = (sample_rate as f32 * 60.0 / bpm) as usize;
+    (0..sample_rate * seconds)
+        .map(|i| if i % period < 64 { 1.0 } else { 0.0 })
+        .collect()
+}
+
+#[test]
+fn click_train_test() {
+    let mut detector = BeatDetector::new(44100);
+    let mut beats = 0;
+    // 分成较小的块传入，模拟播放时的调用方式
+    for chunk in click_train(44100, 120.0, 12).chunks(512) {
+        detector.push(chunk);
+        if let Some(beat) = detector.take_beat() {
+            assert!(beat.energy > 1.0);
+            beats += 1;
+        }
+    }
+    // 前一秒用于积累平均能量，之后每秒两次节拍
+    assert!((20..=22).contains(&beats), "{beats}");
+    // 节拍的位置按帧对齐，间隔会有一帧左右的误差
+    let bpm = detector.bpm();
+    assert!((bpm - 120.0).abs() < 4.0, "{bpm}");
+
+    detector.clear();
+    assert_eq!(detector.bpm(), 0.0);
+    assert_eq!(detector.take_beat(), None);
+}
+
+#[test]
+fn bpm_range_test() {
+    // 过慢的节奏会被折算到常用的范围内
+    let mut detector = BeatDetector::new(44100);
+    detector.push(&click_train(44100, 50.0, 20));
+    let bpm = detector.bpm();
+    assert!((bpm - 100.0).abs() < 4.0, "{bpm}");
+}
+
+#[test]
+fn silence_test() {
+    let mut detector = BeatDetector::new(44100);
+    detector.push(&vec![0.0; 44100 * 5]);
+    assert_eq!(detector.take_beat(), None);
+    assert_eq!(detector.bpm(), 0.0);
+}
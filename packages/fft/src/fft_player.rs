@@ -6,8 +6,13 @@ use symphonia_core::conv::{FromSample, IntoSample};
 use symphonia_core::sample::Sample;
 use wasm_bindgen::prelude::*;
 
+use super::bands::{map_bands, FrequencyScale, FrequencyWeighting};
+use super::beat::{Beat, BeatDetector};
+use super::meter::{LevelMeter, Levels};
 use super::resampler::FastFixedOutResampler;
 
+// 作为原生库使用时（例如播放器后端转发频谱）没有 console，直接使用标准库的 eprintln
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -16,10 +21,96 @@ extern "C" {
     fn warn(s: &str);
 }
 
+#[cfg(target_arch = "wasm32")]
 macro_rules! eprintln {
     ($($t:tt)*) => (warn(&format_args!($($t)*).to_string()))
 }
 
+/// 频谱分析使用的采样率，传入的音频数据都会被重采样到此采样率
+const FFT_SAMPLE_RATE: usize = 44100;
+
+/// 频谱分析前对采样应用的窗函数
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FFTWindow {
+    #[default]
+    Hamming,
+    Hann,
+    /// 旁瓣更低，频段之间的泄漏更少，但主瓣更宽
+    BlackmanHarris,
+}
+
+impl FFTWindow {
+    fn apply(self, samples: &[f32]) -> Vec<f32> {
+        match self {
+            Self::Hamming => windows::hamming_window(samples),
+            Self::Hann => windows::hann_window(samples),
+            Self::BlackmanHarris => windows::blackman_harris_4term(samples),
+        }
+    }
+}
+
+/// 双声道频谱的计算方式
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StereoMode {
+    /// 分别计算左右声道的频谱
+    #[default]
+    LeftRight,
+    /// 分别计算中置（左右之和）和侧边（左右之差）的频谱
+    MidSide,
+}
+
+/// 频谱分析的参数，可以在运行时修改，以在频谱精度和性能之间取舍
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FFTConfig {
+    /// 每次进行频谱分析的采样数量，会被调整为 2 的幂
+    pub size: usize,
+    /// 频谱结果的频段数量，读取时会再插值到读取缓冲区的大小
+    pub bands: usize,
+    /// 频谱的最低频率，单位为赫兹
+    pub min_freq: f32,
+    /// 频谱的最高频率，单位为赫兹
+    pub max_freq: f32,
+    /// 两次频谱分析之间的最小间隔，单位为毫秒，为 0 时每次读取都会进行分析
+    pub interval_ms: u32,
+    pub window: FFTWindow,
+    pub scale: FrequencyScale,
+    pub weighting: FrequencyWeighting,
+}
+
+impl Default for FFTConfig {
+    fn default() -> Self {
+        Self {
+            size: 2048,
+            bands: 2048,
+            min_freq: 80.0,
+            max_freq: 2000.0,
+            interval_ms: 0,
+            window: FFTWindow::default(),
+            scale: FrequencyScale::default(),
+            weighting: FrequencyWeighting::default(),
+        }
+    }
+}
+
+impl FFTConfig {
+    /// 将参数调整到可用的范围内
+    fn normalized(mut self) -> Self {
+        self.size = self.size.clamp(64, 16384).next_power_of_two();
+        self.bands = self.bands.clamp(1, 16384);
+        let nyquist = FFT_SAMPLE_RATE as f32 / 2.0;
+        self.min_freq = self.min_freq.clamp(0.0, nyquist);
+        self.max_freq = self.max_freq.clamp(0.0, nyquist);
+        if self.min_freq >= self.max_freq {
+            let default = Self::default();
+            self.min_freq = default.min_freq;
+            self.max_freq = default.max_freq;
+        }
+        self
+    }
+}
+
 /// 一个接收音频 PCM 数据并转换成频谱的伪播放结构
 /// 该结构会将传入的音频数据转换为单通道音频数据，然后进行频谱分析
 #[wasm_bindgen]
@@ -27,11 +118,17 @@ pub struct FFTPlayer {
     last_fft_time: Instant,
     rate: usize,
     channels: usize,
-    result_buf: [f32; 2048],
+    result_buf: Vec<f32>,
+    /// 双声道频谱中第二个声道的结果
+    second_result_buf: Vec<f32>,
     pcm_queue: VecDeque<f32>,
+    /// 第二个声道的采样，单声道的音频会和 `pcm_queue` 相同
+    second_pcm_queue: VecDeque<f32>,
     fft_duration: usize,
     resampler: Option<FastFixedOutResampler<f32>>,
-    freq_range: Cell<(f32, f32)>,
+    config: Cell<FFTConfig>,
+    beat_detector: BeatDetector,
+    level_meter: LevelMeter,
 }
 
 // numpy.interp()
@@ -70,6 +167,36 @@ fn vec_interp(src: &[f32], dst: &mut [f32]) {
     }
 }
 
+/// 对一段采样进行频谱分析，并将结果映射到频段后插值到输出缓冲区
+fn analyze(
+    samples: &[f32],
+    config: &FFTConfig,
+    result_buf: &mut Vec<f32>,
+    out: &mut [f32],
+) -> bool {
+    let samples = config.window.apply(samples);
+    match samples_fft_to_spectrum(
+        &samples,
+        FFT_SAMPLE_RATE as u32,
+        FrequencyLimit::Range(config.min_freq, config.max_freq),
+        Some(&scaling::divide_by_N_sqrt),
+    ) {
+        Ok(spec) => {
+            // 频段数量变化后之前的结果无法再用于平滑
+            if result_buf.len() != config.bands {
+                *result_buf = vec![0.0; config.bands];
+            }
+            map_bands(&spec, config.scale, config.weighting, result_buf);
+            vec_interp(result_buf, out);
+            true
+        }
+        Err(e) => {
+            eprintln!("FFT error: {:?}", e);
+            false
+        }
+    }
+}
+
 impl Default for FFTPlayer {
     fn default() -> Self {
         Self::new()
@@ -80,11 +207,15 @@ impl FFTPlayer {
     pub fn new() -> Self {
         Self {
             last_fft_time: Instant::now(),
-            result_buf: [0.0; 2048],
+            result_buf: Vec::new(),
+            second_result_buf: Vec::new(),
             pcm_queue: VecDeque::with_capacity(4096),
+            second_pcm_queue: VecDeque::with_capacity(4096),
             fft_duration: 0,
             resampler: None,
-            freq_range: (80.0, 2000.0).into(),
+            config: FFTConfig::default().into(),
+            beat_detector: BeatDetector::new(FFT_SAMPLE_RATE),
+            level_meter: LevelMeter::new(),
             rate: 0,
             channels: 0,
         }
@@ -96,66 +227,98 @@ impl FFTPlayer {
 
     pub fn clear(&mut self) {
         self.pcm_queue.clear();
+        self.second_pcm_queue.clear();
+        self.beat_detector.clear();
+        self.level_meter.clear();
+    }
+
+    /// 取出最近一次的电平测量结果，大约每 100 毫秒更新一次
+    pub fn take_levels(&mut self) -> Option<Levels> {
+        self.level_meter.take_levels()
+    }
+
+    /// 取出最近一次检测到的节拍，可以在每次读取频谱时一并调用
+    pub fn take_beat(&mut self) -> Option<Beat> {
+        self.beat_detector.take_beat()
     }
 
     pub fn set_freq_range(&self, start_freq: f32, end_freq: f32) {
-        self.freq_range.set((start_freq, end_freq));
+        self.set_config(FFTConfig {
+            min_freq: start_freq,
+            max_freq: end_freq,
+            ..self.config()
+        });
     }
 
-    pub fn read(&mut self, buf: &mut [f32]) -> bool {
-        if self.pcm_queue.len() < 2048 {
+    pub fn config(&self) -> FFTConfig {
+        self.config.get()
+    }
+
+    /// 修改频谱分析的参数，会在下一次读取时生效
+    pub fn set_config(&self, config: FFTConfig) {
+        self.config.set(config.normalized());
+    }
+
+    /// 检查是否有足够的数据并且已经到了分析的时间
+    fn ready(&mut self, config: &FFTConfig) -> bool {
+        if self.pcm_queue.len() < config.size {
             self.last_fft_time = Instant::now();
             return false;
         }
+        self.last_fft_time.elapsed().as_millis() >= config.interval_ms as u128
+    }
 
-        let (start_freq, end_freq) = self.freq_range.get();
+    /// 按照距离上次分析经过的时间丢弃已经播放过的采样
+    fn consume(&mut self, config: &FFTConfig) {
+        let elapsed = self.last_fft_time.elapsed();
+        let elapsed_sec = elapsed.as_secs_f64();
+        self.last_fft_time = Instant::now();
 
-        let mut fft_buf = [0.0; 2048];
-        self.pcm_queue
+        let cut_len = (elapsed_sec * FFT_SAMPLE_RATE as f64) as usize;
+        for queue in [&mut self.pcm_queue, &mut self.second_pcm_queue] {
+            queue.drain(..cut_len.min(queue.len()));
+            queue.truncate(config.size * 4);
+        }
+    }
+
+    pub fn read(&mut self, buf: &mut [f32]) -> bool {
+        let config = self.config();
+        if !self.ready(&config) {
+            return false;
+        }
+
+        let fft_buf: Vec<f32> = self.pcm_queue.iter().take(config.size).copied().collect();
+        if !analyze(&fft_buf, &config, &mut self.result_buf, buf) {
+            return false;
+        }
+        self.consume(&config);
+        true
+    }
+
+    /// 分别读取两个声道的频谱数据，用于制作左右对称的可视化效果
+    pub fn read_stereo(&mut self, mode: StereoMode, first: &mut [f32], second: &mut [f32]) -> bool {
+        let config = self.config();
+        if !self.ready(&config) {
+            return false;
+        }
+
+        let samples = self
+            .pcm_queue
             .iter()
-            .take(2048)
-            .enumerate()
-            .for_each(|(i, v)| {
-                fft_buf[i] = *v;
-            });
-
-        let fft_buf = windows::hamming_window(&fft_buf);
-
-        match samples_fft_to_spectrum(
-            &fft_buf,
-            44100,
-            FrequencyLimit::Range(start_freq, end_freq),
-            Some(&scaling::divide_by_N_sqrt),
-        ) {
-            Ok(spec) => {
-                let result_buf_len = self.result_buf.len() as f32;
-                let freq_min = spec.min_fr().val();
-                let freq_max = spec.max_fr().val();
-                let freq_range = freq_max - freq_min;
-                self.result_buf.iter_mut().enumerate().for_each(|(i, v)| {
-                    let freq = i as f32 / result_buf_len * freq_range + freq_min;
-                    let freq = freq.clamp(freq_min, freq_max);
-                    *v += spec.freq_val_exact(freq).val();
-                    *v /= 2.0;
-                });
-                vec_interp(&self.result_buf, buf);
-
-                let elapsed = self.last_fft_time.elapsed();
-                let elapsed_sec = elapsed.as_secs_f64();
-                self.last_fft_time = Instant::now();
-
-                let cut_len = (elapsed_sec * 44100.0) as usize;
-                for _ in 0..cut_len {
-                    self.pcm_queue.pop_front();
-                }
-                self.pcm_queue.truncate(2048 * 4);
-                true
-            }
-            Err(e) => {
-                eprintln!("FFT error: {:?}", e);
-                false
-            }
+            .zip(self.second_pcm_queue.iter())
+            .take(config.size);
+        let (first_buf, second_buf): (Vec<f32>, Vec<f32>) = match mode {
+            StereoMode::LeftRight => samples.map(|(l, r)| (*l, *r)).unzip(),
+            StereoMode::MidSide => samples.map(|(l, r)| ((l + r) / 2.0, (l - r) / 2.0)).unzip(),
+        };
+        if first_buf.len() < config.size
+            || !analyze(&first_buf, &config, &mut self.result_buf, first)
+            || !analyze(&second_buf, &config, &mut self.second_result_buf, second)
+        {
+            return false;
         }
+        self.consume(&config);
+        true
     }
 
     /// 将解码后的音频数据压入播放器
@@ -170,6 +333,10 @@ impl FFTPlayer {
             return;
         }
 
+        // 电平需要按声道分别测量，因此在混合成单声道之前进行
+        self.level_meter
+            .push(rate, channels, decoded.iter().map(|x| (*x).into_sample()));
+
         let should_reset_fft = self.resampler.is_none()
             || self.fft_duration < decoded.len()
             || self.channels != channels
@@ -180,26 +347,38 @@ impl FFTPlayer {
             self.channels = channels;
             self.rate = rate;
 
+            // 保留前两个声道，单声道的音频两个声道相同
             let resampler = FastFixedOutResampler::new_fast_fixed(
                 channels,
                 rate,
-                44100,
-                1,
+                FFT_SAMPLE_RATE,
+                channels.min(2),
                 self.fft_duration as _,
             );
 
             self.resampler = Some(resampler);
 
             self.pcm_queue.clear();
+            self.second_pcm_queue.clear();
+            self.beat_detector.clear();
         }
 
         let rsp = self.resampler.as_mut().unwrap();
 
         rsp.resample(channels, decoded);
 
+        let stereo = channels >= 2;
         while let Some(buf) = rsp.flush() {
-            for v in buf.iter() {
-                self.pcm_queue.push_back(*v);
+            if stereo {
+                for frame in buf.chunks_exact(2) {
+                    self.pcm_queue.push_back(frame[0]);
+                    self.second_pcm_queue.push_back(frame[1]);
+                    self.beat_detector.push(&frame[..1]);
+                }
+            } else {
+                self.pcm_queue.extend(buf.iter().copied());
+                self.second_pcm_queue.extend(buf.iter().copied());
+                self.beat_detector.push(buf);
             }
         }
     }
@@ -259,9 +438,88 @@ impl FFTPlayer {
         self.push_data(rate, channels, decoded);
     }
 
+    /// 修改频谱分析的参数，会在下一次读取时生效
+    /// 较小的采样数量、频段数量和较大的分析间隔可以降低 CPU 占用
+    #[wasm_bindgen(js_name = "setConfig")]
+    pub fn set_config_js(
+        &self,
+        size: usize,
+        bands: usize,
+        min_freq: f32,
+        max_freq: f32,
+        interval_ms: u32,
+    ) {
+        self.set_config(FFTConfig {
+            size,
+            bands,
+            min_freq,
+            max_freq,
+            interval_ms,
+            ..self.config()
+        });
+    }
+
+    /// 设置频谱分析前使用的窗函数
+    #[wasm_bindgen(js_name = "setWindow")]
+    pub fn set_window_js(&self, window: FFTWindow) {
+        self.set_config(FFTConfig {
+            window,
+            ..self.config()
+        });
+    }
+
+    /// 设置频段的划分方式，对数和梅尔刻度更接近人耳的感知
+    #[wasm_bindgen(js_name = "setFrequencyScale")]
+    pub fn set_frequency_scale_js(&self, scale: FrequencyScale) {
+        self.set_config(FFTConfig {
+            scale,
+            ..self.config()
+        });
+    }
+
+    /// 设置各个频段的响度计权方式
+    #[wasm_bindgen(js_name = "setFrequencyWeighting")]
+    pub fn set_frequency_weighting_js(&self, weighting: FrequencyWeighting) {
+        self.set_config(FFTConfig {
+            weighting,
+            ..self.config()
+        });
+    }
+
+    /// 取出最近一次的电平测量结果，包括短期响度、每个声道的 RMS 和真峰值，
+    /// 大约每 100 毫秒更新一次，没有新的结果时返回空值
+    #[wasm_bindgen(js_name = "takeLevels")]
+    pub fn take_levels_js(&mut self) -> Option<Levels> {
+        self.take_levels()
+    }
+
+    /// 取出最近一次检测到的节拍，没有新的节拍时返回空值
+    /// 背景动画可以据此跟随节拍跳动，而不需要在前端处理音频
+    #[wasm_bindgen(js_name = "takeBeat")]
+    pub fn take_beat_js(&mut self) -> Option<Beat> {
+        self.take_beat()
+    }
+
+    /// 当前估计的 BPM，节拍数量还不足以估计时为 0
+    #[wasm_bindgen(js_name = "bpm")]
+    pub fn bpm_js(&self) -> f32 {
+        self.beat_detector.bpm()
+    }
+
     /// 读取频谱数据
     #[wasm_bindgen(js_name = "read")]
     pub fn read_js(&mut self, buf: &mut [f32]) -> bool {
         self.read(buf)
     }
+
+    /// 分别读取左右声道（或者中置和侧边）的频谱数据
+    #[wasm_bindgen(js_name = "readStereo")]
+    pub fn read_stereo_js(
+        &mut self,
+        mode: StereoMode,
+        first: &mut [f32],
+        second: &mut [f32],
+    ) -> bool {
+        self.read_stereo(mode, first, second)
+    }
 }
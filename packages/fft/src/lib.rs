@@ -1,6 +1,12 @@
+mod bands;
+mod beat;
 mod fft_player;
+mod meter;
 mod resampler;
 
+pub use bands::{FrequencyScale, FrequencyWeighting};
+pub use fft_player::{FFTConfig, FFTPlayer, FFTWindow};
+
 use wasm_bindgen::prelude::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -0,0 +1,310 @@
+//! 响度、RMS 和峰值电平测量
+//!
+//! 每 100 毫秒输出一次测量结果，可以用于界面上的电平表，也可以用来检查响度标准化是否生效：
+//!
+//! - 短期响度：按照 ITU-R BS.1770 对信号进行 K 计权后计算最近 3 秒的响度，单位为 LUFS，
+//!   所有声道的权重都视为 1，适合立体声信号
+//! - RMS：每个声道最近 100 毫秒的均方根电平，单位为 dBFS
+//! - 真峰值：每个声道最近 100 毫秒经过 4 倍过采样后的峰值电平，单位为 dBTP
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+/// 每次输出测量结果的间隔，单位为毫秒
+const BLOCK_MS: usize = 100;
+/// 短期响度使用的块数量，共 3 秒
+const SHORT_TERM_BLOCKS: usize = 30;
+/// 电平的下限，静音时输出此值
+const MIN_LEVEL: f32 = -120.0;
+/// 真峰值过采样插值滤波器的抽头数量
+const TRUE_PEAK_TAPS: usize = 12;
+/// 真峰值的过采样倍数
+const TRUE_PEAK_OVERSAMPLE: usize = 4;
+
+/// 一次电平测量的结果
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Levels {
+    /// 短期响度，单位为 LUFS
+    #[wasm_bindgen(js_name = "shortTermLoudness")]
+    pub short_term_loudness: f32,
+    /// 每个声道的 RMS 电平，单位为 dBFS
+    pub rms: Vec<f32>,
+    /// 每个声道的真峰值电平，单位为 dBTP
+    #[wasm_bindgen(js_name = "truePeak")]
+    pub true_peak: Vec<f32>,
+}
+
+fn to_db(power: f32) -> f32 {
+    if power > 0.0 {
+        (10.0 * power.log10()).max(MIN_LEVEL)
+    } else {
+        MIN_LEVEL
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// BS.1770 的 K 计权滤波器，由一个高架滤波器和一个高通滤波器组成，
+/// 系数按照采样率重新计算，和 libebur128 的做法一致
+fn k_weighting(rate: usize) -> [Biquad; 2] {
+    let rate = rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (std::f64::consts::PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new(
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    [shelf, high_pass]
+}
+
+/// 真峰值过采样使用的插值系数，每个插值点使用加汉宁窗的 sinc 函数
+fn true_peak_coefficients() -> [[f32; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLE - 1] {
+    let mut result = [[0.0; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLE - 1];
+    let half = TRUE_PEAK_TAPS as f32 / 2.0;
+    for (phase, coefficients) in result.iter_mut().enumerate() {
+        // 插值点位于历史采样的正中间两个采样之间
+        let center = half - 1.0 + (phase + 1) as f32 / TRUE_PEAK_OVERSAMPLE as f32;
+        for (i, x) in coefficients.iter_mut().enumerate() {
+            let t = center - i as f32;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (std::f32::consts::PI * t).sin() / (std::f32::consts::PI * t)
+            };
+            let window = 0.5 + 0.5 * (std::f32::consts::PI * t / half).cos();
+            *x = sinc * window;
+        }
+    }
+    result
+}
+
+struct ChannelMeter {
+    filters: [Biquad; 2],
+    weighted_sum: f64,
+    square_sum: f64,
+    peak: f32,
+    history: VecDeque<f32>,
+}
+
+pub struct LevelMeter {
+    rate: usize,
+    block_size: usize,
+    block_len: usize,
+    channels: Vec<ChannelMeter>,
+    true_peak_coefficients: [[f32; TRUE_PEAK_TAPS]; TRUE_PEAK_OVERSAMPLE - 1],
+    /// 最近各个块中所有声道 K 计权后的功率之和
+    block_powers: VecDeque<f64>,
+    pending: Option<Levels>,
+}
+
+impl Default for LevelMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LevelMeter {
+    pub fn new() -> Self {
+        Self {
+            rate: 0,
+            block_size: 0,
+            block_len: 0,
+            channels: Vec::new(),
+            true_peak_coefficients: true_peak_coefficients(),
+            block_powers: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+            pending: None,
+        }
+    }
+
+    fn reset(&mut self, rate: usize, channels: usize) {
+        self.rate = rate;
+        self.block_size = (rate * BLOCK_MS / 1000).max(1);
+        self.block_len = 0;
+        self.channels = (0..channels)
+            .map(|_| ChannelMeter {
+                filters: k_weighting(rate),
+                weighted_sum: 0.0,
+                square_sum: 0.0,
+                peak: 0.0,
+                history: VecDeque::from(vec![0.0; TRUE_PEAK_TAPS]),
+            })
+            .collect();
+        self.block_powers.clear();
+        self.pending = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.reset(self.rate, self.channels.len());
+    }
+
+    /// 传入交错排列的多声道采样数据，采样率或者声道数变化时会重新开始测量
+    pub fn push(&mut self, rate: usize, channels: usize, samples: impl Iterator<Item = f32>) {
+        if channels == 0 || rate == 0 {
+            return;
+        }
+        if rate != self.rate || channels != self.channels.len() {
+            self.reset(rate, channels);
+        }
+        let mut channel = 0;
+        for sample in samples {
+            let meter = &mut self.channels[channel];
+            let weighted = meter
+                .filters
+                .iter_mut()
+                .fold(sample as f64, |x, filter| filter.process(x));
+            meter.weighted_sum += weighted * weighted;
+            meter.square_sum += (sample * sample) as f64;
+
+            meter.history.pop_front();
+            meter.history.push_back(sample);
+            meter.peak = meter.peak.max(sample.abs());
+            for coefficients in &self.true_peak_coefficients {
+                let value: f32 = coefficients
+                    .iter()
+                    .zip(&meter.history)
+                    .map(|(a, b)| a * b)
+                    .sum();
+                meter.peak = meter.peak.max(value.abs());
+            }
+
+            channel += 1;
+            if channel == channels {
+                channel = 0;
+                self.block_len += 1;
+                if self.block_len == self.block_size {
+                    self.finish_block();
+                }
+            }
+        }
+    }
+
+    fn finish_block(&mut self) {
+        let len = self.block_len as f64;
+        let power = self.channels.iter().map(|x| x.weighted_sum / len).sum();
+        if self.block_powers.len() == SHORT_TERM_BLOCKS {
+            self.block_powers.pop_front();
+        }
+        self.block_powers.push_back(power);
+        let short_term = self.block_powers.iter().sum::<f64>() / self.block_powers.len() as f64;
+
+        self.pending = Some(Levels {
+            short_term_loudness: (-0.691 + to_db(short_term as f32)).max(MIN_LEVEL),
+            rms: self
+                .channels
+                .iter()
+                .map(|x| to_db((x.square_sum / len) as f32))
+                .collect(),
+            true_peak: self
+                .channels
+                .iter()
+                .map(|x| to_db(x.peak * x.peak))
+                .collect(),
+        });
+
+        for meter in &mut self.channels {
+            meter.weighted_sum = 0.0;
+            meter.square_sum = 0.0;
+            meter.peak = 0.0;
+        }
+        self.block_len = 0;
+    }
+
+    /// 取出最近一次的测量结果，大约每 100 毫秒更新一次
+    pub fn take_levels(&mut self) -> Option<Levels> {
+        self.pending.take()
+    }
+}
+
+#[cfg(test)]
+fn measure_sine(rate: usize, freq: f64, amplitude: f64, phase: f64, seconds: usize) -> Levels {
+    let samples = (0..rate * seconds).flat_map(|i| {
+        let x =
+            amplitude * (2.0 * std::f64::consts::PI * freq * i as f64 / rate as f64 + phase).sin();
+        [x as f32, x as f32]
+    });
+    let mut meter = LevelMeter::new();
+    meter.push(rate, 2, samples);
+    meter.take_levels().unwrap()
+}
+
+#[test]
+fn sine_levels_test() {
+    // BS.1770 中单个声道满幅的 997 赫兹正弦波的响度为 -3.01 LUFS，两个声道时为 0 LUFS
+    let levels = measure_sine(48000, 997.0, 0.5, 0.0, 4);
+    assert!(
+        (levels.short_term_loudness + 6.02).abs() < 0.1,
+        "{levels:?}"
+    );
+    for (rms, peak) in levels.rms.iter().zip(&levels.true_peak) {
+        assert!((rms + 9.03).abs() < 0.05, "{levels:?}");
+        assert!((peak + 6.02).abs() < 0.1, "{levels:?}");
+    }
+
+    // 采样率变化时重新计算滤波器系数，结果应该一致
+    let levels = measure_sine(44100, 997.0, 0.5, 0.0, 4);
+    assert!(
+        (levels.short_term_loudness + 6.02).abs() < 0.1,
+        "{levels:?}"
+    );
+}
+
+#[test]
+fn true_peak_test() {
+    // 四分之一采样率的正弦波相位为 45 度时，采样点都落在峰值的 0.707 倍处，
+    // 采样峰值比实际峰值低约 3dB，真峰值应该能还原出实际峰值
+    let levels = measure_sine(48000, 12000.0, 0.5, std::f64::consts::FRAC_PI_4, 1);
+    for peak in &levels.true_peak {
+        assert!((peak + 6.02).abs() < 0.5, "{levels:?}");
+    }
+}
+
+#[test]
+fn silence_levels_test() {
+    let mut meter = LevelMeter::new();
+    meter.push(48000, 2, vec![0.0; 48000 * 2].into_iter());
+    let levels = meter.take_levels().unwrap();
+    assert_eq!(levels.short_term_loudness, MIN_LEVEL);
+    assert_eq!(levels.rms, vec![MIN_LEVEL; 2]);
+    assert_eq!(levels.true_peak, vec![MIN_LEVEL; 2]);
+    assert_eq!(meter.take_levels(), None);
+}
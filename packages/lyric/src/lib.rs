@@ -1,12 +1,13 @@
-mod ass;
+pub mod ass;
 #[cfg(feature = "qrc")]
-mod eqrc;
-mod eslrc;
-mod lrc;
-mod lys;
-mod qrc;
+pub mod eqrc;
+pub mod eslrc;
+pub mod lrc;
+pub mod lys;
+pub mod qrc;
+pub mod ttml;
 mod utils;
-mod yrc;
+pub mod yrc;
 mod types {
     include!(concat!(env!("OUT_DIR"), "/types.rs"));
 }
@@ -48,3 +49,25 @@ pub struct LyricLine<'a> {
     #[serde(default)]
     pub is_duet: bool,
 }
+
+impl LyricWord<'_> {
+    pub fn into_owned(self) -> LyricWord<'static> {
+        LyricWord {
+            start_time: self.start_time,
+            end_time: self.end_time,
+            word: Cow::Owned(self.word.into_owned()),
+        }
+    }
+}
+
+impl LyricLine<'_> {
+    pub fn into_owned(self) -> LyricLine<'static> {
+        LyricLine {
+            words: self.words.into_iter().map(LyricWord::into_owned).collect(),
+            translated_lyric: self.translated_lyric,
+            roman_lyric: self.roman_lyric,
+            is_bg: self.is_bg,
+            is_duet: self.is_duet,
+        }
+    }
+}
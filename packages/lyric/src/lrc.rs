@@ -1,3 +1,14 @@
+//! LyRiC 歌词格式，同时支持增强 LRC 格式
+//!
+//! 增强 LRC 格式会在每个单词前面使用 `<mm:ss.xx>` 形式的时间戳标记单词的开始时间，
+//! 行尾的时间戳则代表最后一个单词的结束时间，例如：
+//!
+//! ```text
+//! [00:10.82]<00:10.82>Test <00:10.97>Word<00:12.62>
+//! ```
+//!
+//! 解析时会读取 `[offset:<毫秒>]` 标签并将其应用到所有时间戳上，
+//! 正数代表歌词提前显示，负数代表歌词延后显示。
 use wasm_bindgen::prelude::*;
 
 use crate::{utils::process_lyrics, LyricLine, LyricWord};
@@ -10,7 +21,21 @@ use nom::{character::complete::line_ending, IResult};
 
 #[inline]
 pub fn parse_time(src: &str) -> IResult<&str, usize> {
-    let (src, _start) = tag("[")(src)?;
+    parse_time_with_brackets(src, "[", "]")
+}
+
+/// 解析增强 LRC 格式中单词的时间戳 `<mm:ss.xx>`
+#[inline]
+pub fn parse_word_time(src: &str) -> IResult<&str, usize> {
+    parse_time_with_brackets(src, "<", ">")
+}
+
+fn parse_time_with_brackets<'a>(
+    src: &'a str,
+    open: &'static str,
+    close: &'static str,
+) -> IResult<&'a str, usize> {
+    let (src, _start) = tag(open)(src)?;
 
     let (src, min) = take_until1(":")(src)?;
 
@@ -55,7 +80,7 @@ pub fn parse_time(src: &str) -> IResult<&str, usize> {
 
     let time = min as usize * 60 * 1000 + sec as usize * 1000 + ms as usize;
 
-    let (src, _) = tag("]")(src)?;
+    let (src, _) = tag(close)(src)?;
     Ok((src, time))
 }
 
@@ -68,46 +93,77 @@ fn time_test() {
     assert!(parse_time("[168:10.254233]").is_err());
 }
 
+/// 将一行歌词的内容拆分成单词，没有单词时间戳时整行作为一个单词，
+/// `shift` 为该行的时间戳与最早的行时间戳的差值，用于处理重复时间戳的行
+fn parse_words(src: &str, start_time: usize, shift: usize) -> Vec<LyricWord<'_>> {
+    let mut words: Vec<LyricWord> = Vec::new();
+    let mut rest = src;
+    let mut word_start = start_time;
+    let mut has_word_time = false;
+    while !rest.is_empty() {
+        // 找到下一个单词时间戳，没有的话剩下的内容都属于当前单词
+        let next = rest
+            .char_indices()
+            .filter(|(_, c)| *c == '<')
+            .find_map(|(i, _)| parse_word_time(&rest[i..]).ok().map(|x| (i, x)));
+        let Some((i, (after, time))) = next else {
+            words.push(LyricWord {
+                start_time: word_start,
+                end_time: 0,
+                word: Cow::Borrowed(rest),
+            });
+            break;
+        };
+        has_word_time = true;
+        let time = time + shift;
+        if i > 0 {
+            words.push(LyricWord {
+                start_time: word_start,
+                end_time: time,
+                word: Cow::Borrowed(&rest[..i]),
+            });
+        } else if let Some(last) = words.last_mut() {
+            last.end_time = time;
+        }
+        word_start = time;
+        rest = after;
+    }
+    if !has_word_time && words.is_empty() {
+        words.push(LyricWord {
+            start_time,
+            end_time: 0,
+            word: Cow::Borrowed(src),
+        });
+    }
+    words
+}
+
 #[inline]
 pub fn parse_line(src: &str) -> IResult<&str, Vec<LyricLine<'_>>> {
     let (src, times) = many1(parse_time)(src)?;
-    match is_not("\r\n")(src) {
+    let (src, line) = match is_not("\r\n")(src) {
         Ok((src, line)) => {
             let (src, _) = opt(line_ending)(src)?;
-            Ok((
-                src,
-                times
-                    .into_iter()
-                    .map(|t| LyricLine {
-                        words: vec![LyricWord {
-                            start_time: t,
-                            end_time: 0,
-                            word: Cow::Borrowed(line),
-                        }],
-                        ..Default::default()
-                    })
-                    .collect(),
-            ))
+            (src, line)
         }
         Err(nom::Err::Error(nom::error::Error {
             input,
             code: nom::error::ErrorKind::IsNot,
-        })) => Ok((
-            src,
-            times
-                .into_iter()
-                .map(|t| LyricLine {
-                    words: vec![LyricWord {
-                        start_time: t,
-                        end_time: 0,
-                        word: Cow::Borrowed(input),
-                    }],
-                    ..Default::default()
-                })
-                .collect(),
-        )),
-        Err(e) => Err(e),
-    }
+        })) => (src, input),
+        Err(e) => return Err(e),
+    };
+    // 单词时间戳以最早的一个行时间戳为准，其余重复的行按照时间差平移
+    let first_time = times.iter().copied().min().unwrap_or_default();
+    Ok((
+        src,
+        times
+            .into_iter()
+            .map(|t| LyricLine {
+                words: parse_words(line, t, t - first_time),
+                ..Default::default()
+            })
+            .collect(),
+    ))
 }
 
 #[test]
@@ -172,17 +228,75 @@ pub fn parse_lrc(src: &str) -> Vec<LyricLine> {
     let lines = src.lines();
     let mut result = Vec::with_capacity(lines.size_hint().1.unwrap_or(1024).min(1024));
 
+    let mut offset = 0;
+
     for line in lines {
-        if let Ok((_, line)) = parse_line(line) {
+        if let Some(value) = parse_offset(line) {
+            offset = value;
+        } else if let Ok((_, line)) = parse_line(line) {
             result.extend_from_slice(&line);
         }
     }
 
+    if offset != 0 {
+        apply_offset(&mut result, offset);
+    }
     process_lyrics(&mut result);
+    fill_end_times(&mut result);
 
     result
 }
 
+/// 解析 `[offset:<毫秒>]` 标签
+fn parse_offset(src: &str) -> Option<isize> {
+    let src = src.trim();
+    let value = src.strip_prefix('[')?.strip_suffix(']')?;
+    let (key, value) = value.split_once(':')?;
+    if !key.trim().eq_ignore_ascii_case("offset") {
+        return None;
+    }
+    let value = value.trim();
+    value.strip_prefix('+').unwrap_or(value).parse().ok()
+}
+
+fn apply_offset(lines: &mut [LyricLine], offset: isize) {
+    let shift = |time: usize| time.saturating_add_signed(-offset);
+    for line in lines.iter_mut() {
+        for word in line.words.iter_mut() {
+            word.start_time = shift(word.start_time);
+            if word.end_time != 0 {
+                word.end_time = shift(word.end_time);
+            }
+        }
+    }
+}
+
+/// 补全没有结束时间的单词，单词的结束时间为下一个单词的开始时间，
+/// 行内最后一个单词的结束时间为下一个开始时间更晚的歌词行的开始时间，
+/// 最后一行无法确定结束时间，仍然为 0
+fn fill_end_times(lines: &mut [LyricLine]) {
+    for i in 0..lines.len() {
+        let Some(line_start) = lines[i].words.first().map(|x| x.start_time) else {
+            continue;
+        };
+        let next_line_start = lines[i + 1..]
+            .iter()
+            .filter_map(|x| x.words.first())
+            .map(|x| x.start_time)
+            .find(|x| *x > line_start);
+        let words = &mut lines[i].words;
+        for j in 0..words.len() {
+            if words[j].end_time != 0 {
+                continue;
+            }
+            let next_start = words.get(j + 1).map(|x| x.start_time).or(next_line_start);
+            if let Some(next_start) = next_start {
+                words[j].end_time = next_start.max(words[j].start_time);
+            }
+        }
+    }
+}
+
 pub fn write_timestamp(result: &mut String, time: usize) {
     let ms = time % 1000;
     let sec = (time - ms) / 1000;
@@ -191,6 +305,15 @@ pub fn write_timestamp(result: &mut String, time: usize) {
     write!(result, "[{:02}:{:02}.{:03}]", min, sec % 60, ms).unwrap()
 }
 
+/// 写入增强 LRC 格式中单词的时间戳 `<mm:ss.xxx>`
+pub fn write_word_timestamp(result: &mut String, time: usize) {
+    let ms = time % 1000;
+    let sec = (time - ms) / 1000;
+    let min = (sec - sec % 60) / 60;
+
+    write!(result, "<{:02}:{:02}.{:03}>", min, sec % 60, ms).unwrap()
+}
+
 #[inline]
 pub fn stringify_lrc(lines: &[LyricLine]) -> String {
     let capacity: usize = lines
@@ -212,6 +335,36 @@ pub fn stringify_lrc(lines: &[LyricLine]) -> String {
     result
 }
 
+/// 转换为增强 LRC 格式，含有多个单词的歌词行会写出每个单词的时间戳 `<mm:ss.xxx>`
+pub fn stringify_enhanced_lrc(lines: &[LyricLine]) -> String {
+    let capacity: usize = lines
+        .iter()
+        .map(|x| x.words.iter().map(|y| y.word.len() + 11).sum::<usize>() + 24)
+        .sum();
+    let mut result = String::with_capacity(capacity);
+
+    for line in lines {
+        if !line.words.is_empty() {
+            write_timestamp(&mut result, line.words[0].start_time);
+            if line.words.len() > 1 {
+                for word in line.words.iter() {
+                    write_word_timestamp(&mut result, word.start_time);
+                    result.push_str(&word.word);
+                }
+                let last = &line.words[line.words.len() - 1];
+                if last.end_time > last.start_time {
+                    write_word_timestamp(&mut result, last.end_time);
+                }
+            } else {
+                result.push_str(&line.words[0].word);
+            }
+            result.push('\n');
+        }
+    }
+
+    result
+}
+
 #[test]
 fn stringify_lrc_test() {
     let lrc = parse_lrc("[00:01.12] test LyRiC\n[00:10.254] sssxxx");
@@ -221,6 +374,67 @@ fn stringify_lrc_test() {
     );
 }
 
+#[test]
+fn enhanced_lrc_test() {
+    let lrc = parse_lrc("[00:10.82]<00:10.82>Test <00:10.97>Word<00:12.62>\n[00:13.00]Next");
+    assert_eq!(
+        lrc[0].words,
+        vec![
+            LyricWord {
+                start_time: 10820,
+                end_time: 10970,
+                word: Cow::Borrowed("Test ")
+            },
+            LyricWord {
+                start_time: 10970,
+                end_time: 12620,
+                word: Cow::Borrowed("Word")
+            }
+        ]
+    );
+    assert_eq!(
+        stringify_enhanced_lrc(&lrc),
+        "[00:10.820]<00:10.820>Test <00:10.970>Word<00:12.620>\n[00:13.000]Next\n"
+    );
+    assert_eq!(
+        stringify_lrc(&lrc),
+        "[00:10.820]Test Word\n[00:13.000]Next\n"
+    );
+}
+
+#[test]
+fn lrc_offset_test() {
+    let lrc = parse_lrc("[offset:+500]\n[00:01.00]a\n[00:02.00]<00:02.00>b<00:03.00>");
+    assert_eq!(lrc[0].words[0].start_time, 500);
+    assert_eq!(lrc[0].words[0].end_time, 1500);
+    assert_eq!(lrc[1].words[0].start_time, 1500);
+    assert_eq!(lrc[1].words[0].end_time, 2500);
+    let lrc = parse_lrc("[offset:-250]\n[00:01.00]a");
+    assert_eq!(lrc[0].words[0].start_time, 1250);
+}
+
+#[test]
+fn lrc_duplicate_time_test() {
+    let lrc = parse_lrc("[00:05.00][00:01.00]<00:01.00>a<00:01.50>b\n[00:03.00]c");
+    let times: Vec<_> = lrc
+        .iter()
+        .map(|x| {
+            x.words
+                .iter()
+                .map(|x| (x.start_time, x.end_time))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(
+        times,
+        vec![
+            vec![(1000, 1500), (1500, 3000)],
+            vec![(3000, 5000)],
+            vec![(5000, 5500), (5500, 0)],
+        ]
+    );
+}
+
 #[wasm_bindgen(js_name = "parseLrc", skip_typescript)]
 pub fn parse_lrc_js(src: &str) -> JsValue {
     serde_wasm_bindgen::to_value(&parse_lrc(src)).unwrap()
@@ -232,6 +446,12 @@ pub fn stringify_lrc_js(lrc: JsValue) -> String {
     stringify_lrc(&lines)
 }
 
+#[wasm_bindgen(js_name = "stringifyEnhancedLrc", skip_typescript)]
+pub fn stringify_enhanced_lrc_js(lrc: JsValue) -> String {
+    let lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lrc).unwrap();
+    stringify_enhanced_lrc(&lines)
+}
+
 #[test]
 fn lrc_bench_test() {
     let mut times = Vec::with_capacity(1024);
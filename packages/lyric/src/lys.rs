@@ -42,7 +42,15 @@ pub fn parse_property(src: &str) -> IResult<&str, (bool, bool)> {
     let (src, prop) = nom::character::complete::digit1(src)?;
     let (src, _) = tag("]")(src)?;
 
-    let prop = prop.parse::<u8>().unwrap();
+    let prop = match prop.parse::<u8>() {
+        Ok(prop) => prop,
+        Err(_) => {
+            return Err(nom::Err::Error(nom::error::Error {
+                input: src,
+                code: nom::error::ErrorKind::Digit,
+            }))
+        }
+    };
 
     Ok((
         src,
@@ -169,7 +177,7 @@ pub fn stringify_lys(lines: &[LyricLine]) -> String {
             write!(result, "{prop}").unwrap();
             for word in line.words.iter() {
                 let start_time = word.start_time;
-                let duration = word.end_time - word.start_time;
+                let duration = word.end_time.saturating_sub(word.start_time);
                 result.push_str(&word.word);
                 write!(result, "({start_time},{duration})").unwrap();
             }
@@ -209,4 +217,5 @@ fn test_props() {
         "[8]Test(1234,567)\n",
         stringify_lys(&parse_lys("[8]Test(1234,567)"))
     );
+    assert!(parse_line("[999]Test(1234,567)").is_err());
 }
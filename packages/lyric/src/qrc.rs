@@ -129,6 +129,59 @@ pub fn parse_qrc(src: &str) -> Vec<LyricLine> {
     result
 }
 
+/// 从 QQ 音乐的 QRC 歌词文件中取出歌词内容
+///
+/// 解密后的 QRC 歌词会被包裹在 XML 中，歌词内容位于 `LyricContent` 属性里。
+/// 因为歌词内容中可能含有未转义的引号，这里不使用 XML 解析器，
+/// 而是直接取 `LyricContent="` 到最后一个 `"/>` 之间的内容。
+/// 如果没有找到 `LyricContent` 属性，则认为传入的已经是歌词内容，原样返回。
+pub fn extract_qrc_content(src: &str) -> Cow<'_, str> {
+    const ATTR_START: &str = "LyricContent=\"";
+    let Some(start) = src.find(ATTR_START).map(|x| x + ATTR_START.len()) else {
+        return Cow::Borrowed(src);
+    };
+    let content = match src[start..].rfind("\"/>") {
+        Some(end) => &src[start..start + end],
+        None => &src[start..],
+    };
+    match quick_xml::escape::unescape(content) {
+        Ok(content) => Cow::Owned(content.into_owned()),
+        Err(_) => Cow::Borrowed(content),
+    }
+}
+
+#[test]
+fn test_extract_content() {
+    let src = r#"<?xml version="1.0" encoding="utf-8"?>
+<QrcInfos>
+<QrcHeadInfo SaveTime="1" Version="100"/>
+<LyricInfo LyricCount="1">
+<Lyric_1 LyricType="1" LyricContent="[ti:"Test"]
+[0,600]Test(0,300) &amp;(300,300)
+"/>
+</LyricInfo>
+</QrcInfos>"#;
+    let content = extract_qrc_content(src);
+    assert_eq!(content, "[ti:\"Test\"]\n[0,600]Test(0,300) &(300,300)\n");
+    let lines = parse_qrc(&content);
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].words.len(), 2);
+    assert_eq!(
+        extract_qrc_content("[0,300]Test(0,300)"),
+        "[0,300]Test(0,300)"
+    );
+}
+
+/// 解密十六进制字符串格式的 QRC 歌词数据并解析歌词行
+#[cfg(feature = "qrc")]
+pub fn parse_encrypted_qrc(hex_data: &str) -> Vec<LyricLine<'static>> {
+    let decrypted = crate::eqrc::decrypt_qrc_hex(hex_data);
+    parse_qrc(&extract_qrc_content(&decrypted))
+        .into_iter()
+        .map(LyricLine::into_owned)
+        .collect()
+}
+
 pub fn stringify_qrc(lines: &[LyricLine]) -> String {
     let capacity: usize = lines
         .iter()
@@ -139,11 +192,15 @@ pub fn stringify_qrc(lines: &[LyricLine]) -> String {
     for line in lines {
         if !line.words.is_empty() {
             let start_time = line.words[0].start_time;
-            let duration: usize = line.words.iter().map(|x| x.end_time - x.start_time).sum();
+            let duration: usize = line
+                .words
+                .iter()
+                .map(|x| x.end_time.saturating_sub(x.start_time))
+                .sum();
             write!(result, "[{start_time},{duration}]").unwrap();
             for word in line.words.iter() {
                 let start_time = word.start_time;
-                let duration = word.end_time - word.start_time;
+                let duration = word.end_time.saturating_sub(word.start_time);
                 result.push_str(&word.word);
                 write!(result, "({start_time},{duration})").unwrap();
             }
@@ -159,6 +216,11 @@ pub fn parse_qrc_js(src: &str) -> JsValue {
     serde_wasm_bindgen::to_value(&parse_qrc(src)).unwrap()
 }
 
+#[wasm_bindgen(js_name = "extractQrcContent", skip_typescript)]
+pub fn extract_qrc_content_js(src: &str) -> String {
+    extract_qrc_content(src).into_owned()
+}
+
 #[wasm_bindgen(js_name = "stringifyQrc", skip_typescript)]
 pub fn stringify_qrc_js(lrc: JsValue) -> String {
     let lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lrc).unwrap();
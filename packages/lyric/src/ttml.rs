@@ -0,0 +1,552 @@
+//! AMLL 使用的 TTML 歌词格式，基于 Apple Music 的 TTML 歌词文件
+//!
+//! 在 Apple Music 的格式的基础上扩展并支持了以下内容：
+//!
+//! - 使用 `ttm:agent` 区分主唱和对唱，主唱为 `type="person"` 的演唱者
+//! - 使用 `ttm:role="x-bg"` 的 `span` 表示附属于上一行的背景歌词
+//! - 使用 `ttm:role="x-translation"` 和 `ttm:role="x-roman"` 的 `span` 表示翻译和音译
+//! - 使用 `amll:meta` 保存歌曲的元数据
+//!
+//! 单词之间的空白会合并到前一个单词的末尾，导出时会重新放到 `span` 外面。
+//! 和 TypeScript 版本的 TTML 模块不同，`amll:empty-beat` 属性会在解析时被丢弃。
+use std::borrow::Cow;
+use std::fmt::Write;
+
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+    Reader,
+};
+use serde::*;
+use wasm_bindgen::prelude::*;
+
+use crate::{LyricLine, LyricWord};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TTMLMetadata {
+    pub key: String,
+    pub value: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TTMLLyric<'a> {
+    pub metadata: Vec<TTMLMetadata>,
+    pub lyric_lines: Vec<LyricLine<'a>>,
+}
+
+/// 解析 TTML 中的时间戳，格式为 `[[时:]分:]秒[.毫秒]`
+pub fn parse_timespan(src: &str) -> Option<usize> {
+    let src = src.trim();
+    let src = src.strip_suffix('s').unwrap_or(src);
+    let mut parts = src.rsplitn(3, ':');
+    let sec = parts.next()?;
+    let min = parts.next().map(str::parse::<usize>).transpose().ok()?;
+    let hour = parts.next().map(str::parse::<usize>).transpose().ok()?;
+    let (sec, ms) = match sec.split_once('.') {
+        Some((sec, ms)) => {
+            // 只取前三位小数，不足三位的补零
+            let ms: String = ms.chars().chain("000".chars()).take(3).collect();
+            (sec, ms.parse::<usize>().ok()?)
+        }
+        None => (sec, 0),
+    };
+    let sec = sec.parse::<usize>().ok()?;
+    Some((hour.unwrap_or(0) * 3600 + min.unwrap_or(0) * 60 + sec) * 1000 + ms)
+}
+
+#[test]
+fn timespan_test() {
+    assert_eq!(parse_timespan("00:01.12"), Some(1120));
+    assert_eq!(parse_timespan("1:02:03.4"), Some(3723400));
+    assert_eq!(parse_timespan("12.345s"), Some(12345));
+    assert_eq!(parse_timespan("5"), Some(5000));
+    assert_eq!(parse_timespan("a:00.000"), None);
+}
+
+pub fn write_timespan(result: &mut String, time: usize) {
+    let ms = time % 1000;
+    let sec = time / 1000;
+    let (hour, min, sec) = (sec / 3600, sec / 60 % 60, sec % 60);
+
+    if hour > 0 {
+        write!(result, "{:02}:{:02}:{:02}.{:03}", hour, min, sec, ms).unwrap()
+    } else {
+        write!(result, "{:02}:{:02}.{:03}", min, sec, ms).unwrap()
+    }
+}
+
+fn timespan(time: usize) -> String {
+    let mut result = String::with_capacity(12);
+    write_timespan(&mut result, time);
+    result
+}
+
+struct LineState {
+    line: LyricLine<'static>,
+    begin: usize,
+    end: usize,
+    bg_lines: Vec<LyricLine<'static>>,
+}
+
+enum Frame {
+    Line(Box<LineState>),
+    Word {
+        begin: usize,
+        end: usize,
+        text: String,
+    },
+    Translation(String),
+    Roman(String),
+    Other,
+}
+
+fn attribute(el: &BytesStart, name: &str) -> Option<String> {
+    el.try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|x| x.unescape_value().ok())
+        .map(Cow::into_owned)
+}
+
+fn time_attributes(el: &BytesStart) -> Option<(usize, usize)> {
+    Some((
+        parse_timespan(&attribute(el, "begin")?)?,
+        parse_timespan(&attribute(el, "end")?)?,
+    ))
+}
+
+/// 找到离栈顶最近的歌词行
+fn nearest_line(stack: &mut [Frame]) -> Option<&mut LineState> {
+    stack.iter_mut().rev().find_map(|x| match x {
+        Frame::Line(line) => Some(line.as_mut()),
+        _ => None,
+    })
+}
+
+/// 将行内不属于任何单词的文本加入歌词行，空白会合并到上一个单词的末尾
+fn push_line_text(state: &mut LineState, text: &str) {
+    if text.trim().is_empty() {
+        if let Some(word) = state.line.words.last_mut() {
+            word.word.to_mut().push_str(text);
+        }
+    } else {
+        state.line.words.push(LyricWord {
+            start_time: state.begin,
+            end_time: state.end,
+            word: Cow::Owned(text.to_owned()),
+        });
+    }
+}
+
+/// 去掉背景歌词首尾的括号
+fn strip_bg_brackets(line: &mut LyricLine) {
+    if let Some(word) = line.words.first_mut() {
+        if let Some(rest) = word.word.strip_prefix('(') {
+            word.word = Cow::Owned(rest.to_owned());
+            if word.word.is_empty() {
+                line.words.remove(0);
+            }
+        }
+    }
+    if let Some(word) = line.words.last_mut() {
+        let trimmed = word.word.trim_end();
+        if let Some(rest) = trimmed.strip_suffix(')') {
+            word.word = Cow::Owned(rest.to_owned());
+            if word.word.is_empty() {
+                line.words.pop();
+            }
+        }
+    }
+}
+
+pub fn parse_ttml(src: &str) -> Result<TTMLLyric<'static>, quick_xml::Error> {
+    let mut reader = Reader::from_str(src);
+    reader.trim_text(false);
+
+    let mut main_agent_id = "v1".to_owned();
+    let mut result = TTMLLyric::default();
+    let mut stack: Vec<Frame> = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(el) => {
+                let frame = match el.name().as_ref() {
+                    b"p" => match time_attributes(&el) {
+                        Some((begin, end)) => {
+                            let is_duet = attribute(&el, "ttm:agent")
+                                .map(|agent| agent != main_agent_id)
+                                .unwrap_or(false);
+                            Frame::Line(Box::new(LineState {
+                                line: LyricLine {
+                                    is_duet,
+                                    ..Default::default()
+                                },
+                                begin,
+                                end,
+                                bg_lines: Vec::new(),
+                            }))
+                        }
+                        None => Frame::Other,
+                    },
+                    b"span" => match attribute(&el, "ttm:role").as_deref() {
+                        Some("x-bg") => match nearest_line(&mut stack) {
+                            Some(parent) => {
+                                let (begin, end) =
+                                    time_attributes(&el).unwrap_or((parent.begin, parent.end));
+                                Frame::Line(Box::new(LineState {
+                                    line: LyricLine {
+                                        is_bg: true,
+                                        is_duet: parent.line.is_duet,
+                                        ..Default::default()
+                                    },
+                                    begin,
+                                    end,
+                                    bg_lines: Vec::new(),
+                                }))
+                            }
+                            None => Frame::Other,
+                        },
+                        Some("x-translation") => Frame::Translation(String::new()),
+                        Some("x-roman") => Frame::Roman(String::new()),
+                        _ => match time_attributes(&el) {
+                            Some((begin, end)) => Frame::Word {
+                                begin,
+                                end,
+                                text: String::new(),
+                            },
+                            None => Frame::Other,
+                        },
+                    },
+                    _ => {
+                        read_head_element(&el, &mut main_agent_id, &mut result.metadata);
+                        Frame::Other
+                    }
+                };
+                stack.push(frame);
+            }
+            Event::Empty(el) => {
+                read_head_element(&el, &mut main_agent_id, &mut result.metadata);
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                match stack.last_mut() {
+                    Some(Frame::Word { text: word, .. })
+                    | Some(Frame::Translation(word))
+                    | Some(Frame::Roman(word)) => word.push_str(&text),
+                    Some(Frame::Line(state)) => push_line_text(state, &text),
+                    _ => {}
+                }
+            }
+            Event::CData(text) => {
+                let text = String::from_utf8_lossy(&text).into_owned();
+                if let Some(Frame::Word { text: word, .. }) = stack.last_mut() {
+                    word.push_str(&text);
+                }
+            }
+            Event::End(_) => match stack.pop() {
+                Some(Frame::Word { begin, end, text }) => {
+                    if let Some(state) = nearest_line(&mut stack) {
+                        state.line.words.push(LyricWord {
+                            start_time: begin,
+                            end_time: end,
+                            word: Cow::Owned(text),
+                        });
+                    }
+                }
+                Some(Frame::Translation(text)) => {
+                    if let Some(state) = nearest_line(&mut stack) {
+                        state.line.translated_lyric = text.trim().to_owned();
+                    }
+                }
+                Some(Frame::Roman(text)) => {
+                    if let Some(state) = nearest_line(&mut stack) {
+                        state.line.roman_lyric = text.trim().to_owned();
+                    }
+                }
+                Some(Frame::Line(state)) => {
+                    let LineState {
+                        mut line, bg_lines, ..
+                    } = *state;
+                    if line.is_bg {
+                        strip_bg_brackets(&mut line);
+                        if let Some(parent) = nearest_line(&mut stack) {
+                            parent.bg_lines.push(line);
+                        }
+                    } else {
+                        result.lyric_lines.push(line);
+                        result.lyric_lines.extend(bg_lines);
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+/// 读取头部中的演唱者和元数据信息
+fn read_head_element(
+    el: &BytesStart,
+    main_agent_id: &mut String,
+    metadata: &mut Vec<TTMLMetadata>,
+) {
+    match el.name().as_ref() {
+        b"ttm:agent" => {
+            if attribute(el, "type").as_deref() == Some("person") {
+                if let Some(id) = attribute(el, "xml:id") {
+                    *main_agent_id = id;
+                }
+            }
+        }
+        b"amll:meta" => {
+            let (Some(key), Some(value)) = (attribute(el, "key"), attribute(el, "value")) else {
+                return;
+            };
+            match metadata.iter_mut().find(|x| x.key == key) {
+                Some(existing) => existing.value.push(value),
+                None => metadata.push(TTMLMetadata {
+                    key,
+                    value: vec![value],
+                }),
+            }
+        }
+        _ => {}
+    }
+}
+
+fn line_time_range(line: &LyricLine) -> (usize, usize) {
+    let words = line.words.iter().filter(|x| !x.word.trim().is_empty());
+    let begin = words
+        .clone()
+        .map(|x| x.start_time)
+        .min()
+        .unwrap_or_default();
+    // 从 LyRiC 等格式转换而来的最后一行可能没有结束时间
+    let end = words.map(|x| x.end_time).max().unwrap_or_default();
+    (begin, end.max(begin))
+}
+
+/// 写入一个单词，单词末尾的空白会放到 `span` 外面
+fn write_word(result: &mut String, word: &LyricWord, prefix: &str, suffix: &str) {
+    let text = word.word.trim_end();
+    let space = &word.word[text.len()..];
+    if !text.is_empty() {
+        write!(
+            result,
+            r#"<span begin="{}" end="{}">{}{}{}</span>"#,
+            timespan(word.start_time),
+            timespan(word.end_time),
+            escape(prefix),
+            escape(text),
+            escape(suffix),
+        )
+        .unwrap();
+    }
+    result.push_str(&escape(space));
+}
+
+fn write_words(result: &mut String, line: &LyricLine, brackets: bool) {
+    let (prefix, suffix) = if brackets { ("(", ")") } else { ("", "") };
+    let last = line.words.len().saturating_sub(1);
+    for (i, word) in line.words.iter().enumerate() {
+        write_word(
+            result,
+            word,
+            if i == 0 { prefix } else { "" },
+            if i == last { suffix } else { "" },
+        );
+    }
+}
+
+fn write_extra_lyrics(result: &mut String, line: &LyricLine) {
+    if !line.translated_lyric.is_empty() {
+        write!(
+            result,
+            r#"<span ttm:role="x-translation" xml:lang="zh-CN">{}</span>"#,
+            escape(line.translated_lyric.as_str())
+        )
+        .unwrap();
+    }
+    if !line.roman_lyric.is_empty() {
+        write!(
+            result,
+            r#"<span ttm:role="x-roman">{}</span>"#,
+            escape(line.roman_lyric.as_str())
+        )
+        .unwrap();
+    }
+}
+
+/// 将歌词转换为 TTML 格式，没有单词的歌词行会作为段落的分隔
+pub fn stringify_ttml(lyric: &TTMLLyric) -> String {
+    let lines = &lyric.lyric_lines;
+    let mut result = String::with_capacity(
+        lines
+            .iter()
+            .map(|x| x.words.iter().map(|y| y.word.len() + 48).sum::<usize>() + 64)
+            .sum::<usize>()
+            + 512,
+    );
+
+    result.push_str(concat!(
+        r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata""#,
+        r#" xmlns:amll="http://www.example.com/ns/amll""#,
+        r#" xmlns:itunes="http://music.apple.com/lyric-ttml-internal">"#,
+        r#"<head><metadata><ttm:agent type="person" xml:id="v1"/>"#,
+    ));
+    if lines.iter().any(|x| x.is_duet) {
+        result.push_str(r#"<ttm:agent type="other" xml:id="v2"/>"#);
+    }
+    for metadata in &lyric.metadata {
+        for value in &metadata.value {
+            write!(
+                result,
+                r#"<amll:meta key="{}" value="{}"/>"#,
+                escape(metadata.key.as_str()),
+                escape(value.as_str())
+            )
+            .unwrap();
+        }
+    }
+    result.push_str("</metadata></head>");
+
+    let duration = lines.last().map(|x| line_time_range(x).1).unwrap_or(0);
+    write!(result, r#"<body dur="{}">"#, timespan(duration)).unwrap();
+
+    let mut key = 0;
+    for param in lines.split(|x| x.words.is_empty()) {
+        if param.is_empty() {
+            continue;
+        }
+        let begin = line_time_range(&param[0]).0;
+        let end = line_time_range(&param[param.len() - 1]).1;
+        write!(
+            result,
+            r#"<div begin="{}" end="{}">"#,
+            timespan(begin),
+            timespan(end)
+        )
+        .unwrap();
+
+        let mut i = 0;
+        while i < param.len() {
+            let line = &param[i];
+            i += 1;
+            let (begin, end) = line_time_range(line);
+            key += 1;
+            write!(
+                result,
+                r#"<p begin="{}" end="{}" ttm:agent="{}" itunes:key="L{key}">"#,
+                timespan(begin),
+                timespan(end),
+                if line.is_duet { "v2" } else { "v1" },
+            )
+            .unwrap();
+            if line.words.len() > 1 {
+                write_words(&mut result, line, false);
+            } else if let Some(word) = line.words.first() {
+                result.push_str(&escape(word.word.as_ref()));
+            }
+
+            // 背景歌词紧跟在其所属的歌词行后面
+            while let Some(bg_line) = param.get(i).filter(|x| x.is_bg) {
+                i += 1;
+                let (begin, end) = line_time_range(bg_line);
+                write!(
+                    result,
+                    r#"<span ttm:role="x-bg" begin="{}" end="{}">"#,
+                    timespan(begin),
+                    timespan(end)
+                )
+                .unwrap();
+                if bg_line.words.len() > 1 {
+                    write_words(&mut result, bg_line, true);
+                } else if let Some(word) = bg_line.words.first() {
+                    write!(result, "({})", escape(word.word.as_ref())).unwrap();
+                }
+                write_extra_lyrics(&mut result, bg_line);
+                result.push_str("</span>");
+            }
+
+            write_extra_lyrics(&mut result, line);
+            result.push_str("</p>");
+        }
+
+        result.push_str("</div>");
+    }
+
+    result.push_str("</body></tt>");
+    result
+}
+
+#[test]
+fn ttml_test() {
+    let src = r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:amll="http://www.example.com/ns/amll"><head><metadata><ttm:agent type="person" xml:id="v1"/><ttm:agent type="other" xml:id="v2"/><amll:meta key="musicName" value="Test"/><amll:meta key="artists" value="A"/><amll:meta key="artists" value="B"/></metadata></head><body dur="00:05.000"><div begin="00:01.000" end="00:05.000"><p begin="00:01.000" end="00:03.000" ttm:agent="v1"><span begin="00:01.000" end="00:01.500">Hello</span> <span begin="00:01.500" end="00:03.000">World</span><span ttm:role="x-bg" begin="00:02.000" end="00:03.000"><span begin="00:02.000" end="00:02.500">(Oh</span> <span begin="00:02.500" end="00:03.000">yeah)</span></span><span ttm:role="x-translation" xml:lang="zh-CN">你好 &amp; 世界</span><span ttm:role="x-roman">ni hao</span></p><p begin="00:03.000" end="00:05.000" ttm:agent="v2">Line</p></div></body></tt>"#;
+    let lyric = parse_ttml(src).unwrap();
+    assert_eq!(
+        lyric.metadata,
+        vec![
+            TTMLMetadata {
+                key: "musicName".into(),
+                value: vec!["Test".into()]
+            },
+            TTMLMetadata {
+                key: "artists".into(),
+                value: vec!["A".into(), "B".into()]
+            }
+        ]
+    );
+    let lines = &lyric.lyric_lines;
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0].words,
+        vec![
+            LyricWord {
+                start_time: 1000,
+                end_time: 1500,
+                word: Cow::Borrowed("Hello ")
+            },
+            LyricWord {
+                start_time: 1500,
+                end_time: 3000,
+                word: Cow::Borrowed("World")
+            }
+        ]
+    );
+    assert_eq!(lines[0].translated_lyric, "你好 & 世界");
+    assert_eq!(lines[0].roman_lyric, "ni hao");
+    assert!(!lines[0].is_duet);
+    assert!(lines[1].is_bg);
+    assert_eq!(lines[1].words[0].word, "Oh ");
+    assert_eq!(lines[1].words[1].word, "yeah");
+    assert!(lines[2].is_duet);
+    assert_eq!(
+        lines[2].words,
+        vec![LyricWord {
+            start_time: 3000,
+            end_time: 5000,
+            word: Cow::Borrowed("Line")
+        }]
+    );
+
+    assert_eq!(parse_ttml(&stringify_ttml(&lyric)).unwrap(), lyric);
+}
+
+#[wasm_bindgen(js_name = "parseTTML", skip_typescript)]
+pub fn parse_ttml_js(src: &str) -> Result<JsValue, String> {
+    match parse_ttml(src) {
+        Ok(lyric) => Ok(serde_wasm_bindgen::to_value(&lyric).unwrap()),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+#[wasm_bindgen(js_name = "stringifyTTML", skip_typescript)]
+pub fn stringify_ttml_js(lyric: JsValue) -> String {
+    let lyric: TTMLLyric = serde_wasm_bindgen::from_value(lyric).unwrap();
+    stringify_ttml(&lyric)
+}
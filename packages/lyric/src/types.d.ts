@@ -1,6 +1,8 @@
 
 /**
  * 解析 LyRiC 格式的歌词字符串
+ *
+ * 支持带有 `<mm:ss.xx>` 单词时间戳的增强 LRC 格式，并会应用 `[offset:<毫秒>]` 标签的时间偏移
  * @param src 歌词字符串
  * @returns 成功解析出来的歌词
  */
@@ -13,6 +15,15 @@ export function parseLrc(src: string): LyricLine[];
  */
 export function stringifyLrc(lines: LyricLine[]): string;
 
+/**
+ * 将歌词数组转换为增强 LRC 格式的字符串
+ *
+ * 含有多个单词的歌词行会带上 `<mm:ss.xxx>` 单词时间戳
+ * @param lines 歌词数组
+ * @returns 增强 LRC 格式的字符串
+ */
+export function stringifyEnhancedLrc(lines: LyricLine[]): string;
+
 /**
  * 解析 YRC 格式的歌词字符串
  * @param src 歌词字符串
@@ -34,6 +45,15 @@ export function stringifyYrc(lines: LyricLine[]): string;
  */
 export function parseQrc(src: string): LyricLine[];
 
+/**
+ * 从 QRC 歌词文件的 XML 中取出歌词内容
+ *
+ * 如果传入的不是 XML 格式的 QRC 歌词文件，则会原样返回
+ * @param src 解密后的 QRC 歌词文件内容
+ * @returns 可以通过 `parseQrc` 解析的歌词字符串
+ */
+export function extractQrcContent(src: string): string;
+
 /**
  * 将歌词数组转换为 QRC 格式的字符串
  * @param lines 歌词数组
@@ -69,6 +89,23 @@ export function parseEslrc(src: string): LyricLine[];
  */
 export function stringifyEslrc(lines: LyricLine[]): string;
 
+/**
+ * 解析 AMLL 使用的 TTML 格式的歌词字符串
+ *
+ * 支持对唱、背景歌词、翻译和音译，单词之间的空白会合并到前一个单词的末尾
+ * @param src 歌词字符串
+ * @returns 解析出来的元数据和歌词
+ * @throws 如果 XML 格式错误则会抛出错误
+ */
+export function parseTTML(src: string): TTMLLyric;
+
+/**
+ * 将歌词转换为 AMLL 使用的 TTML 格式的字符串
+ * @param lyric 元数据和歌词数组，没有单词的歌词行会作为段落的分隔
+ * @returns TTML 格式的字符串
+ */
+export function stringifyTTML(lyric: TTMLLyric): string;
+
 /**
  * 将歌词数组转换为 ASS 字幕格式的字符串
  * 
@@ -104,6 +141,10 @@ export interface LyricLine {
      * 如果是 LyRiC 等只能表达一行歌词的格式，这里就只会有一个单词
      */
     words: LyricWord[];
+    /** 该行的翻译歌词 */
+    translatedLyric?: string;
+    /** 该行的音译歌词 */
+    romanLyric?: string;
     /**
      * 该行是否为背景歌词行
      * 此选项只有作为 Lyricify Syllable 文件格式导入导出时才有意义
@@ -116,6 +157,22 @@ export interface LyricLine {
     isDuet?: boolean;
 }
 
+/**
+ * TTML 歌词中的一项元数据，同一个键可以有多个值
+ */
+export interface TTMLMetadata {
+    key: string;
+    value: string[];
+}
+
+/**
+ * TTML 歌词文件的内容
+ */
+export interface TTMLLyric {
+    metadata: TTMLMetadata[];
+    lyricLines: LyricLine[];
+}
+
 /**
  * 解密十六进制字符串格式的 Qrc 歌词数据
  * 解密后可去头尾 XML 数据后通过调用 `parseQrc` 解析歌词行
@@ -125,11 +125,15 @@ pub fn stringify_yrc(lines: &[LyricLine]) -> String {
     for line in lines {
         if !line.words.is_empty() {
             let start_time = line.words[0].start_time;
-            let duration: usize = line.words.iter().map(|x| x.end_time - x.start_time).sum();
+            let duration: usize = line
+                .words
+                .iter()
+                .map(|x| x.end_time.saturating_sub(x.start_time))
+                .sum();
             write!(result, "[{start_time},{duration}]").unwrap();
             for word in line.words.iter() {
                 let start_time = word.start_time;
-                let duration = word.end_time - word.start_time;
+                let duration = word.end_time.saturating_sub(word.start_time);
                 write!(result, "({start_time},{duration},0)").unwrap();
                 for c in word.word.chars() {
                     // 目前已知 YRC 不允许直接出现英文括号，所以要换成中文括号
@@ -159,3 +163,13 @@ pub fn stringify_yrc_js(lrc: JsValue) -> String {
     let lines: Vec<LyricLine> = serde_wasm_bindgen::from_value(lrc).unwrap();
     stringify_yrc(&lines)
 }
+
+#[test]
+fn stringify_unfinished_line_test() {
+    // 从 LyRiC 转换而来的最后一行没有结束时间，导出时不应该溢出
+    let lines = crate::lrc::parse_lrc("[00:01.00]a\n[00:02.00]b");
+    assert_eq!(
+        stringify_yrc(&lines),
+        "[1000,1000](1000,1000,0)a\n[2000,0](2000,0,0)b\n"
+    );
+}
@@ -0,0 +1,183 @@
+//! 命令行控制
+//!
+//! 播放器运行时可以通过命令行控制播放，方便窗口管理器、脚本和快捷键守护程序在没有 MPRIS 的环境下使用：
+//!
+//! - `amll-player play` / `amll-player pause`：继续或者暂停播放
+//! - `amll-player next` / `amll-player prev`：下一首或者上一首
+//! - `amll-player seek <秒>`：跳转到指定的播放进度
+//! - `amll-player add <文件>...`：把文件添加到播放列表末尾
+//!
+//! 命令会通过应用缓存文件夹中的 `control.sock` 本地套接字发送给正在运行的实例，
+//! 每个连接发送一行 JSON 格式的指令，实例处理后回复一行 `ok` 或者错误信息。
+//! 套接字和本地 WebSocket 服务器一样通过 [`crate::local_socket`] 创建，只有当前用户可以访问。
+//!
+//! Windows 上的命名管道还没有实现，所以 Windows 上不支持命令行控制，
+//! 执行控制命令时会直接报错退出，可以改用 HTTP 控制接口或者 WebSocket 服务器控制播放。
+use std::{path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tauri::AppHandle;
+use ws_protocol::Body;
+
+const SOCKET_NAME: &str = "control.sock";
+/// 等待对方发送指令或者回复的最长时间
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "action")]
+pub enum CliCommand {
+    Play,
+    Pause,
+    Next,
+    Prev,
+    /// 跳转到指定的播放进度，单位为毫秒
+    Seek {
+        position: f64,
+    },
+    Add {
+        paths: Vec<PathBuf>,
+    },
+}
+
+impl CliCommand {
+    /// 解析命令行参数，第一个参数不是控制命令时返回 `None`，此时正常启动播放器
+    fn parse(args: &[String]) -> Option<anyhow::Result<Self>> {
+        let (verb, rest) = args.split_first()?;
+        let command = match verb.as_str() {
+            "play" => Ok(Self::Play),
+            "pause" => Ok(Self::Pause),
+            "next" => Ok(Self::Next),
+            "prev" => Ok(Self::Prev),
+            "seek" => rest
+                .first()
+                .and_then(|x| x.parse::<f64>().ok())
+                .filter(|x| x.is_finite() && *x >= 0.0)
+                .map(|x| Self::Seek {
+                    position: x * 1000.0,
+                })
+                .ok_or_else(|| anyhow::anyhow!("用法: seek <秒>")),
+            "add" if rest.is_empty() => Err(anyhow::anyhow!("用法: add <文件>...")),
+            // 正在运行的实例的工作目录可能不同，需要传入绝对路径
+            "add" => std::env::current_dir()
+                .map(|dir| Self::Add {
+                    paths: rest.iter().map(|x| dir.join(x)).collect(),
+                })
+                .map_err(anyhow::Error::from),
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    fn execute(self, app: &AppHandle) {
+        let body = match self {
+            Self::Play => Body::Resume,
+            Self::Pause => Body::Pause,
+            Self::Next => Body::ForwardSong,
+            Self::Prev => Body::BackwardSong,
+            Self::Seek { position } => Body::SetPlayProgress { progress: position },
+            Self::Add { paths } => {
+                crate::open_files::add(app.clone(), paths);
+                return;
+            }
+        };
+        crate::send_control(app, body);
+    }
+}
+
+/// 控制套接字的路径，命令行需要在 Tauri 初始化之前获取，所以直接使用应用标识符拼接缓存文件夹
+fn socket_path() -> Option<PathBuf> {
+    tauri::api::path::cache_dir().map(|x| x.join(crate::deep_link::IDENTIFIER).join(SOCKET_NAME))
+}
+
+/// 启动参数是控制命令时发送给正在运行的实例，返回进程的退出代码，不是控制命令时返回 `None`
+pub fn run() -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match CliCommand::parse(&args)? {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{err}");
+            return Some(2);
+        }
+    };
+    if cfg!(not(unix)) {
+        eprintln!("Windows 暂不支持命令行控制，请使用 HTTP 控制接口或者 WebSocket 服务器");
+        return Some(1);
+    }
+    match send(&command) {
+        Ok(()) => Some(0),
+        Err(err) => {
+            eprintln!("命令发送失败，播放器可能没有在运行: {err:?}");
+            Some(1)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send(command: &CliCommand) -> anyhow::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    let path = socket_path().ok_or_else(|| anyhow::anyhow!("无法获取缓存文件夹"))?;
+    let mut stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.write_all(format!("{}\n", serde_json::to_string(command)?).as_bytes())?;
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        err => anyhow::bail!("{err}"),
+    }
+}
+
+#[cfg(not(unix))]
+fn send(_command: &CliCommand) -> anyhow::Result<()> {
+    anyhow::bail!("Windows 暂不支持命令行控制，请使用 HTTP 控制接口或者 WebSocket 服务器")
+}
+
+/// 开始在控制套接字上接收命令行发送的指令
+#[cfg(unix)]
+pub fn listen(app: AppHandle) {
+    let Some(path) = socket_path() else {
+        return;
+    };
+    // 同时只会有一个实例在运行，上次运行时留下的套接字文件会被直接替换
+    let listener = match crate::local_socket::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("命令行控制套接字 {} 开启失败: {err:?}", path.display());
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(anyhow::Error::from)
+                .and_then(|stream| handle_conn(&app, stream));
+            if let Err(err) = result {
+                println!("命令行控制指令处理失败: {err:?}");
+            }
+        }
+    });
+}
+
+/// Windows 上的命名管道还没有实现，不会接收命令行发送的指令
+#[cfg(not(unix))]
+pub fn listen(_app: AppHandle) {
+    println!("Windows 暂不支持命令行控制");
+}
+
+#[cfg(unix)]
+fn handle_conn(app: &AppHandle, stream: std::os::unix::net::UnixStream) -> anyhow::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let reply = match serde_json::from_str::<CliCommand>(&line) {
+        Ok(command) => {
+            println!("收到命令行控制指令: {command:?}");
+            command.execute(app);
+            "ok".to_string()
+        }
+        Err(err) => format!("无法识别的指令: {err}"),
+    };
+    (&stream).write_all(format!("{reply}\n").as_bytes())?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! 配置文件的读写
+//!
+//! 各个模块的设置和数据都以 JSON 文件的形式保存在应用数据文件夹中，
+//! 读取失败时使用默认值，保存失败时只输出日志，不会影响正常使用。
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// 用于区分同时写入的多个临时文件
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// 读取 JSON 文件，文件不存在或者解析失败时返回空值，`name` 为输出日志时使用的名称
+pub fn load_json<T: DeserializeOwned>(path: Option<&Path>, name: &str) -> Option<T> {
+    let data = std::fs::read(path?).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            println!("{name}解析失败: {err:?}");
+            None
+        }
+    }
+}
+
+/// 将数据保存为 JSON 文件，路径为空时不保存，`name` 为输出日志时使用的名称
+pub fn save_json<T: Serialize + ?Sized>(path: Option<&Path>, value: &T, name: &str) {
+    let Some(path) = path else {
+        return;
+    };
+    if let Err(err) = write_json(path, value) {
+        println!("{name}保存失败: {err:?}");
+    }
+}
+
+/// 先写入临时文件再重命名，避免程序在写入时退出导致文件损坏
+fn write_json<T: Serialize + ?Sized>(path: &Path, value: &T) -> anyhow::Result<()> {
+    let data = serde_json::to_vec(value)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(
+        ".{}.tmp",
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&temp, data)?;
+    if let Err(err) = std::fs::rename(&temp, path) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err.into());
+    }
+    Ok(())
+}
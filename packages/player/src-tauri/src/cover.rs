@@ -0,0 +1,268 @@
+//! 封面图片缓存模块
+//!
+//! 封面图片会以其 SHA-256 哈希值为文件名保存到缓存文件夹中，
+//! 并通过自定义协议 `amll-cover` 提供给前端，避免将大体积的图片数据以 Base64 的形式通过 IPC 传输。
+//! 封面图片还可以获取小、中、大三种尺寸的缩略图，缩略图在第一次被请求时才会生成并缓存，
+//! 避免扫描音乐库时为每张新封面解码和缩放图片。
+//! 缩略图的 URL 为原图 URL 后加上 `.<尺寸>`，例如 `amll-cover://localhost/<哈希值>.small`。
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use image::{DynamicImage, ImageOutputFormat};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tauri::{
+    http::{Request, Response, ResponseBuilder},
+    AppHandle, Manager,
+};
+
+use crate::error::{CommandError, CommandResult, ErrorCode};
+
+/// 自定义协议的名称
+pub const COVER_PROTOCOL: &str = "amll-cover";
+
+/// 用于生成唯一的临时文件名，避免多个线程同时写入同一张图片时互相干扰
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub fn cover_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// 获取封面图片在前端可以使用的 URL，Windows 下的 WebView2 需要使用 `https://<协议>.localhost` 的形式
+pub fn cover_url(hash: &str) -> String {
+    if cfg!(windows) {
+        format!("https://{COVER_PROTOCOL}.localhost/{hash}")
+    } else {
+        format!("{COVER_PROTOCOL}://localhost/{hash}")
+    }
+}
+
+/// 缩略图的 JPEG 编码质量
+const THUMBNAIL_QUALITY: u8 = 85;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CoverSize {
+    Small,
+    Medium,
+    Large,
+    /// 原始尺寸
+    Original,
+}
+
+impl CoverSize {
+    fn from_suffix(suffix: &str) -> Option<Self> {
+        match suffix {
+            "small" => Some(Self::Small),
+            "medium" => Some(Self::Medium),
+            "large" => Some(Self::Large),
+            _ => None,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Self::Small => "small",
+            Self::Medium => "medium",
+            Self::Large => "large",
+            Self::Original => "",
+        }
+    }
+
+    /// 缩略图的最大边长，单位为像素
+    fn max_side(self) -> Option<u32> {
+        match self {
+            Self::Small => Some(64),
+            Self::Medium => Some(256),
+            Self::Large => Some(512),
+            Self::Original => None,
+        }
+    }
+}
+
+/// 根据图片数据的头部判断图片格式
+fn sniff_mime(data: &[u8]) -> &'static str {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        "image/png"
+    } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        "image/jpeg"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else if data.starts_with(b"BM") {
+        "image/bmp"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+pub struct CoverCache {
+    dir: PathBuf,
+}
+
+impl CoverCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// 哈希值必须是 64 位的十六进制字符串，防止通过协议访问缓存文件夹以外的文件
+    fn path_of(&self, hash: &str) -> Option<PathBuf> {
+        if hash.len() == 64 && hash.bytes().all(|x| x.is_ascii_hexdigit()) {
+            Some(self.dir.join(hash))
+        } else {
+            None
+        }
+    }
+
+    fn thumbnail_path_of(&self, hash: &str, size: CoverSize) -> Option<PathBuf> {
+        match size {
+            CoverSize::Original => self.path_of(hash),
+            size => self
+                .path_of(hash)
+                .map(|x| x.with_extension(format!("{}.jpg", size.suffix()))),
+        }
+    }
+
+    /// 先写入临时文件再重命名，避免读取到写入了一半的文件
+    fn write_atomic(&self, path: &Path, data: &[u8]) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let temp = self.dir.join(format!(
+            "{}.tmp",
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp, data)?;
+        if let Err(err) = std::fs::rename(&temp, path) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    /// 将图片保存到缓存中并返回其哈希值，已经缓存过的图片不会重复写入
+    pub fn store(&self, data: &[u8]) -> anyhow::Result<String> {
+        let hash = cover_hash(data);
+        let path = self.dir.join(&hash);
+        if !path.is_file() {
+            self.write_atomic(&path, data)?;
+        }
+        Ok(hash)
+    }
+
+    fn write_thumbnail(
+        &self,
+        hash: &str,
+        image: &DynamicImage,
+        size: CoverSize,
+    ) -> anyhow::Result<PathBuf> {
+        let (Some(path), Some(max_side)) = (self.thumbnail_path_of(hash, size), size.max_side())
+        else {
+            anyhow::bail!("无效的缩略图参数");
+        };
+        // 原图比缩略图还小时不放大，只重新编码
+        let thumbnail = if image.width() > max_side || image.height() > max_side {
+            image.thumbnail(max_side, max_side)
+        } else {
+            image.clone()
+        };
+        let mut data = Vec::new();
+        DynamicImage::ImageRgb8(thumbnail.to_rgb8()).write_to(
+            &mut Cursor::new(&mut data),
+            ImageOutputFormat::Jpeg(THUMBNAIL_QUALITY),
+        )?;
+        self.write_atomic(&path, &data)?;
+        Ok(path)
+    }
+
+    /// 获取指定尺寸的封面图片，缩略图还没有生成时会从原图生成
+    pub fn load_sized(&self, hash: &str, size: CoverSize) -> Option<Vec<u8>> {
+        let path = self.thumbnail_path_of(hash, size)?;
+        if let Ok(data) = std::fs::read(&path) {
+            return Some(data);
+        }
+        if size == CoverSize::Original {
+            return None;
+        }
+        let image = image::load_from_memory(&self.load(hash)?).ok()?;
+        match self.write_thumbnail(hash, &image, size) {
+            Ok(path) => std::fs::read(path).ok(),
+            Err(err) => {
+                println!("封面缩略图生成失败: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// 将图片保存到缓存中并返回其 URL，保存失败时返回空字符串
+    pub fn store_url(&self, data: &[u8]) -> String {
+        match self.store(data) {
+            Ok(hash) => cover_url(&hash),
+            Err(err) => {
+                println!("封面图片缓存失败: {err:?}");
+                String::new()
+            }
+        }
+    }
+
+    /// 获取已缓存的封面图片文件的路径，供需要以文件形式读取封面的系统接口使用
+    pub fn file_path(&self, hash: &str) -> Option<PathBuf> {
+        self.path_of(hash).filter(|x| x.is_file())
+    }
+
+    pub fn load(&self, hash: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.path_of(hash)?).ok()
+    }
+}
+
+/// 获取指定尺寸的封面图片的 URL，缩略图会在需要时生成
+#[tauri::command]
+pub async fn get_cover_thumbnail(
+    app: AppHandle,
+    hash: String,
+    size: CoverSize,
+) -> CommandResult<String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        match app.state::<CoverCache>().load_sized(&hash, size) {
+            Some(_) if size == CoverSize::Original => Ok(cover_url(&hash)),
+            Some(_) => Ok(format!("{}.{}", cover_url(&hash), size.suffix())),
+            None => Err(CommandError::new(
+                ErrorCode::FileNotFound,
+                format!("封面图片 {hash} 不存在"),
+            )),
+        }
+    })
+    .await?
+}
+
+/// 处理 `amll-cover` 协议的请求，URL 的最后一段为封面图片的哈希值，后面可以加上缩略图的尺寸
+pub fn handle_cover_protocol(
+    app: &AppHandle,
+    request: &Request,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let name = request
+        .uri()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let (hash, size) = match name.split_once('.') {
+        Some((hash, suffix)) => (hash, CoverSize::from_suffix(suffix)),
+        None => (name, Some(CoverSize::Original)),
+    };
+    let data = size.and_then(|size| app.state::<CoverCache>().load_sized(hash, size));
+    match data {
+        Some(data) => ResponseBuilder::new()
+            .status(200)
+            .mimetype(sniff_mime(&data))
+            .header("Cache-Control", "max-age=31536000, immutable")
+            .header("Access-Control-Allow-Origin", "*")
+            .body(data),
+        None => ResponseBuilder::new().status(404).body(Vec::new()),
+    }
+}
@@ -0,0 +1,106 @@
+//! 专辑封面图片数据的分块传输
+//!
+//! 较大的封面图片会被拆分为 [`Body::SetMusicAlbumCoverImageDataBegin`] 和若干个
+//! [`Body::SetMusicAlbumCoverImageDataChunk`] 发送，避免一条巨大的信息长时间占用连接。
+//! 接收时由 [`CoverAssembler`] 重新组装，校验哈希后还原为 [`Body::SetMusicAlbumCoverImageData`]，
+//! 因此其它模块和前端无需关心封面是否经过了分块传输。
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use sha2::{Digest, Sha256};
+use ws_protocol::Body;
+
+/// 每个分块的大小，超过此大小的封面图片会被分块发送
+pub const CHUNK_SIZE: usize = 256 * 1024;
+/// 接收分块传输的封面图片时允许的最大大小
+const MAX_COVER_SIZE: u32 = 64 * 1024 * 1024;
+
+/// 用于为每次分块传输分配唯一的 ID
+static COVER_ID: AtomicU32 = AtomicU32::new(1);
+
+fn hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// 将封面图片数据拆分为分块传输的信息
+pub fn split_cover(data: &[u8]) -> Vec<Body> {
+    let id = COVER_ID.fetch_add(1, Ordering::Relaxed);
+    let mut result = Vec::with_capacity(data.len() / CHUNK_SIZE + 2);
+    result.push(Body::SetMusicAlbumCoverImageDataBegin {
+        id,
+        total_size: data.len() as u32,
+        hash: hash(data).as_str().into(),
+    });
+    for (i, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+        result.push(Body::SetMusicAlbumCoverImageDataChunk {
+            id,
+            offset: (i * CHUNK_SIZE) as u32,
+            data: chunk.to_vec(),
+        });
+    }
+    result
+}
+
+struct PendingCover {
+    id: u32,
+    hash: String,
+    data: Vec<u8>,
+    received: usize,
+}
+
+/// 组装分块传输的封面图片，每个连接同一时间只会组装一张封面，
+/// 新的分块传输开始时会丢弃尚未完成的封面
+#[derive(Default)]
+pub struct CoverAssembler {
+    pending: Option<PendingCover>,
+}
+
+impl CoverAssembler {
+    /// 处理接收到的信息，分块传输的信息会被暂存，组装完成时返回还原后的封面信息，
+    /// 其它信息会被原样返回
+    pub fn on_body(&mut self, body: Body) -> anyhow::Result<Option<Body>> {
+        match body {
+            Body::SetMusicAlbumCoverImageDataBegin {
+                id,
+                total_size,
+                hash,
+            } => {
+                if total_size > MAX_COVER_SIZE {
+                    self.pending = None;
+                    anyhow::bail!("封面图片 {id} 过大: {total_size} 字节");
+                }
+                self.pending = Some(PendingCover {
+                    id,
+                    hash: hash.to_string().to_ascii_lowercase(),
+                    data: vec![0; total_size as usize],
+                    received: 0,
+                });
+                Ok(None)
+            }
+            Body::SetMusicAlbumCoverImageDataChunk { id, offset, data } => {
+                let Some(pending) = self.pending.as_mut().filter(|x| x.id == id) else {
+                    anyhow::bail!("收到了未开始传输的封面图片 {id} 的分块");
+                };
+                let start = offset as usize;
+                let Some(dest) = pending.data.get_mut(start..start + data.len()) else {
+                    self.pending = None;
+                    anyhow::bail!("封面图片 {id} 的分块超出范围");
+                };
+                dest.copy_from_slice(&data);
+                pending.received += data.len();
+                if pending.received < pending.data.len() {
+                    return Ok(None);
+                }
+                let Some(pending) = self.pending.take() else {
+                    return Ok(None);
+                };
+                if hash(&pending.data) != pending.hash {
+                    anyhow::bail!("封面图片 {id} 的哈希校验失败");
+                }
+                Ok(Some(Body::SetMusicAlbumCoverImageData {
+                    data: pending.data,
+                }))
+            }
+            body => Ok(Some(body)),
+        }
+    }
+}
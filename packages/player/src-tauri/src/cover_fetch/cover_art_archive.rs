@@ -0,0 +1,45 @@
+//! Cover Art Archive 封面来源
+//!
+//! 先通过 MusicBrainz 搜索专辑对应的发行版本，再获取其在 Cover Art Archive 上的正面封面。
+use async_trait::async_trait;
+use tauri::{AppHandle, Manager};
+
+use super::CoverProvider;
+use crate::musicbrainz::MusicBrainzClient;
+
+/// 最多尝试的发行版本数量
+const MAX_RELEASES: usize = 3;
+/// 低于该匹配程度的发行版本会被忽略
+const MIN_SCORE: u32 = 80;
+
+pub struct CoverArtArchiveProvider;
+
+#[async_trait]
+impl CoverProvider for CoverArtArchiveProvider {
+    fn name(&self) -> &'static str {
+        "coverArtArchive"
+    }
+
+    async fn search(
+        &self,
+        app: &AppHandle,
+        artist: &str,
+        album: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let releases = app
+            .state::<MusicBrainzClient>()
+            .search_releases(album, artist, 0)
+            .await?;
+        Ok(releases
+            .into_iter()
+            .filter(|x| x.score >= MIN_SCORE)
+            .take(MAX_RELEASES)
+            .map(|x| {
+                format!(
+                    "https://coverartarchive.org/release/{}/front-500",
+                    x.release_id
+                )
+            })
+            .collect())
+    }
+}
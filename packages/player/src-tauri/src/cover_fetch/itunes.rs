@@ -0,0 +1,54 @@
+//! iTunes 封面来源
+//!
+//! 使用 iTunes Search API 搜索专辑，并将返回的缩略图地址替换成大尺寸的封面地址。
+use async_trait::async_trait;
+use tauri::AppHandle;
+
+use super::CoverProvider;
+
+const ITUNES_SEARCH_URL: &str = "https://itunes.apple.com/search";
+
+pub struct ITunesProvider;
+
+#[async_trait]
+impl CoverProvider for ITunesProvider {
+    fn name(&self) -> &'static str {
+        "itunes"
+    }
+
+    async fn search(
+        &self,
+        _app: &AppHandle,
+        artist: &str,
+        album: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let term = format!("{artist} {album}");
+        let result = crate::http::get_json(
+            ITUNES_SEARCH_URL,
+            &[
+                ("term", term.trim()),
+                ("entity", "album"),
+                ("media", "music"),
+                ("limit", "5"),
+            ],
+        )
+        .await?;
+        let album = album.trim().to_lowercase();
+        let mut results: Vec<(bool, String)> = result["results"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                let url = x["artworkUrl100"].as_str()?;
+                let same_name = x["collectionName"]
+                    .as_str()
+                    .map(|x| x.trim().to_lowercase() == album)
+                    .unwrap_or(false);
+                Some((same_name, url.replace("100x100bb", "1000x1000bb")))
+            })
+            .collect();
+        // 专辑名称完全相同的结果排在前面
+        results.sort_by_key(|x| !x.0);
+        Ok(results.into_iter().map(|x| x.1).collect())
+    }
+}
@@ -0,0 +1,156 @@
+//! 在线封面获取模块
+//!
+//! 为没有内嵌封面的本地音乐从网络上获取专辑封面。每个封面来源都实现了 [`CoverProvider`]，
+//! 会按照顺序依次尝试，直到成功下载到一张图片为止。
+//! 下载的图片会保存到封面缓存中，艺术家和专辑对应的封面哈希值也会记录到磁盘上，
+//! 之后获取同一张专辑的封面时无需再次请求网络。
+use std::path::PathBuf;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    cover::{cover_url, CoverCache},
+    error::{CommandError, CommandResult, ErrorCode},
+};
+
+mod cover_art_archive;
+mod itunes;
+mod netease;
+
+#[async_trait]
+pub trait CoverProvider: Send + Sync {
+    /// 封面来源的名称，用于在命令中指定使用的来源
+    fn name(&self) -> &'static str;
+
+    /// 搜索专辑封面，返回按照匹配程度排序的候选图片 URL
+    async fn search(
+        &self,
+        app: &AppHandle,
+        artist: &str,
+        album: &str,
+    ) -> anyhow::Result<Vec<String>>;
+}
+
+/// 所有可用的封面来源，未指定来源时按照该顺序尝试
+pub fn providers() -> Vec<Box<dyn CoverProvider>> {
+    vec![
+        Box::new(cover_art_archive::CoverArtArchiveProvider),
+        Box::new(itunes::ITunesProvider),
+        Box::new(netease::NeteaseProvider),
+    ]
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchedCover {
+    pub hash: String,
+    /// 封面图片的 `amll-cover` 协议 URL
+    pub url: String,
+    /// 提供该封面的来源，从磁盘缓存中读取时为空
+    pub provider: Option<String>,
+}
+
+/// 记录艺术家和专辑对应的封面哈希值
+pub struct CoverFetchCache {
+    dir: PathBuf,
+}
+
+impl CoverFetchCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path_of(&self, artist: &str, album: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(artist.trim().to_lowercase());
+        hasher.update([0]);
+        hasher.update(album.trim().to_lowercase());
+        self.dir.join(format!("{:x}", hasher.finalize()))
+    }
+
+    fn get(&self, artist: &str, album: &str) -> Option<String> {
+        std::fs::read_to_string(self.path_of(artist, album))
+            .ok()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+    }
+
+    fn set(&self, artist: &str, album: &str, hash: &str) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path_of(artist, album), hash)?;
+        Ok(())
+    }
+}
+
+pub async fn fetch_cover_from_providers(
+    app: &AppHandle,
+    artist: &str,
+    album: &str,
+    provider_names: Option<&[String]>,
+) -> anyhow::Result<Option<FetchedCover>> {
+    let fetch_cache = app.state::<CoverFetchCache>();
+    let covers = app.state::<CoverCache>();
+    if let Some(hash) = fetch_cache.get(artist, album) {
+        if covers.load(&hash).is_some() {
+            return Ok(Some(FetchedCover {
+                url: cover_url(&hash),
+                hash,
+                provider: None,
+            }));
+        }
+    }
+
+    let mut providers = providers();
+    if let Some(names) = provider_names {
+        providers.retain(|x| names.iter().any(|name| name == x.name()));
+        providers.sort_by_key(|x| names.iter().position(|name| name == x.name()));
+    }
+
+    for provider in providers {
+        let urls = match provider.search(app, artist, album).await {
+            Ok(urls) => urls,
+            Err(err) => {
+                println!("从 {} 搜索封面失败: {err:?}", provider.name());
+                continue;
+            }
+        };
+        for url in urls {
+            let data = match crate::http::get_bytes(&url).await {
+                Ok(data) if !data.is_empty() => data,
+                Ok(_) => continue,
+                Err(err) => {
+                    println!("从 {} 下载封面 {url} 失败: {err:?}", provider.name());
+                    continue;
+                }
+            };
+            let hash = covers.store(&data)?;
+            if let Err(err) = fetch_cache.set(artist, album, &hash) {
+                println!("封面获取结果缓存失败: {err:?}");
+            }
+            return Ok(Some(FetchedCover {
+                url: cover_url(&hash),
+                hash,
+                provider: Some(provider.name().to_string()),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// 从网络上获取专辑封面，`providers` 可以指定使用的来源及其顺序，
+/// 可选的来源有 `coverArtArchive`、`itunes` 和 `netease`
+#[tauri::command]
+pub async fn fetch_cover(
+    app: AppHandle,
+    artist: String,
+    album: String,
+    providers: Option<Vec<String>>,
+) -> CommandResult<Option<FetchedCover>> {
+    if album.trim().is_empty() {
+        return Err(CommandError::new(ErrorCode::InvalidInput, "专辑名称为空"));
+    }
+    Ok(fetch_cover_from_providers(&app, &artist, &album, providers.as_deref()).await?)
+}
@@ -0,0 +1,48 @@
+//! 网易云音乐封面来源
+//!
+//! 使用网易云音乐的专辑搜索接口获取专辑封面。
+use async_trait::async_trait;
+use tauri::AppHandle;
+
+use super::CoverProvider;
+
+const NETEASE_SEARCH_URL: &str = "https://music.163.com/api/search/get";
+/// 网易云音乐搜索接口中专辑的类型编号
+const SEARCH_TYPE_ALBUM: &str = "10";
+
+pub struct NeteaseProvider;
+
+#[async_trait]
+impl CoverProvider for NeteaseProvider {
+    fn name(&self) -> &'static str {
+        "netease"
+    }
+
+    async fn search(
+        &self,
+        _app: &AppHandle,
+        artist: &str,
+        album: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let keywords = format!("{album} {artist}");
+        let result = crate::http::get_json(
+            NETEASE_SEARCH_URL,
+            &[
+                ("s", keywords.trim()),
+                ("type", SEARCH_TYPE_ALBUM),
+                ("limit", "5"),
+            ],
+        )
+        .await?;
+        if result["code"].as_i64() != Some(200) {
+            anyhow::bail!("网易云音乐搜索失败: {}", result["code"]);
+        }
+        Ok(result["result"]["albums"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x["picUrl"].as_str())
+            .map(|x| format!("{x}?param=1000y1000"))
+            .collect())
+    }
+}
@@ -0,0 +1,106 @@
+//! `amll://` 深层链接
+//!
+//! 注册 `amll://` 协议，让浏览器扩展和其它应用可以把歌曲或者 WebSocket 服务器交给播放器：
+//!
+//! - `amll://play?path=<文件路径>`：播放本地的音乐文件或者播放列表
+//! - `amll://lyrics?id=<歌词 ID>`：在前端打开对应的歌词
+//! - `amll://connect?ws=<服务器地址>`：连接到远程的 AMLL WebSocket 服务器
+//!
+//! 所有链接都会通过 `on-deep-link` 事件通知前端。任何网页都可以打开链接，
+//! 所以连接服务器的链接不会直接执行，需要前端询问用户并确认后再调用 `ws_connect_to` 连接。
+//!
+//! 已经有实例在运行时，新启动的进程会把第一个启动参数转发给正在运行的实例后退出，
+//! 所以通过打开方式打开的文件也会从这里传入，不是链接而是已存在的文件时会直接打开。
+use std::{path::PathBuf, str::FromStr};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+pub const SCHEME: &str = "amll";
+/// 传给 [`tauri_plugin_deep_link::prepare`] 的标识符，需要和打包配置中的一致
+pub const IDENTIFIER: &str = "net.stevexmh.amllplayer";
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "action")]
+pub enum DeepLink {
+    Play { path: PathBuf },
+    Lyrics { id: String },
+    Connect { ws: String },
+}
+
+impl FromStr for DeepLink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = url::Url::parse(s.trim())?;
+        if url.scheme() != SCHEME {
+            anyhow::bail!("不是 {SCHEME}:// 链接: {s}");
+        }
+        // 同时兼容 amll://play?... 和 amll:play?... 两种写法
+        let action = url
+            .host_str()
+            .filter(|x| !x.is_empty())
+            .unwrap_or(url.path())
+            .trim_matches('/')
+            .to_lowercase();
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("链接缺少参数 {name}: {s}"))
+        };
+        match action.as_str() {
+            "play" => Ok(Self::Play {
+                path: param("path")?.into(),
+            }),
+            "lyrics" => Ok(Self::Lyrics { id: param("id")? }),
+            "connect" => Ok(Self::Connect { ws: param("ws")? }),
+            other => anyhow::bail!("不支持的链接操作: {other}"),
+        }
+    }
+}
+
+/// 处理收到的链接，播放会直接在后端执行，打开歌词和确认连接由前端负责
+pub fn handle(app: &AppHandle, request: &str) {
+    if !request.trim().starts_with(&format!("{SCHEME}:")) {
+        let path = PathBuf::from(request.trim());
+        if path.is_file() {
+            crate::open_files::open(app.clone(), vec![path]);
+        } else {
+            println!("收到的启动参数既不是链接也不是文件: {request}");
+        }
+        return;
+    }
+    let link = match request.parse::<DeepLink>() {
+        Ok(link) => link,
+        Err(err) => {
+            println!("深层链接解析失败: {err:?}");
+            return;
+        }
+    };
+    println!("收到深层链接: {link:?}");
+    match &link {
+        DeepLink::Play { path } => crate::open_files::open(app.clone(), vec![path.clone()]),
+        DeepLink::Lyrics { .. } | DeepLink::Connect { .. } => {}
+    }
+    let _ = app.emit_all("on-deep-link", link);
+}
+
+/// 注册协议处理，并处理启动参数中传入的链接
+pub fn register(app: &AppHandle) {
+    let app_handle = app.clone();
+    if let Err(err) =
+        tauri_plugin_deep_link::register(SCHEME, move |request| handle(&app_handle, &request))
+    {
+        println!("{SCHEME}:// 协议注册失败: {err:?}");
+    }
+    // 程序没有在运行时，链接会作为启动参数传入
+    let prefix = format!("{SCHEME}:");
+    for arg in std::env::args_os().skip(1) {
+        let arg = arg.to_string_lossy();
+        if arg.starts_with(&prefix) {
+            handle(app, &arg);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Discord Rich Presence 集成
+//!
+//! 通过 Discord 客户端的本地 IPC 发布当前播放的歌曲、专辑封面和播放进度，
+//! 在 Discord 的个人资料中显示为“正在收听”。
+//! 开启隐私模式时只显示正在听歌，不会公开歌曲名称、歌手和专辑封面。
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use discord_rich_presence::{
+    activity::{Activity, ActivityType, Assets, Timestamps},
+    DiscordIpc, DiscordIpcClient,
+};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::{
+    history::now_millis,
+    now_playing::{NowPlaying, NowPlayingStatus},
+};
+
+/// 编译时可以通过 `AMLL_DISCORD_CLIENT_ID` 环境变量指定默认的 Discord 应用 ID
+const DEFAULT_CLIENT_ID: Option<&str> = option_env!("AMLL_DISCORD_CLIENT_ID");
+/// 没有可以公开访问的专辑封面时使用的 Discord 应用图片资源名称
+const FALLBACK_IMAGE: &str = "amll";
+/// 切歌时会连续收到多条信息，等待此时间后再合并更新，同时避免触发 Discord 的频率限制
+const UPDATE_DELAY: Duration = Duration::from_secs(1);
+/// 两次尝试连接 Discord 之间的最小间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscordConfig {
+    pub enabled: bool,
+    /// 隐私模式，开启后不会公开歌曲名称、歌手和专辑封面
+    pub privacy: bool,
+    /// Discord 应用 ID，为空时使用编译时指定的默认值
+    pub client_id: Option<String>,
+}
+
+impl DiscordConfig {
+    fn client_id(&self) -> Option<String> {
+        self.client_id
+            .clone()
+            .filter(|x| !x.trim().is_empty())
+            .or_else(|| DEFAULT_CLIENT_ID.map(str::to_string))
+    }
+}
+
+/// 发送给后台线程的更新
+enum Update {
+    Status(NowPlayingStatus),
+    Config(DiscordConfig),
+}
+
+fn set_activity(
+    client: &mut DiscordIpcClient,
+    status: &NowPlayingStatus,
+    privacy: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if status.music_id.is_empty() {
+        return client.clear_activity();
+    }
+    let artists = status.artists.join(" / ");
+    let cover = status
+        .cover_url
+        .as_deref()
+        .filter(|x| !privacy && x.starts_with("https://"))
+        .unwrap_or(FALLBACK_IMAGE);
+    let mut assets = Assets::new().large_image(cover);
+    if !privacy && !status.album_name.is_empty() {
+        assets = assets.large_text(&status.album_name);
+    }
+    let (details, state) = if privacy {
+        ("正在听歌", "")
+    } else {
+        (status.music_name.as_str(), artists.as_str())
+    };
+    let mut activity = Activity::new()
+        .activity_type(ActivityType::Listening)
+        .details(details)
+        .assets(assets);
+    if !state.is_empty() {
+        activity = activity.state(state);
+    }
+    // 暂停时不显示进度，Discord 会在有时间戳时自动计时
+    if !status.paused {
+        let start = now_millis() as i64 - status.position as i64;
+        let mut timestamps = Timestamps::new().start(start);
+        if status.duration > 0 {
+            timestamps = timestamps.end(start + status.duration as i64);
+        }
+        activity = activity.timestamps(timestamps);
+    }
+    client.set_activity(activity)
+}
+
+/// 后台线程，负责连接 Discord 并在状态变化时更新
+fn run(receiver: Receiver<Update>) {
+    let mut config = DiscordConfig::default();
+    let mut status = None;
+    let mut client: Option<DiscordIpcClient> = None;
+    let mut connected_at: Option<Instant> = None;
+    let mut dirty = false;
+    loop {
+        match receiver.recv_timeout(UPDATE_DELAY) {
+            Ok(Update::Status(new_status)) => {
+                status = Some(new_status);
+                dirty = true;
+                continue;
+            }
+            Ok(Update::Config(new_config)) => {
+                // 应用 ID 变化或者关闭时需要断开旧的连接
+                if !new_config.enabled || new_config.client_id() != config.client_id() {
+                    if let Some(mut client) = client.take() {
+                        let _ = client.clear_activity();
+                        let _ = client.close();
+                    }
+                }
+                config = new_config;
+                dirty = true;
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if !config.enabled || !dirty {
+            continue;
+        }
+        if client.is_none() {
+            if connected_at.is_some_and(|x| x.elapsed() < RECONNECT_INTERVAL) {
+                continue;
+            }
+            connected_at = Some(Instant::now());
+            let Some(client_id) = config.client_id() else {
+                println!("没有设置 Discord 应用 ID，无法开启 Rich Presence");
+                config.enabled = false;
+                continue;
+            };
+            client = DiscordIpcClient::new(&client_id)
+                .and_then(|mut client| client.connect().map(|_| client))
+                .map_err(|err| println!("Discord 连接失败: {err:?}"))
+                .ok();
+        }
+        let (Some(current), Some(status)) = (client.as_mut(), &status) else {
+            continue;
+        };
+        match set_activity(current, status, config.privacy) {
+            Ok(()) => dirty = false,
+            Err(err) => {
+                // 可能是 Discord 被关闭了，之后重新连接
+                println!("Discord Rich Presence 更新失败: {err:?}");
+                client = None;
+            }
+        }
+    }
+    if let Some(mut client) = client {
+        let _ = client.close();
+    }
+}
+
+pub struct DiscordPresence {
+    path: Option<PathBuf>,
+    config: DiscordConfig,
+    sender: Sender<Update>,
+}
+
+impl DiscordPresence {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let config: DiscordConfig =
+            crate::config::load_json(path.as_deref(), "Discord Rich Presence 配置")
+                .unwrap_or_default();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || run(receiver));
+        let _ = sender.send(Update::Config(config.clone()));
+        Self {
+            path,
+            config,
+            sender,
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(
+            self.path.as_deref(),
+            &self.config,
+            "Discord Rich Presence 配置",
+        );
+    }
+
+    pub fn config(&self) -> DiscordConfig {
+        self.config.clone()
+    }
+
+    pub fn set_config(&mut self, config: DiscordConfig) {
+        self.config = config;
+        self.save();
+        let _ = self.sender.send(Update::Config(self.config.clone()));
+    }
+
+    /// 歌曲信息或者播放状态变化时更新，需要在 [`NowPlaying`] 处理信息之后调用
+    pub fn on_body(&self, app: &AppHandle, body: &Body) {
+        if !self.config.enabled {
+            return;
+        }
+        let changed = matches!(
+            body,
+            Body::SetMusicId { .. }
+                | Body::SetMusicAlbum { .. }
+                | Body::SetMusicArtists { .. }
+                | Body::SetMusicAlbumCoverImageURL { .. }
+                | Body::OnPaused
+                | Body::OnResumed
+                | Body::SetPlayProgress { .. }
+        );
+        if changed {
+            let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+            let _ = self.sender.send(Update::Status(status));
+        }
+    }
+}
+
+/// 获取 Discord Rich Presence 的设置
+#[tauri::command]
+pub fn discord_get_config(discord: State<Mutex<DiscordPresence>>) -> DiscordConfig {
+    discord.lock().unwrap().config()
+}
+
+/// 修改 Discord Rich Presence 的设置，包括开关和隐私模式
+#[tauri::command]
+pub fn discord_set_config(
+    app: AppHandle,
+    discord: State<Mutex<DiscordPresence>>,
+    config: DiscordConfig,
+) {
+    let mut discord = discord.lock().unwrap();
+    let enabled = config.enabled;
+    discord.set_config(config);
+    // 刚开启时立即发布当前的播放状态
+    if enabled {
+        let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+        let _ = discord.sender.send(Update::Status(status));
+    }
+}
@@ -0,0 +1,375 @@
+//! DLNA/UPnP 媒体渲染器
+//!
+//! 开启后播放器会作为 UPnP MediaRenderer 出现在局域网中，BubbleUPnP、foobar2000 等控制点可以把歌曲投送到播放器。
+//! 渲染器实现了 AVTransport、RenderingControl 和 ConnectionManager 三个服务中控制点常用的动作，
+//! 设备发现见 [`ssdp`]，设备和服务的描述以及 SOAP 控制见 [`service`]。
+//! 控制点需要通过轮询获取播放状态，渲染器不会发送 GENA 事件通知。
+//!
+//! 播放器后端不播放音乐，收到的歌曲地址和播放控制会通过 `on-dlna-command` 事件交给前端，
+//! 由前端的 `src/dlna.ts` 用网页的音频元素播放，并通过 [`dlna_report_position`] 汇报实际的播放进度。
+//! Windows 上的网页运行在 HTTPS 源下，WebView2 会把局域网中的 HTTP 地址当作混合内容拦截，
+//! 因此目前只能播放 HTTPS 地址，其它系统不受影响。
+//! 歌曲信息（DIDL-Lite 元数据）和播放进度会按照从 WebSocket 客户端收到的信息处理，
+//! 因此歌词、封面和系统媒体控件等都会和普通播放源一样工作。
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::error::CommandResult;
+
+mod service;
+mod ssdp;
+
+/// 播放时发送播放进度的间隔
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_NAME: &str = "AMLL Player";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct DlnaConfig {
+    /// 设备的 UUID，控制点根据它识别设备，因此需要保存下来
+    uuid: String,
+    /// 显示在控制点中的设备名称
+    name: String,
+}
+
+impl DlnaConfig {
+    fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let hex: String = (0..32)
+            .map(|_| format!("{:x}", rng.gen_range(0..16)))
+            .collect();
+        Self {
+            uuid: format!(
+                "{}-{}-{}-{}-{}",
+                &hex[..8],
+                &hex[8..12],
+                &hex[12..16],
+                &hex[16..20],
+                &hex[20..]
+            ),
+            name: DEFAULT_NAME.to_string(),
+        }
+    }
+}
+
+/// 投送的歌曲信息，从控制点发送的 DIDL-Lite 元数据中读取
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DlnaTrack {
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub cover_url: Option<String>,
+    /// 单位为毫秒，元数据中没有时为 0
+    pub duration: u64,
+}
+
+/// 通过 `on-dlna-command` 事件发送给前端的播放指令
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum DlnaCommand {
+    /// 加载新的歌曲，加载后保持停止状态，等待控制点发送播放指令
+    #[serde(rename_all = "camelCase")]
+    SetUri {
+        uri: String,
+        track: DlnaTrack,
+    },
+    Play,
+    Pause,
+    Stop,
+    /// 跳转到指定位置，单位为毫秒
+    Seek {
+        position: u64,
+    },
+    /// 设置音量，范围为 0 到 1
+    SetVolume {
+        volume: f64,
+    },
+    SetMute {
+        muted: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TransportState {
+    NoMediaPresent,
+    Stopped,
+    Playing,
+    PausedPlayback,
+}
+
+impl TransportState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::NoMediaPresent => "NO_MEDIA_PRESENT",
+            Self::Stopped => "STOPPED",
+            Self::Playing => "PLAYING",
+            Self::PausedPlayback => "PAUSED_PLAYBACK",
+        }
+    }
+}
+
+/// 渲染器的播放状态，控制点查询时返回
+struct Transport {
+    uri: String,
+    /// 控制点发送的原始元数据，查询时原样返回
+    metadata: String,
+    track: DlnaTrack,
+    state: TransportState,
+    /// 开始播放或者暂停时的播放进度，单位为毫秒
+    position: u64,
+    /// 开始播放的时间，没有在播放时为空
+    started_at: Option<Instant>,
+    /// 范围为 0 到 100
+    volume: u8,
+    muted: bool,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            uri: String::new(),
+            metadata: String::new(),
+            track: DlnaTrack::default(),
+            state: TransportState::NoMediaPresent,
+            position: 0,
+            started_at: None,
+            volume: 100,
+            muted: false,
+        }
+    }
+}
+
+impl Transport {
+    /// 根据开始播放的时间推算当前的播放进度
+    fn position(&self) -> u64 {
+        let position = self.position
+            + self
+                .started_at
+                .map(|x| x.elapsed().as_millis() as u64)
+                .unwrap_or_default();
+        match self.track.duration {
+            0 => position,
+            duration => position.min(duration),
+        }
+    }
+
+    fn set_state(&mut self, state: TransportState) {
+        self.position = self.position();
+        self.started_at = (state == TransportState::Playing).then(Instant::now);
+        self.state = state;
+    }
+}
+
+type SharedTransport = Arc<Mutex<Transport>>;
+
+/// 把指令发送给前端
+fn emit_command(app: &AppHandle, command: DlnaCommand) {
+    if let Err(err) = app.emit_all("on-dlna-command", command) {
+        println!("DLNA 播放指令发送失败: {err:?}");
+    }
+}
+
+/// 播放时定时发送播放进度，播放到结尾时切换到停止状态，控制点会据此播放下一首歌曲
+fn report_progress(app: AppHandle, transport: SharedTransport, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(PROGRESS_INTERVAL);
+        let (position, ended) = {
+            let mut transport = transport.lock().unwrap();
+            if transport.state != TransportState::Playing {
+                continue;
+            }
+            let position = transport.position();
+            let ended = transport.track.duration > 0 && position >= transport.track.duration;
+            if ended {
+                transport.set_state(TransportState::Stopped);
+                transport.position = 0;
+            }
+            (position, ended)
+        };
+        crate::on_local_body(
+            &app,
+            Body::OnPlayProgress {
+                progress: position as f64,
+            },
+        );
+        if ended {
+            crate::on_local_body(&app, Body::OnPaused);
+        }
+    }
+}
+
+struct Running {
+    stop: Arc<AtomicBool>,
+    server: async_std::task::JoinHandle<()>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+pub struct DlnaRenderer {
+    config_path: Option<PathBuf>,
+    config: DlnaConfig,
+    transport: SharedTransport,
+    running: Option<Running>,
+}
+
+impl DlnaRenderer {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let config = crate::config::load_json(path.as_deref(), "DLNA 渲染器设置");
+        let generated = config.is_none();
+        let renderer = Self {
+            config_path: path,
+            config: config.unwrap_or_else(DlnaConfig::generate),
+            transport: Arc::default(),
+            running: None,
+        };
+        // 第一次开启时生成的 UUID 需要保存下来，控制点才能在重启后识别出同一台设备
+        if generated {
+            renderer.save();
+        }
+        renderer
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.config_path.as_deref(), &self.config, "DLNA 渲染器设置");
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.running.is_some()
+    }
+
+    pub fn set_enabled(&mut self, app: &AppHandle, enabled: bool) -> anyhow::Result<()> {
+        if let Some(running) = self.running.take() {
+            running.stop.store(true, Ordering::Relaxed);
+            async_std::task::block_on(running.server.cancel());
+            for thread in running.threads {
+                let _ = thread.join();
+            }
+            println!("已关闭 DLNA 渲染器");
+        }
+        if !enabled {
+            return Ok(());
+        }
+        let listener = std::net::TcpListener::bind("0.0.0.0:0")?;
+        let port = listener.local_addr()?.port();
+        let socket = ssdp::bind()?;
+        let device = Arc::new(service::Device {
+            uuid: self.config.uuid.clone(),
+            name: self.config.name.clone(),
+            transport: self.transport.clone(),
+        });
+        let stop = Arc::new(AtomicBool::new(false));
+        let server = async_std::task::spawn(service::serve(
+            async_std::net::TcpListener::from(listener),
+            app.clone(),
+            device,
+        ));
+        let threads = vec![
+            std::thread::spawn({
+                let uuid = self.config.uuid.clone();
+                let stop = stop.clone();
+                move || ssdp::run(socket, uuid, port, stop)
+            }),
+            std::thread::spawn({
+                let app = app.clone();
+                let transport = self.transport.clone();
+                let stop = stop.clone();
+                move || report_progress(app, transport, stop)
+            }),
+        ];
+        println!("已开启 DLNA 渲染器 {}，端口为 {port}", self.config.name);
+        self.running = Some(Running {
+            stop,
+            server,
+            threads,
+        });
+        Ok(())
+    }
+
+    pub fn set_name(&mut self, name: &str) {
+        let name = name.trim();
+        self.config.name = if name.is_empty() {
+            DEFAULT_NAME.to_string()
+        } else {
+            name.to_string()
+        };
+        self.save();
+    }
+}
+
+/// 开启或关闭 DLNA 渲染器
+#[tauri::command]
+pub fn dlna_set_enabled(
+    app: AppHandle,
+    renderer: State<Mutex<DlnaRenderer>>,
+    enabled: bool,
+) -> CommandResult<()> {
+    Ok(renderer.lock().unwrap().set_enabled(&app, enabled)?)
+}
+
+#[tauri::command]
+pub fn dlna_is_enabled(renderer: State<Mutex<DlnaRenderer>>) -> bool {
+    renderer.lock().unwrap().is_enabled()
+}
+
+/// 设置显示在控制点中的设备名称，渲染器正在运行时会重新开启使控制点更新名称
+#[tauri::command]
+pub fn dlna_set_name(
+    app: AppHandle,
+    renderer: State<Mutex<DlnaRenderer>>,
+    name: String,
+) -> CommandResult<()> {
+    let mut renderer = renderer.lock().unwrap();
+    renderer.set_name(&name);
+    if renderer.is_enabled() {
+        renderer.set_enabled(&app, true)?;
+    }
+    Ok(())
+}
+
+/// 前端汇报实际的播放进度和状态，用于校正推算出的播放进度，`position` 单位为毫秒
+///
+/// `ended` 表示歌曲已经播放完毕或者无法播放，此时切换到停止状态，控制点会据此播放下一首歌曲
+#[tauri::command]
+pub fn dlna_report_position(
+    app: AppHandle,
+    renderer: State<Mutex<DlnaRenderer>>,
+    position: f64,
+    paused: bool,
+    ended: bool,
+) {
+    let transport = renderer.lock().unwrap().transport.clone();
+    let mut transport = transport.lock().unwrap();
+    if transport.state == TransportState::NoMediaPresent {
+        return;
+    }
+    if ended {
+        let was_stopped = transport.state == TransportState::Stopped;
+        transport.set_state(TransportState::Stopped);
+        transport.position = 0;
+        drop(transport);
+        if !was_stopped {
+            crate::on_local_body(&app, Body::OnPaused);
+        }
+        return;
+    }
+    transport.position = position.max(0.0) as u64;
+    transport.started_at = None;
+    let state = match (paused, transport.state) {
+        (false, _) => TransportState::Playing,
+        (true, TransportState::Stopped) => TransportState::Stopped,
+        (true, _) => TransportState::PausedPlayback,
+    };
+    transport.set_state(state);
+}
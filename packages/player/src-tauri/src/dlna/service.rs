@@ -0,0 +1,875 @@
+//! 渲染器的 HTTP 服务
+//!
+//! - `GET /description.xml`：设备描述
+//! - `GET /{服务}.xml`：服务描述（SCPD）
+//! - `POST /{服务}/control`：SOAP 控制
+//! - `SUBSCRIBE|UNSUBSCRIBE /{服务}/event`：只返回订阅成功，不会发送事件通知
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use async_std::net::{TcpListener, TcpStream};
+use futures::prelude::*;
+use quick_xml::{escape::escape, events::Event, Reader};
+use tauri::AppHandle;
+use ws_protocol::Body;
+
+use super::{emit_command, DlnaCommand, DlnaTrack, SharedTransport, TransportState};
+
+pub const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:MediaRenderer:1";
+const AV_TRANSPORT: &str = "urn:schemas-upnp-org:service:AVTransport:1";
+const RENDERING_CONTROL: &str = "urn:schemas-upnp-org:service:RenderingControl:1";
+const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+pub const SERVICE_TYPES: &[&str] = &[AV_TRANSPORT, RENDERING_CONTROL, CONNECTION_MANAGER];
+const XML: (&str, &str) = ("Content-Type", "text/xml; charset=\"utf-8\"");
+/// 请求的最大大小，包括请求头和请求体
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// 接受连接失败后重试的间隔
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// 可以接收的音频格式
+const SINK_PROTOCOL_INFO: &str = "http-get:*:audio/mpeg:*,http-get:*:audio/flac:*,http-get:*:audio/x-flac:*,http-get:*:audio/ogg:*,http-get:*:audio/opus:*,http-get:*:audio/mp4:*,http-get:*:audio/aac:*,http-get:*:audio/wav:*,http-get:*:audio/x-wav:*,http-get:*:audio/L16:*,http-get:*:audio/webm:*";
+
+/// 动作的参数，`true` 表示输出参数，最后一项为关联的状态变量
+type Argument = (&'static str, bool, &'static str);
+/// 状态变量的名称、类型和允许的取值
+type StateVariable = (&'static str, &'static str, &'static [&'static str]);
+
+const AV_TRANSPORT_ACTIONS: &[(&str, &[Argument])] = &[
+    (
+        "SetAVTransportURI",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("CurrentURI", false, "AVTransportURI"),
+            ("CurrentURIMetaData", false, "AVTransportURIMetaData"),
+        ],
+    ),
+    (
+        "GetMediaInfo",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("NrTracks", true, "NumberOfTracks"),
+            ("MediaDuration", true, "CurrentMediaDuration"),
+            ("CurrentURI", true, "AVTransportURI"),
+            ("CurrentURIMetaData", true, "AVTransportURIMetaData"),
+            ("NextURI", true, "NextAVTransportURI"),
+            ("NextURIMetaData", true, "NextAVTransportURIMetaData"),
+            ("PlayMedium", true, "PlaybackStorageMedium"),
+            ("RecordMedium", true, "RecordStorageMedium"),
+            ("WriteStatus", true, "RecordMediumWriteStatus"),
+        ],
+    ),
+    (
+        "GetTransportInfo",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("CurrentTransportState", true, "TransportState"),
+            ("CurrentTransportStatus", true, "TransportStatus"),
+            ("CurrentSpeed", true, "TransportPlaySpeed"),
+        ],
+    ),
+    (
+        "GetPositionInfo",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Track", true, "CurrentTrack"),
+            ("TrackDuration", true, "CurrentTrackDuration"),
+            ("TrackMetaData", true, "CurrentTrackMetaData"),
+            ("TrackURI", true, "CurrentTrackURI"),
+            ("RelTime", true, "RelativeTimePosition"),
+            ("AbsTime", true, "AbsoluteTimePosition"),
+            ("RelCount", true, "RelativeCounterPosition"),
+            ("AbsCount", true, "AbsoluteCounterPosition"),
+        ],
+    ),
+    (
+        "GetDeviceCapabilities",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("PlayMedia", true, "PossiblePlaybackStorageMedia"),
+            ("RecMedia", true, "PossibleRecordStorageMedia"),
+            ("RecQualityModes", true, "PossibleRecordQualityModes"),
+        ],
+    ),
+    (
+        "GetTransportSettings",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("PlayMode", true, "CurrentPlayMode"),
+            ("RecQualityMode", true, "CurrentRecordQualityMode"),
+        ],
+    ),
+    (
+        "GetCurrentTransportActions",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Actions", true, "CurrentTransportActions"),
+        ],
+    ),
+    ("Stop", &[("InstanceID", false, "A_ARG_TYPE_InstanceID")]),
+    (
+        "Play",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Speed", false, "TransportPlaySpeed"),
+        ],
+    ),
+    ("Pause", &[("InstanceID", false, "A_ARG_TYPE_InstanceID")]),
+    (
+        "Seek",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Unit", false, "A_ARG_TYPE_SeekMode"),
+            ("Target", false, "A_ARG_TYPE_SeekTarget"),
+        ],
+    ),
+];
+
+const AV_TRANSPORT_VARIABLES: &[StateVariable] = &[
+    (
+        "TransportState",
+        "string",
+        &["STOPPED", "PLAYING", "PAUSED_PLAYBACK", "NO_MEDIA_PRESENT"],
+    ),
+    ("TransportStatus", "string", &["OK", "ERROR_OCCURRED"]),
+    ("TransportPlaySpeed", "string", &["1"]),
+    ("PlaybackStorageMedium", "string", &["NETWORK", "NONE"]),
+    ("RecordStorageMedium", "string", &["NOT_IMPLEMENTED"]),
+    ("PossiblePlaybackStorageMedia", "string", &[]),
+    ("PossibleRecordStorageMedia", "string", &[]),
+    ("PossibleRecordQualityModes", "string", &[]),
+    ("RecordMediumWriteStatus", "string", &["NOT_IMPLEMENTED"]),
+    ("CurrentPlayMode", "string", &["NORMAL"]),
+    ("CurrentRecordQualityMode", "string", &["NOT_IMPLEMENTED"]),
+    ("NumberOfTracks", "ui4", &[]),
+    ("CurrentTrack", "ui4", &[]),
+    ("CurrentTrackDuration", "string", &[]),
+    ("CurrentMediaDuration", "string", &[]),
+    ("CurrentTrackMetaData", "string", &[]),
+    ("CurrentTrackURI", "string", &[]),
+    ("AVTransportURI", "string", &[]),
+    ("AVTransportURIMetaData", "string", &[]),
+    ("NextAVTransportURI", "string", &[]),
+    ("NextAVTransportURIMetaData", "string", &[]),
+    ("RelativeTimePosition", "string", &[]),
+    ("AbsoluteTimePosition", "string", &[]),
+    ("RelativeCounterPosition", "i4", &[]),
+    ("AbsoluteCounterPosition", "i4", &[]),
+    ("CurrentTransportActions", "string", &[]),
+    ("LastChange", "string", &[]),
+    ("A_ARG_TYPE_SeekMode", "string", &["REL_TIME", "ABS_TIME"]),
+    ("A_ARG_TYPE_SeekTarget", "string", &[]),
+    ("A_ARG_TYPE_InstanceID", "ui4", &[]),
+];
+
+const RENDERING_CONTROL_ACTIONS: &[(&str, &[Argument])] = &[
+    (
+        "GetVolume",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Channel", false, "A_ARG_TYPE_Channel"),
+            ("CurrentVolume", true, "Volume"),
+        ],
+    ),
+    (
+        "SetVolume",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Channel", false, "A_ARG_TYPE_Channel"),
+            ("DesiredVolume", false, "Volume"),
+        ],
+    ),
+    (
+        "GetMute",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Channel", false, "A_ARG_TYPE_Channel"),
+            ("CurrentMute", true, "Mute"),
+        ],
+    ),
+    (
+        "SetMute",
+        &[
+            ("InstanceID", false, "A_ARG_TYPE_InstanceID"),
+            ("Channel", false, "A_ARG_TYPE_Channel"),
+            ("DesiredMute", false, "Mute"),
+        ],
+    ),
+];
+
+const RENDERING_CONTROL_VARIABLES: &[StateVariable] = &[
+    ("Volume", "ui2", &[]),
+    ("Mute", "boolean", &[]),
+    ("LastChange", "string", &[]),
+    ("A_ARG_TYPE_Channel", "string", &["Master"]),
+    ("A_ARG_TYPE_InstanceID", "ui4", &[]),
+];
+
+const CONNECTION_MANAGER_ACTIONS: &[(&str, &[Argument])] = &[
+    (
+        "GetProtocolInfo",
+        &[
+            ("Source", true, "SourceProtocolInfo"),
+            ("Sink", true, "SinkProtocolInfo"),
+        ],
+    ),
+    (
+        "GetCurrentConnectionIDs",
+        &[("ConnectionIDs", true, "CurrentConnectionIDs")],
+    ),
+    (
+        "GetCurrentConnectionInfo",
+        &[
+            ("ConnectionID", false, "A_ARG_TYPE_ConnectionID"),
+            ("RcsID", true, "A_ARG_TYPE_RcsID"),
+            ("AVTransportID", true, "A_ARG_TYPE_AVTransportID"),
+            ("ProtocolInfo", true, "A_ARG_TYPE_ProtocolInfo"),
+            (
+                "PeerConnectionManager",
+                true,
+                "A_ARG_TYPE_ConnectionManager",
+            ),
+            ("PeerConnectionID", true, "A_ARG_TYPE_ConnectionID"),
+            ("Direction", true, "A_ARG_TYPE_Direction"),
+            ("Status", true, "A_ARG_TYPE_ConnectionStatus"),
+        ],
+    ),
+];
+
+const CONNECTION_MANAGER_VARIABLES: &[StateVariable] = &[
+    ("SourceProtocolInfo", "string", &[]),
+    ("SinkProtocolInfo", "string", &[]),
+    ("CurrentConnectionIDs", "string", &[]),
+    (
+        "A_ARG_TYPE_ConnectionStatus",
+        "string",
+        &[
+            "OK",
+            "ContentFormatMismatch",
+            "InsufficientBandwidth",
+            "UnreliableChannel",
+            "Unknown",
+        ],
+    ),
+    ("A_ARG_TYPE_ConnectionManager", "string", &[]),
+    ("A_ARG_TYPE_Direction", "string", &["Input", "Output"]),
+    ("A_ARG_TYPE_ProtocolInfo", "string", &[]),
+    ("A_ARG_TYPE_ConnectionID", "i4", &[]),
+    ("A_ARG_TYPE_AVTransportID", "i4", &[]),
+    ("A_ARG_TYPE_RcsID", "i4", &[]),
+];
+
+/// 渲染器提供的服务，路径中使用的名称和服务类型
+const SERVICES: &[(&str, &str)] = &[
+    ("AVTransport", AV_TRANSPORT),
+    ("RenderingControl", RENDERING_CONTROL),
+    ("ConnectionManager", CONNECTION_MANAGER),
+];
+
+pub struct Device {
+    pub uuid: String,
+    pub name: String,
+    pub transport: SharedTransport,
+}
+
+fn device_description(device: &Device) -> String {
+    let mut services = String::new();
+    for (name, service_type) in SERVICES {
+        write!(
+            services,
+            "<service><serviceType>{service_type}</serviceType><serviceId>urn:upnp-org:serviceId:{name}</serviceId><SCPDURL>/{name}.xml</SCPDURL><controlURL>/{name}/control</controlURL><eventSubURL>/{name}/event</eventSubURL></service>"
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0"><specVersion><major>1</major><minor>0</minor></specVersion><device><deviceType>{DEVICE_TYPE}</deviceType><friendlyName>{}</friendlyName><manufacturer>AMLL</manufacturer><modelName>AMLL Player</modelName><modelNumber>{}</modelNumber><UDN>uuid:{}</UDN><serviceList>{services}</serviceList></device></root>"#,
+        escape(&device.name),
+        env!("CARGO_PKG_VERSION"),
+        device.uuid
+    )
+}
+
+fn scpd(actions: &[(&str, &[Argument])], variables: &[StateVariable]) -> String {
+    let mut result = String::from(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<scpd xmlns="urn:schemas-upnp-org:service-1-0"><specVersion><major>1</major><minor>0</minor></specVersion><actionList>"#,
+    );
+    for (name, arguments) in actions {
+        write!(result, "<action><name>{name}</name><argumentList>").unwrap();
+        for (argument, out, variable) in *arguments {
+            write!(
+                result,
+                "<argument><name>{argument}</name><direction>{}</direction><relatedStateVariable>{variable}</relatedStateVariable></argument>",
+                if *out { "out" } else { "in" }
+            )
+            .unwrap();
+        }
+        result.push_str("</argumentList></action>");
+    }
+    result.push_str("</actionList><serviceStateTable>");
+    for (name, data_type, allowed) in variables {
+        let send_events = if *name == "LastChange" { "yes" } else { "no" };
+        write!(
+            result,
+            r#"<stateVariable sendEvents="{send_events}"><name>{name}</name><dataType>{data_type}</dataType>"#
+        )
+        .unwrap();
+        if !allowed.is_empty() {
+            result.push_str("<allowedValueList>");
+            for value in *allowed {
+                write!(result, "<allowedValue>{value}</allowedValue>").unwrap();
+            }
+            result.push_str("</allowedValueList>");
+        }
+        result.push_str("</stateVariable>");
+    }
+    result.push_str("</serviceStateTable></scpd>");
+    result
+}
+
+/// 将毫秒转换为 `H:MM:SS` 格式的时间
+fn format_time(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// 解析 `H:MM:SS` 或者 `H:MM:SS.mmm` 格式的时间，返回毫秒
+fn parse_time(time: &str) -> Option<u64> {
+    let mut parts = time.trim().split(':').rev();
+    let secs: f64 = parts.next()?.parse().ok()?;
+    let mins: u64 = parts
+        .next()
+        .map(|x| x.parse())
+        .transpose()
+        .ok()?
+        .unwrap_or(0);
+    let hours: u64 = parts
+        .next()
+        .map(|x| x.parse())
+        .transpose()
+        .ok()?
+        .unwrap_or(0);
+    Some((hours * 3600 + mins * 60) * 1000 + (secs * 1000.0) as u64)
+}
+
+/// 读取 DIDL-Lite 元数据中第一个条目的歌曲信息
+fn parse_didl(metadata: &str) -> DlnaTrack {
+    let mut track = DlnaTrack::default();
+    let mut reader = Reader::from_str(metadata);
+    reader.trim_text(true);
+    let mut current_field = Vec::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                if e.local_name().as_ref() == b"res" && track.duration == 0 {
+                    if let Some(duration) = e
+                        .attributes()
+                        .flatten()
+                        .find(|x| x.key.local_name().as_ref() == b"duration")
+                        .and_then(|x| parse_time(&String::from_utf8_lossy(&x.value)))
+                    {
+                        track.duration = duration;
+                    }
+                }
+                current_field = e.name().as_ref().to_vec();
+            }
+            Ok(Event::Text(text)) => {
+                let Ok(text) = text.unescape() else {
+                    continue;
+                };
+                let text = text.into_owned();
+                match current_field.as_slice() {
+                    b"dc:title" if track.title.is_empty() => track.title = text,
+                    b"upnp:artist" | b"dc:creator" if !track.artists.contains(&text) => {
+                        track.artists.push(text)
+                    }
+                    b"upnp:album" if track.album.is_empty() => track.album = text,
+                    b"upnp:albumArtURI" if track.cover_url.is_none() => {
+                        track.cover_url = Some(text)
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::End(e)) => {
+                current_field.clear();
+                if e.local_name().as_ref() == b"item" {
+                    break;
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    track
+}
+
+/// 读取 SOAP 请求中的动作名称和参数
+fn parse_soap(body: &str) -> Option<(String, Vec<(String, String)>)> {
+    let mut reader = Reader::from_str(body);
+    reader.trim_text(true);
+    let mut depth = 0;
+    let mut action = None;
+    let mut args = Vec::new();
+    loop {
+        match reader.read_event().ok()? {
+            // 第三层为动作，第四层为参数：Envelope > Body > 动作 > 参数
+            Event::Start(e) => {
+                depth += 1;
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                match depth {
+                    3 => action = Some(name),
+                    4 => args.push((name, String::new())),
+                    _ => {}
+                }
+            }
+            // 没有参数的动作和值为空的参数可能是空元素
+            Event::Empty(e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                match depth {
+                    2 => action = Some(name),
+                    3 => args.push((name, String::new())),
+                    _ => {}
+                }
+            }
+            Event::Text(text) if depth == 4 => {
+                if let Some((_, value)) = args.last_mut() {
+                    value.push_str(&text.unescape().ok()?);
+                }
+            }
+            Event::CData(data) if depth == 4 => {
+                if let Some((_, value)) = args.last_mut() {
+                    value.push_str(&String::from_utf8_lossy(&data));
+                }
+            }
+            Event::End(_) => depth -= 1,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Some((action?, args))
+}
+
+fn soap_envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<s:Envelope xmlns:s="http://schemas.xmlsoap.org/soap/envelope/" s:encodingStyle="http://schemas.xmlsoap.org/soap/encoding/"><s:Body>{body}</s:Body></s:Envelope>"#
+    )
+}
+
+fn soap_response(service_type: &str, action: &str, args: &[(&str, String)]) -> String {
+    let mut body = format!(r#"<u:{action}Response xmlns:u="{service_type}">"#);
+    for (name, value) in args {
+        write!(body, "<{name}>{}</{name}>", escape(value)).unwrap();
+    }
+    write!(body, "</u:{action}Response>").unwrap();
+    soap_envelope(&body)
+}
+
+fn soap_fault(code: u16, description: &str) -> String {
+    soap_envelope(&format!(
+        r#"<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail><UPnPError xmlns="urn:schemas-upnp-org:control-1-0"><errorCode>{code}</errorCode><errorDescription>{description}</errorDescription></UPnPError></detail></s:Fault>"#
+    ))
+}
+
+/// 处理控制点调用的动作，返回输出参数，出错时返回 UPnP 错误码和描述
+fn handle_action(
+    app: &AppHandle,
+    device: &Device,
+    action: &str,
+    args: &[(String, String)],
+) -> Result<Vec<(&'static str, String)>, (u16, &'static str)> {
+    let arg = |name: &str| {
+        args.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .unwrap_or_default()
+    };
+    let mut bodies = Vec::new();
+    let mut commands = Vec::new();
+    let result = {
+        let mut transport = device.transport.lock().unwrap();
+        match action {
+            "SetAVTransportURI" => {
+                let uri = arg("CurrentURI").trim().to_string();
+                if uri.is_empty() {
+                    return Err((714, "Illegal MIME-type"));
+                }
+                let metadata = arg("CurrentURIMetaData").to_string();
+                let track = parse_didl(&metadata);
+                println!("DLNA 控制点投送了歌曲: {} ({uri})", track.title);
+                bodies.push(Body::SetMusicId {
+                    id: uri.as_str().into(),
+                    name: track.title.as_str().into(),
+                    duration: track.duration,
+                });
+                bodies.push(Body::SetMusicAlbum {
+                    id: "".into(),
+                    name: track.album.as_str().into(),
+                });
+                bodies.push(Body::SetMusicArtists {
+                    artists: track
+                        .artists
+                        .iter()
+                        .map(|x| ws_protocol::Artist {
+                            id: "".into(),
+                            name: x.as_str().into(),
+                        })
+                        .collect(),
+                });
+                if let Some(cover_url) = &track.cover_url {
+                    bodies.push(Body::SetMusicAlbumCoverImageURL {
+                        img_url: cover_url.as_str().into(),
+                    });
+                }
+                bodies.push(Body::OnPaused);
+                commands.push(DlnaCommand::SetUri {
+                    uri: uri.clone(),
+                    track: track.clone(),
+                });
+                transport.uri = uri;
+                transport.metadata = metadata;
+                transport.track = track;
+                transport.position = 0;
+                transport.started_at = None;
+                transport.state = TransportState::Stopped;
+                Ok(vec![])
+            }
+            "Play" | "Pause" | "Stop" | "Seek"
+                if transport.state == TransportState::NoMediaPresent =>
+            {
+                Err((701, "Transition not available"))
+            }
+            "Play" => {
+                if transport.state != TransportState::Playing {
+                    transport.set_state(TransportState::Playing);
+                    commands.push(DlnaCommand::Play);
+                    bodies.push(Body::OnResumed);
+                }
+                Ok(vec![])
+            }
+            "Pause" => {
+                if transport.state == TransportState::Playing {
+                    transport.set_state(TransportState::PausedPlayback);
+                    commands.push(DlnaCommand::Pause);
+                    bodies.push(Body::OnPaused);
+                }
+                Ok(vec![])
+            }
+            "Stop" => {
+                transport.set_state(TransportState::Stopped);
+                transport.position = 0;
+                commands.push(DlnaCommand::Stop);
+                bodies.push(Body::OnPaused);
+                bodies.push(Body::OnPlayProgress { progress: 0.0 });
+                Ok(vec![])
+            }
+            "Seek" => match arg("Unit") {
+                "REL_TIME" | "ABS_TIME" => match parse_time(arg("Target")) {
+                    Some(position) => {
+                        transport.position = position;
+                        if transport.started_at.is_some() {
+                            transport.started_at = Some(std::time::Instant::now());
+                        }
+                        commands.push(DlnaCommand::Seek { position });
+                        bodies.push(Body::OnPlayProgress {
+                            progress: position as f64,
+                        });
+                        Ok(vec![])
+                    }
+                    None => Err((711, "Illegal seek target")),
+                },
+                _ => Err((710, "Seek mode not supported")),
+            },
+            "GetTransportInfo" => Ok(vec![
+                (
+                    "CurrentTransportState",
+                    transport.state.as_str().to_string(),
+                ),
+                ("CurrentTransportStatus", "OK".to_string()),
+                ("CurrentSpeed", "1".to_string()),
+            ]),
+            "GetPositionInfo" => {
+                let position = format_time(transport.position());
+                let has_media = transport.state != TransportState::NoMediaPresent;
+                Ok(vec![
+                    ("Track", (has_media as u8).to_string()),
+                    ("TrackDuration", format_time(transport.track.duration)),
+                    ("TrackMetaData", transport.metadata.clone()),
+                    ("TrackURI", transport.uri.clone()),
+                    ("RelTime", position.clone()),
+                    ("AbsTime", position),
+                    ("RelCount", i32::MAX.to_string()),
+                    ("AbsCount", i32::MAX.to_string()),
+                ])
+            }
+            "GetMediaInfo" => {
+                let has_media = transport.state != TransportState::NoMediaPresent;
+                Ok(vec![
+                    ("NrTracks", (has_media as u8).to_string()),
+                    ("MediaDuration", format_time(transport.track.duration)),
+                    ("CurrentURI", transport.uri.clone()),
+                    ("CurrentURIMetaData", transport.metadata.clone()),
+                    ("NextURI", String::new()),
+                    ("NextURIMetaData", String::new()),
+                    (
+                        "PlayMedium",
+                        if has_media { "NETWORK" } else { "NONE" }.to_string(),
+                    ),
+                    ("RecordMedium", "NOT_IMPLEMENTED".to_string()),
+                    ("WriteStatus", "NOT_IMPLEMENTED".to_string()),
+                ])
+            }
+            "GetDeviceCapabilities" => Ok(vec![
+                ("PlayMedia", "NETWORK".to_string()),
+                ("RecMedia", "NOT_IMPLEMENTED".to_string()),
+                ("RecQualityModes", "NOT_IMPLEMENTED".to_string()),
+            ]),
+            "GetTransportSettings" => Ok(vec![
+                ("PlayMode", "NORMAL".to_string()),
+                ("RecQualityMode", "NOT_IMPLEMENTED".to_string()),
+            ]),
+            "GetCurrentTransportActions" => Ok(vec![(
+                "Actions",
+                match transport.state {
+                    TransportState::NoMediaPresent => "",
+                    TransportState::Playing => "Pause,Stop,Seek",
+                    _ => "Play,Stop,Seek",
+                }
+                .to_string(),
+            )]),
+            "GetVolume" => Ok(vec![("CurrentVolume", transport.volume.to_string())]),
+            "SetVolume" => match arg("DesiredVolume").parse::<u8>() {
+                Ok(volume) if volume <= 100 => {
+                    transport.volume = volume;
+                    commands.push(DlnaCommand::SetVolume {
+                        volume: volume as f64 / 100.0,
+                    });
+                    Ok(vec![])
+                }
+                _ => Err((402, "Invalid Args")),
+            },
+            "GetMute" => Ok(vec![("CurrentMute", (transport.muted as u8).to_string())]),
+            "SetMute" => {
+                let muted = matches!(arg("DesiredMute"), "1" | "true" | "True");
+                transport.muted = muted;
+                commands.push(DlnaCommand::SetMute { muted });
+                Ok(vec![])
+            }
+            "GetProtocolInfo" => Ok(vec![
+                ("Source", String::new()),
+                ("Sink", SINK_PROTOCOL_INFO.to_string()),
+            ]),
+            "GetCurrentConnectionIDs" => Ok(vec![("ConnectionIDs", "0".to_string())]),
+            "GetCurrentConnectionInfo" => Ok(vec![
+                ("RcsID", "0".to_string()),
+                ("AVTransportID", "0".to_string()),
+                ("ProtocolInfo", String::new()),
+                ("PeerConnectionManager", String::new()),
+                ("PeerConnectionID", "-1".to_string()),
+                ("Direction", "Input".to_string()),
+                ("Status", "OK".to_string()),
+            ]),
+            _ => Err((401, "Invalid Action")),
+        }
+    };
+    for command in commands {
+        emit_command(app, command);
+    }
+    for body in bodies {
+        crate::on_local_body(app, body);
+    }
+    result
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Request {
+    fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// 读取请求，请求体的长度由 `Content-Length` 决定
+async fn read_request(stream: &mut TcpStream) -> anyhow::Result<Option<Request>> {
+    let mut data = Vec::with_capacity(4096);
+    let mut buf = [0u8; 4096];
+    let head_len = loop {
+        if let Some(pos) = data.windows(4).position(|x| x == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(None);
+        }
+        data.extend_from_slice(&buf[..len]);
+        if data.len() > MAX_REQUEST_SIZE {
+            anyhow::bail!("请求过大");
+        }
+    };
+    let head = String::from_utf8_lossy(&data[..head_len]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_ascii_uppercase();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|x| x.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    let content_length = headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+        .and_then(|(_, v)| v.parse::<usize>().ok())
+        .unwrap_or_default();
+    let request_len = head_len
+        .checked_add(content_length)
+        .filter(|&x| x <= MAX_REQUEST_SIZE)
+        .ok_or_else(|| anyhow::anyhow!("请求过大"))?;
+    while data.len() < request_len {
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..len]);
+    }
+    let body_end = data.len().min(request_len);
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&data[head_len..body_end]).into_owned(),
+    }))
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> std::io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {status}\r\nContent-Length: {}\r\nServer: AMLLPlayer/{} UPnP/1.0\r\nConnection: close\r\n",
+        body.len(),
+        env!("CARGO_PKG_VERSION")
+    );
+    for (k, v) in headers {
+        write!(head, "{k}: {v}\r\n").unwrap();
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.flush().await
+}
+
+async fn handle_conn(
+    mut stream: TcpStream,
+    app: AppHandle,
+    device: Arc<Device>,
+) -> anyhow::Result<()> {
+    let Some(req) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    let path = req.path.split('?').next().unwrap_or_default();
+    if req.method == "GET" && path == "/description.xml" {
+        return Ok(
+            write_response(&mut stream, "200 OK", &[XML], &device_description(&device)).await?,
+        );
+    }
+    let Some((service, service_type, rest)) = SERVICES.iter().find_map(|(name, service_type)| {
+        let rest = path.strip_prefix('/')?.strip_prefix(name)?;
+        Some((*name, *service_type, rest))
+    }) else {
+        return Ok(write_response(&mut stream, "404 Not Found", &[], "").await?);
+    };
+    match (req.method.as_str(), rest) {
+        ("GET", ".xml") => {
+            let xml = match service {
+                "AVTransport" => scpd(AV_TRANSPORT_ACTIONS, AV_TRANSPORT_VARIABLES),
+                "RenderingControl" => scpd(RENDERING_CONTROL_ACTIONS, RENDERING_CONTROL_VARIABLES),
+                _ => scpd(CONNECTION_MANAGER_ACTIONS, CONNECTION_MANAGER_VARIABLES),
+            };
+            write_response(&mut stream, "200 OK", &[XML], &xml).await?;
+        }
+        ("POST", "/control") => {
+            // SOAPACTION 请求头的格式为 "服务类型#动作"，动作以请求体中的为准
+            let soap = parse_soap(&req.body).filter(|_| {
+                req.header("SOAPACTION")
+                    .map_or(true, |x| x.trim_matches('"').starts_with(service_type))
+            });
+            let Some((action, args)) = soap else {
+                return Ok(write_response(
+                    &mut stream,
+                    "500 Internal Server Error",
+                    &[XML],
+                    &soap_fault(401, "Invalid Action"),
+                )
+                .await?);
+            };
+            match handle_action(&app, &device, &action, &args) {
+                Ok(args) => {
+                    write_response(
+                        &mut stream,
+                        "200 OK",
+                        &[XML],
+                        &soap_response(service_type, &action, &args),
+                    )
+                    .await?;
+                }
+                Err((code, description)) => {
+                    println!("DLNA 动作 {action} 执行失败: {code} {description}");
+                    write_response(
+                        &mut stream,
+                        "500 Internal Server Error",
+                        &[XML],
+                        &soap_fault(code, description),
+                    )
+                    .await?;
+                }
+            }
+        }
+        // 不支持事件通知，订阅总是成功但不会收到任何事件，控制点会改为轮询
+        ("SUBSCRIBE", "/event") => {
+            let sid = format!("uuid:{}-{service}", device.uuid);
+            write_response(
+                &mut stream,
+                "200 OK",
+                &[("SID", &sid), ("TIMEOUT", "Second-1800")],
+                "",
+            )
+            .await?;
+        }
+        ("UNSUBSCRIBE", "/event") => {
+            write_response(&mut stream, "200 OK", &[], "").await?;
+        }
+        _ => {
+            write_response(&mut stream, "405 Method Not Allowed", &[], "").await?;
+        }
+    }
+    Ok(())
+}
+
+pub async fn serve(listener: TcpListener, app: AppHandle, device: Arc<Device>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                // 文件描述符耗尽等错误通常是暂时的，稍后重试而不是关闭渲染器
+                println!("DLNA 连接接受失败: {err:?}");
+                async_std::task::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        let device = device.clone();
+        async_std::task::spawn(async move {
+            if let Err(err) = handle_conn(stream, app, device).await {
+                println!("DLNA 请求处理失败: {err:?}");
+            }
+        });
+    }
+}
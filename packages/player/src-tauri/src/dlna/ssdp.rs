@@ -0,0 +1,140 @@
+//! SSDP 设备发现
+//!
+//! 在多播地址 `239.255.255.250:1900` 上回应控制点的 `M-SEARCH` 搜索请求，
+//! 并定时广播 `ssdp:alive` 通知，关闭时广播 `ssdp:byebye` 使控制点移除设备。
+//! 设备描述的地址使用与对方通信时实际使用的网卡地址，因此在多网卡的设备上也能正确访问。
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use super::service::{DEVICE_TYPE, SERVICE_TYPES};
+
+const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+/// 控制点缓存设备信息的时间，单位为秒
+const MAX_AGE: u64 = 1800;
+/// 广播 `ssdp:alive` 通知的间隔，需要明显短于缓存时间
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(300);
+const SERVER: &str = concat!("AMLLPlayer/", env!("CARGO_PKG_VERSION"), " UPnP/1.0");
+
+/// 绑定 SSDP 端口并加入多播组，系统中的其它 UPnP 程序可能也在使用这个端口，因此需要允许端口复用
+pub fn bind() -> anyhow::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, SSDP_PORT)).into())?;
+    socket.join_multicast_v4(&MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_ttl_v4(2)?;
+    socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+    Ok(socket.into())
+}
+
+/// 与指定地址通信时使用的本机地址
+fn local_ip_for(peer: SocketAddr) -> Option<std::net::IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect(peer).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// 设备需要通告的所有类型以及对应的 USN
+fn notification_types(uuid: &str) -> Vec<(String, String)> {
+    let device = format!("uuid:{uuid}");
+    let mut result = vec![
+        (
+            "upnp:rootdevice".to_string(),
+            format!("{device}::upnp:rootdevice"),
+        ),
+        (device.clone(), device.clone()),
+        (DEVICE_TYPE.to_string(), format!("{device}::{DEVICE_TYPE}")),
+    ];
+    result.extend(
+        SERVICE_TYPES
+            .iter()
+            .map(|x| (x.to_string(), format!("{device}::{x}"))),
+    );
+    result
+}
+
+fn notify(socket: &UdpSocket, uuid: &str, port: u16, alive: bool) {
+    let target = SocketAddr::V4(SocketAddrV4::new(MULTICAST_ADDR, SSDP_PORT));
+    let Some(ip) = local_ip_for(target) else {
+        return;
+    };
+    for (nt, usn) in notification_types(uuid) {
+        let message = if alive {
+            format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: {MULTICAST_ADDR}:{SSDP_PORT}\r\nCACHE-CONTROL: max-age={MAX_AGE}\r\nLOCATION: http://{ip}:{port}/description.xml\r\nNT: {nt}\r\nNTS: ssdp:alive\r\nSERVER: {SERVER}\r\nUSN: {usn}\r\n\r\n"
+            )
+        } else {
+            format!(
+                "NOTIFY * HTTP/1.1\r\nHOST: {MULTICAST_ADDR}:{SSDP_PORT}\r\nNT: {nt}\r\nNTS: ssdp:byebye\r\nUSN: {usn}\r\n\r\n"
+            )
+        };
+        if let Err(err) = socket.send_to(message.as_bytes(), target) {
+            println!("SSDP 通知发送失败: {err:?}");
+            return;
+        }
+    }
+}
+
+/// 回应 `M-SEARCH` 请求，只回应搜索目标与设备匹配的请求
+fn respond(socket: &UdpSocket, request: &str, from: SocketAddr, uuid: &str, port: u16) {
+    let mut lines = request.lines();
+    if !lines
+        .next()
+        .is_some_and(|x| x.starts_with("M-SEARCH * HTTP/1.1"))
+    {
+        return;
+    }
+    let Some(st) = lines.find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("ST")
+            .then(|| value.trim().to_string())
+    }) else {
+        return;
+    };
+    let Some(ip) = local_ip_for(from) else {
+        return;
+    };
+    for (nt, usn) in notification_types(uuid) {
+        if st != "ssdp:all" && st != nt {
+            continue;
+        }
+        let message = format!(
+            "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={MAX_AGE}\r\nEXT:\r\nLOCATION: http://{ip}:{port}/description.xml\r\nSERVER: {SERVER}\r\nST: {nt}\r\nUSN: {usn}\r\n\r\n"
+        );
+        if let Err(err) = socket.send_to(message.as_bytes(), from) {
+            println!("SSDP 搜索回应发送失败: {err:?}");
+            return;
+        }
+    }
+}
+
+pub fn run(socket: UdpSocket, uuid: String, port: u16, stop: Arc<AtomicBool>) {
+    let mut last_notify: Option<Instant> = None;
+    let mut buf = [0u8; 2048];
+    while !stop.load(Ordering::Relaxed) {
+        if !matches!(last_notify, Some(x) if x.elapsed() < NOTIFY_INTERVAL) {
+            notify(&socket, &uuid, port, true);
+            last_notify = Some(Instant::now());
+        }
+        // 读取超时只是为了定时检查是否需要停止
+        if let Ok((len, from)) = socket.recv_from(&mut buf) {
+            respond(
+                &socket,
+                &String::from_utf8_lossy(&buf[..len]),
+                from,
+                &uuid,
+                port,
+            );
+        }
+    }
+    notify(&socket, &uuid, port, false);
+}
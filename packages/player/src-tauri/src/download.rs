@@ -0,0 +1,574 @@
+//! 远程歌曲下载
+//!
+//! 将远程音乐库和网络文件夹中的歌曲下载到本地文件夹，方便离线收听。
+//! 下载任务按照添加的顺序在后台线程上逐个进行，每次通过范围请求下载一段，
+//! 因此可以随时暂停，继续时从已经下载的位置接着下载，也可以限制下载速度。
+//! 下载中的数据先写入目标文件夹中的临时文件，完成后再改成正式的文件名，
+//! 并把歌曲信息、封面和歌词嵌入到文件的标签中。
+//!
+//! 下载进度通过 `on-download-progress` 事件发送给前端，内容为任务的最新状态。
+//! 下载队列只保存在内存中，退出播放器后没有完成的任务需要重新添加。
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use lofty::{FileType, Probe};
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use url::Url;
+
+use crate::{
+    library::{webdav, MusicLibrary},
+    metadata::is_audio_file,
+    remote_library::StreamOptions,
+    tag_writer::{write_tags, MusicMetadataChanges},
+};
+
+/// 每次范围请求的最大长度
+const CHUNK_SIZE: u64 = 512 * 1024;
+/// 限制速度时每次范围请求的最小长度，避免请求过于频繁
+const MIN_CHUNK_SIZE: u64 = 16 * 1024;
+const DEFAULT_FOLDER_NAME: &str = "AMLL Player";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DownloadConfig {
+    /// 保存下载歌曲的文件夹，为空时使用系统音乐文件夹中的 `AMLL Player` 文件夹
+    pub folder: Option<String>,
+    /// 最高下载速度，单位为 KB/s，为空时不限制
+    pub max_speed: Option<u64>,
+}
+
+/// 需要下载的远程歌曲
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RemoteSource {
+    /// 远程音乐库中的歌曲，不指定转码要求，由服务器决定下载的格式
+    #[serde(rename_all = "camelCase")]
+    RemoteLibrary { server_id: String, song_id: String },
+    /// 网络文件夹中的歌曲，只能下载已添加到音乐库的网络文件夹中的文件
+    NetworkFolder { url: String },
+    /// 其它可以直接下载的地址
+    Url { url: String },
+}
+
+/// 下载完成后嵌入到文件中的歌曲信息，为空的字段不会写入
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DownloadInfo {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub cover_url: Option<String>,
+    /// 歌词内容（LRC、TTML 等），会原样写入歌词标签
+    pub lyric: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DownloadState {
+    Queued,
+    Downloading,
+    Paused,
+    Completed,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DownloadTask {
+    pub id: u64,
+    pub source: RemoteSource,
+    pub info: DownloadInfo,
+    pub state: DownloadState,
+    /// 已经下载的字节数
+    pub downloaded: u64,
+    /// 文件的总长度，服务器没有返回时为空
+    pub total: Option<u64>,
+    /// 下载完成后的文件路径
+    pub file_path: Option<String>,
+    pub error: Option<String>,
+    /// 添加任务时的下载文件夹，修改设置后已添加的任务仍然下载到原来的文件夹
+    #[serde(skip)]
+    folder: Option<PathBuf>,
+}
+
+pub struct DownloadManager {
+    path: Option<PathBuf>,
+    config: DownloadConfig,
+    tasks: Vec<DownloadTask>,
+    next_id: u64,
+    /// 是否有正在运行的下载线程
+    running: bool,
+}
+
+impl DownloadManager {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let config = crate::config::load_json(path.as_deref(), "下载设置").unwrap_or_default();
+        Self {
+            path,
+            config,
+            tasks: Vec::new(),
+            next_id: 1,
+            running: false,
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.path.as_deref(), &self.config, "下载设置");
+    }
+
+    fn folder(&self) -> Option<PathBuf> {
+        match &self.config.folder {
+            Some(folder) => Some(PathBuf::from(folder)),
+            None => tauri::api::path::audio_dir().map(|x| x.join(DEFAULT_FOLDER_NAME)),
+        }
+    }
+
+    fn task_mut(&mut self, id: u64) -> Option<&mut DownloadTask> {
+        self.tasks.iter_mut().find(|x| x.id == id)
+    }
+
+    /// 有等待下载的任务并且没有正在运行的下载线程时启动下载线程
+    fn start(&mut self, app: &AppHandle) {
+        if self.running || !self.tasks.iter().any(|x| x.state == DownloadState::Queued) {
+            return;
+        }
+        self.running = true;
+        let app = app.clone();
+        std::thread::spawn(move || run(app));
+    }
+}
+
+/// 下载中的临时文件，以 `.` 开头使其在大多数系统上默认隐藏
+fn temp_path(folder: &Path, id: u64) -> PathBuf {
+    folder.join(format!(".amll-download-{id}.tmp"))
+}
+
+fn emit_progress(app: &AppHandle, task: &DownloadTask) {
+    if let Err(err) = app.emit_all("on-download-progress", task) {
+        println!("下载进度发送失败: {err:?}");
+    }
+}
+
+/// 修改任务的状态并发送给前端，任务已经被取消时返回 `None`
+fn update_task(
+    app: &AppHandle,
+    id: u64,
+    f: impl FnOnce(&mut DownloadTask),
+) -> Option<DownloadTask> {
+    let manager = app.state::<Mutex<DownloadManager>>();
+    let mut manager = manager.lock().unwrap();
+    let task = manager.task_mut(id)?;
+    f(task);
+    let task = task.clone();
+    drop(manager);
+    emit_progress(app, &task);
+    Some(task)
+}
+
+/// 下载线程，依次下载等待中的任务，没有任务时退出
+fn run(app: AppHandle) {
+    loop {
+        let next = {
+            let manager = app.state::<Mutex<DownloadManager>>();
+            let mut manager = manager.lock().unwrap();
+            let max_speed = manager.config.max_speed;
+            match manager
+                .tasks
+                .iter_mut()
+                .find(|x| x.state == DownloadState::Queued)
+            {
+                Some(task) => {
+                    task.state = DownloadState::Downloading;
+                    task.error = None;
+                    Some((task.clone(), max_speed))
+                }
+                None => {
+                    manager.running = false;
+                    None
+                }
+            }
+        };
+        let Some((task, max_speed)) = next else {
+            return;
+        };
+        emit_progress(&app, &task);
+        let result = match &task.folder {
+            Some(folder) => download(&app, &task, folder, max_speed),
+            None => Err(anyhow::anyhow!("没有找到保存下载歌曲的文件夹")),
+        };
+        match result {
+            Ok(Some(file_path)) => {
+                println!("歌曲已下载到 {}", file_path.display());
+            }
+            Ok(None) => {}
+            Err(err) => {
+                println!("歌曲下载失败: {err:?}");
+                update_task(&app, task.id, |task| {
+                    task.state = DownloadState::Failed;
+                    task.error = Some(err.to_string());
+                });
+            }
+        }
+    }
+}
+
+/// 获取远程歌曲的下载地址、身份验证请求头以及服务器上的文件名
+fn resolve_source(
+    app: &AppHandle,
+    source: &RemoteSource,
+) -> anyhow::Result<(String, Option<String>, Option<String>)> {
+    match source {
+        RemoteSource::RemoteLibrary { server_id, song_id } => {
+            let client =
+                tauri::async_runtime::block_on(crate::remote_library::client(app, server_id))?;
+            Ok((
+                client.stream_url(song_id, &StreamOptions::default()),
+                None,
+                None,
+            ))
+        }
+        RemoteSource::NetworkFolder { url } => {
+            let folders = app
+                .state::<Mutex<MusicLibrary>>()
+                .lock()
+                .unwrap()
+                .folders()?;
+            let Some(root) = folders
+                .iter()
+                .find(|x| webdav::is_remote_folder(x) && url.starts_with(x.as_str()))
+            else {
+                anyhow::bail!("{url} 不在已添加的网络文件夹中");
+            };
+            Ok((url.clone(), webdav::authorization(root), file_name_of(url)))
+        }
+        RemoteSource::Url { url } => Ok((url.clone(), None, file_name_of(url))),
+    }
+}
+
+/// 地址中的音乐文件名，地址不是音乐文件时为空
+fn file_name_of(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let name = url.path_segments()?.last()?;
+    let name = percent_decode_str(name).decode_utf8_lossy().into_owned();
+    is_audio_file(Path::new(&name)).then_some(name)
+}
+
+/// 替换文件名中不允许使用的字符
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    name.trim().trim_matches('.').to_string()
+}
+
+/// 根据文件内容判断音频格式对应的扩展名
+fn guess_extension(path: &Path) -> anyhow::Result<&'static str> {
+    let file_type = Probe::open(path)?.guess_file_type()?.file_type();
+    Ok(match file_type {
+        Some(FileType::Mpeg) => "mp3",
+        Some(FileType::Flac) => "flac",
+        Some(FileType::Mp4) => "m4a",
+        Some(FileType::Vorbis) => "ogg",
+        Some(FileType::Opus) => "opus",
+        Some(FileType::Wav) => "wav",
+        Some(FileType::Aiff) => "aiff",
+        Some(FileType::Ape) => "ape",
+        Some(FileType::WavPack) => "wv",
+        Some(FileType::Aac) => "aac",
+        _ => anyhow::bail!("无法识别下载的文件格式"),
+    })
+}
+
+/// 下载完成后的文件路径，优先使用服务器上的文件名，同名文件已经存在时在文件名后面加上序号
+fn target_path(
+    folder: &Path,
+    temp: &Path,
+    info: &DownloadInfo,
+    file_name: Option<String>,
+) -> anyhow::Result<PathBuf> {
+    let (stem, extension) = match file_name {
+        Some(name) => {
+            let name = Path::new(&name);
+            (
+                name.file_stem()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                name.extension()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            )
+        }
+        None => {
+            let stem = match (info.artist.trim(), info.title.trim()) {
+                ("", title) => title.to_string(),
+                (artist, title) => format!("{artist} - {title}"),
+            };
+            (stem, guess_extension(temp)?.to_string())
+        }
+    };
+    let stem = match sanitize_file_name(&stem) {
+        stem if stem.is_empty() => "未知歌曲".to_string(),
+        stem => stem,
+    };
+    let mut path = folder.join(format!("{stem}.{extension}"));
+    let mut index = 2;
+    while path.exists() {
+        path = folder.join(format!("{stem} ({index}).{extension}"));
+        index += 1;
+    }
+    Ok(path)
+}
+
+/// 下载任务对应的歌曲，完成时把任务标记为已完成并返回保存的文件路径，被暂停或者取消时返回 `None`
+fn download(
+    app: &AppHandle,
+    task: &DownloadTask,
+    folder: &Path,
+    max_speed: Option<u64>,
+) -> anyhow::Result<Option<PathBuf>> {
+    let (url, authorization, file_name) = resolve_source(app, &task.source)?;
+    std::fs::create_dir_all(folder)?;
+    let temp = temp_path(folder, task.id);
+    let mut file = OpenOptions::new().create(true).append(true).open(&temp)?;
+    // 以临时文件的实际长度为准，上次中断时最后一段可能没有写入
+    let mut offset = file.metadata()?.len();
+    let mut total = task.total;
+    let chunk_size = match max_speed {
+        Some(speed) => (speed * 1024).clamp(MIN_CHUNK_SIZE, CHUNK_SIZE),
+        None => CHUNK_SIZE,
+    };
+
+    while !total.is_some_and(|total| offset >= total) {
+        let started_at = Instant::now();
+        let range = format!("bytes={offset}-{}", offset + chunk_size - 1);
+        let mut headers = vec![("Range", range.as_str())];
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization.as_str()));
+        }
+        let response =
+            tauri::async_runtime::block_on(crate::http::send_raw("GET", &url, &headers, None))?;
+        let data = match response.status {
+            206 => {
+                total = response
+                    .headers
+                    .get("content-range")
+                    .and_then(|x| x.rsplit('/').next())
+                    .and_then(|x| x.parse().ok())
+                    .or(total);
+                response.data
+            }
+            // 服务器不支持范围请求时会返回整个文件，只需要写入还没有下载的部分
+            200 => {
+                total = Some(response.data.len() as u64);
+                response
+                    .data
+                    .get(offset as usize..)
+                    .unwrap_or_default()
+                    .to_vec()
+            }
+            // 已经下载到文件末尾
+            416 => Vec::new(),
+            401 | 403 => anyhow::bail!("下载 {url} 时被拒绝访问，请检查用户名和密码"),
+            status => anyhow::bail!("下载 {url} 失败，状态码为 {status}"),
+        };
+        if data.is_empty() {
+            total = Some(offset);
+            break;
+        }
+        file.write_all(&data)?;
+        offset += data.len() as u64;
+
+        let Some(task) = update_task(app, task.id, |task| {
+            task.downloaded = offset;
+            task.total = total;
+        }) else {
+            // 任务已经被取消
+            drop(file);
+            let _ = std::fs::remove_file(&temp);
+            return Ok(None);
+        };
+        if task.state == DownloadState::Paused {
+            return Ok(None);
+        }
+
+        if let Some(speed) = max_speed.filter(|x| *x > 0) {
+            let expected = Duration::from_secs_f64(data.len() as f64 / (speed * 1024) as f64);
+            if let Some(wait) = expected.checked_sub(started_at.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+    file.flush()?;
+    drop(file);
+
+    let path = target_path(folder, &temp, &task.info, file_name)?;
+    // 最后一段下载完成后任务仍然可能被取消，需要在持有锁时确认任务还在再移动文件，
+    // 移动后任务已经是完成状态，之后再取消只会从列表中移除
+    let completed = {
+        let manager = app.state::<Mutex<DownloadManager>>();
+        let mut manager = manager.lock().unwrap();
+        match manager.task_mut(task.id) {
+            Some(task) => {
+                std::fs::rename(&temp, &path)?;
+                task.state = DownloadState::Completed;
+                task.file_path = Some(path.to_string_lossy().into_owned());
+                Some(task.clone())
+            }
+            None => None,
+        }
+    };
+    let Some(completed) = completed else {
+        let _ = std::fs::remove_file(&temp);
+        return Ok(None);
+    };
+    // 标签写入失败时保留下载的文件，部分格式（例如 WAV）不支持嵌入歌词
+    if let Err(err) = embed_info(&path, &task.info) {
+        println!("下载的歌曲 {} 标签写入失败: {err:?}", path.display());
+    }
+    emit_progress(app, &completed);
+    Ok(Some(path))
+}
+
+/// 将歌曲信息、封面和歌词嵌入到下载的文件中
+fn embed_info(path: &Path, info: &DownloadInfo) -> anyhow::Result<()> {
+    let non_empty = |x: &str| (!x.trim().is_empty()).then(|| x.trim().to_string());
+    let cover = match info.cover_url.as_deref().filter(|x| !x.is_empty()) {
+        Some(url) => match tauri::async_runtime::block_on(crate::http::get_bytes(url)) {
+            Ok(data) => Some(BASE64.encode(data)),
+            Err(err) => {
+                println!("下载的歌曲封面获取失败: {err:?}");
+                None
+            }
+        },
+        None => None,
+    };
+    let changes = MusicMetadataChanges {
+        title: non_empty(&info.title),
+        artist: non_empty(&info.artist),
+        album: non_empty(&info.album),
+        lyric: info.lyric.as_deref().and_then(non_empty),
+        cover,
+    };
+    if changes.title.is_none()
+        && changes.artist.is_none()
+        && changes.album.is_none()
+        && changes.lyric.is_none()
+        && changes.cover.is_none()
+    {
+        return Ok(());
+    }
+    write_tags(path, &changes)
+}
+
+#[tauri::command]
+pub fn download_get_config(manager: State<Mutex<DownloadManager>>) -> DownloadConfig {
+    manager.lock().unwrap().config.clone()
+}
+
+/// 修改下载文件夹和最高下载速度，新的文件夹只对之后添加的任务生效，新的速度限制从下一首歌曲开始生效
+#[tauri::command]
+pub fn download_set_config(manager: State<Mutex<DownloadManager>>, config: DownloadConfig) {
+    let mut manager = manager.lock().unwrap();
+    manager.config = DownloadConfig {
+        folder: config
+            .folder
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty()),
+        max_speed: config.max_speed.filter(|x| *x > 0),
+    };
+    manager.save();
+}
+
+/// 添加下载任务，返回新任务的状态
+#[tauri::command]
+pub fn download_enqueue(
+    app: AppHandle,
+    manager: State<Mutex<DownloadManager>>,
+    source: RemoteSource,
+    info: Option<DownloadInfo>,
+) -> DownloadTask {
+    let mut manager = manager.lock().unwrap();
+    let task = DownloadTask {
+        id: manager.next_id,
+        source,
+        info: info.unwrap_or_default(),
+        state: DownloadState::Queued,
+        downloaded: 0,
+        total: None,
+        file_path: None,
+        error: None,
+        folder: manager.folder(),
+    };
+    manager.next_id += 1;
+    manager.tasks.push(task.clone());
+    manager.start(&app);
+    task
+}
+
+#[tauri::command]
+pub fn download_list(manager: State<Mutex<DownloadManager>>) -> Vec<DownloadTask> {
+    manager.lock().unwrap().tasks.clone()
+}
+
+/// 暂停下载任务，正在下载的任务会在当前这一段下载完成后暂停
+#[tauri::command]
+pub fn download_pause(app: AppHandle, manager: State<Mutex<DownloadManager>>, id: u64) {
+    let mut manager = manager.lock().unwrap();
+    let Some(task) = manager.task_mut(id) else {
+        return;
+    };
+    if matches!(
+        task.state,
+        DownloadState::Queued | DownloadState::Downloading
+    ) {
+        task.state = DownloadState::Paused;
+        emit_progress(&app, task);
+    }
+}
+
+/// 继续已暂停或者下载失败的任务，会从已经下载的位置接着下载
+#[tauri::command]
+pub fn download_resume(app: AppHandle, manager: State<Mutex<DownloadManager>>, id: u64) {
+    let mut manager = manager.lock().unwrap();
+    let Some(task) = manager.task_mut(id) else {
+        return;
+    };
+    if matches!(task.state, DownloadState::Paused | DownloadState::Failed) {
+        task.state = DownloadState::Queued;
+        task.error = None;
+        emit_progress(&app, task);
+    }
+    manager.start(&app);
+}
+
+/// 取消下载任务并删除已经下载的部分，已完成的任务只会从列表中移除
+#[tauri::command]
+pub fn download_cancel(manager: State<Mutex<DownloadManager>>, id: u64) {
+    let mut manager = manager.lock().unwrap();
+    let Some(index) = manager.tasks.iter().position(|x| x.id == id) else {
+        return;
+    };
+    let task = manager.tasks.remove(index);
+    // 正在下载的任务由下载线程在下一段下载完成后删除临时文件
+    if !matches!(
+        task.state,
+        DownloadState::Downloading | DownloadState::Completed
+    ) {
+        if let Some(folder) = &task.folder {
+            let _ = std::fs::remove_file(temp_path(folder, id));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! 结构化的错误信息
+//!
+//! 命令返回的错误和事件中的错误除了给人阅读的错误信息以外还会附带与语言无关的错误代码，
+//! 前端可以根据错误代码进行处理（例如提示文件不存在或者格式不受支持），而不需要解析中文的错误信息。
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use serde::Serialize;
+use symphonia::core::errors::Error as SymphoniaError;
+
+/// 与语言无关的错误代码
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// 文件或者文件夹不存在
+    FileNotFound,
+    /// 没有权限读取或者写入文件
+    PermissionDenied,
+    /// 文件的容器格式或者编码格式不受支持
+    UnsupportedFormat,
+    /// 文件已损坏，无法解码
+    DecodeFailed,
+    /// 网络请求超时
+    NetworkTimeout,
+    /// 网络连接失败或者服务器返回了错误
+    NetworkError,
+    /// 传入的参数不正确
+    InvalidInput,
+    Other,
+}
+
+impl From<std::io::ErrorKind> for ErrorCode {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::NotFound => Self::FileNotFound,
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            std::io::ErrorKind::TimedOut => Self::NetworkTimeout,
+            std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted => Self::NetworkError,
+            std::io::ErrorKind::InvalidInput => Self::InvalidInput,
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => {
+                Self::DecodeFailed
+            }
+            _ => Self::Other,
+        }
+    }
+}
+
+impl ErrorCode {
+    /// 根据错误链中第一个可以识别的错误推断错误代码
+    pub fn of(err: &anyhow::Error) -> Self {
+        for cause in err.chain() {
+            if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+                return err.kind().into();
+            }
+            if let Some(err) = cause.downcast_ref::<SymphoniaError>() {
+                return match err {
+                    SymphoniaError::IoError(err) => err.kind().into(),
+                    SymphoniaError::Unsupported(_) => Self::UnsupportedFormat,
+                    _ => Self::DecodeFailed,
+                };
+            }
+            if cause.downcast_ref::<tauri::api::Error>().is_some() {
+                return Self::NetworkError;
+            }
+        }
+        Self::Other
+    }
+}
+
+/// 命令返回的错误
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandError {
+    pub code: ErrorCode,
+    /// 给人阅读的错误信息
+    pub message: String,
+}
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(ErrorCode::of(&err), err.to_string())
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(err: std::io::Error) -> Self {
+        Self::new(err.kind().into(), err.to_string())
+    }
+}
+
+impl From<tauri::Error> for CommandError {
+    fn from(err: tauri::Error) -> Self {
+        Self::new(ErrorCode::Other, err.to_string())
+    }
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
+
+/// 后台处理信息时发生的内部错误，会通过 `on-internal-error` 事件发送给前端
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct InternalError {
+    /// 发生错误时正在处理的内容，例如信息的类型名称
+    pub context: String,
+    pub message: String,
+}
+
+/// 获取互斥锁，锁因为其它线程在持有时发生 panic 而中毒时仍然返回其中的数据
+///
+/// 用于在处理信息的模块发生 panic 之后继续运行的后台循环，这些循环不能因为锁中毒而退出
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// 从 panic 的内容中取出错误信息
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "未知错误".to_string()
+    }
+}
@@ -0,0 +1,187 @@
+//! 向 WebSocket 客户端转发频谱数据
+//!
+//! 播放源发送的音频数据会在后台线程上进行频谱分析，
+//! 结果以 [`Body::OnFFTData`] 的形式按固定的帧率广播给声明支持频谱数据的客户端，
+//! 电视、LED 点阵等外部的可视化设备不需要自己采集和分析音频就可以显示频谱。
+//! 频段数量和帧率都可以调整，以适应带宽和处理能力有限的设备。
+//! 没有客户端支持频谱数据时不会进行分析。
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use fft::{FFTConfig, FFTPlayer, FrequencyScale};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::server::AMLLWebSocketServer;
+
+/// 播放源发送的音频数据的采样率，为交错排列的双声道 16 位整数
+const AUDIO_SAMPLE_RATE: usize = 48000;
+const AUDIO_CHANNELS: usize = 2;
+/// 最多允许的频段数量
+const MAX_BANDS: usize = 1024;
+/// 最高允许的帧率
+const MAX_FPS: u32 = 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FFTForwardConfig {
+    /// 是否向客户端转发频谱数据
+    pub enabled: bool,
+    /// 每帧频谱的频段数量
+    pub bands: usize,
+    /// 每秒发送的频谱帧数
+    pub fps: u32,
+}
+
+impl Default for FFTForwardConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bands: 64,
+            fps: 30,
+        }
+    }
+}
+
+impl FFTForwardConfig {
+    fn normalized(mut self) -> Self {
+        self.bands = self.bands.clamp(1, MAX_BANDS);
+        self.fps = self.fps.clamp(1, MAX_FPS);
+        self
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(1) / self.fps
+    }
+}
+
+enum Update {
+    Config(FFTForwardConfig),
+    Audio(Vec<u8>),
+    Clear,
+}
+
+fn run(app: AppHandle, receiver: Receiver<Update>) {
+    let mut config = FFTForwardConfig::default();
+    let mut player = FFTPlayer::new();
+    let mut buf = Vec::new();
+    let mut last_sent = Instant::now();
+    loop {
+        let timeout = config.interval().saturating_sub(last_sent.elapsed());
+        match receiver.recv_timeout(timeout) {
+            Ok(Update::Config(new_config)) => {
+                config = new_config;
+                // 外部设备通常需要覆盖整个可听频率范围，并且低频的频段更重要
+                player.set_config(FFTConfig {
+                    bands: config.bands,
+                    min_freq: 40.0,
+                    max_freq: 16000.0,
+                    scale: FrequencyScale::Logarithmic,
+                    ..FFTConfig::default()
+                });
+                player.clear();
+                buf = vec![0.0; config.bands];
+            }
+            Ok(Update::Audio(data)) => {
+                let samples: Vec<i16> = data
+                    .chunks_exact(2)
+                    .map(|x| i16::from_le_bytes([x[0], x[1]]))
+                    .collect();
+                player.push_data(AUDIO_SAMPLE_RATE, AUDIO_CHANNELS, &samples);
+            }
+            Ok(Update::Clear) => player.clear(),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if !config.enabled || last_sent.elapsed() < config.interval() {
+            continue;
+        }
+        last_sent = Instant::now();
+
+        let ws = app.state::<Mutex<AMLLWebSocketServer>>();
+        let mut ws = ws.lock().unwrap();
+        if ws
+            .connections_with(ws_protocol::capabilities::SPECTRUM)
+            .is_empty()
+        {
+            player.clear();
+            continue;
+        }
+        if player.read(&mut buf) {
+            tauri::async_runtime::block_on(
+                ws.boardcast_message(Body::OnFFTData { data: buf.clone() }),
+            );
+        }
+    }
+}
+
+pub struct FFTForwarder {
+    path: Option<PathBuf>,
+    config: FFTForwardConfig,
+    sender: Sender<Update>,
+}
+
+impl FFTForwarder {
+    pub fn load(app: AppHandle, path: Option<PathBuf>) -> Self {
+        let config: FFTForwardConfig =
+            crate::config::load_json(path.as_deref(), "频谱转发设置").unwrap_or_default();
+        let config = config.normalized();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || run(app, receiver));
+        let _ = sender.send(Update::Config(config.clone()));
+        Self {
+            path,
+            config,
+            sender,
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.path.as_deref(), &self.config, "频谱转发设置");
+    }
+
+    pub fn config(&self) -> FFTForwardConfig {
+        self.config.clone()
+    }
+
+    pub fn set_config(&mut self, config: FFTForwardConfig) {
+        self.config = config.normalized();
+        self.save();
+        let _ = self.sender.send(Update::Config(self.config.clone()));
+    }
+
+    /// 将音频数据交给后台线程分析，切歌或者暂停时清空之前的数据
+    pub fn on_body(&self, body: &Body) {
+        if !self.config.enabled {
+            return;
+        }
+        match body {
+            Body::OnAudioData { data } => {
+                let _ = self.sender.send(Update::Audio(data.clone()));
+            }
+            Body::SetMusicId { .. } | Body::OnPaused => {
+                let _ = self.sender.send(Update::Clear);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 获取频谱转发的设置
+#[tauri::command]
+pub fn fft_forward_get_config(forwarder: State<Mutex<FFTForwarder>>) -> FFTForwardConfig {
+    forwarder.lock().unwrap().config()
+}
+
+/// 修改频谱转发的设置，包括是否开启、频段数量和帧率
+#[tauri::command]
+pub fn fft_forward_set_config(forwarder: State<Mutex<FFTForwarder>>, config: FFTForwardConfig) {
+    forwarder.lock().unwrap().set_config(config);
+}
@@ -0,0 +1,145 @@
+//! 音频指纹模块
+//!
+//! 解码音频文件开头最多 120 秒的内容并计算 Chromaprint 指纹，
+//! 可以通过 AcoustID 查询指纹对应的录音信息，用于识别没有标签的音乐文件。
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rusty_chromaprint::{Configuration, FingerprintCompressor, Fingerprinter};
+use serde::{Deserialize, Serialize};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+use tauri::api::http::{ClientBuilder, HttpRequestBuilder, ResponseType};
+
+use crate::error::CommandResult;
+
+/// 计算指纹时最多解码的时长，单位为秒，与 fpcalc 的默认值一致
+const MAX_FINGERPRINT_DURATION: u64 = 120;
+const ACOUSTID_LOOKUP_URL: &str = "https://api.acoustid.org/v2/lookup";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MusicFingerprint {
+    /// 压缩后的指纹，与 fpcalc 输出的格式相同，可以直接用于 AcoustID 查询
+    pub fingerprint: String,
+    /// 音频文件的完整时长，单位为秒
+    pub duration: f64,
+}
+
+pub fn fingerprint_file(path: &Path) -> anyhow::Result<MusicFingerprint> {
+    let mss = MediaSourceStream::new(crate::media_source::open_mapped(path)?, Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|x| x.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let track = crate::media_source::audio_track(probed.format.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("文件中没有音轨"))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let sample_rate = params
+        .sample_rate
+        .ok_or_else(|| anyhow::anyhow!("无法获取音频的采样率"))?;
+    let channels = params
+        .channels
+        .map(|x| x.count() as u32)
+        .ok_or_else(|| anyhow::anyhow!("无法获取音频的声道数"))?;
+    let mut decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default())?;
+
+    let config = Configuration::preset_test2();
+    let mut printer = Fingerprinter::new(&config);
+    printer.start(sample_rate, channels)?;
+
+    let max_frames = sample_rate as u64 * MAX_FINGERPRINT_DURATION;
+    let mut decoded_frames = 0u64;
+    let mut sample_buf: Option<SampleBuffer<i16>> = None;
+    while decoded_frames < max_frames {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // 损坏的数据包直接跳过
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let buf = sample_buf
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        buf.copy_interleaved_ref(decoded);
+        let frames =
+            (buf.samples().len() as u64 / channels as u64).min(max_frames - decoded_frames);
+        printer.consume(&buf.samples()[..(frames * channels as u64) as usize]);
+        decoded_frames += frames;
+    }
+    printer.finish();
+
+    let duration = match (params.n_frames, params.time_base) {
+        (Some(n_frames), Some(time_base)) => {
+            let time = time_base.calc_time(n_frames);
+            time.seconds as f64 + time.frac
+        }
+        _ => decoded_frames as f64 / sample_rate as f64,
+    };
+    let compressed = FingerprintCompressor::from(&config).compress(printer.fingerprint());
+    Ok(MusicFingerprint {
+        fingerprint: URL_SAFE_NO_PAD.encode(compressed),
+        duration,
+    })
+}
+
+#[tauri::command]
+pub async fn fingerprint_music_file(path: PathBuf) -> CommandResult<MusicFingerprint> {
+    Ok(tauri::async_runtime::spawn_blocking(move || fingerprint_file(&path)).await??)
+}
+
+/// 使用 AcoustID 查询指纹对应的录音信息，返回 AcoustID 接口结果中的 `results` 字段
+pub async fn lookup_acoustid(
+    client_key: &str,
+    fingerprint: &MusicFingerprint,
+) -> anyhow::Result<serde_json::Value> {
+    let client = ClientBuilder::new().build()?;
+    let query = HashMap::from([
+        ("client".to_string(), client_key.to_string()),
+        ("meta".to_string(), "recordings releasegroups".to_string()),
+        (
+            "duration".to_string(),
+            (fingerprint.duration.round() as u64).to_string(),
+        ),
+        ("fingerprint".to_string(), fingerprint.fingerprint.clone()),
+    ]);
+    let request = HttpRequestBuilder::new("GET", ACOUSTID_LOOKUP_URL)?
+        .query(query)
+        .response_type(ResponseType::Json);
+    let response = client.send(request).await?.read().await?;
+    if response.data["status"] != "ok" {
+        anyhow::bail!("AcoustID 查询失败: {}", response.data["error"]["message"]);
+    }
+    Ok(response.data["results"].clone())
+}
+
+#[tauri::command]
+pub async fn acoustid_lookup(
+    client_key: String,
+    fingerprint: MusicFingerprint,
+) -> CommandResult<serde_json::Value> {
+    Ok(lookup_acoustid(&client_key, &fingerprint).await?)
+}
@@ -0,0 +1,80 @@
+//! 支持的文件格式
+//!
+//! 列出可以读取的文件扩展名和实际注册到 Symphonia 中的解码器，
+//! 前端可以据此过滤文件选择对话框，并在文件无法播放时给出准确的提示。
+use serde::Serialize;
+use symphonia::core::codecs::{self, CodecType};
+
+use crate::{metadata, playlist};
+
+/// 需要检查的编码格式，Symphonia 没有提供遍历已注册解码器的方法，只能逐个查询
+const KNOWN_CODECS: &[CodecType] = &[
+    codecs::CODEC_TYPE_PCM_S16LE,
+    codecs::CODEC_TYPE_PCM_ALAW,
+    codecs::CODEC_TYPE_PCM_MULAW,
+    codecs::CODEC_TYPE_ADPCM_MS,
+    codecs::CODEC_TYPE_ADPCM_IMA_WAV,
+    codecs::CODEC_TYPE_VORBIS,
+    codecs::CODEC_TYPE_MP1,
+    codecs::CODEC_TYPE_MP2,
+    codecs::CODEC_TYPE_MP3,
+    codecs::CODEC_TYPE_AAC,
+    codecs::CODEC_TYPE_OPUS,
+    codecs::CODEC_TYPE_SPEEX,
+    codecs::CODEC_TYPE_MUSEPACK,
+    codecs::CODEC_TYPE_EAC3,
+    codecs::CODEC_TYPE_AC4,
+    codecs::CODEC_TYPE_DCA,
+    codecs::CODEC_TYPE_WMA,
+    codecs::CODEC_TYPE_FLAC,
+    codecs::CODEC_TYPE_WAVPACK,
+    codecs::CODEC_TYPE_MONKEYS_AUDIO,
+    codecs::CODEC_TYPE_ALAC,
+    codecs::CODEC_TYPE_TTA,
+];
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CodecInfo {
+    /// 编码格式的简短名称，例如 `flac`
+    pub name: String,
+    /// 编码格式的完整名称
+    pub long_name: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SupportedFormats {
+    pub audio_extensions: Vec<String>,
+    /// 只读取其中音轨的视频文件扩展名
+    pub video_extensions: Vec<String>,
+    pub playlist_extensions: Vec<String>,
+    /// 可以解码的编码格式
+    pub codecs: Vec<CodecInfo>,
+}
+
+fn to_strings(x: &[&str]) -> Vec<String> {
+    x.iter().map(|x| x.to_string()).collect()
+}
+
+/// 获取支持的文件扩展名和可以解码的编码格式
+#[tauri::command]
+pub fn get_supported_formats() -> SupportedFormats {
+    let registry = symphonia::default::get_codecs();
+    let mut codecs: Vec<CodecInfo> = Vec::new();
+    for descriptor in KNOWN_CODECS.iter().filter_map(|x| registry.get_codec(*x)) {
+        // 不同的编码格式可能由同一个解码器处理
+        if codecs.iter().all(|x| x.name != descriptor.short_name) {
+            codecs.push(CodecInfo {
+                name: descriptor.short_name.to_string(),
+                long_name: descriptor.long_name.to_string(),
+            });
+        }
+    }
+    SupportedFormats {
+        audio_extensions: to_strings(metadata::AUDIO_EXTENSIONS),
+        video_extensions: to_strings(metadata::VIDEO_EXTENSIONS),
+        playlist_extensions: to_strings(playlist::PLAYLIST_EXTENSIONS),
+        codecs,
+    }
+}
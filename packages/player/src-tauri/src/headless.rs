@@ -0,0 +1,61 @@
+//! 无窗口模式
+//!
+//! 使用 `--headless` 参数启动时不会显示主窗口和托盘图标，音乐库、歌词同步以及 WebSocket 和 HTTP 服务器照常运行，
+//! 完全通过 WebSocket 或者 HTTP 接口控制，适合用树莓派等设备驱动外部的歌词显示屏。
+//!
+//! 平时由前端开启的 WebSocket 服务器在此模式下会在启动时直接开启，
+//! 地址可以通过 `--ws-addr=<地址>` 指定，HTTP 服务器只有传入 `--http-addr=<地址>` 时才会开启。
+//!
+//! 注意 Tauri 仍然需要图形环境进行初始化，没有显示器的设备上可以使用 Xvfb 等虚拟显示服务器。
+use std::sync::Mutex;
+
+use tauri::{App, Manager};
+
+use crate::{http_server::HttpServer, server::AMLLWebSocketServer, ws_mdns::MdnsService};
+
+const HEADLESS_FLAG: &str = "--headless";
+const WS_ADDR_FLAG: &str = "--ws-addr=";
+const HTTP_ADDR_FLAG: &str = "--http-addr=";
+/// 与前端默认开启的地址保持一致
+const DEFAULT_WS_ADDR: &str = "localhost:11444";
+
+fn arg_value(prefix: &str) -> Option<String> {
+    std::env::args()
+        .skip(1)
+        .find_map(|x| x.strip_prefix(prefix).map(|x| x.to_string()))
+        .filter(|x| !x.trim().is_empty())
+}
+
+/// 是否以无窗口模式启动
+pub fn is_headless() -> bool {
+    std::env::args_os().skip(1).any(|x| x == HEADLESS_FLAG)
+}
+
+/// 关闭主窗口并开启服务器，需要在所有状态都交给 Tauri 管理之后调用
+pub fn start(app: &App) {
+    if let Some(window) = app.get_window("main") {
+        if let Err(err) = window.close() {
+            println!("主窗口关闭失败: {err:?}");
+        }
+    }
+    let ws_addr = arg_value(WS_ADDR_FLAG).unwrap_or_else(|| DEFAULT_WS_ADDR.to_string());
+    let ws = app.state::<Mutex<AMLLWebSocketServer>>();
+    let mut ws = ws.lock().unwrap();
+    ws.reopen(ws_addr.clone());
+    crate::advertise_server(
+        &ws,
+        &mut app.state::<Mutex<MdnsService>>().lock().unwrap(),
+        &[ws_addr.clone()],
+    );
+    let http_addr = arg_value(HTTP_ADDR_FLAG);
+    if let Some(http_addr) = &http_addr {
+        app.state::<Mutex<HttpServer>>()
+            .lock()
+            .unwrap()
+            .reopen(http_addr.clone());
+    }
+    println!(
+        "已以无窗口模式启动，WebSocket 服务器地址 {ws_addr}，HTTP 服务器地址 {}",
+        http_addr.as_deref().unwrap_or("未开启")
+    );
+}
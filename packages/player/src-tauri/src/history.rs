@@ -0,0 +1,185 @@
+//! 播放历史记录模块
+//!
+//! 根据 WebSocket 客户端发送过来的播放信息统计每首歌曲的播放情况，
+//! 并将其持久化保存到应用数据文件夹中，用于实现“最近播放”和“最常播放”等功能。
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+use ws_protocol::Body;
+
+/// 最多保留的历史记录条数，超出时会丢弃最旧的记录
+const MAX_HISTORY_ENTRIES: usize = 10000;
+/// 两次播放进度之间的差距超过该值时视为跳转，不计入播放时长，单位为毫秒
+const MAX_PROGRESS_STEP: f64 = 5000.0;
+/// 播放进度达到总时长的该比例时视为完整播放
+const COMPLETED_RATIO: f64 = 0.9;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub music_id: String,
+    pub music_name: String,
+    /// 开始播放的时间，为 UNIX 时间戳，单位为毫秒
+    pub started_at: u64,
+    /// 实际播放的时长，单位为毫秒
+    pub played_duration: u64,
+    /// 是否完整播放，否则视为被跳过
+    pub completed: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayCount {
+    pub music_id: String,
+    pub music_name: String,
+    pub play_count: usize,
+    pub skip_count: usize,
+    pub last_played_at: u64,
+}
+
+struct CurrentPlay {
+    entry: HistoryEntry,
+    duration: u64,
+    last_progress: Option<f64>,
+}
+
+pub struct PlayHistory {
+    path: Option<PathBuf>,
+    entries: Vec<HistoryEntry>,
+    current: Option<CurrentPlay>,
+}
+
+/// 当前的 UNIX 时间戳，单位为毫秒
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl PlayHistory {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let entries =
+            crate::config::load_json(path.as_deref(), "播放历史记录文件").unwrap_or_default();
+        Self {
+            path,
+            entries,
+            current: None,
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.path.as_deref(), &self.entries, "播放历史记录");
+    }
+
+    /// 结束当前正在记录的播放，并将其写入历史记录中
+    pub fn finish_current(&mut self) {
+        if let Some(current) = self.current.take() {
+            let mut entry = current.entry;
+            let progress = current.last_progress.unwrap_or_default();
+            entry.completed = current.duration > 0
+                && (entry.played_duration as f64 >= current.duration as f64 * COMPLETED_RATIO
+                    || progress >= current.duration as f64 * COMPLETED_RATIO);
+            self.entries.push(entry);
+            if self.entries.len() > MAX_HISTORY_ENTRIES {
+                let overflow = self.entries.len() - MAX_HISTORY_ENTRIES;
+                self.entries.drain(..overflow);
+            }
+            self.save();
+        }
+    }
+
+    pub fn on_body(&mut self, body: &Body) {
+        match body {
+            Body::SetMusicId { id, name, duration } => {
+                let id = id.to_string();
+                if let Some(current) = &self.current {
+                    if current.entry.music_id == id {
+                        return;
+                    }
+                }
+                self.finish_current();
+                if id.is_empty() {
+                    return;
+                }
+                self.current = Some(CurrentPlay {
+                    entry: HistoryEntry {
+                        music_id: id,
+                        music_name: name.to_string(),
+                        started_at: now_millis(),
+                        played_duration: 0,
+                        completed: false,
+                    },
+                    duration: *duration,
+                    last_progress: None,
+                });
+            }
+            Body::OnPlayProgress { progress } => {
+                if let Some(current) = &mut self.current {
+                    if let Some(last_progress) = current.last_progress {
+                        let step = progress - last_progress;
+                        if step > 0.0 && step < MAX_PROGRESS_STEP {
+                            current.entry.played_duration += step as u64;
+                        }
+                    }
+                    current.last_progress = Some(*progress);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 获取最近的播放记录，按时间从新到旧排列
+    pub fn recent(&self, limit: usize) -> Vec<HistoryEntry> {
+        self.entries.iter().rev().take(limit).cloned().collect()
+    }
+
+    /// 获取每首歌曲的播放次数，按播放次数从多到少排列
+    pub fn play_counts(&self, limit: usize) -> Vec<PlayCount> {
+        let mut counts: HashMap<&str, PlayCount> = HashMap::new();
+        for entry in &self.entries {
+            let count = counts
+                .entry(entry.music_id.as_str())
+                .or_insert_with(|| PlayCount {
+                    music_id: entry.music_id.clone(),
+                    music_name: entry.music_name.clone(),
+                    play_count: 0,
+                    skip_count: 0,
+                    last_played_at: 0,
+                });
+            if entry.completed {
+                count.play_count += 1;
+            } else {
+                count.skip_count += 1;
+            }
+            if entry.started_at >= count.last_played_at {
+                count.last_played_at = entry.started_at;
+                count.music_name = entry.music_name.clone();
+            }
+        }
+        let mut counts: Vec<_> = counts.into_values().collect();
+        counts.sort_by(|a, b| {
+            b.play_count
+                .cmp(&a.play_count)
+                .then(b.last_played_at.cmp(&a.last_played_at))
+        });
+        counts.truncate(limit);
+        counts
+    }
+}
+
+#[tauri::command]
+pub fn get_play_history(history: State<Mutex<PlayHistory>>, limit: usize) -> Vec<HistoryEntry> {
+    history.lock().unwrap().recent(limit)
+}
+
+#[tauri::command]
+pub fn get_play_counts(history: State<Mutex<PlayHistory>>, limit: usize) -> Vec<PlayCount> {
+    history.lock().unwrap().play_counts(limit)
+}
@@ -0,0 +1,205 @@
+//! 全局快捷键
+//!
+//! 在后端注册系统范围的快捷键，窗口最小化或者失去焦点时也可以控制播放。
+//! 播放控制类的快捷键会被转换成对应的控制指令广播给播放源，
+//! 快捷键的绑定会持久化保存到应用数据文件夹中，并可以在运行时重新绑定。
+use std::{collections::HashMap, path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, GlobalShortcutManager, Manager, State};
+use ws_protocol::Body;
+
+use crate::{error::CommandResult, now_playing::NowPlaying};
+
+/// 每次调整音量的幅度
+const VOLUME_STEP: f64 = 0.05;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum HotkeyAction {
+    PlayPause,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    ToggleWindow,
+    ToggleDesktopLyrics,
+}
+
+impl HotkeyAction {
+    const ALL: [Self; 7] = [
+        Self::PlayPause,
+        Self::Next,
+        Self::Previous,
+        Self::VolumeUp,
+        Self::VolumeDown,
+        Self::ToggleWindow,
+        Self::ToggleDesktopLyrics,
+    ];
+
+    fn default_accelerator(self) -> &'static str {
+        match self {
+            Self::PlayPause => "CmdOrCtrl+Alt+P",
+            Self::Next => "CmdOrCtrl+Alt+Right",
+            Self::Previous => "CmdOrCtrl+Alt+Left",
+            Self::VolumeUp => "CmdOrCtrl+Alt+Up",
+            Self::VolumeDown => "CmdOrCtrl+Alt+Down",
+            Self::ToggleWindow => "CmdOrCtrl+Alt+M",
+            Self::ToggleDesktopLyrics => "CmdOrCtrl+Alt+D",
+        }
+    }
+}
+
+/// 快捷键注册失败时的信息，通常是因为快捷键已经被其它应用占用
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HotkeyError {
+    pub action: HotkeyAction,
+    pub accelerator: String,
+    pub message: String,
+}
+
+/// 执行操作，托盘菜单等其它入口也会使用
+pub(crate) fn perform(app: &AppHandle, action: HotkeyAction) {
+    let status = || app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+    match action {
+        HotkeyAction::PlayPause => {
+            let body = if status().paused {
+                Body::Resume
+            } else {
+                Body::Pause
+            };
+            crate::send_control(app, body);
+        }
+        HotkeyAction::Next => crate::send_control(app, Body::ForwardSong),
+        HotkeyAction::Previous => crate::send_control(app, Body::BackwardSong),
+        HotkeyAction::VolumeUp | HotkeyAction::VolumeDown => {
+            let step = if action == HotkeyAction::VolumeUp {
+                VOLUME_STEP
+            } else {
+                -VOLUME_STEP
+            };
+            let volume = (status().volume.unwrap_or(1.0) + step).clamp(0.0, 1.0);
+            crate::send_control(app, Body::SetVolume { volume });
+        }
+        HotkeyAction::ToggleWindow => {
+            if let Some(window) = app.get_window("main") {
+                let result = match window.is_visible() {
+                    Ok(true) => window.hide(),
+                    _ => window.show().and_then(|_| window.set_focus()),
+                };
+                if let Err(err) = result {
+                    println!("窗口显示状态切换失败: {err:?}");
+                }
+            }
+        }
+        // 桌面歌词由前端负责显示，这里只通知前端
+        HotkeyAction::ToggleDesktopLyrics => {
+            let _ = app.emit_all("toggle-desktop-lyrics", ());
+        }
+    }
+}
+
+/// 执行快捷键对应的操作并通知前端
+fn trigger(app: &AppHandle, action: HotkeyAction) {
+    perform(app, action);
+    let _ = app.emit_all("on-hotkey", action);
+}
+
+pub struct Hotkeys {
+    app: AppHandle,
+    path: Option<PathBuf>,
+    /// 各个操作绑定的快捷键，没有绑定的操作不会出现在这里
+    bindings: HashMap<HotkeyAction, String>,
+}
+
+impl Hotkeys {
+    /// 读取保存的快捷键绑定，没有保存过时使用默认的绑定
+    pub fn load(app: AppHandle, path: Option<PathBuf>) -> Self {
+        let bindings =
+            crate::config::load_json(path.as_deref(), "快捷键配置").unwrap_or_else(|| {
+                HotkeyAction::ALL
+                    .into_iter()
+                    .map(|x| (x, x.default_accelerator().to_string()))
+                    .collect()
+            });
+        Self {
+            app,
+            path,
+            bindings,
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.path.as_deref(), &self.bindings, "快捷键配置");
+    }
+
+    pub fn bindings(&self) -> HashMap<HotkeyAction, String> {
+        self.bindings.clone()
+    }
+
+    /// 重新注册所有快捷键，返回注册失败的快捷键
+    pub fn register_all(&self) -> Vec<HotkeyError> {
+        let mut manager = self.app.global_shortcut_manager();
+        if let Err(err) = manager.unregister_all() {
+            println!("全局快捷键注销失败: {err:?}");
+        }
+        let mut errors = Vec::new();
+        for (&action, accelerator) in &self.bindings {
+            let app = self.app.clone();
+            if let Err(err) = manager.register(accelerator, move || trigger(&app, action)) {
+                println!("全局快捷键 {accelerator} 注册失败: {err:?}");
+                errors.push(HotkeyError {
+                    action,
+                    accelerator: accelerator.clone(),
+                    message: err.to_string(),
+                });
+            }
+        }
+        errors
+    }
+
+    /// 修改操作绑定的快捷键，传入空值时取消绑定，快捷键已经绑定到其它操作时返回错误
+    pub fn bind(
+        &mut self,
+        action: HotkeyAction,
+        accelerator: Option<String>,
+    ) -> anyhow::Result<Vec<HotkeyError>> {
+        let accelerator = accelerator
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty());
+        match accelerator {
+            Some(accelerator) => {
+                let used = self
+                    .bindings
+                    .iter()
+                    .find(|(x, y)| **x != action && y.eq_ignore_ascii_case(&accelerator));
+                if let Some((other, _)) = used {
+                    anyhow::bail!("快捷键 {accelerator} 已经绑定到 {other:?}");
+                }
+                self.bindings.insert(action, accelerator);
+            }
+            None => {
+                self.bindings.remove(&action);
+            }
+        }
+        self.save();
+        Ok(self.register_all())
+    }
+}
+
+/// 获取所有快捷键绑定
+#[tauri::command]
+pub fn hotkeys_get(hotkeys: State<Mutex<Hotkeys>>) -> HashMap<HotkeyAction, String> {
+    hotkeys.lock().unwrap().bindings()
+}
+
+/// 修改操作绑定的快捷键，传入空值时取消绑定，返回注册失败的快捷键
+#[tauri::command]
+pub fn hotkeys_bind(
+    hotkeys: State<Mutex<Hotkeys>>,
+    action: HotkeyAction,
+    accelerator: Option<String>,
+) -> CommandResult<Vec<HotkeyError>> {
+    Ok(hotkeys.lock().unwrap().bind(action, accelerator)?)
+}
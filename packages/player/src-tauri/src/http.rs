@@ -0,0 +1,196 @@
+//! 网络请求的辅助函数
+//!
+//! 封装 Tauri 自带的 HTTP 客户端，供封面、歌词等在线数据的获取模块使用。
+use std::{collections::HashMap, time::Duration};
+
+use serde_json::Value;
+use tauri::api::http::{Body, ClientBuilder, FormBody, FormPart, HttpRequestBuilder, ResponseType};
+
+/// 请求超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const USER_AGENT: &str = concat!("AMLLPlayer/", env!("CARGO_PKG_VERSION"));
+/// 最多跟随的跳转次数
+const MAX_REDIRECTIONS: usize = 5;
+
+fn build_request(
+    url: &str,
+    query: &[(&str, &str)],
+    headers: &[(&str, &str)],
+    response_type: ResponseType,
+) -> anyhow::Result<HttpRequestBuilder> {
+    let mut header_map = HashMap::from([("User-Agent".to_string(), USER_AGENT.to_string())]);
+    header_map.extend(headers.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let mut request = HttpRequestBuilder::new("GET", url)?
+        .headers(header_map)
+        .timeout(REQUEST_TIMEOUT)
+        .response_type(response_type);
+    if !query.is_empty() {
+        request = request.query(
+            query
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        );
+    }
+    Ok(request)
+}
+
+/// 发送 GET 请求并将结果解析为 JSON
+pub async fn get_json(url: &str, query: &[(&str, &str)]) -> anyhow::Result<Value> {
+    get_json_with_headers(url, query, &[]).await
+}
+
+/// 发送带有额外请求头的 GET 请求并将结果解析为 JSON
+pub async fn get_json_with_headers(
+    url: &str,
+    query: &[(&str, &str)],
+    headers: &[(&str, &str)],
+) -> anyhow::Result<Value> {
+    let client = ClientBuilder::new()
+        .max_redirections(MAX_REDIRECTIONS)
+        .build()?;
+    let response = client
+        .send(build_request(url, query, headers, ResponseType::Json)?)
+        .await?
+        .read()
+        .await?;
+    if !(200..300).contains(&response.status) {
+        anyhow::bail!("请求 {url} 失败，状态码为 {}", response.status);
+    }
+    Ok(response.data)
+}
+
+/// 发送 GET 请求并获取原始数据
+pub async fn get_bytes(url: &str) -> anyhow::Result<Vec<u8>> {
+    let client = ClientBuilder::new()
+        .max_redirections(MAX_REDIRECTIONS)
+        .build()?;
+    let response = client
+        .send(build_request(url, &[], &[], ResponseType::Binary)?)
+        .await?
+        .bytes()
+        .await?;
+    if !(200..300).contains(&response.status) {
+        anyhow::bail!("请求 {url} 失败，状态码为 {}", response.status);
+    }
+    Ok(response.data)
+}
+
+/// 发送 GET 请求并获取原始数据，由调用方检查每一次跳转的地址，用于限制插件可以访问的主机
+///
+/// 响应内容超过 `max_size` 字节时返回错误，声明了 `Content-Length` 的响应在下载前就会被拒绝，
+/// Tauri 的客户端无法分块读取响应，没有声明长度的响应只能在下载后检查
+pub async fn get_bytes_checked(
+    url: &str,
+    max_size: usize,
+    check: impl Fn(&url::Url) -> anyhow::Result<()>,
+) -> anyhow::Result<Vec<u8>> {
+    // 关闭自动跳转，否则只有第一个地址会被检查
+    let client = ClientBuilder::new().max_redirections(0).build()?;
+    let mut url = url::Url::parse(url)?;
+    for _ in 0..=MAX_REDIRECTIONS {
+        check(&url)?;
+        let response = client
+            .send(build_request(url.as_str(), &[], &[], ResponseType::Binary)?)
+            .await?;
+        let location = response
+            .headers()
+            .get("location")
+            .and_then(|x| x.to_str().ok())
+            .map(str::to_string);
+        let content_length = response
+            .headers()
+            .get("content-length")
+            .and_then(|x| x.to_str().ok()?.parse::<u64>().ok());
+        if let Some(len) = content_length.filter(|&x| x > max_size as u64) {
+            anyhow::bail!("请求 {url} 的响应过大: {len} 字节");
+        }
+        let response = response.bytes().await?;
+        match (response.status, location) {
+            (200..=299, _) if response.data.len() > max_size => {
+                anyhow::bail!("请求 {url} 的响应过大: {} 字节", response.data.len())
+            }
+            (200..=299, _) => return Ok(response.data),
+            (300..=399, Some(location)) => url = url.join(&location)?,
+            (status, _) => anyhow::bail!("请求 {url} 失败，状态码为 {status}"),
+        }
+    }
+    anyhow::bail!("请求 {url} 的跳转次数过多")
+}
+
+/// 发送 POST 请求并将结果解析为 JSON，请求体为表单或者 JSON
+async fn post(url: &str, body: Body, headers: &[(&str, &str)]) -> anyhow::Result<Value> {
+    let client = ClientBuilder::new()
+        .max_redirections(MAX_REDIRECTIONS)
+        .build()?;
+    let mut header_map = HashMap::from([("User-Agent".to_string(), USER_AGENT.to_string())]);
+    header_map.extend(headers.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let request = HttpRequestBuilder::new("POST", url)?
+        .headers(header_map)
+        .timeout(REQUEST_TIMEOUT)
+        .response_type(ResponseType::Json)
+        .body(body);
+    let response = client.send(request).await?.read().await?;
+    if !(200..300).contains(&response.status) {
+        anyhow::bail!(
+            "请求 {url} 失败，状态码为 {}: {}",
+            response.status,
+            response.data
+        );
+    }
+    Ok(response.data)
+}
+
+/// 以 `application/x-www-form-urlencoded` 表单的形式发送 POST 请求并将结果解析为 JSON
+pub async fn post_form(url: &str, form: &[(&str, &str)]) -> anyhow::Result<Value> {
+    let form = form
+        .iter()
+        .map(|(k, v)| (k.to_string(), FormPart::Text(v.to_string())))
+        .collect();
+    post(url, Body::Form(FormBody::new(form)), &[]).await
+}
+
+/// 发送请求体为 JSON 的 POST 请求并将结果解析为 JSON
+pub async fn post_json(url: &str, body: Value, headers: &[(&str, &str)]) -> anyhow::Result<Value> {
+    post(url, Body::Json(body), headers).await
+}
+
+/// 不检查状态码的原始响应，响应头的名称均为小写
+pub struct RawResponse {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub data: Vec<u8>,
+}
+
+/// 以任意方法发送请求并返回原始响应，用于 WebDAV 等需要自行处理状态码和响应头的场景
+pub async fn send_raw(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Option<String>,
+) -> anyhow::Result<RawResponse> {
+    let client = ClientBuilder::new()
+        .max_redirections(MAX_REDIRECTIONS)
+        .build()?;
+    let mut header_map = HashMap::from([("User-Agent".to_string(), USER_AGENT.to_string())]);
+    header_map.extend(headers.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+    let mut request = HttpRequestBuilder::new(method, url)?
+        .headers(header_map)
+        .timeout(REQUEST_TIMEOUT)
+        .response_type(ResponseType::Binary);
+    if let Some(body) = body {
+        request = request.body(Body::Text(body));
+    }
+    let response = client.send(request).await?;
+    let headers = response
+        .headers()
+        .iter()
+        .filter_map(|(k, v)| Some((k.as_str().to_lowercase(), v.to_str().ok()?.to_string())))
+        .collect();
+    let response = response.bytes().await?;
+    Ok(RawResponse {
+        status: response.status,
+        headers,
+        data: response.data,
+    })
+}
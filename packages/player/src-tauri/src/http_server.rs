@@ -0,0 +1,510 @@
+//! HTTP 控制接口
+//!
+//! 与 WebSocket 服务器一同提供的简易 HTTP 服务器，脚本、Stream Deck 插件和浏览器等
+//! 无需实现二进制的 WebSocket 协议即可获取播放状态和控制播放：
+//!
+//! - `GET /status`：当前播放的歌曲和进度
+//! - `GET /playlist`：最近播放的歌曲，可以通过 `limit` 参数限制数量
+//! - `POST /control/{play,pause,next,prev}`：控制播放
+//! - `POST /control/seek?position=毫秒`：跳转播放进度
+//! - `GET /events`：以 Server-Sent Events 的形式推送从 WebSocket 客户端接收到的信息和歌词行的变化
+//! - `GET /overlay`：可以直接添加为 OBS 浏览器源的歌词叠加层页面
+//! - `GET /lyric`、`GET /cover`：当前歌曲的歌词和专辑封面图片
+//!
+//! 播放器本身不播放音乐，控制指令会广播给所有 WebSocket 客户端，由实际播放音乐的客户端执行。
+//! WebSocket 服务器设置了令牌时，HTTP 请求同样需要通过 `token` 参数或者 `X-AMLL-Token` 请求头提供令牌。
+//!
+//! 为了避免其它网页借用户的浏览器控制播放，控制指令只接受 POST 请求，
+//! 响应中不包含允许跨域的请求头，并且会拒绝来自其它网页的请求。
+//! 其它接口只允许通过 [`http_set_allowed_origins`] 设置的网页跨域读取，
+//! `Host` 请求头不是本机或者服务器监听的地址的请求都会被拒绝，以防止 DNS 重绑定。
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_std::channel::Sender;
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task::{block_on, JoinHandle};
+use futures::prelude::*;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    history::PlayHistory, now_playing::NowPlaying, server::AMLLWebSocketServer, ws_auth::WsAuth,
+};
+
+/// 歌词叠加层页面
+const OVERLAY_PAGE: &str = include_str!("overlay.html");
+/// 请求头的最大大小
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// 等待客户端发送完整请求头的最长时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// `/playlist` 默认返回的歌曲数量
+const DEFAULT_PLAYLIST_LIMIT: usize = 50;
+/// 每个 SSE 连接最多缓存的事件数量，超过后新的事件会被丢弃
+const SSE_QUEUE_SIZE: usize = 256;
+
+type Subscribers = Arc<Mutex<Vec<Sender<String>>>>;
+type AllowedOrigins = Arc<Mutex<Vec<String>>>;
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn param(&self, key: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 请求是否来自本服务器的页面，脚本等非浏览器客户端通常不会发送 `Origin` 请求头
+    fn is_same_origin(&self) -> bool {
+        let Some(origin) = self.header("Origin") else {
+            return true;
+        };
+        let host = self.header("Host").unwrap_or_default();
+        origin
+            .strip_prefix("http://")
+            .is_some_and(|x| !host.is_empty() && x.eq_ignore_ascii_case(host))
+    }
+
+    /// `Host` 请求头是否为本机或者接受连接的地址，DNS 重绑定的请求会带有攻击者的域名
+    fn is_allowed_host(&self, local: IpAddr) -> bool {
+        let Some(host) = self.header("Host") else {
+            return false;
+        };
+        let host = match host.strip_prefix('[') {
+            Some(host) => host.split(']').next().unwrap_or_default(),
+            None => host.rsplit_once(':').map_or(host, |(host, _)| host),
+        };
+        let local = match local {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(local, IpAddr::V4),
+            ip => ip,
+        };
+        host.eq_ignore_ascii_case("localhost")
+            || host
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback() || ip == local)
+    }
+
+    /// 允许跨域读取响应的来源，只有同源或者在允许列表中的 `Origin` 会被返回
+    fn cors_origin(&self, allowed: &[String]) -> Option<String> {
+        let origin = self.header("Origin")?;
+        let permitted = self.is_same_origin()
+            || allowed
+                .iter()
+                .any(|x| x.trim_end_matches('/').eq_ignore_ascii_case(origin));
+        permitted.then(|| origin.to_string())
+    }
+}
+
+/// 解析请求行和请求头，不支持请求体
+fn parse_request(head: &str) -> Option<Request> {
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_ascii_uppercase();
+    let target = request_line.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = url::form_urlencoded::parse(query.as_bytes())
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    let headers = lines
+        .filter_map(|x| x.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect();
+    Some(Request {
+        method,
+        path: path.trim_end_matches('/').to_string(),
+        query,
+        headers,
+    })
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+    /// 允许跨域读取响应的来源，为空时不允许其它网页读取
+    allow_origin: Option<String>,
+}
+
+impl Response {
+    fn json<T: Serialize>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self {
+                status: 200,
+                content_type: "application/json; charset=utf-8",
+                body,
+                allow_origin: None,
+            },
+            Err(err) => Self::text(500, &err.to_string()),
+        }
+    }
+
+    fn bytes(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+            allow_origin: None,
+        }
+    }
+
+    fn text(status: u16, text: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: text.as_bytes().to_vec(),
+            allow_origin: None,
+        }
+    }
+
+    /// 允许指定的来源跨域读取此响应
+    fn allow_origin(mut self, origin: Option<String>) -> Self {
+        self.allow_origin = origin;
+        self
+    }
+
+    async fn write(self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len(),
+            cors_headers(self.allow_origin.as_deref())
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&self.body).await?;
+        stream.flush().await
+    }
+}
+
+/// 允许跨域读取的响应头，响应内容随 `Origin` 变化，因此需要同时返回 `Vary`
+fn cors_headers(origin: Option<&str>) -> String {
+    match origin {
+        Some(origin) => format!("Access-Control-Allow-Origin: {origin}\r\nVary: Origin\r\n"),
+        None => String::new(),
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
+
+/// 根据文件头判断图片的类型
+fn image_type(data: &[u8]) -> &'static str {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        "image/jpeg"
+    } else if data.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if data.starts_with(b"GIF8") {
+        "image/gif"
+    } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// 读取完整的请求头，超过大小上限时停止读取，连接在读取完成前被关闭时返回空值
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<Vec<u8>>> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|x| x == b"\r\n\r\n") && head.len() <= MAX_REQUEST_SIZE {
+        let len = stream.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(None);
+        }
+        head.extend_from_slice(&buf[..len]);
+    }
+    Ok(Some(head))
+}
+
+fn sse_event<T: Serialize>(event: &str, data: &T) -> Option<String> {
+    let data = serde_json::to_string(data).ok()?;
+    Some(format!("event: {event}\ndata: {data}\n\n"))
+}
+
+pub struct HttpServer {
+    app: AppHandle,
+    auth: Arc<WsAuth>,
+    handle: Option<JoinHandle<()>>,
+    subscribers: Subscribers,
+    allowed_origins: AllowedOrigins,
+}
+
+impl HttpServer {
+    pub fn new(app: AppHandle, auth: Arc<WsAuth>) -> Self {
+        Self {
+            app,
+            auth,
+            handle: None,
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            allowed_origins: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// 设置允许跨域读取播放状态的网页来源，例如 `https://example.com`
+    pub fn set_allowed_origins(&self, origins: Vec<String>) {
+        *self.allowed_origins.lock().unwrap() = origins;
+    }
+
+    /// 重新开启 HTTP 服务器，地址为空时关闭服务器
+    pub fn reopen(&mut self, addr: String) {
+        block_on(async {
+            if let Some(task) = self.handle.take() {
+                task.cancel().await;
+            }
+        });
+        self.subscribers.lock().unwrap().clear();
+        if addr.trim().is_empty() {
+            return;
+        }
+        let app = self.app.clone();
+        let auth = self.auth.clone();
+        let subscribers = self.subscribers.clone();
+        let allowed_origins = self.allowed_origins.clone();
+        self.handle = Some(async_std::task::spawn(async move {
+            let listener = match TcpListener::bind(addr.trim()).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    println!("HTTP 服务器 {addr} 开启失败: {err:?}");
+                    return;
+                }
+            };
+            println!("已开启 HTTP 服务器到 {addr}");
+            while let Ok((stream, _)) = listener.accept().await {
+                let app = app.clone();
+                let auth = auth.clone();
+                let subscribers = subscribers.clone();
+                let allowed_origins = allowed_origins.clone();
+                async_std::task::spawn(async move {
+                    if let Err(err) =
+                        Self::handle_conn(stream, app, auth, subscribers, allowed_origins).await
+                    {
+                        println!("HTTP 请求处理失败: {err:?}");
+                    }
+                });
+            }
+        }));
+    }
+
+    /// 向所有 SSE 连接推送事件
+    pub fn publish<T: Serialize>(&self, event: &str, data: &T) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|x| !x.is_closed());
+        if subscribers.is_empty() {
+            return;
+        }
+        if let Some(event) = sse_event(event, data) {
+            for subscriber in subscribers.iter() {
+                let _ = subscriber.try_send(event.clone());
+            }
+        }
+    }
+
+    /// 推送从 WebSocket 客户端接收到的信息，专辑封面图片和音频数据体积较大，
+    /// 图片数据只会以 `cover` 事件通知其发生了变化，音频数据则不会推送
+    pub fn publish_body(&self, body: &ws_protocol::Body) {
+        match body {
+            ws_protocol::Body::SetMusicAlbumCoverImageData { .. } => self.publish("cover", &()),
+            ws_protocol::Body::OnAudioData { .. } => {}
+            body => self.publish("body", body),
+        }
+    }
+
+    async fn handle_conn(
+        mut stream: TcpStream,
+        app: AppHandle,
+        auth: Arc<WsAuth>,
+        subscribers: Subscribers,
+        allowed_origins: AllowedOrigins,
+    ) -> anyhow::Result<()> {
+        let head = match async_std::future::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+            Ok(head) => head?,
+            Err(_) => return Ok(()),
+        };
+        let Some(head) = head else {
+            return Ok(());
+        };
+        if head.len() > MAX_REQUEST_SIZE {
+            return Ok(Response::text(400, "请求头过大").write(&mut stream).await?);
+        }
+        let Some(req) = parse_request(&String::from_utf8_lossy(&head)) else {
+            return Ok(Response::text(400, "请求格式错误")
+                .write(&mut stream)
+                .await?);
+        };
+        if !req.is_allowed_host(stream.local_addr()?.ip()) {
+            return Ok(Response::text(403, "不允许的 Host")
+                .write(&mut stream)
+                .await?);
+        }
+        let ip = stream.peer_addr()?.ip();
+        if auth.locked_out(ip).is_some() {
+            return Ok(Response::text(429, "错误次数过多，请稍后再试")
+                .write(&mut stream)
+                .await?);
+        }
+        let token = req.param("token").or_else(|| req.header("X-AMLL-Token"));
+        let Some(role) = auth.verify(ip, token) else {
+            return Ok(Response::text(401, "令牌错误").write(&mut stream).await?);
+        };
+        if req.path.starts_with("/control/") {
+            let res = if !role.can_control() {
+                Response::text(403, "只读令牌不能控制播放")
+            } else if !req.is_same_origin() {
+                Response::text(403, "不允许其它网页控制播放")
+            } else {
+                Self::route(&app, &req).await
+            };
+            return Ok(res.write(&mut stream).await?);
+        }
+        let origin = match req.path.as_str() {
+            // OBS 浏览器源从同一来源加载叠加层页面，不需要允许跨域
+            "/overlay" | "/lyric" => None,
+            _ => req.cors_origin(&allowed_origins.lock().unwrap()),
+        };
+        if req.path == "/events" {
+            return Self::handle_events(stream, &app, &subscribers, origin).await;
+        }
+        Self::route(&app, &req)
+            .await
+            .allow_origin(origin)
+            .write(&mut stream)
+            .await?;
+        Ok(())
+    }
+
+    async fn route(app: &AppHandle, req: &Request) -> Response {
+        match (req.method.as_str(), req.path.as_str()) {
+            ("GET", "/status") => {
+                let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+                Response::json(&status)
+            }
+            ("GET", "/overlay") => {
+                Response::bytes("text/html; charset=utf-8", OVERLAY_PAGE.as_bytes().to_vec())
+            }
+            ("GET", "/lyric") => {
+                let lyric = app
+                    .state::<Mutex<NowPlaying>>()
+                    .lock()
+                    .unwrap()
+                    .lyric()
+                    .to_vec();
+                Response::json(&lyric)
+            }
+            ("GET", "/cover") => {
+                let cover = app
+                    .state::<Mutex<NowPlaying>>()
+                    .lock()
+                    .unwrap()
+                    .cover_data()
+                    .map(<[u8]>::to_vec);
+                match cover {
+                    Some(cover) => Response::bytes(image_type(&cover), cover),
+                    None => Response::text(404, "没有专辑封面"),
+                }
+            }
+            ("GET", "/playlist") => {
+                let limit = req
+                    .param("limit")
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(DEFAULT_PLAYLIST_LIMIT);
+                let recent = app
+                    .state::<Mutex<PlayHistory>>()
+                    .lock()
+                    .unwrap()
+                    .recent(limit);
+                Response::json(&recent)
+            }
+            ("POST", path) if path.starts_with("/control/") => {
+                let body = match &path["/control/".len()..] {
+                    "play" => ws_protocol::Body::Resume,
+                    "pause" => ws_protocol::Body::Pause,
+                    "next" => ws_protocol::Body::ForwardSong,
+                    "prev" => ws_protocol::Body::BackwardSong,
+                    "seek" => match req.param("position").and_then(|x| x.parse().ok()) {
+                        Some(progress) => ws_protocol::Body::SetPlayProgress { progress },
+                        None => return Response::text(400, "缺少 position 参数"),
+                    },
+                    _ => return Response::text(404, "未知的控制指令"),
+                };
+                let app = app.clone();
+                async_std::task::spawn_blocking(move || {
+                    let ws = app.state::<Mutex<AMLLWebSocketServer>>();
+                    block_on(ws.lock().unwrap().boardcast_message(body));
+                })
+                .await;
+                Response::json(&serde_json::json!({ "ok": true }))
+            }
+            (_, "/status" | "/playlist" | "/overlay" | "/lyric" | "/cover") => {
+                Response::text(405, "不支持的请求方法")
+            }
+            (_, path) if path.starts_with("/control/") => {
+                Response::text(405, "控制指令只支持 POST 请求")
+            }
+            _ => Response::text(404, "未找到"),
+        }
+    }
+
+    /// 保持连接并以 Server-Sent Events 的形式推送事件，连接后会先推送一次当前的播放状态
+    async fn handle_events(
+        mut stream: TcpStream,
+        app: &AppHandle,
+        subscribers: &Subscribers,
+        origin: Option<String>,
+    ) -> anyhow::Result<()> {
+        let (sender, receiver) = async_std::channel::bounded(SSE_QUEUE_SIZE);
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n{}Connection: keep-alive\r\n\r\n",
+            cors_headers(origin.as_deref())
+        );
+        stream.write_all(head.as_bytes()).await?;
+        let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+        if let Some(event) = sse_event("status", &status) {
+            stream.write_all(event.as_bytes()).await?;
+        }
+        stream.flush().await?;
+        subscribers.lock().unwrap().push(sender);
+        while let Ok(event) = receiver.recv().await {
+            stream.write_all(event.as_bytes()).await?;
+            stream.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+/// 重新开启 HTTP 控制接口，地址为空时关闭
+#[tauri::command]
+pub fn http_reopen_server(http: State<Mutex<HttpServer>>, addr: String) {
+    http.lock().unwrap().reopen(addr);
+}
+
+/// 设置允许跨域读取播放状态的网页来源，控制指令不受影响，始终不允许跨域
+#[tauri::command]
+pub fn http_set_allowed_origins(http: State<Mutex<HttpServer>>, origins: Vec<String>) {
+    http.lock().unwrap().set_allowed_origins(origins);
+}
@@ -0,0 +1,87 @@
+//! 音乐文件夹浏览
+//!
+//! 列出文件夹内的子文件夹和音乐文件，已被索引的音乐文件会直接附带音乐库中的元数据，
+//! 未被索引的文件只返回文件名，避免在浏览时读取文件。
+use std::{cmp::Ordering, path::Path};
+
+use serde::Serialize;
+
+use super::{LibraryTrack, MusicLibrary};
+use crate::metadata::is_audio_file;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BrowseFolder {
+    pub path: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BrowseFile {
+    pub file_path: String,
+    pub name: String,
+    /// 音乐库中该文件的索引信息，未被索引时为空
+    pub track: Option<LibraryTrack>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderListing {
+    pub path: String,
+    pub parent: Option<String>,
+    pub folders: Vec<BrowseFolder>,
+    pub files: Vec<BrowseFile>,
+}
+
+fn compare_name(a: &str, b: &str) -> Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+impl MusicLibrary {
+    pub fn browse_folder(&self, path: &Path) -> anyhow::Result<FolderListing> {
+        let mut folders = Vec::new();
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            let entry_path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            // 符号链接需要跟随到实际的目标上判断类型
+            let is_dir = if file_type.is_symlink() {
+                entry_path.is_dir()
+            } else {
+                file_type.is_dir()
+            };
+            if is_dir {
+                folders.push(BrowseFolder {
+                    path: entry_path.to_string_lossy().into_owned(),
+                    name,
+                });
+            } else if is_audio_file(&entry_path) {
+                let file_path = entry_path.to_string_lossy().into_owned();
+                files.push(BrowseFile {
+                    track: self.track_by_path(&file_path)?,
+                    file_path,
+                    name,
+                });
+            }
+        }
+        folders.sort_by(|a, b| compare_name(&a.name, &b.name));
+        files.sort_by(|a, b| compare_name(&a.name, &b.name));
+
+        Ok(FolderListing {
+            path: path.to_string_lossy().into_owned(),
+            parent: path.parent().map(|x| x.to_string_lossy().into_owned()),
+            folders,
+            files,
+        })
+    }
+}
@@ -0,0 +1,602 @@
+//! 本地音乐库模块
+//!
+//! 扫描用户配置的音乐文件夹，将歌曲的元数据索引到 SQLite 数据库中，
+//! 使得前端无需在每次启动时重新扫描所有音乐文件。
+//! 音乐文件夹会被持续监听，其中的文件变化会增量更新到索引中。
+//! 歌曲的标题、艺术家、专辑和歌词会建立 FTS5 全文索引以供搜索。
+//! 智能播放列表的规则也保存在同一个数据库中。
+//! 音乐文件夹也可以是 WebDAV 网络文件夹（详见 [`webdav`] 模块），网络文件夹不会被监听，只在手动扫描时更新。
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::{cover::CoverCache, error::CommandResult, history::PlayHistory, metadata::parse_year};
+
+mod browse;
+mod scanner;
+mod search;
+mod smart;
+mod watcher;
+pub mod webdav;
+
+pub use browse::FolderListing;
+pub use scanner::ScanSummary;
+use scanner::ScannedTrack;
+pub use smart::{SmartPlaylist, SmartPlaylistRules};
+pub use watcher::LibraryWatcher;
+
+/// 数据库结构的迁移语句，每一项对应一个版本，版本号保存在 `user_version` 中
+const MIGRATIONS: &[&str] = &[
+    r#"
+    CREATE TABLE folders (
+        path TEXT PRIMARY KEY NOT NULL
+    );
+    CREATE TABLE tracks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        file_path TEXT NOT NULL UNIQUE,
+        title TEXT NOT NULL DEFAULT '',
+        artist TEXT NOT NULL DEFAULT '',
+        album TEXT NOT NULL DEFAULT '',
+        duration REAL NOT NULL DEFAULT 0,
+        cover_hash TEXT NOT NULL DEFAULT '',
+        file_size INTEGER NOT NULL DEFAULT 0,
+        modified_at INTEGER NOT NULL DEFAULT 0,
+        added_at INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX tracks_artist ON tracks (artist);
+    CREATE INDEX tracks_album ON tracks (album);
+"#,
+    r#"
+    ALTER TABLE tracks ADD COLUMN lyric TEXT NOT NULL DEFAULT '';
+    -- 旧版本的索引中没有歌词，清空修改时间使下次扫描时重新读取
+    UPDATE tracks SET modified_at = 0;
+    CREATE VIRTUAL TABLE tracks_fts USING fts5 (
+        title, artist, album, lyric,
+        content = 'tracks', content_rowid = 'id',
+        tokenize = 'trigram'
+    );
+    CREATE TRIGGER tracks_fts_insert AFTER INSERT ON tracks BEGIN
+        INSERT INTO tracks_fts (rowid, title, artist, album, lyric)
+        VALUES (new.id, new.title, new.artist, new.album, new.lyric);
+    END;
+    CREATE TRIGGER tracks_fts_delete AFTER DELETE ON tracks BEGIN
+        INSERT INTO tracks_fts (tracks_fts, rowid, title, artist, album, lyric)
+        VALUES ('delete', old.id, old.title, old.artist, old.album, old.lyric);
+    END;
+    CREATE TRIGGER tracks_fts_update AFTER UPDATE ON tracks BEGIN
+        INSERT INTO tracks_fts (tracks_fts, rowid, title, artist, album, lyric)
+        VALUES ('delete', old.id, old.title, old.artist, old.album, old.lyric);
+        INSERT INTO tracks_fts (rowid, title, artist, album, lyric)
+        VALUES (new.id, new.title, new.artist, new.album, new.lyric);
+    END;
+    INSERT INTO tracks_fts (tracks_fts) VALUES ('rebuild');
+"#,
+    r#"
+    CREATE TABLE smart_playlists (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        rules TEXT NOT NULL
+    );
+"#,
+    r#"
+    ALTER TABLE tracks ADD COLUMN genre TEXT NOT NULL DEFAULT '';
+    ALTER TABLE tracks ADD COLUMN year INTEGER;
+    -- 旧版本的索引中没有流派和年份，清空修改时间使下次扫描时重新读取
+    UPDATE tracks SET modified_at = 0;
+    CREATE INDEX tracks_genre ON tracks (genre);
+"#,
+];
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryTrack {
+    pub id: i64,
+    pub file_path: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// 时长，单位为秒
+    pub duration: f64,
+    /// 封面图片数据的 SHA-256 哈希值，可以通过 `amll-cover` 协议获取图片，没有封面时为空字符串
+    pub cover_hash: String,
+    pub file_size: u64,
+    /// 文件的修改时间，为 UNIX 时间戳，单位为秒
+    pub modified_at: i64,
+    pub added_at: i64,
+    pub genre: String,
+    /// 从标签的日期中解析出来的年份
+    pub year: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryAlbum {
+    pub name: String,
+    pub artist: String,
+    pub track_count: usize,
+    pub cover_hash: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryArtist {
+    pub name: String,
+    pub track_count: usize,
+    pub album_count: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryPage<T> {
+    pub total: usize,
+    pub items: Vec<T>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum LibrarySortBy {
+    #[default]
+    Title,
+    Artist,
+    Album,
+    Duration,
+    AddedAt,
+}
+
+impl LibrarySortBy {
+    fn order_clause(self) -> &'static str {
+        match self {
+            Self::Title => "title COLLATE NOCASE, id",
+            Self::Artist => "artist COLLATE NOCASE, album COLLATE NOCASE, id",
+            Self::Album => "album COLLATE NOCASE, id",
+            Self::Duration => "duration, id",
+            Self::AddedAt => "added_at DESC, id DESC",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LibraryQuery {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub sort_by: LibrarySortBy,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+const TRACK_COLUMNS: &str = "id, file_path, title, artist, album, duration, cover_hash, file_size, modified_at, added_at, genre, year";
+
+fn track_from_row(row: &rusqlite::Row) -> rusqlite::Result<LibraryTrack> {
+    Ok(LibraryTrack {
+        id: row.get(0)?,
+        file_path: row.get(1)?,
+        title: row.get(2)?,
+        artist: row.get(3)?,
+        album: row.get(4)?,
+        duration: row.get(5)?,
+        cover_hash: row.get(6)?,
+        file_size: row.get::<_, i64>(7)? as u64,
+        modified_at: row.get(8)?,
+        added_at: row.get(9)?,
+        genre: row.get(10)?,
+        year: row.get(11)?,
+    })
+}
+
+pub struct MusicLibrary {
+    conn: Connection,
+}
+
+impl MusicLibrary {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> anyhow::Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i as i64 + 1)?;
+            tx.commit()?;
+        }
+        Ok(Self { conn })
+    }
+
+    pub fn folders(&self) -> anyhow::Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path FROM folders ORDER BY path")?;
+        let folders = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(folders)
+    }
+
+    pub fn add_folder(&self, path: &str) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO folders (path) VALUES (?1)",
+            params![path],
+        )?;
+        Ok(())
+    }
+
+    /// 移除音乐文件夹，同时移除该文件夹下已索引的歌曲
+    pub fn remove_folder(&self, path: &str) -> anyhow::Result<()> {
+        let folder = Path::new(path);
+        let is_remote = webdav::is_remote_folder(path);
+        let removed: Vec<String> = self
+            .track_mtimes()?
+            .into_iter()
+            .map(|(file_path, _)| file_path)
+            .filter(|file_path| {
+                if is_remote {
+                    file_path.starts_with(path)
+                } else {
+                    Path::new(file_path).starts_with(folder)
+                }
+            })
+            .collect();
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM folders WHERE path = ?1", params![path])?;
+        for file_path in removed {
+            tx.execute(
+                "DELETE FROM tracks WHERE file_path = ?1",
+                params![file_path],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 获取所有已索引歌曲的路径和修改时间，用于增量扫描
+    pub fn track_mtimes(&self) -> anyhow::Result<Vec<(String, i64)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT file_path, modified_at FROM tracks")?;
+        let result = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(result)
+    }
+
+    /// 将扫描结果写入数据库，并移除已经不存在的歌曲
+    pub(crate) fn apply_changes(
+        &self,
+        scanned: &[ScannedTrack],
+        removed: &[String],
+    ) -> anyhow::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_secs() as i64)
+            .unwrap_or_default();
+        let tx = self.conn.unchecked_transaction()?;
+        {
+            let mut upsert = tx.prepare(
+                "INSERT INTO tracks
+                (file_path, title, artist, album, lyric, duration, cover_hash, file_size, modified_at, added_at, genre, year)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                ON CONFLICT (file_path) DO UPDATE SET
+                title = excluded.title,
+                artist = excluded.artist,
+                album = excluded.album,
+                lyric = excluded.lyric,
+                duration = excluded.duration,
+                cover_hash = excluded.cover_hash,
+                file_size = excluded.file_size,
+                modified_at = excluded.modified_at,
+                genre = excluded.genre,
+                year = excluded.year",
+            )?;
+            for track in scanned {
+                upsert.execute(params![
+                    track.file_path,
+                    track.metadata.name,
+                    track.metadata.artist,
+                    track.metadata.album,
+                    track.metadata.lyric,
+                    track.metadata.duration,
+                    track.cover_hash,
+                    track.file_size as i64,
+                    track.modified_at,
+                    now,
+                    track.metadata.genre,
+                    parse_year(&track.metadata.date),
+                ])?;
+            }
+            let mut delete = tx.prepare("DELETE FROM tracks WHERE file_path = ?1")?;
+            for file_path in removed {
+                delete.execute(params![file_path])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn track_by_path(&self, file_path: &str) -> anyhow::Result<Option<LibraryTrack>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {TRACK_COLUMNS} FROM tracks WHERE file_path = ?1"),
+                params![file_path],
+                track_from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn query_tracks(&self, query: &LibraryQuery) -> anyhow::Result<LibraryPage<LibraryTrack>> {
+        let limit = query.limit.map(|x| x as i64).unwrap_or(-1);
+        let offset = query.offset as i64;
+        let mut conditions = vec!["1"];
+        let mut args: Vec<&dyn rusqlite::ToSql> = vec![];
+        if let Some(artist) = &query.artist {
+            conditions.push("artist = ?");
+            args.push(artist);
+        }
+        if let Some(album) = &query.album {
+            conditions.push("album = ?");
+            args.push(album);
+        }
+        let condition = conditions.join(" AND ");
+
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM tracks WHERE {condition}"),
+            args.as_slice(),
+            |row| row.get(0),
+        )?;
+
+        args.push(&limit);
+        args.push(&offset);
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {TRACK_COLUMNS} FROM tracks WHERE {condition} ORDER BY {} LIMIT ? OFFSET ?",
+            query.sort_by.order_clause()
+        ))?;
+        let items = stmt
+            .query_map(args.as_slice(), track_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(LibraryPage {
+            total: total as usize,
+            items,
+        })
+    }
+
+    pub fn albums(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> anyhow::Result<LibraryPage<LibraryAlbum>> {
+        let total: i64 =
+            self.conn
+                .query_row("SELECT COUNT(DISTINCT album) FROM tracks", [], |row| {
+                    row.get(0)
+                })?;
+        let mut stmt = self.conn.prepare(
+            "SELECT album, MAX(artist), COUNT(*), MAX(cover_hash) FROM tracks
+            GROUP BY album ORDER BY album COLLATE NOCASE LIMIT ?1 OFFSET ?2",
+        )?;
+        let items = stmt
+            .query_map(
+                params![limit.map(|x| x as i64).unwrap_or(-1), offset as i64],
+                |row| {
+                    Ok(LibraryAlbum {
+                        name: row.get(0)?,
+                        artist: row.get(1)?,
+                        track_count: row.get::<_, i64>(2)? as usize,
+                        cover_hash: row.get(3)?,
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?;
+        Ok(LibraryPage {
+            total: total as usize,
+            items,
+        })
+    }
+
+    pub fn artists(
+        &self,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> anyhow::Result<LibraryPage<LibraryArtist>> {
+        let total: i64 =
+            self.conn
+                .query_row("SELECT COUNT(DISTINCT artist) FROM tracks", [], |row| {
+                    row.get(0)
+                })?;
+        let mut stmt = self.conn.prepare(
+            "SELECT artist, COUNT(*), COUNT(DISTINCT album) FROM tracks
+            GROUP BY artist ORDER BY artist COLLATE NOCASE LIMIT ?1 OFFSET ?2",
+        )?;
+        let items = stmt
+            .query_map(
+                params![limit.map(|x| x as i64).unwrap_or(-1), offset as i64],
+                |row| {
+                    Ok(LibraryArtist {
+                        name: row.get(0)?,
+                        track_count: row.get::<_, i64>(1)? as usize,
+                        album_count: row.get::<_, i64>(2)? as usize,
+                    })
+                },
+            )?
+            .collect::<Result<_, _>>()?;
+        Ok(LibraryPage {
+            total: total as usize,
+            items,
+        })
+    }
+}
+
+#[tauri::command]
+pub fn library_get_folders(library: State<Mutex<MusicLibrary>>) -> CommandResult<Vec<String>> {
+    Ok(library.lock().unwrap().folders()?)
+}
+
+#[tauri::command]
+pub fn library_add_folder(
+    library: State<Mutex<MusicLibrary>>,
+    watcher: State<Mutex<LibraryWatcher>>,
+    path: String,
+) -> CommandResult<()> {
+    let library = library.lock().unwrap();
+    library.add_folder(&path)?;
+    let folders = library.folders()?;
+    watcher.lock().unwrap().sync_folders(&folders);
+    Ok(())
+}
+
+/// 添加 WebDAV 网络文件夹，会先使用用户名和密码列出一次文件夹，可以访问时才会保存，
+/// 返回统一格式后的文件夹地址，之后移除文件夹时需要使用这个地址
+#[tauri::command]
+pub async fn library_add_network_folder(
+    library: State<'_, Mutex<MusicLibrary>>,
+    url: String,
+    username: Option<String>,
+    password: Option<String>,
+) -> CommandResult<String> {
+    let url = webdav::normalize_folder_url(&url)?;
+    let username = username.filter(|x| !x.trim().is_empty());
+    let folder = match &username {
+        Some(username) => webdav::WebDavFolder::with_credentials(
+            &url,
+            username.trim(),
+            password.as_deref().unwrap_or_default(),
+        ),
+        None => webdav::WebDavFolder::open(&url),
+    };
+    tauri::async_runtime::spawn_blocking(move || folder.check()).await??;
+    if let Some(username) = &username {
+        webdav::save_credentials(&url, username.trim(), &password.unwrap_or_default())?;
+    }
+    library.lock().unwrap().add_folder(&url)?;
+    println!("已添加网络文件夹 {url}");
+    Ok(url)
+}
+
+#[tauri::command]
+pub fn library_remove_folder(
+    library: State<Mutex<MusicLibrary>>,
+    watcher: State<Mutex<LibraryWatcher>>,
+    path: String,
+) -> CommandResult<()> {
+    let library = library.lock().unwrap();
+    library.remove_folder(&path)?;
+    if webdav::is_remote_folder(&path) {
+        webdav::delete_credentials(&path);
+    }
+    let folders = library.folders()?;
+    watcher.lock().unwrap().sync_folders(&folders);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn library_scan(app: AppHandle) -> CommandResult<ScanSummary> {
+    Ok(tauri::async_runtime::spawn_blocking(move || {
+        scanner::scan_library(
+            &app.state::<Mutex<MusicLibrary>>(),
+            &app.state::<CoverCache>(),
+        )
+    })
+    .await??)
+}
+
+#[tauri::command]
+pub fn library_query(
+    library: State<Mutex<MusicLibrary>>,
+    query: LibraryQuery,
+) -> CommandResult<LibraryPage<LibraryTrack>> {
+    Ok(library.lock().unwrap().query_tracks(&query)?)
+}
+
+#[tauri::command]
+pub fn library_search(
+    library: State<Mutex<MusicLibrary>>,
+    query: String,
+    limit: Option<usize>,
+) -> CommandResult<Vec<LibraryTrack>> {
+    Ok(library.lock().unwrap().search(&query, limit)?)
+}
+
+#[tauri::command]
+pub fn browse_music_folder(
+    library: State<Mutex<MusicLibrary>>,
+    path: PathBuf,
+) -> CommandResult<FolderListing> {
+    Ok(library.lock().unwrap().browse_folder(&path)?)
+}
+
+#[tauri::command]
+pub fn library_albums(
+    library: State<Mutex<MusicLibrary>>,
+    offset: usize,
+    limit: Option<usize>,
+) -> CommandResult<LibraryPage<LibraryAlbum>> {
+    Ok(library.lock().unwrap().albums(offset, limit)?)
+}
+
+#[tauri::command]
+pub fn library_artists(
+    library: State<Mutex<MusicLibrary>>,
+    offset: usize,
+    limit: Option<usize>,
+) -> CommandResult<LibraryPage<LibraryArtist>> {
+    Ok(library.lock().unwrap().artists(offset, limit)?)
+}
+
+#[tauri::command]
+pub fn smart_playlist_create(
+    library: State<Mutex<MusicLibrary>>,
+    name: String,
+    rules: SmartPlaylistRules,
+) -> CommandResult<SmartPlaylist> {
+    Ok(library
+        .lock()
+        .unwrap()
+        .create_smart_playlist(&name, rules)?)
+}
+
+#[tauri::command]
+pub fn smart_playlist_list(
+    library: State<Mutex<MusicLibrary>>,
+) -> CommandResult<Vec<SmartPlaylist>> {
+    Ok(library.lock().unwrap().smart_playlists()?)
+}
+
+#[tauri::command]
+pub fn smart_playlist_delete(library: State<Mutex<MusicLibrary>>, id: i64) -> CommandResult<()> {
+    Ok(library.lock().unwrap().delete_smart_playlist(id)?)
+}
+
+#[tauri::command]
+pub fn smart_playlist_evaluate(
+    library: State<Mutex<MusicLibrary>>,
+    history: State<Mutex<PlayHistory>>,
+    id: i64,
+) -> CommandResult<Vec<LibraryTrack>> {
+    let play_counts: Vec<_> = history
+        .lock()
+        .unwrap()
+        .play_counts(usize::MAX)
+        .into_iter()
+        .map(|x| (x.music_id, x.play_count))
+        .collect();
+    Ok(library
+        .lock()
+        .unwrap()
+        .evaluate_smart_playlist(id, &play_counts)?)
+}
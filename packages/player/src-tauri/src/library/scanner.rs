@@ -0,0 +1,229 @@
+//! 音乐库文件夹扫描
+//!
+//! 根据文件的修改时间进行增量扫描，只重新读取新增或被修改过的音乐文件，
+//! 并移除已经不存在的音乐文件的索引。需要重新读取的文件会在 rayon 线程池中并行读取，
+//! 每读取一批就写入数据库，避免第一次扫描大型音乐库时所有歌曲的元数据都留在内存中。
+//! 网络文件夹的文件列表和元数据读取见 [`super::webdav`]。
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::UNIX_EPOCH,
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use super::{
+    webdav::{self, RemoteFile, WebDavFolder},
+    MusicLibrary,
+};
+use crate::{
+    cover::CoverCache,
+    error,
+    metadata::{is_audio_file, read_music_metadata, MusicMetadata},
+};
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub failed: usize,
+}
+
+/// 每读取这么多首歌曲就写入一次数据库
+const SCAN_BATCH_SIZE: usize = 500;
+
+/// 一首读取完成，等待写入数据库的歌曲
+pub(crate) struct ScannedTrack {
+    pub file_path: String,
+    /// 不包含图片数据，封面图片已经保存到封面缓存中
+    pub metadata: MusicMetadata,
+    pub cover_hash: String,
+    pub file_size: u64,
+    pub modified_at: i64,
+}
+
+impl ScannedTrack {
+    /// 把封面图片保存到封面缓存中，并丢弃元数据中的图片数据
+    pub fn new(
+        file_path: String,
+        mut metadata: MusicMetadata,
+        covers: &CoverCache,
+        file_size: u64,
+        modified_at: i64,
+    ) -> anyhow::Result<Self> {
+        let cover_hash = match metadata.cover.take() {
+            Some(cover) => covers.store(&cover)?,
+            None => String::new(),
+        };
+        metadata.pictures = Vec::new();
+        Ok(Self {
+            file_path,
+            metadata,
+            cover_hash,
+            file_size,
+            modified_at,
+        })
+    }
+}
+
+/// 获取文件的大小和修改时间（UNIX 时间戳，单位为秒）
+pub(crate) fn file_stat(path: &Path) -> Option<(u64, i64)> {
+    let meta = std::fs::metadata(path).ok()?;
+    let modified_at = meta
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or_default();
+    Some((meta.len(), modified_at))
+}
+
+/// 递归收集文件夹下的所有音乐文件
+pub(crate) fn collect_audio_files(dir: &Path, result: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => collect_audio_files(&path, result),
+            Ok(file_type) if file_type.is_file() && is_audio_file(&path) => result.push(path),
+            _ => {}
+        }
+    }
+}
+
+/// 读取音乐文件的元数据，封面图片会被保存到封面缓存中
+pub(crate) fn scan_file(path: &Path, covers: &CoverCache) -> anyhow::Result<ScannedTrack> {
+    let (file_size, modified_at) =
+        file_stat(path).ok_or_else(|| anyhow::anyhow!("无法读取文件信息"))?;
+    let metadata = read_music_metadata(path)?;
+    ScannedTrack::new(
+        path.to_string_lossy().into_owned(),
+        metadata,
+        covers,
+        file_size,
+        modified_at,
+    )
+}
+
+/// 需要重新读取的音乐文件
+enum PendingFile<'a> {
+    Local(PathBuf),
+    Remote(&'a WebDavFolder, RemoteFile),
+}
+
+impl PendingFile<'_> {
+    fn scan(&self, covers: &CoverCache) -> anyhow::Result<ScannedTrack> {
+        match self {
+            Self::Local(path) => scan_file(path, covers),
+            Self::Remote(folder, file) => folder.scan_file(file, covers),
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Self::Local(path) => path.display().to_string(),
+            Self::Remote(_, file) => file.url.clone(),
+        }
+    }
+}
+
+pub fn scan_library(
+    library: &Mutex<MusicLibrary>,
+    covers: &CoverCache,
+) -> anyhow::Result<ScanSummary> {
+    let (folders, known) = {
+        let library = error::lock(library);
+        let known: HashMap<String, i64> = library.track_mtimes()?.into_iter().collect();
+        (library.folders()?, known)
+    };
+
+    let mut files = Vec::new();
+    let mut remote_folders = Vec::new();
+    for folder in &folders {
+        if webdav::is_remote_folder(folder) {
+            remote_folders.push(WebDavFolder::open(folder));
+        } else {
+            collect_audio_files(Path::new(folder), &mut files);
+        }
+    }
+
+    let mut seen = HashSet::with_capacity(files.len());
+    let mut pending = Vec::new();
+    for path in files {
+        let file_path = path.to_string_lossy().into_owned();
+        let known_mtime = known.get(&file_path).copied();
+        seen.insert(file_path);
+        if let (Some(known_mtime), Some((_, modified_at))) = (known_mtime, file_stat(&path)) {
+            if known_mtime == modified_at {
+                continue;
+            }
+        }
+        pending.push((PendingFile::Local(path), known_mtime.is_some()));
+    }
+    for folder in &remote_folders {
+        let files = match folder.list_files() {
+            Ok(files) => files,
+            Err(err) => {
+                // 网络文件夹暂时无法访问时保留已有的索引
+                println!("列出网络文件夹中的音乐文件失败: {err:?}");
+                seen.extend(
+                    known
+                        .keys()
+                        .filter(|x| x.starts_with(folder.root()))
+                        .cloned(),
+                );
+                continue;
+            }
+        };
+        for file in files {
+            let known_mtime = known.get(&file.url).copied();
+            seen.insert(file.url.clone());
+            if known_mtime == Some(file.modified_at) {
+                continue;
+            }
+            pending.push((PendingFile::Remote(folder, file), known_mtime.is_some()));
+        }
+    }
+
+    let mut summary = ScanSummary::default();
+    for batch in pending.chunks(SCAN_BATCH_SIZE) {
+        let results: Vec<_> = batch
+            .par_iter()
+            .map(|(file, is_known)| (file.scan(covers), *is_known, file))
+            .collect();
+        let mut scanned = Vec::with_capacity(results.len());
+        for (result, is_known, file) in results {
+            match result {
+                Ok(track) => {
+                    if is_known {
+                        summary.updated += 1;
+                    } else {
+                        summary.added += 1;
+                    }
+                    scanned.push(track);
+                }
+                Err(err) => {
+                    println!("读取音乐文件 {} 的元数据失败: {err:?}", file.name());
+                    summary.failed += 1;
+                }
+            }
+        }
+        error::lock(library).apply_changes(&scanned, &[])?;
+    }
+
+    let removed: Vec<String> = known
+        .into_keys()
+        .filter(|file_path| !seen.contains(file_path))
+        .collect();
+    summary.removed = removed.len();
+
+    error::lock(library).apply_changes(&[], &removed)?;
+
+    Ok(summary)
+}
@@ -0,0 +1,83 @@
+//! 音乐库全文搜索
+//!
+//! 使用 FTS5 的 trigram 分词器建立索引，可以直接匹配中日韩文字的任意子串，
+//! 也天然支持前缀匹配。trigram 无法索引少于三个字符的词，这类词会回退为 `LIKE` 匹配。
+use rusqlite::params_from_iter;
+
+use super::{track_from_row, LibraryTrack, MusicLibrary, TRACK_COLUMNS};
+
+/// 未指定数量时默认返回的搜索结果数量
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+fn escape_like(term: &str) -> String {
+    let mut result = String::with_capacity(term.len() + 2);
+    result.push('%');
+    for c in term.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result.push('%');
+    result
+}
+
+impl MusicLibrary {
+    /// 搜索标题、艺术家、专辑或歌词中包含所有关键词的歌曲，关键词以空白分隔
+    pub fn search(&self, query: &str, limit: Option<usize>) -> anyhow::Result<Vec<LibraryTrack>> {
+        let mut match_terms = Vec::new();
+        let mut like_terms = Vec::new();
+        for term in query.split_whitespace() {
+            if term.chars().count() >= 3 {
+                match_terms.push(format!("\"{}\"", term.replace('"', "\"\"")));
+            } else {
+                like_terms.push(escape_like(term));
+            }
+        }
+        if match_terms.is_empty() && like_terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let has_match = !match_terms.is_empty();
+        let mut conditions = Vec::new();
+        let mut args = Vec::new();
+        if has_match {
+            conditions.push("tracks_fts MATCH ?".to_string());
+            args.push(match_terms.join(" AND "));
+        }
+        for term in like_terms {
+            conditions.push(
+                ["title", "artist", "album", "lyric"]
+                    .map(|column| format!("tracks.{column} LIKE ? ESCAPE '\\'"))
+                    .join(" OR "),
+            );
+            args.extend([term.clone(), term.clone(), term.clone(), term]);
+        }
+        let condition = conditions
+            .iter()
+            .map(|x| format!("({x})"))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        // 只有使用了 MATCH 时才能按照相关度排序
+        let order = if has_match {
+            "tracks_fts.rank, tracks.id"
+        } else {
+            "tracks.title COLLATE NOCASE, tracks.id"
+        };
+        let columns = TRACK_COLUMNS
+            .split(", ")
+            .map(|column| format!("tracks.{column}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {columns} FROM tracks_fts JOIN tracks ON tracks.id = tracks_fts.rowid
+            WHERE {condition} ORDER BY {order} LIMIT {limit}"
+        ))?;
+        let tracks = stmt
+            .query_map(params_from_iter(args.iter()), track_from_row)?
+            .collect::<Result<_, _>>()?;
+        Ok(tracks)
+    }
+}
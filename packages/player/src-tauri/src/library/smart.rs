@@ -0,0 +1,287 @@
+//! 智能播放列表
+//!
+//! 智能播放列表由若干条件规则组成，规则会被编译成 SQL 查询条件，
+//! 每次获取歌曲时都会重新对音乐库进行查询，因此音乐库的变化会自动反映到播放列表中。
+//! 播放次数来自播放历史记录，查询前会写入临时表 `play_counts` 并与音乐库中的歌曲关联，
+//! 播放记录中的歌曲 ID 与音乐库中的文件路径相同时才能对应上。
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+use super::{track_from_row, LibrarySortBy, LibraryTrack, MusicLibrary, TRACK_COLUMNS};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SmartField {
+    Title,
+    Artist,
+    Album,
+    FilePath,
+    /// 时长，单位为秒
+    Duration,
+    /// 加入音乐库的时间，为 UNIX 时间戳，单位为秒
+    AddedAt,
+    Genre,
+    Year,
+    /// 完整播放的次数
+    PlayCount,
+}
+
+impl SmartField {
+    fn column(self) -> &'static str {
+        match self {
+            Self::Title => "title",
+            Self::Artist => "artist",
+            Self::Album => "album",
+            Self::FilePath => "file_path",
+            Self::Duration => "duration",
+            Self::AddedAt => "added_at",
+            Self::Genre => "genre",
+            Self::Year => "year",
+            Self::PlayCount => "COALESCE(play_counts.play_count, 0)",
+        }
+    }
+
+    fn is_numeric(self) -> bool {
+        matches!(
+            self,
+            Self::Duration | Self::AddedAt | Self::Year | Self::PlayCount
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SmartOperator {
+    Eq,
+    Ne,
+    Contains,
+    NotContains,
+    StartsWith,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SmartValue {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartRule {
+    pub field: SmartField,
+    pub operator: SmartOperator,
+    pub value: SmartValue,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SmartMatch {
+    /// 满足所有规则
+    #[default]
+    All,
+    /// 满足任意一条规则
+    Any,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SmartPlaylistRules {
+    pub match_mode: SmartMatch,
+    pub rules: Vec<SmartRule>,
+    pub sort_by: LibrarySortBy,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SmartPlaylist {
+    pub id: i64,
+    pub name: String,
+    pub rules: SmartPlaylistRules,
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl SmartRule {
+    /// 将规则编译成 SQL 条件，参数会被追加到 `args` 中
+    fn to_sql(&self, args: &mut Vec<SqlValue>) -> anyhow::Result<String> {
+        let column = self.field.column();
+        let value = match (&self.value, self.field.is_numeric()) {
+            (SmartValue::Number(x), true) => SqlValue::Real(*x),
+            (SmartValue::Text(x), false) => SqlValue::Text(x.to_owned()),
+            (SmartValue::Number(x), false) => SqlValue::Text(x.to_string()),
+            (SmartValue::Text(x), true) => SqlValue::Real(
+                x.trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("规则 {:?} 需要数字类型的值: {x}", self.field))?,
+            ),
+        };
+        let sql = match (self.operator, value) {
+            (SmartOperator::Contains | SmartOperator::NotContains, SqlValue::Text(x)) => {
+                args.push(SqlValue::Text(format!("%{}%", escape_like(&x))));
+                if self.operator == SmartOperator::Contains {
+                    format!("{column} LIKE ? ESCAPE '\\'")
+                } else {
+                    format!("{column} NOT LIKE ? ESCAPE '\\'")
+                }
+            }
+            (SmartOperator::StartsWith, SqlValue::Text(x)) => {
+                args.push(SqlValue::Text(format!("{}%", escape_like(&x))));
+                format!("{column} LIKE ? ESCAPE '\\'")
+            }
+            (
+                SmartOperator::Contains | SmartOperator::NotContains | SmartOperator::StartsWith,
+                _,
+            ) => {
+                anyhow::bail!("规则 {:?} 不支持文本匹配操作", self.field)
+            }
+            (operator, value) => {
+                let operator = match operator {
+                    SmartOperator::Eq => "=",
+                    SmartOperator::Ne => "<>",
+                    SmartOperator::Gt => ">",
+                    SmartOperator::Ge => ">=",
+                    SmartOperator::Lt => "<",
+                    SmartOperator::Le => "<=",
+                    _ => unreachable!(),
+                };
+                args.push(value);
+                if self.field.is_numeric() {
+                    format!("{column} {operator} ?")
+                } else {
+                    format!("{column} {operator} ? COLLATE NOCASE")
+                }
+            }
+        };
+        Ok(sql)
+    }
+}
+
+impl SmartPlaylistRules {
+    fn to_sql(&self, args: &mut Vec<SqlValue>) -> anyhow::Result<String> {
+        if self.rules.is_empty() {
+            return Ok("1".into());
+        }
+        let conditions = self
+            .rules
+            .iter()
+            .map(|rule| rule.to_sql(args).map(|x| format!("({x})")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(match self.match_mode {
+            SmartMatch::All => conditions.join(" AND "),
+            SmartMatch::Any => conditions.join(" OR "),
+        })
+    }
+}
+
+impl MusicLibrary {
+    pub fn create_smart_playlist(
+        &self,
+        name: &str,
+        rules: SmartPlaylistRules,
+    ) -> anyhow::Result<SmartPlaylist> {
+        // 先编译一次以尽早发现无效的规则
+        rules.to_sql(&mut Vec::new())?;
+        self.conn.execute(
+            "INSERT INTO smart_playlists (name, rules) VALUES (?1, ?2)",
+            params![name, serde_json::to_string(&rules)?],
+        )?;
+        Ok(SmartPlaylist {
+            id: self.conn.last_insert_rowid(),
+            name: name.to_string(),
+            rules,
+        })
+    }
+
+    pub fn smart_playlists(&self) -> anyhow::Result<Vec<SmartPlaylist>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id, name, rules FROM smart_playlists ORDER BY id")?;
+        let rows: Vec<(i64, String, String)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+        rows.into_iter()
+            .map(|(id, name, rules)| {
+                Ok(SmartPlaylist {
+                    id,
+                    name,
+                    rules: serde_json::from_str(&rules)?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn delete_smart_playlist(&self, id: i64) -> anyhow::Result<()> {
+        self.conn
+            .execute("DELETE FROM smart_playlists WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// 将播放历史记录中的播放次数写入临时表，供规则中的 [`SmartField::PlayCount`] 使用
+    fn load_play_counts(&self, play_counts: &[(String, usize)]) -> anyhow::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch(
+            "CREATE TEMP TABLE IF NOT EXISTS play_counts (
+                music_id TEXT PRIMARY KEY NOT NULL,
+                play_count INTEGER NOT NULL
+            );
+            DELETE FROM temp.play_counts;",
+        )?;
+        {
+            let mut insert =
+                tx.prepare("INSERT INTO temp.play_counts (music_id, play_count) VALUES (?1, ?2)")?;
+            for (music_id, play_count) in play_counts {
+                insert.execute(params![music_id, *play_count as i64])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// 根据智能播放列表的规则从音乐库中查询歌曲，`play_counts` 为每首歌曲 ID 的播放次数
+    pub fn evaluate_smart_playlist(
+        &self,
+        id: i64,
+        play_counts: &[(String, usize)],
+    ) -> anyhow::Result<Vec<LibraryTrack>> {
+        let rules: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT rules FROM smart_playlists WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(rules) = rules else {
+            anyhow::bail!("智能播放列表 {id} 不存在");
+        };
+        let rules: SmartPlaylistRules = serde_json::from_str(&rules)?;
+        self.load_play_counts(play_counts)?;
+
+        let mut args = Vec::new();
+        let condition = rules.to_sql(&mut args)?;
+        args.push(SqlValue::Integer(
+            rules.limit.map(|x| x as i64).unwrap_or(-1),
+        ));
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {TRACK_COLUMNS} FROM tracks
+            LEFT JOIN temp.play_counts ON play_counts.music_id = tracks.file_path
+            WHERE {condition} ORDER BY {} LIMIT ?",
+            rules.sort_by.order_clause()
+        ))?;
+        let tracks = stmt
+            .query_map(params_from_iter(args), track_from_row)?
+            .collect::<Result<_, _>>()?;
+        Ok(tracks)
+    }
+}
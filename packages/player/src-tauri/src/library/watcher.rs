@@ -0,0 +1,201 @@
+//! 音乐库文件夹监听
+//!
+//! 使用 `notify` 监听音乐文件夹内的文件变化，增量更新音乐库索引，
+//! 并通过 `library-changed` 事件将变化内容发送给前端。
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::{mpsc::Receiver, Mutex},
+    time::Duration,
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use super::{
+    scanner::{collect_audio_files, file_stat, scan_file},
+    LibraryTrack, MusicLibrary,
+};
+use crate::{cover::CoverCache, metadata::is_audio_file};
+
+/// 合并文件变化事件的等待时长，避免复制大量文件时频繁更新
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(500);
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryDiff {
+    pub added: Vec<LibraryTrack>,
+    pub updated: Vec<LibraryTrack>,
+    pub removed: Vec<String>,
+}
+
+impl LibraryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+pub struct LibraryWatcher {
+    watcher: Option<RecommendedWatcher>,
+    watched: HashSet<PathBuf>,
+}
+
+impl LibraryWatcher {
+    pub fn new(app: AppHandle) -> Self {
+        let (sx, rx) = std::sync::mpsc::channel();
+        let watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(evt) => {
+                    if evt.kind.is_access() {
+                        return;
+                    }
+                    for path in evt.paths {
+                        let _ = sx.send(path);
+                    }
+                }
+                Err(err) => {
+                    println!("音乐库文件夹监听出错: {err:?}");
+                }
+            });
+        let watcher = match watcher {
+            Ok(watcher) => {
+                std::thread::spawn(move || Self::process_events(app, rx));
+                Some(watcher)
+            }
+            Err(err) => {
+                println!("音乐库文件夹监听器创建失败: {err:?}");
+                None
+            }
+        };
+        Self {
+            watcher,
+            watched: HashSet::new(),
+        }
+    }
+
+    /// 根据当前的音乐文件夹列表更新需要监听的文件夹
+    pub fn sync_folders(&mut self, folders: &[String]) {
+        let Some(watcher) = &mut self.watcher else {
+            return;
+        };
+        // 网络文件夹无法监听，只在手动扫描时更新
+        let folders: HashSet<PathBuf> = folders
+            .iter()
+            .filter(|x| !super::webdav::is_remote_folder(x))
+            .map(PathBuf::from)
+            .collect();
+        for folder in self.watched.difference(&folders) {
+            if let Err(err) = watcher.unwatch(folder) {
+                println!("取消监听音乐文件夹 {} 失败: {err:?}", folder.display());
+            }
+        }
+        for folder in folders.difference(&self.watched) {
+            if let Err(err) = watcher.watch(folder, RecursiveMode::Recursive) {
+                println!("监听音乐文件夹 {} 失败: {err:?}", folder.display());
+            }
+        }
+        self.watched = folders;
+    }
+
+    fn process_events(app: AppHandle, rx: Receiver<PathBuf>) {
+        while let Ok(path) = rx.recv() {
+            let mut paths = HashSet::from([path]);
+            while let Ok(path) = rx.recv_timeout(DEBOUNCE_DURATION) {
+                paths.insert(path);
+            }
+            let library = app.state::<Mutex<MusicLibrary>>();
+            match apply_fs_changes(&library, &app.state::<CoverCache>(), paths) {
+                Ok(diff) => {
+                    if !diff.is_empty() {
+                        if let Err(err) = app.emit_all("library-changed", diff) {
+                            println!("音乐库变化事件发送失败: {err:?}");
+                        }
+                    }
+                }
+                Err(err) => {
+                    println!("音乐库增量更新失败: {err:?}");
+                }
+            }
+        }
+    }
+}
+
+/// 根据发生变化的路径增量更新音乐库，路径可以是文件或者文件夹
+pub fn apply_fs_changes(
+    library: &Mutex<MusicLibrary>,
+    covers: &CoverCache,
+    paths: HashSet<PathBuf>,
+) -> anyhow::Result<LibraryDiff> {
+    let mut files = Vec::new();
+    let mut missing = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            collect_audio_files(&path, &mut files);
+        } else if path.is_file() {
+            if is_audio_file(&path) {
+                files.push(path);
+            }
+        } else {
+            missing.push(path);
+        }
+    }
+
+    let known: HashMap<String, i64> = library
+        .lock()
+        .unwrap()
+        .track_mtimes()?
+        .into_iter()
+        .collect();
+
+    let removed: Vec<String> = known
+        .keys()
+        .filter(|file_path| {
+            missing
+                .iter()
+                .any(|missing| PathBuf::from(file_path).starts_with(missing))
+        })
+        .cloned()
+        .collect();
+
+    let mut scanned = Vec::new();
+    let mut added_paths = Vec::new();
+    let mut updated_paths = Vec::new();
+    for path in files {
+        let file_path = path.to_string_lossy().into_owned();
+        let known_mtime = known.get(&file_path).copied();
+        if let (Some(known_mtime), Some((_, modified_at))) = (known_mtime, file_stat(&path)) {
+            if known_mtime == modified_at {
+                continue;
+            }
+        }
+        match scan_file(&path, covers) {
+            Ok(track) => {
+                if known_mtime.is_some() {
+                    updated_paths.push(file_path);
+                } else {
+                    added_paths.push(file_path);
+                }
+                scanned.push(track);
+            }
+            Err(err) => {
+                println!("读取音乐文件 {} 的元数据失败: {err:?}", path.display());
+            }
+        }
+    }
+
+    let library = library.lock().unwrap();
+    library.apply_changes(&scanned, &removed)?;
+
+    let mut diff = LibraryDiff {
+        removed,
+        ..Default::default()
+    };
+    for file_path in added_paths {
+        diff.added.extend(library.track_by_path(&file_path)?);
+    }
+    for file_path in updated_paths {
+        diff.updated.extend(library.track_by_path(&file_path)?);
+    }
+    Ok(diff)
+}
@@ -0,0 +1,516 @@
+//! WebDAV 网络音乐文件夹
+//!
+//! 音乐文件夹也可以是 WebDAV 服务器上的地址（`http://` 或者 `https://`），扫描时通过 `PROPFIND` 逐层列出其中的音乐文件，
+//! 同样根据文件的修改时间进行增量扫描。读取元数据时使用 HTTP 范围请求，只下载解析标签所需的部分，
+//! 读取结果和本地文件一样保存在音乐库数据库中。用户名和密码保存在系统的凭据管理器中，不会写入数据库。
+//!
+//! 网络文件夹中的歌曲通过自定义协议 `amll-share` 播放，地址为 `amll-share://localhost/?url=<编码后的文件地址>`，
+//! 协议会带上身份验证信息并转发播放器发出的范围请求，因此可以正常跳转。
+//! 协议只会转发已添加的网络文件夹中的文件。
+//!
+//! SMB 共享没有在这里实现，可以在系统中挂载后（Windows 上也可以直接使用 `\\服务器\共享` 路径）作为本地文件夹添加。
+use std::{
+    collections::HashSet,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use percent_encoding::percent_decode_str;
+use quick_xml::{events::Event, Reader};
+use serde::{Deserialize, Serialize};
+use symphonia::core::io::MediaSource;
+use tauri::{
+    http::{Request, Response, ResponseBuilder},
+    AppHandle, Manager,
+};
+use url::Url;
+
+use super::{scanner::ScannedTrack, MusicLibrary};
+use crate::{
+    cover::CoverCache,
+    metadata::{is_audio_file, read_source_metadata},
+};
+
+/// 自定义协议的名称
+pub const SHARE_PROTOCOL: &str = "amll-share";
+/// 保存用户名和密码时使用的服务名称
+const KEYRING_SERVICE: &str = "net.stevexmh.amllplayer.library-share";
+/// 读取元数据时每次范围请求的大小
+const READ_CHUNK_SIZE: u64 = 256 * 1024;
+/// 播放时每次转发的最大长度，播放器会根据需要继续请求后面的部分
+const STREAM_CHUNK_SIZE: u64 = 2 * 1024 * 1024;
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/><getcontentlength/><getlastmodified/></prop></propfind>"#;
+
+/// 已读取过的网络文件夹的身份验证请求头，避免每次范围请求都访问凭据管理器
+static AUTHORIZATIONS: Mutex<Vec<(String, Option<String>)>> = Mutex::new(Vec::new());
+
+/// 音乐文件夹是否为网络文件夹
+pub fn is_remote_folder(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/// 统一网络文件夹的地址格式，文件夹地址总是以 `/` 结尾
+pub fn normalize_folder_url(url: &str) -> anyhow::Result<String> {
+    let mut url = Url::parse(url.trim())?;
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("网络文件夹只支持 HTTP 或者 HTTPS 地址: {url}");
+    }
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+    Ok(url.into())
+}
+
+#[derive(Serialize, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+fn keyring_entry(folder: &str) -> anyhow::Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, folder)?)
+}
+
+fn basic_authorization(username: &str, password: &str) -> String {
+    format!("Basic {}", BASE64.encode(format!("{username}:{password}")))
+}
+
+/// 将用户名和密码保存到凭据管理器中
+pub fn save_credentials(folder: &str, username: &str, password: &str) -> anyhow::Result<()> {
+    let credentials = Credentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    };
+    keyring_entry(folder)?.set_password(&serde_json::to_string(&credentials)?)?;
+    let mut authorizations = AUTHORIZATIONS.lock().unwrap();
+    authorizations.retain(|(x, _)| x != folder);
+    authorizations.push((
+        folder.to_string(),
+        Some(basic_authorization(username, password)),
+    ));
+    Ok(())
+}
+
+/// 删除保存的用户名和密码
+pub fn delete_credentials(folder: &str) {
+    AUTHORIZATIONS.lock().unwrap().retain(|(x, _)| x != folder);
+    match keyring_entry(folder).and_then(|x| Ok(x.delete_password()?)) {
+        Ok(()) => {}
+        Err(err)
+            if matches!(
+                err.downcast_ref::<keyring::Error>(),
+                Some(keyring::Error::NoEntry)
+            ) => {}
+        Err(err) => println!("网络文件夹 {folder} 的密码删除失败: {err:?}"),
+    }
+}
+
+/// 读取网络文件夹的身份验证请求头，没有保存用户名和密码时为空
+pub(crate) fn authorization(folder: &str) -> Option<String> {
+    let mut authorizations = AUTHORIZATIONS.lock().unwrap();
+    if let Some((_, authorization)) = authorizations.iter().find(|(x, _)| x == folder) {
+        return authorization.clone();
+    }
+    let authorization = match keyring_entry(folder).and_then(|x| Ok(x.get_password()?)) {
+        Ok(secret) => match serde_json::from_str::<Credentials>(&secret) {
+            Ok(x) => Some(basic_authorization(&x.username, &x.password)),
+            Err(err) => {
+                println!("网络文件夹 {folder} 保存的凭据解析失败: {err:?}");
+                None
+            }
+        },
+        Err(err)
+            if matches!(
+                err.downcast_ref::<keyring::Error>(),
+                Some(keyring::Error::NoEntry)
+            ) =>
+        {
+            None
+        }
+        Err(err) => {
+            println!("网络文件夹 {folder} 的密码读取失败: {err:?}");
+            None
+        }
+    };
+    authorizations.push((folder.to_string(), authorization.clone()));
+    authorization
+}
+
+/// 网络文件夹中的一个音乐文件
+pub(crate) struct RemoteFile {
+    pub url: String,
+    pub file_size: u64,
+    /// 文件的修改时间，为 UNIX 时间戳，单位为秒
+    pub modified_at: i64,
+}
+
+#[derive(Default)]
+struct PropfindEntry {
+    href: String,
+    is_dir: bool,
+    file_size: u64,
+    modified_at: i64,
+}
+
+/// 解析 `PROPFIND` 返回的 `multistatus`，元素的命名空间前缀因服务器而异，因此只比较本地名称
+fn parse_multistatus(xml: &str) -> anyhow::Result<Vec<PropfindEntry>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut result = Vec::new();
+    let mut entry: Option<PropfindEntry> = None;
+    let mut current_field = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = e.local_name();
+                match name.as_ref() {
+                    b"response" => entry = Some(PropfindEntry::default()),
+                    b"collection" => {
+                        if let Some(entry) = &mut entry {
+                            entry.is_dir = true;
+                        }
+                    }
+                    _ => current_field = name.as_ref().to_vec(),
+                }
+            }
+            Event::Empty(e) => {
+                if e.local_name().as_ref() == b"collection" {
+                    if let Some(entry) = &mut entry {
+                        entry.is_dir = true;
+                    }
+                }
+            }
+            Event::Text(text) => {
+                if let Some(entry) = &mut entry {
+                    let text = text.unescape()?.into_owned();
+                    match current_field.as_slice() {
+                        b"href" => entry.href = text,
+                        b"getcontentlength" => entry.file_size = text.parse().unwrap_or_default(),
+                        b"getlastmodified" => {
+                            entry.modified_at = httpdate::parse_http_date(&text)
+                                .ok()
+                                .and_then(|x| x.duration_since(std::time::UNIX_EPOCH).ok())
+                                .map(|x| x.as_secs() as i64)
+                                .unwrap_or_default();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"response" {
+                    if let Some(entry) = entry.take() {
+                        result.push(entry);
+                    }
+                }
+                current_field.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+/// 已添加的网络文件夹
+#[derive(Clone)]
+pub(crate) struct WebDavFolder {
+    root: String,
+    authorization: Option<String>,
+}
+
+impl WebDavFolder {
+    /// 打开网络文件夹，使用凭据管理器中保存的用户名和密码
+    pub fn open(root: &str) -> Self {
+        Self {
+            root: root.to_string(),
+            authorization: authorization(root),
+        }
+    }
+
+    /// 使用还没有保存的用户名和密码打开网络文件夹，用于添加前的连接检查
+    pub fn with_credentials(root: &str, username: &str, password: &str) -> Self {
+        Self {
+            root: root.to_string(),
+            authorization: Some(basic_authorization(username, password)),
+        }
+    }
+
+    fn headers<'a>(&'a self, extra: &[(&'a str, &'a str)]) -> Vec<(&'a str, &'a str)> {
+        let mut headers = extra.to_vec();
+        if let Some(authorization) = &self.authorization {
+            headers.push(("Authorization", authorization.as_str()));
+        }
+        headers
+    }
+
+    fn propfind(&self, url: &str, depth: &str) -> anyhow::Result<Vec<PropfindEntry>> {
+        let response = tauri::async_runtime::block_on(crate::http::send_raw(
+            "PROPFIND",
+            url,
+            &self.headers(&[
+                ("Depth", depth),
+                ("Content-Type", "application/xml; charset=utf-8"),
+            ]),
+            Some(PROPFIND_BODY.to_string()),
+        ))?;
+        match response.status {
+            207 => parse_multistatus(&String::from_utf8_lossy(&response.data)),
+            401 | 403 => anyhow::bail!("网络文件夹 {url} 拒绝访问，请检查用户名和密码"),
+            status => anyhow::bail!("列出网络文件夹 {url} 失败，状态码为 {status}"),
+        }
+    }
+
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// 检查网络文件夹是否可以访问
+    pub fn check(&self) -> anyhow::Result<()> {
+        let entries = self.propfind(&self.root, "0")?;
+        if !entries.first().is_some_and(|x| x.is_dir) {
+            anyhow::bail!("{} 不是 WebDAV 文件夹", self.root);
+        }
+        Ok(())
+    }
+
+    /// 逐层列出网络文件夹中的所有音乐文件，很多服务器不允许 `Depth: infinity`
+    pub fn list_files(&self) -> anyhow::Result<Vec<RemoteFile>> {
+        let root = Url::parse(&self.root)?;
+        let mut result = Vec::new();
+        let mut visited = HashSet::from([root.to_string()]);
+        let mut pending = vec![root];
+        while let Some(dir) = pending.pop() {
+            let entries = match self.propfind(dir.as_str(), "1") {
+                Ok(entries) => entries,
+                // 根文件夹无法访问时整个文件夹都无法扫描，子文件夹出错时跳过即可
+                Err(err) if dir.as_str() == self.root => return Err(err),
+                Err(err) => {
+                    println!("{err:?}");
+                    continue;
+                }
+            };
+            for entry in entries {
+                let Ok(url) = dir.join(&entry.href) else {
+                    continue;
+                };
+                // 结果中包括文件夹自身，只处理根文件夹之下的文件
+                if !url.as_str().starts_with(&self.root) || !visited.insert(url.to_string()) {
+                    continue;
+                }
+                if entry.is_dir {
+                    pending.push(url);
+                } else if is_audio_file(&decoded_path(&url)) {
+                    result.push(RemoteFile {
+                        url: url.into(),
+                        file_size: entry.file_size,
+                        modified_at: entry.modified_at,
+                    });
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// 通过范围请求读取文件中的一段，返回的数据、内容类型以及文件的总长度
+    fn read_range(
+        &self,
+        url: &str,
+        start: u64,
+        end: u64,
+    ) -> anyhow::Result<(Vec<u8>, Option<String>, Option<u64>)> {
+        let range = format!("bytes={start}-{end}");
+        let response = tauri::async_runtime::block_on(crate::http::send_raw(
+            "GET",
+            url,
+            &self.headers(&[("Range", &range)]),
+            None,
+        ))?;
+        let content_type = response.headers.get("content-type").cloned();
+        match response.status {
+            206 => {
+                let total = response
+                    .headers
+                    .get("content-range")
+                    .and_then(|x| x.rsplit('/').next())
+                    .and_then(|x| x.parse().ok());
+                Ok((response.data, content_type, total))
+            }
+            // 服务器不支持范围请求时会返回整个文件
+            200 => {
+                let total = response.data.len() as u64;
+                let data = response
+                    .data
+                    .get(start as usize..response.data.len().min(end as usize + 1))
+                    .unwrap_or_default()
+                    .to_vec();
+                Ok((data, content_type, Some(total)))
+            }
+            416 => Ok((Vec::new(), content_type, None)),
+            status => anyhow::bail!("读取网络文件 {url} 失败，状态码为 {status}"),
+        }
+    }
+
+    /// 读取网络文件的元数据，封面图片会被保存到封面缓存中
+    pub fn scan_file(
+        &self,
+        file: &RemoteFile,
+        covers: &CoverCache,
+    ) -> anyhow::Result<ScannedTrack> {
+        let url = Url::parse(&file.url)?;
+        let source = RangeSource {
+            folder: self.clone(),
+            url: file.url.clone(),
+            len: file.file_size,
+            pos: 0,
+            chunk_start: 0,
+            chunk: Vec::new(),
+        };
+        let metadata = read_source_metadata(Box::new(source), &decoded_path(&url))?;
+        ScannedTrack::new(
+            file.url.clone(),
+            metadata,
+            covers,
+            file.file_size,
+            file.modified_at,
+        )
+    }
+}
+
+/// 解码后的文件路径，用于判断文件类型和获取文件名
+fn decoded_path(url: &Url) -> PathBuf {
+    PathBuf::from(&*percent_decode_str(url.path()).decode_utf8_lossy())
+}
+
+/// 通过范围请求按块读取网络文件，作为 Symphonia 的读取源
+struct RangeSource {
+    folder: WebDavFolder,
+    url: String,
+    len: u64,
+    pos: u64,
+    chunk_start: u64,
+    chunk: Vec<u8>,
+}
+
+impl Read for RangeSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let chunk_end = self.chunk_start + self.chunk.len() as u64;
+        if !(self.chunk_start..chunk_end).contains(&self.pos) {
+            let end = (self.pos + READ_CHUNK_SIZE).min(self.len) - 1;
+            let (data, _, _) = self
+                .folder
+                .read_range(&self.url, self.pos, end)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+            if data.is_empty() {
+                return Ok(0);
+            }
+            self.chunk_start = self.pos;
+            self.chunk = data;
+        }
+        let offset = (self.pos - self.chunk_start) as usize;
+        let len = buf.len().min(self.chunk.len() - offset);
+        buf[..len].copy_from_slice(&self.chunk[offset..offset + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for RangeSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::End(x) => self.len as i64 + x,
+            SeekFrom::Current(x) => self.pos as i64 + x,
+        };
+        if pos < 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "不能跳转到文件开头之前",
+            ));
+        }
+        self.pos = pos as u64;
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for RangeSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+}
+
+/// 解析播放器发出的 `Range` 请求头，只支持单个范围
+fn parse_range(range: &str) -> Option<(u64, Option<u64>)> {
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    Some((start.trim().parse().ok()?, end.trim().parse().ok()))
+}
+
+pub fn handle_share_protocol(
+    app: &AppHandle,
+    request: &Request,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let Some(url) = Url::parse(request.uri()).ok().and_then(|x| {
+        x.query_pairs()
+            .find(|(k, _)| k == "url")
+            .map(|(_, v)| v.into_owned())
+    }) else {
+        return ResponseBuilder::new().status(400).body(Vec::new());
+    };
+    let folders = app
+        .state::<Mutex<MusicLibrary>>()
+        .lock()
+        .unwrap()
+        .folders()?;
+    let Some(root) = folders
+        .iter()
+        .find(|x| is_remote_folder(x) && url.starts_with(x.as_str()))
+    else {
+        return ResponseBuilder::new().status(403).body(Vec::new());
+    };
+    let (start, end) = request
+        .headers()
+        .get("range")
+        .and_then(|x| x.to_str().ok())
+        .and_then(parse_range)
+        .unwrap_or((0, None));
+    let end = end.unwrap_or(u64::MAX).min(start + STREAM_CHUNK_SIZE - 1);
+    let (data, content_type, total) = match WebDavFolder::open(root).read_range(&url, start, end) {
+        Ok(result) => result,
+        Err(err) => {
+            println!("网络文件转发失败: {err:?}");
+            return ResponseBuilder::new().status(502).body(Vec::new());
+        }
+    };
+    let Some(total) = total.filter(|_| !data.is_empty()) else {
+        return ResponseBuilder::new().status(416).body(Vec::new());
+    };
+    ResponseBuilder::new()
+        .status(206)
+        .mimetype(
+            content_type
+                .as_deref()
+                .unwrap_or("application/octet-stream"),
+        )
+        .header("Accept-Ranges", "bytes")
+        .header(
+            "Content-Range",
+            format!("bytes {start}-{}/{total}", start + data.len() as u64 - 1),
+        )
+        .header("Access-Control-Allow-Origin", "*")
+        .body(data)
+}
@@ -0,0 +1,44 @@
+//! 本地套接字
+//!
+//! 本地 WebSocket 服务器和命令行控制共用的 Unix 域套接字，套接字文件只有当前用户可以访问。
+//! 套接字会先在只有当前用户可以访问的临时文件夹中创建并设置好权限，再移动到目标路径，
+//! 避免其它用户在创建和设置权限之间的间隙连接。
+//!
+//! Windows 上应当使用命名管道，但目前还没有实现，依赖本地套接字的功能在 Windows 上不可用。
+use std::{
+    os::unix::{
+        fs::{DirBuilderExt, PermissionsExt},
+        net::UnixListener,
+    },
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// 用于区分同时创建的多个临时文件夹
+static BIND_ID: AtomicU32 = AtomicU32::new(0);
+
+/// 在指定的路径上创建只有当前用户可以访问的套接字，路径上已有的文件会被替换
+pub fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    let parent = path
+        .parent()
+        .filter(|x| !x.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    std::fs::create_dir_all(parent)?;
+    // 临时文件夹的路径不能太长，否则会超过套接字路径的长度限制
+    let dir = parent.join(format!(
+        ".amll-{}-{}",
+        std::process::id(),
+        BIND_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let temp = dir.join("s");
+    let result = UnixListener::bind(&temp).and_then(|listener| {
+        std::fs::set_permissions(&temp, std::fs::Permissions::from_mode(0o600))?;
+        // 上次运行时留下的套接字文件会被直接替换
+        std::fs::rename(&temp, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
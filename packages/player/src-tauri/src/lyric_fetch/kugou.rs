@@ -0,0 +1,95 @@
+//! 酷狗音乐歌词来源
+//!
+//! 下载歌词时需要搜索结果中的 ID 和 AccessKey，因此候选结果的 ID 格式为 `<ID>_<AccessKey>`。
+use async_trait::async_trait;
+use base64::Engine;
+
+use super::{json_id, non_empty, LyricCandidate, LyricContent, LyricProvider, LyricQuery};
+
+const KUGOU_SEARCH_URL: &str = "https://lyrics.kugou.com/search";
+const KUGOU_DOWNLOAD_URL: &str = "https://lyrics.kugou.com/download";
+
+pub struct KugouProvider;
+
+#[async_trait]
+impl LyricProvider for KugouProvider {
+    fn name(&self) -> &'static str {
+        "kugou"
+    }
+
+    async fn search(&self, query: &LyricQuery) -> anyhow::Result<Vec<LyricCandidate>> {
+        let keyword = if query.artist.trim().is_empty() {
+            query.title.clone()
+        } else {
+            format!("{} - {}", query.artist, query.title)
+        };
+        let duration = query
+            .duration
+            .map(|x| ((x * 1000.0) as u64).to_string())
+            .unwrap_or_default();
+        let result = crate::http::get_json(
+            KUGOU_SEARCH_URL,
+            &[
+                ("ver", "1"),
+                ("man", "yes"),
+                ("client", "pc"),
+                ("keyword", keyword.trim()),
+                ("duration", &duration),
+                ("hash", ""),
+            ],
+        )
+        .await?;
+        if result["status"].as_i64() != Some(200) {
+            anyhow::bail!("酷狗音乐搜索失败: {}", result["errmsg"]);
+        }
+        Ok(result["candidates"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                let id = json_id(&x["id"])?;
+                let access_key = json_id(&x["accesskey"])?;
+                Some(LyricCandidate {
+                    provider: self.name().to_string(),
+                    id: format!("{id}_{access_key}"),
+                    title: x["song"].as_str().unwrap_or_default().to_string(),
+                    artist: x["singer"].as_str().unwrap_or_default().to_string(),
+                    album: String::new(),
+                    duration: x["duration"].as_f64().map(|x| x / 1000.0),
+                })
+            })
+            .collect())
+    }
+
+    async fn fetch(&self, candidate: &LyricCandidate) -> anyhow::Result<Option<LyricContent>> {
+        let Some((id, access_key)) = candidate.id.split_once('_') else {
+            anyhow::bail!("无效的酷狗音乐歌词 ID: {}", candidate.id);
+        };
+        let result = crate::http::get_json(
+            KUGOU_DOWNLOAD_URL,
+            &[
+                ("ver", "1"),
+                ("client", "pc"),
+                ("id", id),
+                ("accesskey", access_key),
+                ("fmt", "lrc"),
+                ("charset", "utf8"),
+            ],
+        )
+        .await?;
+        if result["status"].as_i64() != Some(200) {
+            anyhow::bail!("酷狗音乐歌词获取失败: {}", result["info"]);
+        }
+        let Some(content) = result["content"].as_str() else {
+            return Ok(None);
+        };
+        let data = base64::engine::general_purpose::STANDARD.decode(content)?;
+        Ok(
+            non_empty(Some(&String::from_utf8_lossy(&data))).map(|lyric| LyricContent {
+                format: "lrc".into(),
+                lyric,
+                translation: None,
+            }),
+        )
+    }
+}
@@ -0,0 +1,56 @@
+//! LRCLIB 歌词来源
+//!
+//! LRCLIB 是开放的歌词数据库，只返回带有时间轴的歌词，纯文本歌词会被忽略。
+use async_trait::async_trait;
+
+use super::{json_id, non_empty, LyricCandidate, LyricContent, LyricProvider, LyricQuery};
+
+const LRCLIB_API_URL: &str = "https://lrclib.net/api";
+
+pub struct LrclibProvider;
+
+#[async_trait]
+impl LyricProvider for LrclibProvider {
+    fn name(&self) -> &'static str {
+        "lrclib"
+    }
+
+    async fn search(&self, query: &LyricQuery) -> anyhow::Result<Vec<LyricCandidate>> {
+        let mut params = vec![("track_name", query.title.trim())];
+        if !query.artist.trim().is_empty() {
+            params.push(("artist_name", query.artist.trim()));
+        }
+        if !query.album.trim().is_empty() {
+            params.push(("album_name", query.album.trim()));
+        }
+        let result = crate::http::get_json(&format!("{LRCLIB_API_URL}/search"), &params).await?;
+        Ok(result
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|x| x["syncedLyrics"].is_string())
+            .filter_map(|x| {
+                Some(LyricCandidate {
+                    provider: self.name().to_string(),
+                    id: json_id(&x["id"])?,
+                    title: x["trackName"].as_str().unwrap_or_default().to_string(),
+                    artist: x["artistName"].as_str().unwrap_or_default().to_string(),
+                    album: x["albumName"].as_str().unwrap_or_default().to_string(),
+                    duration: x["duration"].as_f64(),
+                })
+            })
+            .collect())
+    }
+
+    async fn fetch(&self, candidate: &LyricCandidate) -> anyhow::Result<Option<LyricContent>> {
+        let result =
+            crate::http::get_json(&format!("{LRCLIB_API_URL}/get/{}", candidate.id), &[]).await?;
+        Ok(
+            non_empty(result["syncedLyrics"].as_str()).map(|lyric| LyricContent {
+                format: "lrc".into(),
+                lyric,
+                translation: None,
+            }),
+        )
+    }
+}
@@ -0,0 +1,344 @@
+//! 在线歌词获取模块
+//!
+//! 每个歌词来源都实现了 [`LyricProvider`]，搜索时会同时向所有来源发送请求，
+//! 再根据标题、艺术家、专辑和时长的相似程度对候选结果进行排序，只获取排名靠前的歌词内容。
+//! 搜索结果会按照搜索条件缓存到磁盘上，之后搜索同一首歌曲时无需再次请求网络。
+//! 除了内置的来源以外，已启用的插件也可以提供歌词来源。
+use std::{path::PathBuf, sync::Mutex};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    error::{CommandError, CommandResult, ErrorCode},
+    plugin::PluginManager,
+};
+
+mod kugou;
+mod lrclib;
+mod netease;
+mod qq;
+
+/// 默认获取歌词内容的候选结果数量
+const DEFAULT_LIMIT: usize = 5;
+/// 时长相差在该范围内时视为完全匹配，单位为秒
+const DURATION_TOLERANCE: f64 = 2.0;
+/// 时长相差超过该值时时长得分为 0，单位为秒
+const DURATION_MAX_DIFF: f64 = 15.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricQuery {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// 歌曲时长，单位为秒
+    pub duration: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricCandidate {
+    #[serde(default)]
+    pub provider: String,
+    /// 歌曲在该来源中的 ID
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// 歌曲时长，单位为秒
+    pub duration: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricContent {
+    /// 歌词的格式，可能为 `lrc` 或 `yrc`
+    pub format: String,
+    pub lyric: String,
+    /// LRC 格式的翻译歌词
+    pub translation: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricSearchResult {
+    #[serde(flatten)]
+    pub candidate: LyricCandidate,
+    /// 匹配程度，范围为 0 ~ 1
+    pub score: f64,
+    #[serde(flatten)]
+    pub content: LyricContent,
+}
+
+#[async_trait]
+pub trait LyricProvider: Send + Sync {
+    /// 歌词来源的名称，用于在命令中指定使用的来源
+    fn name(&self) -> &str;
+
+    /// 搜索歌曲，返回候选结果
+    async fn search(&self, query: &LyricQuery) -> anyhow::Result<Vec<LyricCandidate>>;
+
+    /// 获取候选结果的歌词内容，没有歌词时返回 `None`
+    async fn fetch(&self, candidate: &LyricCandidate) -> anyhow::Result<Option<LyricContent>>;
+}
+
+/// 所有可用的歌词来源
+pub fn providers() -> Vec<Box<dyn LyricProvider>> {
+    vec![
+        Box::new(netease::NeteaseProvider),
+        Box::new(qq::QQMusicProvider),
+        Box::new(kugou::KugouProvider),
+        Box::new(lrclib::LrclibProvider),
+    ]
+}
+
+/// 只保留字母和数字并转换为小写，忽略空格、标点和大小写的差异
+fn normalize(text: &str) -> Vec<char> {
+    text.chars()
+        .filter(|x| x.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// 计算两个字符串的相似程度，范围为 0 ~ 1
+///
+/// 使用字符二元组的 Dice 系数，一方包含另一方时（例如带有 `(Live)` 之类的后缀）至少为 0.8
+fn text_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize(a), normalize(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+    let contains = |long: &[char], short: &[char]| long.windows(short.len()).any(|x| x == short);
+    let contained = if a.len() > b.len() {
+        contains(&a, &b)
+    } else {
+        contains(&b, &a)
+    };
+    if a.len() < 2 || b.len() < 2 {
+        return if contained { 0.8 } else { 0.0 };
+    }
+    let mut b_pairs: Vec<&[char]> = b.windows(2).collect();
+    let mut matched = 0;
+    for pair in a.windows(2) {
+        if let Some(i) = b_pairs.iter().position(|x| *x == pair) {
+            b_pairs.swap_remove(i);
+            matched += 1;
+        }
+    }
+    let dice = (2 * matched) as f64 / (a.len() + b.len() - 2) as f64;
+    if contained {
+        dice.max(0.8)
+    } else {
+        dice
+    }
+}
+
+fn duration_score(a: Option<f64>, b: Option<f64>) -> f64 {
+    match (a, b) {
+        (Some(a), Some(b)) if a > 0.0 && b > 0.0 => {
+            let diff = (a - b).abs();
+            if diff <= DURATION_TOLERANCE {
+                1.0
+            } else {
+                (1.0 - (diff - DURATION_TOLERANCE) / (DURATION_MAX_DIFF - DURATION_TOLERANCE))
+                    .max(0.0)
+            }
+        }
+        // 缺少时长信息时给一个中间值，避免影响其他条件的排序
+        _ => 0.5,
+    }
+}
+
+/// 计算候选结果与搜索条件的匹配程度，范围为 0 ~ 1
+fn candidate_score(query: &LyricQuery, candidate: &LyricCandidate) -> f64 {
+    let title = text_similarity(&query.title, &candidate.title);
+    let artist = if query.artist.trim().is_empty() {
+        0.5
+    } else {
+        text_similarity(&query.artist, &candidate.artist)
+    };
+    let album = if query.album.trim().is_empty() || candidate.album.trim().is_empty() {
+        0.5
+    } else {
+        text_similarity(&query.album, &candidate.album)
+    };
+    let duration = duration_score(query.duration, candidate.duration);
+    title * 0.45 + artist * 0.3 + album * 0.05 + duration * 0.2
+}
+
+/// 按照搜索条件缓存歌词搜索结果
+pub struct LyricCache {
+    dir: PathBuf,
+}
+
+impl LyricCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path_of(&self, query: &LyricQuery, providers: &[&str]) -> PathBuf {
+        let mut hasher = Sha256::new();
+        for part in [&query.title, &query.artist, &query.album] {
+            hasher.update(normalize(part).into_iter().collect::<String>());
+            hasher.update([0]);
+        }
+        // 时长只精确到秒，避免同一首歌曲的不同文件因为细微的时长差异而无法命中缓存
+        hasher.update(
+            query
+                .duration
+                .map(|x| x.round() as i64)
+                .unwrap_or(-1)
+                .to_le_bytes(),
+        );
+        for provider in providers {
+            hasher.update(provider);
+            hasher.update([0]);
+        }
+        self.dir.join(format!("{:x}.json", hasher.finalize()))
+    }
+
+    fn get(&self, query: &LyricQuery, providers: &[&str]) -> Option<Vec<LyricSearchResult>> {
+        let data = std::fs::read(self.path_of(query, providers)).ok()?;
+        serde_json::from_slice(&data).ok()
+    }
+
+    fn set(
+        &self,
+        query: &LyricQuery,
+        providers: &[&str],
+        results: &[LyricSearchResult],
+    ) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        std::fs::write(self.path_of(query, providers), serde_json::to_vec(results)?)?;
+        Ok(())
+    }
+}
+
+pub async fn search_lyrics_from_providers(
+    app: &AppHandle,
+    query: &LyricQuery,
+    provider_names: Option<&[String]>,
+    limit: usize,
+) -> anyhow::Result<Vec<LyricSearchResult>> {
+    let mut providers = providers();
+    providers.extend(
+        app.state::<Mutex<PluginManager>>()
+            .lock()
+            .unwrap()
+            .lyric_providers(),
+    );
+    if let Some(names) = provider_names {
+        providers.retain(|x| names.iter().any(|name| name == x.name()));
+    }
+    let names: Vec<&str> = providers.iter().map(|x| x.name()).collect();
+
+    let cache = app.state::<LyricCache>();
+    if let Some(results) = cache.get(query, &names) {
+        return Ok(results.into_iter().take(limit).collect());
+    }
+
+    let searches = join_all(providers.iter().map(|x| x.search(query))).await;
+    let mut candidates = Vec::new();
+    for (provider, result) in providers.iter().zip(searches) {
+        match result {
+            Ok(result) => candidates.extend(result.into_iter().map(|x| (provider, x))),
+            Err(err) => {
+                println!("从 {} 搜索歌词失败: {err:?}", provider.name());
+            }
+        }
+    }
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .map(|(provider, candidate)| (candidate_score(query, &candidate), provider, candidate))
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    candidates.truncate(limit);
+
+    let contents = join_all(
+        candidates
+            .iter()
+            .map(|(_, provider, candidate)| provider.fetch(candidate)),
+    )
+    .await;
+    let mut results = Vec::new();
+    for ((score, _, candidate), content) in candidates.into_iter().zip(contents) {
+        match content {
+            Ok(Some(content)) => results.push(LyricSearchResult {
+                candidate,
+                score,
+                content,
+            }),
+            Ok(None) => {}
+            Err(err) => {
+                println!(
+                    "从 {} 获取歌曲 {} 的歌词失败: {err:?}",
+                    candidate.provider, candidate.id
+                );
+            }
+        }
+    }
+
+    if !results.is_empty() {
+        if let Err(err) = cache.set(query, &names, &results) {
+            println!("歌词搜索结果缓存失败: {err:?}");
+        }
+    }
+    Ok(results)
+}
+
+/// 从网络上搜索歌词，返回按照匹配程度排序的结果，`duration` 的单位为秒
+///
+/// `providers` 可以指定使用的来源，可选的来源有 `netease`、`qq`、`kugou`、`lrclib` 以及已启用的插件的 ID
+#[tauri::command]
+pub async fn search_lyrics(
+    app: AppHandle,
+    title: String,
+    artist: String,
+    album: Option<String>,
+    duration: Option<f64>,
+    providers: Option<Vec<String>>,
+    limit: Option<usize>,
+) -> CommandResult<Vec<LyricSearchResult>> {
+    if title.trim().is_empty() {
+        return Err(CommandError::new(ErrorCode::InvalidInput, "歌曲标题为空"));
+    }
+    let query = LyricQuery {
+        title,
+        artist,
+        album: album.unwrap_or_default(),
+        duration,
+    };
+    Ok(search_lyrics_from_providers(
+        &app,
+        &query,
+        providers.as_deref(),
+        limit.unwrap_or(DEFAULT_LIMIT),
+    )
+    .await?)
+}
+
+/// 将 JSON 中的 ID 统一转换为字符串，部分接口的 ID 为数字
+fn json_id(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(x) if !x.is_empty() => Some(x.clone()),
+        serde_json::Value::Number(x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+/// 将多个艺术家的名称连接起来
+fn join_artists<'a>(names: impl Iterator<Item = &'a str>) -> String {
+    names.collect::<Vec<_>>().join("/")
+}
+
+/// 过滤掉空白的歌词文本
+fn non_empty(text: Option<&str>) -> Option<String> {
+    text.filter(|x| !x.trim().is_empty()).map(str::to_string)
+}
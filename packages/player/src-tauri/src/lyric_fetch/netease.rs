@@ -0,0 +1,90 @@
+//! 网易云音乐歌词来源
+//!
+//! 有逐字歌词（YRC）时优先返回逐字歌词，否则返回 LRC 歌词。
+use async_trait::async_trait;
+
+use super::{
+    join_artists, json_id, non_empty, LyricCandidate, LyricContent, LyricProvider, LyricQuery,
+};
+
+const NETEASE_SEARCH_URL: &str = "https://music.163.com/api/search/get";
+const NETEASE_LYRIC_URL: &str = "https://music.163.com/api/song/lyric";
+/// 网易云音乐搜索接口中单曲的类型编号
+const SEARCH_TYPE_SONG: &str = "1";
+
+pub struct NeteaseProvider;
+
+#[async_trait]
+impl LyricProvider for NeteaseProvider {
+    fn name(&self) -> &'static str {
+        "netease"
+    }
+
+    async fn search(&self, query: &LyricQuery) -> anyhow::Result<Vec<LyricCandidate>> {
+        let keywords = format!("{} {}", query.title, query.artist);
+        let result = crate::http::get_json(
+            NETEASE_SEARCH_URL,
+            &[
+                ("s", keywords.trim()),
+                ("type", SEARCH_TYPE_SONG),
+                ("limit", "10"),
+            ],
+        )
+        .await?;
+        if result["code"].as_i64() != Some(200) {
+            anyhow::bail!("网易云音乐搜索失败: {}", result["code"]);
+        }
+        Ok(result["result"]["songs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                Some(LyricCandidate {
+                    provider: self.name().to_string(),
+                    id: json_id(&x["id"])?,
+                    title: x["name"].as_str().unwrap_or_default().to_string(),
+                    artist: join_artists(
+                        x["artists"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|x| x["name"].as_str()),
+                    ),
+                    album: x["album"]["name"].as_str().unwrap_or_default().to_string(),
+                    duration: x["duration"].as_f64().map(|x| x / 1000.0),
+                })
+            })
+            .collect())
+    }
+
+    async fn fetch(&self, candidate: &LyricCandidate) -> anyhow::Result<Option<LyricContent>> {
+        let result = crate::http::get_json(
+            NETEASE_LYRIC_URL,
+            &[
+                ("id", candidate.id.as_str()),
+                ("lv", "-1"),
+                ("tv", "-1"),
+                ("yv", "-1"),
+            ],
+        )
+        .await?;
+        if result["code"].as_i64() != Some(200) {
+            anyhow::bail!("网易云音乐歌词获取失败: {}", result["code"]);
+        }
+        let translation = non_empty(result["tlyric"]["lyric"].as_str());
+        if let Some(lyric) = non_empty(result["yrc"]["lyric"].as_str()) {
+            return Ok(Some(LyricContent {
+                format: "yrc".into(),
+                lyric,
+                translation,
+            }));
+        }
+        Ok(
+            non_empty(result["lrc"]["lyric"].as_str()).map(|lyric| LyricContent {
+                format: "lrc".into(),
+                lyric,
+                translation,
+            }),
+        )
+    }
+}
@@ -0,0 +1,89 @@
+//! QQ 音乐歌词来源
+//!
+//! 歌词接口返回的歌词和翻译均经过 Base64 编码，且需要带上 QQ 音乐的 Referer 才能访问。
+use async_trait::async_trait;
+use base64::Engine;
+
+use super::{
+    join_artists, json_id, non_empty, LyricCandidate, LyricContent, LyricProvider, LyricQuery,
+};
+
+const QQ_SEARCH_URL: &str = "https://c.y.qq.com/soso/fcgi-bin/client_search_cp";
+const QQ_LYRIC_URL: &str = "https://c.y.qq.com/lyric/fcgi-bin/fcg_query_lyric_new.fcg";
+const QQ_REFERER: &str = "https://y.qq.com/portal/player.html";
+
+pub struct QQMusicProvider;
+
+fn decode_base64(value: &serde_json::Value) -> Option<String> {
+    let data = base64::engine::general_purpose::STANDARD
+        .decode(value.as_str()?)
+        .ok()?;
+    non_empty(Some(&String::from_utf8_lossy(&data)))
+}
+
+#[async_trait]
+impl LyricProvider for QQMusicProvider {
+    fn name(&self) -> &'static str {
+        "qq"
+    }
+
+    async fn search(&self, query: &LyricQuery) -> anyhow::Result<Vec<LyricCandidate>> {
+        let keywords = format!("{} {}", query.title, query.artist);
+        let result = crate::http::get_json(
+            QQ_SEARCH_URL,
+            &[
+                ("w", keywords.trim()),
+                ("format", "json"),
+                ("p", "1"),
+                ("n", "10"),
+                ("cr", "1"),
+            ],
+        )
+        .await?;
+        if result["code"].as_i64() != Some(0) {
+            anyhow::bail!("QQ 音乐搜索失败: {}", result["code"]);
+        }
+        Ok(result["data"]["song"]["list"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                Some(LyricCandidate {
+                    provider: self.name().to_string(),
+                    id: json_id(&x["songmid"])?,
+                    title: x["songname"].as_str().unwrap_or_default().to_string(),
+                    artist: join_artists(
+                        x["singer"]
+                            .as_array()
+                            .into_iter()
+                            .flatten()
+                            .filter_map(|x| x["name"].as_str()),
+                    ),
+                    album: x["albumname"].as_str().unwrap_or_default().to_string(),
+                    duration: x["interval"].as_f64(),
+                })
+            })
+            .collect())
+    }
+
+    async fn fetch(&self, candidate: &LyricCandidate) -> anyhow::Result<Option<LyricContent>> {
+        let result = crate::http::get_json_with_headers(
+            QQ_LYRIC_URL,
+            &[
+                ("songmid", candidate.id.as_str()),
+                ("format", "json"),
+                ("g_tk", "5381"),
+            ],
+            &[("Referer", QQ_REFERER)],
+        )
+        .await?;
+        if result["code"].as_i64() != Some(0) {
+            anyhow::bail!("QQ 音乐歌词获取失败: {}", result["code"]);
+        }
+        Ok(decode_base64(&result["lyric"]).map(|lyric| LyricContent {
+            format: "lrc".into(),
+            lyric,
+            translation: decode_base64(&result["trans"]),
+        }))
+    }
+}
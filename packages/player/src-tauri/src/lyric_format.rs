@@ -0,0 +1,304 @@
+//! 歌词格式的解析与生成
+//!
+//! 歌词的解析和生成由 `lyric` 模块完成，此处将其暴露为命令供前端使用，
+//! 并提供转换为 WS 协议歌词行的方法，以便将解析出来的歌词直接发送给 WS 客户端。
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use lyric::{ttml::TTMLLyric, LyricLine};
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::{
+    error::{CommandError, CommandResult, ErrorCode},
+    server::AMLLWebSocketServer,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LyricFormat {
+    Lrc,
+    /// 带有单词时间戳的增强 LRC 格式，解析时与 LRC 相同
+    EnhancedLrc,
+    /// ESLyric 逐词歌词格式
+    Eslrc,
+    /// 网易云音乐逐词歌词格式
+    Yrc,
+    /// QQ 音乐逐词歌词格式，可以是解密后带有 XML 的歌词文件
+    Qrc,
+    /// 十六进制字符串格式的加密 QRC 歌词
+    Eqrc,
+    /// Lyricify Syllable 逐词歌词格式
+    Lys,
+    Ttml,
+    /// ASS 字幕格式，只能用于导出
+    Ass,
+}
+
+impl LyricFormat {
+    /// 根据文件扩展名判断歌词格式，`.lrc` 会被当作 LyRiC 格式
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "lrc" => Some(Self::Lrc),
+            "yrc" => Some(Self::Yrc),
+            "qrc" => Some(Self::Qrc),
+            "lys" => Some(Self::Lys),
+            "ttml" => Some(Self::Ttml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Lrc | Self::EnhancedLrc | Self::Eslrc => "lrc",
+            Self::Yrc => "yrc",
+            Self::Qrc | Self::Eqrc => "qrc",
+            Self::Lys => "lys",
+            Self::Ttml => "ttml",
+            Self::Ass => "ass",
+        }
+    }
+}
+
+/// 将各种格式的歌词解析为歌词行，TTML 格式中的元数据会被丢弃
+pub fn parse_lyric_lines(src: &str, format: LyricFormat) -> CommandResult<Vec<LyricLine<'static>>> {
+    let lines = match format {
+        LyricFormat::Lrc | LyricFormat::EnhancedLrc => lyric::lrc::parse_lrc(src),
+        LyricFormat::Eslrc => lyric::eslrc::parse_eslrc(src),
+        LyricFormat::Yrc => lyric::yrc::parse_yrc(src),
+        LyricFormat::Qrc => {
+            return Ok(lyric::qrc::parse_qrc(&lyric::qrc::extract_qrc_content(src))
+                .into_iter()
+                .map(LyricLine::into_owned)
+                .collect())
+        }
+        LyricFormat::Eqrc => return Ok(lyric::qrc::parse_encrypted_qrc(src.trim())),
+        LyricFormat::Lys => lyric::lys::parse_lys(src),
+        LyricFormat::Ttml => {
+            return lyric::ttml::parse_ttml(src)
+                .map(|x| x.lyric_lines)
+                .map_err(|err| CommandError::new(ErrorCode::DecodeFailed, err.to_string()))
+        }
+        LyricFormat::Ass => {
+            return Err(CommandError::new(
+                ErrorCode::UnsupportedFormat,
+                "不支持解析 ASS 字幕格式",
+            ))
+        }
+    };
+    Ok(lines.into_iter().map(LyricLine::into_owned).collect())
+}
+
+/// 将歌词行转换为指定格式的歌词
+pub fn stringify_lyric_lines(lines: &[LyricLine], format: LyricFormat) -> CommandResult<String> {
+    Ok(match format {
+        LyricFormat::Lrc => lyric::lrc::stringify_lrc(lines),
+        LyricFormat::EnhancedLrc => lyric::lrc::stringify_enhanced_lrc(lines),
+        LyricFormat::Eslrc => lyric::eslrc::stringify_eslrc(lines),
+        LyricFormat::Yrc => lyric::yrc::stringify_yrc(lines),
+        LyricFormat::Qrc => lyric::qrc::stringify_qrc(lines),
+        LyricFormat::Eqrc => {
+            return Err(CommandError::new(
+                ErrorCode::UnsupportedFormat,
+                "不支持导出加密的 QRC 歌词",
+            ))
+        }
+        LyricFormat::Lys => lyric::lys::stringify_lys(lines),
+        LyricFormat::Ttml => lyric::ttml::stringify_ttml(&TTMLLyric {
+            metadata: Vec::new(),
+            lyric_lines: lines.to_vec(),
+        }),
+        LyricFormat::Ass => lyric::ass::stringify_ass(lines),
+    })
+}
+
+/// 转换歌词的格式，TTML 之间的转换会保留元数据
+pub fn convert_lyric_text(
+    input: &str,
+    from_format: LyricFormat,
+    to_format: LyricFormat,
+) -> CommandResult<String> {
+    if from_format == LyricFormat::Ttml && to_format == LyricFormat::Ttml {
+        let lyric = lyric::ttml::parse_ttml(input)
+            .map_err(|err| CommandError::new(ErrorCode::DecodeFailed, err.to_string()))?;
+        return Ok(lyric::ttml::stringify_ttml(&lyric));
+    }
+    let lines = parse_lyric_lines(input, from_format)?;
+    if lines.is_empty() {
+        return Err(CommandError::new(
+            ErrorCode::DecodeFailed,
+            "没有解析出任何歌词行",
+        ));
+    }
+    stringify_lyric_lines(&lines, to_format)
+}
+
+/// 将歌词行转换为 WS 协议中的歌词行
+pub fn to_protocol_lines(lines: &[LyricLine]) -> Vec<ws_protocol::LyricLine> {
+    lines
+        .iter()
+        .map(|line| ws_protocol::LyricLine {
+            words: line
+                .words
+                .iter()
+                .map(|word| ws_protocol::LyricWord {
+                    start_time: word.start_time as u32,
+                    end_time: word.end_time as u32,
+                    word: word.word.as_ref().into(),
+                })
+                .collect(),
+            translated_lyric: line.translated_lyric.as_str().into(),
+            roman_lyric: line.roman_lyric.as_str().into(),
+            is_bg: line.is_bg,
+            is_duet: line.is_duet,
+        })
+        .collect()
+}
+
+/// 解析 LyRiC 格式的歌词，支持增强 LRC 格式的单词时间戳和 `[offset]` 标签
+#[tauri::command]
+pub fn parse_lrc_lyric(src: String) -> Vec<LyricLine<'static>> {
+    lyric::lrc::parse_lrc(&src)
+        .into_iter()
+        .map(LyricLine::into_owned)
+        .collect()
+}
+
+/// 将歌词行转换为 LyRiC 格式，`enhanced` 为 `true` 时含有多个单词的歌词行会使用增强 LRC 格式
+#[tauri::command]
+pub fn stringify_lrc_lyric(lines: Vec<LyricLine<'static>>, enhanced: Option<bool>) -> String {
+    if enhanced.unwrap_or(false) {
+        lyric::lrc::stringify_enhanced_lrc(&lines)
+    } else {
+        lyric::lrc::stringify_lrc(&lines)
+    }
+}
+
+/// 将 LRC、ESLyric、YRC、QRC（包括加密的 QRC）、Lyricify Syllable 或 TTML 格式的歌词解析为逐词歌词行
+#[tauri::command]
+pub fn parse_lyric(src: String, format: LyricFormat) -> CommandResult<Vec<LyricLine<'static>>> {
+    parse_lyric_lines(&src, format)
+}
+
+/// 转换歌词的格式
+#[tauri::command]
+pub fn convert_lyric(
+    input: String,
+    from_format: LyricFormat,
+    to_format: LyricFormat,
+) -> CommandResult<String> {
+    convert_lyric_text(&input, from_format, to_format)
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricFileConversion {
+    pub input: String,
+    /// 转换成功时为输出文件的路径
+    pub output: Option<String>,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+}
+
+fn convert_lyric_file(
+    path: &Path,
+    from_format: Option<LyricFormat>,
+    to_format: LyricFormat,
+    output_dir: Option<&Path>,
+) -> CommandResult<PathBuf> {
+    let from_format = from_format
+        .or_else(|| LyricFormat::from_path(path))
+        .ok_or_else(|| {
+            CommandError::new(ErrorCode::UnsupportedFormat, "无法根据扩展名判断歌词格式")
+        })?;
+    let input = std::fs::read_to_string(path)?;
+    let output = convert_lyric_text(&input, from_format, to_format)?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| CommandError::new(ErrorCode::InvalidInput, "无效的文件路径"))?;
+    let output_path = match output_dir {
+        Some(dir) => dir.join(file_name),
+        None => path.to_path_buf(),
+    }
+    .with_extension(to_format.extension());
+    if output_path == path {
+        return Err(CommandError::new(
+            ErrorCode::InvalidInput,
+            "输出文件与输入文件相同",
+        ));
+    }
+    std::fs::write(&output_path, output)?;
+    Ok(output_path)
+}
+
+/// 批量转换歌词文件的格式，未指定 `from_format` 时根据扩展名判断每个文件的格式
+///
+/// 输出文件与输入文件同名，扩展名为目标格式的扩展名，
+/// 未指定 `output_dir` 时保存在输入文件所在的文件夹中
+#[tauri::command]
+pub async fn convert_lyric_files(
+    paths: Vec<String>,
+    from_format: Option<LyricFormat>,
+    to_format: LyricFormat,
+    output_dir: Option<String>,
+) -> CommandResult<Vec<LyricFileConversion>> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let output_dir = output_dir.map(PathBuf::from);
+        if let Some(dir) = &output_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(paths
+            .into_iter()
+            .map(|input| {
+                match convert_lyric_file(
+                    Path::new(&input),
+                    from_format,
+                    to_format,
+                    output_dir.as_deref(),
+                ) {
+                    Ok(output) => LyricFileConversion {
+                        input,
+                        output: Some(output.to_string_lossy().into_owned()),
+                        error: None,
+                        error_code: None,
+                    },
+                    Err(err) => LyricFileConversion {
+                        input,
+                        output: None,
+                        error: Some(err.message),
+                        error_code: Some(err.code),
+                    },
+                }
+            })
+            .collect())
+    })
+    .await?
+}
+
+/// 解析 AMLL 使用的 TTML 格式的歌词，包括元数据、对唱、背景歌词、翻译和音译
+#[tauri::command]
+pub fn parse_ttml_lyric(src: String) -> CommandResult<TTMLLyric<'static>> {
+    lyric::ttml::parse_ttml(&src)
+        .map_err(|err| CommandError::new(ErrorCode::DecodeFailed, err.to_string()))
+}
+
+/// 将歌词转换为 AMLL 使用的 TTML 格式
+#[tauri::command]
+pub fn stringify_ttml_lyric(lyric: TTMLLyric<'static>) -> String {
+    lyric::ttml::stringify_ttml(&lyric)
+}
+
+/// 解析 LyRiC 格式的歌词并发送给所有 WS 客户端
+#[tauri::command]
+pub fn boardcast_lrc_lyric(ws: State<'_, Mutex<AMLLWebSocketServer>>, src: String) {
+    let data = to_protocol_lines(&lyric::lrc::parse_lrc(&src));
+    let ws = ws.clone();
+    tauri::async_runtime::block_on(
+        ws.lock()
+            .unwrap()
+            .boardcast_message(ws_protocol::Body::SetLyric { data }),
+    );
+}
@@ -0,0 +1,269 @@
+//! 歌词存储模块
+//!
+//! 将在线获取或者用户编辑过的歌词保存到 SQLite 数据库中，
+//! 以歌曲 ID 和标签哈希值作为键，使得音乐文件本身没有内嵌歌词时也可以立即加载歌词。
+//! 标签哈希值由标题、艺术家、专辑和时长计算得到，歌曲 ID 改变（例如文件被移动）时仍然可以匹配。
+//! 用户手动覆盖的歌词不会被之后自动获取的歌词替换。
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::State;
+
+use crate::{error::CommandResult, lyric_format::LyricFormat, metadata::MusicMetadata};
+
+/// 数据库结构的迁移语句，每一项对应一个版本，版本号保存在 `user_version` 中
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE lyrics (
+        music_id TEXT NOT NULL,
+        tag_hash TEXT NOT NULL DEFAULT '',
+        lyric TEXT NOT NULL,
+        format TEXT NOT NULL DEFAULT '',
+        source TEXT NOT NULL DEFAULT '',
+        offset_ms REAL NOT NULL DEFAULT 0,
+        overridden INTEGER NOT NULL DEFAULT 0,
+        updated_at INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (music_id, tag_hash)
+    );
+    CREATE INDEX lyrics_tag_hash ON lyrics (tag_hash);
+"#];
+
+/// 用于计算标签哈希值的歌曲信息
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TrackIdentity {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// 时长，单位为秒
+    pub duration: f64,
+}
+
+impl TrackIdentity {
+    pub fn of_metadata(metadata: &MusicMetadata) -> Self {
+        Self {
+            title: metadata.name.clone(),
+            artist: metadata.artist.clone(),
+            album: metadata.album.clone(),
+            duration: metadata.duration,
+        }
+    }
+
+    /// 计算标签哈希值，标题为空时无法可靠地识别歌曲，返回空字符串
+    pub fn tag_hash(&self) -> String {
+        let title = self.title.trim().to_lowercase();
+        if title.is_empty() {
+            return String::new();
+        }
+        // 不同格式读取出来的时长会有细微差别，取整到秒
+        let key = format!(
+            "{title}\0{}\0{}\0{}",
+            self.artist.trim().to_lowercase(),
+            self.album.trim().to_lowercase(),
+            self.duration.round() as i64
+        );
+        format!("{:x}", Sha256::digest(key.as_bytes()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredLyric {
+    pub music_id: String,
+    #[serde(default)]
+    pub tag_hash: String,
+    pub lyric: String,
+    pub format: Option<LyricFormat>,
+    /// 歌词的来源，例如 `netease`、`sidecar`、`user`
+    #[serde(default)]
+    pub source: String,
+    /// 歌词的时间偏移，单位为毫秒，正数代表歌词提前显示
+    #[serde(default)]
+    pub offset: f64,
+    /// 是否为用户手动覆盖的歌词
+    #[serde(default)]
+    pub overridden: bool,
+    /// 最后更新的时间，为 UNIX 时间戳，单位为秒
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+fn format_to_sql(format: Option<LyricFormat>) -> String {
+    format
+        .and_then(|x| serde_json::to_value(x).ok())
+        .and_then(|x| x.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn format_from_sql(format: String) -> Option<LyricFormat> {
+    serde_json::from_value(serde_json::Value::String(format)).ok()
+}
+
+const LYRIC_COLUMNS: &str =
+    "music_id, tag_hash, lyric, format, source, offset_ms, overridden, updated_at";
+
+fn lyric_from_row(row: &rusqlite::Row) -> rusqlite::Result<StoredLyric> {
+    Ok(StoredLyric {
+        music_id: row.get(0)?,
+        tag_hash: row.get(1)?,
+        lyric: row.get(2)?,
+        format: format_from_sql(row.get(3)?),
+        source: row.get(4)?,
+        offset: row.get(5)?,
+        overridden: row.get(6)?,
+        updated_at: row.get(7)?,
+    })
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LyricPurgeFilter {
+    pub music_id: Option<String>,
+    pub source: Option<String>,
+    /// 只清除在此时间之前更新的歌词，为 UNIX 时间戳，单位为秒
+    pub older_than: Option<i64>,
+    /// 是否同时清除用户手动覆盖的歌词
+    pub include_overridden: bool,
+}
+
+pub struct LyricStore {
+    conn: Connection,
+}
+
+impl LyricStore {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> anyhow::Result<Self> {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = conn.transaction()?;
+            tx.execute_batch(migration)?;
+            tx.pragma_update(None, "user_version", i as i64 + 1)?;
+            tx.commit()?;
+        }
+        Ok(Self { conn })
+    }
+
+    /// 查询歌曲的歌词，歌曲 ID 和标签哈希值都匹配的记录优先，其次是用户覆盖过的和最近更新的记录
+    pub fn get(&self, music_id: &str, tag_hash: &str) -> anyhow::Result<Option<StoredLyric>> {
+        let lyric = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {LYRIC_COLUMNS} FROM lyrics
+                    WHERE music_id = ?1 OR (tag_hash = ?2 AND tag_hash != '')
+                    ORDER BY (music_id = ?1 AND tag_hash = ?2) DESC,
+                        (music_id = ?1) DESC, overridden DESC, updated_at DESC
+                    LIMIT 1"
+                ),
+                params![music_id, tag_hash],
+                lyric_from_row,
+            )
+            .optional()?;
+        Ok(lyric)
+    }
+
+    /// 保存歌词，`overridden` 为假时不会替换用户手动覆盖过的歌词，返回是否写入了数据库
+    pub fn put(&self, lyric: &StoredLyric) -> anyhow::Result<bool> {
+        let changed = self.conn.execute(
+            "INSERT INTO lyrics
+                (music_id, tag_hash, lyric, format, source, offset_ms, overridden, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (music_id, tag_hash) DO UPDATE SET
+                lyric = excluded.lyric,
+                format = excluded.format,
+                source = excluded.source,
+                offset_ms = excluded.offset_ms,
+                overridden = excluded.overridden,
+                updated_at = excluded.updated_at
+            WHERE excluded.overridden OR NOT lyrics.overridden",
+            params![
+                lyric.music_id,
+                lyric.tag_hash,
+                lyric.lyric,
+                format_to_sql(lyric.format),
+                lyric.source,
+                lyric.offset,
+                lyric.overridden,
+                now(),
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// 按照条件清除歌词，返回清除的记录数量
+    pub fn purge(&self, filter: &LyricPurgeFilter) -> anyhow::Result<usize> {
+        let removed = self.conn.execute(
+            "DELETE FROM lyrics
+            WHERE (?1 IS NULL OR music_id = ?1)
+                AND (?2 IS NULL OR source = ?2)
+                AND (?3 IS NULL OR updated_at < ?3)
+                AND (?4 OR NOT overridden)",
+            params![
+                filter.music_id,
+                filter.source,
+                filter.older_than,
+                filter.include_overridden,
+            ],
+        )?;
+        Ok(removed)
+    }
+}
+
+/// 查询歌曲保存的歌词，`track` 用于在歌曲 ID 不匹配时根据标签查找
+#[tauri::command]
+pub fn lyric_store_get(
+    store: State<Mutex<LyricStore>>,
+    music_id: String,
+    track: Option<TrackIdentity>,
+) -> CommandResult<Option<StoredLyric>> {
+    let tag_hash = track.map(|x| x.tag_hash()).unwrap_or_default();
+    Ok(store.lock().unwrap().get(&music_id, &tag_hash)?)
+}
+
+/// 保存歌曲的歌词，`overridden` 为真时会作为用户手动覆盖的歌词保存，返回是否写入了数据库
+#[tauri::command]
+pub fn lyric_store_put(
+    store: State<Mutex<LyricStore>>,
+    mut lyric: StoredLyric,
+    track: Option<TrackIdentity>,
+) -> CommandResult<bool> {
+    if let Some(track) = track {
+        lyric.tag_hash = track.tag_hash();
+    }
+    if lyric.source.is_empty() && lyric.overridden {
+        lyric.source = "user".into();
+    }
+    Ok(store.lock().unwrap().put(&lyric)?)
+}
+
+/// 清除保存的歌词，不指定任何条件时清除所有自动获取的歌词
+#[tauri::command]
+pub fn lyric_store_purge(
+    store: State<Mutex<LyricStore>>,
+    filter: Option<LyricPurgeFilter>,
+) -> CommandResult<usize> {
+    Ok(store.lock().unwrap().purge(&filter.unwrap_or_default())?)
+}
@@ -0,0 +1,264 @@
+//! 歌词同步模块
+//!
+//! 根据 WebSocket 客户端发送过来的歌词和播放进度，在后端计算当前应当显示的歌词行和单词，
+//! 并在其发生变化时通过 `lyric-line-changed` 和 `lyric-word-changed` 事件通知前端，
+//! 使歌词同步不再依赖前端的 `requestAnimationFrame` 循环。
+//! 两次播放进度之间的位置由共享的 [`PlaybackClock`] 推算，并会减去音频输出的延迟。
+//!
+//! 每首歌曲还可以单独设置歌词的时间偏移，偏移量会持久化保存到应用数据文件夹中，
+//! 之后再次播放同一首歌曲时会自动应用。
+use std::{collections::HashMap, path::PathBuf, sync::Mutex, time::Duration};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::{
+    error::{self, CommandError, CommandResult, ErrorCode},
+    http_server::HttpServer,
+    playback_clock::PlaybackClock,
+    scripting::Scripting,
+};
+
+/// 计算歌词位置的间隔
+const TICK_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLineChanged {
+    /// 当前歌词行的下标，处于间奏等没有歌词的位置时为空
+    pub index: Option<usize>,
+    /// 当前歌词行的播放进度，范围为 0 ~ 1
+    pub progress: f64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricWordChanged {
+    pub line_index: usize,
+    pub word_index: usize,
+    /// 当前单词的播放进度，范围为 0 ~ 1
+    pub progress: f64,
+}
+
+struct TimedLine {
+    start: f64,
+    end: f64,
+    words: Vec<(f64, f64)>,
+}
+
+/// 将歌词行转换为只有时间信息的形式，没有结束时间的歌词行会持续到下一行开始
+fn timed_lines(lines: &[ws_protocol::LyricLine]) -> Vec<TimedLine> {
+    let mut result: Vec<TimedLine> = lines
+        .iter()
+        .map(|line| {
+            let words: Vec<(f64, f64)> = line
+                .words
+                .iter()
+                .map(|x| (x.start_time as f64, x.end_time as f64))
+                .collect();
+            TimedLine {
+                start: words.iter().map(|x| x.0).fold(f64::INFINITY, f64::min),
+                end: words.iter().map(|x| x.1).fold(0.0, f64::max),
+                words,
+            }
+        })
+        .collect();
+    for i in 0..result.len() {
+        if result[i].end <= result[i].start {
+            let start = result[i].start;
+            result[i].end = result[i + 1..]
+                .iter()
+                .map(|x| x.start)
+                .find(|x| *x > start)
+                .unwrap_or(f64::INFINITY);
+        }
+    }
+    result
+}
+
+fn progress_of(position: f64, start: f64, end: f64) -> f64 {
+    if end.is_finite() && end > start {
+        ((position - start) / (end - start)).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+pub struct LyricSync {
+    offsets_path: Option<PathBuf>,
+    /// 每首歌曲的歌词时间偏移，单位为毫秒，正数代表歌词提前显示
+    offsets: HashMap<String, f64>,
+    music_id: String,
+    lines: Vec<TimedLine>,
+    clock: PlaybackClock,
+    /// 音频输出延迟的补偿，单位为毫秒
+    latency: f64,
+    current_line: Option<usize>,
+    current_word: Option<(usize, usize)>,
+}
+
+impl LyricSync {
+    pub fn new(app: AppHandle, clock: PlaybackClock, offsets_path: Option<PathBuf>) -> Self {
+        let offsets = crate::config::load_json(offsets_path.as_deref(), "歌词时间偏移文件")
+            .unwrap_or_default();
+        std::thread::spawn(move || Self::run(app));
+        Self {
+            offsets_path,
+            offsets,
+            music_id: String::new(),
+            lines: Vec::new(),
+            clock,
+            latency: 0.0,
+            current_line: None,
+            current_word: None,
+        }
+    }
+
+    /// 持续计算歌词位置的后台线程，其它模块 panic 导致锁中毒时仍然继续运行
+    fn run(app: AppHandle) {
+        loop {
+            std::thread::sleep(TICK_INTERVAL);
+            // 线程启动时状态可能还没有交给 Tauri 管理
+            let Some(sync) = app.try_state::<Mutex<LyricSync>>() else {
+                continue;
+            };
+            let (line, word) = error::lock(&sync).tick();
+            if let Some(line) = line {
+                if let Some(http) = app.try_state::<Mutex<HttpServer>>() {
+                    error::lock(&http).publish("lyric-line-changed", &line);
+                }
+                if let Some(scripting) = app.try_state::<Mutex<Scripting>>() {
+                    error::lock(&scripting).on_lyric_line(&line);
+                }
+                if let Err(err) = app.emit_all("lyric-line-changed", line) {
+                    println!("歌词行变化事件发送失败: {err:?}");
+                }
+            }
+            if let Some(word) = word {
+                if let Err(err) = app.emit_all("lyric-word-changed", word) {
+                    println!("歌词单词变化事件发送失败: {err:?}");
+                }
+            }
+        }
+    }
+
+    /// 实际听到的音频所在的位置加上歌词时间偏移后的位置，单位为毫秒
+    fn position(&self) -> Option<f64> {
+        let offset = self.offsets.get(&self.music_id).copied().unwrap_or(0.0);
+        Some(self.clock.position()? - self.latency + offset)
+    }
+
+    fn save_offsets(&self) {
+        crate::config::save_json(self.offsets_path.as_deref(), &self.offsets, "歌词时间偏移");
+    }
+
+    /// 设置歌曲的歌词时间偏移，偏移量为 0 时会删除该歌曲的记录
+    pub fn set_offset(&mut self, music_id: String, offset: f64) {
+        if offset == 0.0 {
+            self.offsets.remove(&music_id);
+        } else {
+            self.offsets.insert(music_id, offset);
+        }
+        // 偏移改变后需要重新计算当前的歌词行
+        self.current_line = None;
+        self.current_word = None;
+        self.save_offsets();
+    }
+
+    fn reset(&mut self) {
+        self.current_line = None;
+        self.current_word = None;
+    }
+
+    pub fn on_body(&mut self, body: &Body) {
+        match body {
+            Body::SetMusicId { id, .. } => {
+                self.music_id = id.to_string();
+                self.lines.clear();
+                self.reset();
+            }
+            Body::SetLyric { data } => {
+                self.lines = timed_lines(data);
+                self.current_line = None;
+                self.current_word = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// 计算当前的歌词行和单词，返回发生了变化的部分
+    fn tick(&mut self) -> (Option<LyricLineChanged>, Option<LyricWordChanged>) {
+        let Some(position) = self.position() else {
+            return (None, None);
+        };
+        // 有多行歌词同时播放（例如背景歌词）时取最晚开始的一行
+        let line_index = self
+            .lines
+            .iter()
+            .rposition(|x| x.start <= position && position < x.end);
+        let line_changed = if line_index != self.current_line {
+            self.current_line = line_index;
+            Some(LyricLineChanged {
+                index: line_index,
+                progress: line_index
+                    .map(|i| progress_of(position, self.lines[i].start, self.lines[i].end))
+                    .unwrap_or_default(),
+            })
+        } else {
+            None
+        };
+
+        let word = line_index.and_then(|i| {
+            let word_index = self.lines[i].words.iter().rposition(|x| x.0 <= position)?;
+            Some((i, word_index))
+        });
+        let word_changed = match word {
+            Some((line_index, word_index)) if word != self.current_word => {
+                let (start, end) = self.lines[line_index].words[word_index];
+                Some(LyricWordChanged {
+                    line_index,
+                    word_index,
+                    progress: progress_of(position, start, end),
+                })
+            }
+            _ => None,
+        };
+        self.current_word = word;
+
+        (line_changed, word_changed)
+    }
+}
+
+/// 设置音频输出延迟的补偿，单位为毫秒，值越大歌词显示得越晚
+#[tauri::command]
+pub fn set_lyric_sync_latency(sync: State<Mutex<LyricSync>>, latency: f64) {
+    sync.lock().unwrap().latency = latency;
+}
+
+/// 获取歌曲的歌词时间偏移，单位为毫秒，未指定歌曲时为当前播放的歌曲
+#[tauri::command]
+pub fn get_lyric_offset(sync: State<Mutex<LyricSync>>, music_id: Option<String>) -> f64 {
+    let sync = sync.lock().unwrap();
+    let music_id = music_id.unwrap_or_else(|| sync.music_id.clone());
+    sync.offsets.get(&music_id).copied().unwrap_or(0.0)
+}
+
+/// 设置歌曲的歌词时间偏移，单位为毫秒，正数代表歌词提前显示，未指定歌曲时为当前播放的歌曲
+#[tauri::command]
+pub fn set_lyric_offset(
+    sync: State<Mutex<LyricSync>>,
+    music_id: Option<String>,
+    offset_ms: f64,
+) -> CommandResult<()> {
+    let mut sync = sync.lock().unwrap();
+    let music_id = music_id.unwrap_or_else(|| sync.music_id.clone());
+    if music_id.is_empty() {
+        return Err(CommandError::new(
+            ErrorCode::InvalidInput,
+            "当前没有正在播放的歌曲",
+        ));
+    }
+    sync.set_offset(music_id, offset_ms);
+    Ok(())
+}
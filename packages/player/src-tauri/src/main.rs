@@ -1,24 +1,158 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use crate::server::AMLLWebSocketServer;
+use crate::{
+    cover::{CoverCache, COVER_PROTOCOL},
+    cover_fetch::CoverFetchCache,
+    error::CommandResult,
+    history::PlayHistory,
+    http_server::HttpServer,
+    library::{LibraryWatcher, MusicLibrary},
+    lyric_fetch::LyricCache,
+    lyric_store::LyricStore,
+    lyric_sync::LyricSync,
+    musicbrainz::MusicBrainzClient,
+    now_playing::NowPlaying,
+    server::{AMLLWebSocketServer, ConnectionInfo, ConnectionTarget, ListenerStatus},
+    ws_auth::WsAuth,
+    ws_client::AMLLWebSocketClient,
+    ws_mdns::MdnsService,
+    ws_stats::ServerStats,
+    ws_tls::{TlsInfo, TlsSource},
+};
 use std::{
     collections::HashSet,
-    net::SocketAddr,
+    path::PathBuf,
     sync::{Arc, Mutex},
 };
-use tauri::{Manager, State};
+use tauri::{AppHandle, Manager, RunEvent, State};
 
+mod cli;
+mod config;
+mod cover;
+mod cover_chunk;
+mod cover_fetch;
+mod deep_link;
+mod discord;
+mod dlna;
+mod download;
+mod error;
+mod fft_forward;
+mod fingerprint;
+mod formats;
+mod headless;
+mod history;
+mod hotkeys;
+mod http;
+mod http_server;
+mod library;
+#[cfg(unix)]
+mod local_socket;
+mod lyric_fetch;
+mod lyric_format;
+mod lyric_store;
+mod lyric_sync;
+mod media_session;
+mod media_source;
+mod metadata;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod musicbrainz;
+mod now_playing;
+mod open_files;
+mod output_device;
+mod playback_clock;
+mod playlist;
+mod plugin;
+mod power;
+mod remote_library;
+mod romanize;
+mod scripting;
+mod scrobble;
 mod server;
+#[cfg(windows)]
+mod smtc;
+mod stream;
+mod tag_writer;
+#[cfg(windows)]
+mod taskbar;
+mod tray;
+mod watchdog;
+mod waveform;
+mod ws_auth;
+mod ws_client;
+mod ws_mdns;
+mod ws_queue;
+mod ws_stats;
+mod ws_tls;
+
+/// 根据 WebSocket 服务器监听的地址更新 mDNS 广播
+fn advertise_server(ws: &AMLLWebSocketServer, mdns: &mut MdnsService, addrs: &[String]) {
+    if let Err(err) = mdns.advertise(addrs, ws.tls_info().is_some(), ws.auth().token().is_some()) {
+        println!("mDNS 服务广播失败: {err:?}");
+    }
+}
 
 // Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
 #[tauri::command]
-fn reopen_connection(addr: &str, ws: State<Mutex<AMLLWebSocketServer>>) {
-    ws.lock().unwrap().reopen(addr.to_string());
+fn reopen_connection(
+    addr: &str,
+    ws: State<Mutex<AMLLWebSocketServer>>,
+    mdns: State<Mutex<MdnsService>>,
+) {
+    let mut ws = ws.lock().unwrap();
+    ws.reopen(addr.to_string());
+    advertise_server(&ws, &mut mdns.lock().unwrap(), &[addr.to_string()]);
+}
+
+/// 让 WebSocket 服务器同时监听多个地址，例如 `127.0.0.1:11444` 和 `[::]:11444`，传入空列表时关闭服务器，
+/// `pick_free_port` 为真时端口被占用会自动改用空闲端口，实际的端口可以通过 [`ws_get_listeners`] 获取
+#[tauri::command]
+fn ws_listen(
+    addrs: Vec<String>,
+    pick_free_port: Option<bool>,
+    ws: State<Mutex<AMLLWebSocketServer>>,
+    mdns: State<Mutex<MdnsService>>,
+) {
+    let mut ws = ws.lock().unwrap();
+    ws.listen(addrs.clone(), pick_free_port.unwrap_or(false));
+    advertise_server(&ws, &mut mdns.lock().unwrap(), &addrs);
+}
+
+/// 在本地套接字（Unix 域套接字）上提供 WebSocket 服务，传入空值时关闭，
+/// 不提供路径时使用应用缓存文件夹中的 `amll.sock`，返回实际使用的路径。
+/// Windows 上的命名管道还没有实现，开启时会返回错误
+#[tauri::command]
+fn ws_listen_local(
+    app: AppHandle,
+    ws: State<Mutex<AMLLWebSocketServer>>,
+    enabled: bool,
+    path: Option<PathBuf>,
+) -> CommandResult<Option<PathBuf>> {
+    let path = enabled
+        .then(|| {
+            path.or_else(|| {
+                app.path_resolver()
+                    .app_cache_dir()
+                    .map(|x| x.join("amll.sock"))
+            })
+        })
+        .flatten();
+    if let Some(parent) = path.as_ref().and_then(|x| x.parent()) {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    ws.lock().unwrap().listen_local(path.clone())?;
+    Ok(path)
 }
 
+/// 获取 WebSocket 服务器各个监听地址的状态
 #[tauri::command]
-fn get_connections(ws: State<Mutex<AMLLWebSocketServer>>) -> Vec<SocketAddr> {
+fn ws_get_listeners(ws: State<Mutex<AMLLWebSocketServer>>) -> Vec<ListenerStatus> {
+    ws.lock().unwrap().listener_statuses()
+}
+
+#[tauri::command]
+fn get_connections(ws: State<Mutex<AMLLWebSocketServer>>) -> Vec<ConnectionInfo> {
     ws.lock().unwrap().get_connections()
 }
 
@@ -28,17 +162,473 @@ fn boardcast_message(ws: State<'_, Mutex<AMLLWebSocketServer>>, data: ws_protoco
     tauri::async_runtime::block_on(ws.lock().unwrap().boardcast_message(data));
 }
 
+/// 获取 WebSocket 服务器的统计信息，包括各类信息的收发数量、字节数和每个客户端的发送队列状态
+#[tauri::command]
+fn ws_get_stats(ws: State<Mutex<AMLLWebSocketServer>>) -> ServerStats {
+    tauri::async_runtime::block_on(ws.lock().unwrap().stats())
+}
+
+/// 向指定的 WebSocket 客户端发送信息，`target` 可以是连接的 ID 或者地址
+#[tauri::command]
+fn ws_send_to(
+    ws: State<'_, Mutex<AMLLWebSocketServer>>,
+    target: ConnectionTarget,
+    data: ws_protocol::Body,
+) -> CommandResult<()> {
+    Ok(tauri::async_runtime::block_on(
+        ws.lock().unwrap().send_to(target, data),
+    )?)
+}
+
+/// 获取 WebSocket 服务器的令牌，没有设置令牌时为空
+#[tauri::command]
+fn ws_get_token(ws: State<Mutex<AMLLWebSocketServer>>) -> Option<String> {
+    ws.lock().unwrap().auth().token()
+}
+
+/// 设置 WebSocket 服务器的令牌，传入空值时关闭身份验证，已连接的客户端不受影响
+#[tauri::command]
+fn ws_set_token(ws: State<Mutex<AMLLWebSocketServer>>, token: Option<String>) {
+    ws.lock().unwrap().auth().set_token(token);
+}
+
+/// 生成新的六位数字配对码作为 WebSocket 服务器的令牌并返回
+#[tauri::command]
+fn ws_rotate_token(ws: State<Mutex<AMLLWebSocketServer>>) -> String {
+    ws.lock().unwrap().auth().rotate_token()
+}
+
+/// 获取 WebSocket 服务器的只读令牌，使用只读令牌连接的客户端不能控制播放
+#[tauri::command]
+fn ws_get_read_only_token(ws: State<Mutex<AMLLWebSocketServer>>) -> Option<String> {
+    ws.lock().unwrap().auth().read_only_token()
+}
+
+/// 设置 WebSocket 服务器的只读令牌，传入空值时取消只读令牌
+#[tauri::command]
+fn ws_set_read_only_token(ws: State<Mutex<AMLLWebSocketServer>>, token: Option<String>) {
+    ws.lock().unwrap().auth().set_read_only_token(token);
+}
+
+/// 生成新的六位数字配对码作为 WebSocket 服务器的只读令牌并返回
+#[tauri::command]
+fn ws_rotate_read_only_token(ws: State<Mutex<AMLLWebSocketServer>>) -> String {
+    ws.lock().unwrap().auth().rotate_read_only_token()
+}
+
+/// 修改已连接的 WebSocket 客户端的权限
+#[tauri::command]
+fn ws_set_client_role(
+    ws: State<Mutex<AMLLWebSocketServer>>,
+    target: ConnectionTarget,
+    role: ws_protocol::ClientRole,
+) -> CommandResult<()> {
+    Ok(ws.lock().unwrap().set_role(target, role)?)
+}
+
+/// 设置 WebSocket 服务器的 TLS 证书来源，传入空值时关闭 TLS，返回证书的信息
+#[tauri::command]
+fn ws_set_tls(
+    app: AppHandle,
+    ws: State<Mutex<AMLLWebSocketServer>>,
+    source: Option<TlsSource>,
+) -> CommandResult<Option<TlsInfo>> {
+    let tls = match source {
+        Some(source) => {
+            let dir = app.path_resolver().app_data_dir().map(|x| x.join("tls"));
+            Some(ws_tls::load_tls(&source, dir.as_deref())?)
+        }
+        None => None,
+    };
+    let info = tls.as_ref().map(|x| x.1.clone());
+    ws.lock().unwrap().set_tls(tls);
+    Ok(info)
+}
+
+/// 获取 WebSocket 服务器当前使用的证书信息，没有开启 TLS 时为空
+#[tauri::command]
+fn ws_get_tls_info(ws: State<Mutex<AMLLWebSocketServer>>) -> Option<TlsInfo> {
+    ws.lock().unwrap().tls_info()
+}
+
+/// 向播放源广播控制指令，用于系统媒体控件等在 WebSocket 连接以外触发的播放控制
+pub(crate) fn send_control(app: &AppHandle, body: ws_protocol::Body) {
+    let ws = app.state::<Mutex<AMLLWebSocketServer>>();
+    tauri::async_runtime::block_on(ws.lock().unwrap().boardcast_message(body));
+}
+
+/// 处理从 WebSocket 客户端接收到的信息主体，分发给需要播放信息的各个模块
+///
+/// 某个模块处理信息时发生 panic 不会中断连接，也不会影响之后的信息，
+/// 错误会被记录下来并通过 `on-internal-error` 事件通知前端。
+/// panic 时持有的锁会中毒，之后会清除这些锁的中毒状态，使其它命令和后台线程可以继续使用对应的模块，
+/// 但发生 panic 的模块的状态可能只更新了一部分
+pub(crate) fn on_client_body(app: &AppHandle, body: &ws_protocol::Body) {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        dispatch_client_body(app, body)
+    }));
+    if let Err(payload) = result {
+        let error = error::InternalError {
+            context: body.type_name().to_string(),
+            message: error::panic_message(payload.as_ref()),
+        };
+        println!(
+            "处理信息 {} 时发生内部错误: {}",
+            error.context, error.message
+        );
+        if let Err(err) = app.emit_all("on-internal-error", error) {
+            println!("内部错误事件发送失败: {err:?}");
+        }
+        clear_poison(app);
+    }
+}
+
+/// 清除分发信息的各个模块的锁的中毒状态
+fn clear_poison(app: &AppHandle) {
+    fn clear<T: Send + 'static>(app: &AppHandle) {
+        if let Some(state) = app.try_state::<Mutex<T>>() {
+            state.clear_poison();
+        }
+    }
+    clear::<PlayHistory>(app);
+    clear::<LyricSync>(app);
+    clear::<NowPlaying>(app);
+    clear::<stream::StreamBuffer>(app);
+    clear::<fft_forward::FFTForwarder>(app);
+    clear::<HttpServer>(app);
+    clear::<scrobble::Scrobbler>(app);
+    clear::<scripting::Scripting>(app);
+    clear::<power::PowerInhibitor>(app);
+    clear::<watchdog::Watchdog>(app);
+    clear::<discord::DiscordPresence>(app);
+    #[cfg(windows)]
+    clear::<smtc::Smtc>(app);
+    #[cfg(target_os = "linux")]
+    clear::<mpris::Mpris>(app);
+}
+
+/// 处理由播放器自身生成的信息（例如系统媒体会话监听和 DLNA 渲染器），
+/// 按照从 WebSocket 客户端收到的信息分发给各个模块并发送给前端
+pub(crate) fn on_local_body(app: &AppHandle, body: ws_protocol::Body) {
+    on_client_body(app, &body);
+    if !stream::should_emit(app, &body) {
+        return;
+    }
+    if let Err(err) = app.emit_all("on-client-body", body) {
+        println!("播放信息事件发送失败: {err:?}");
+    }
+}
+
+fn dispatch_client_body(app: &AppHandle, body: &ws_protocol::Body) {
+    app.state::<playback_clock::PlaybackClock>().on_body(body);
+    error::lock(&app.state::<Mutex<PlayHistory>>()).on_body(body);
+    error::lock(&app.state::<Mutex<LyricSync>>()).on_body(body);
+    error::lock(&app.state::<Mutex<NowPlaying>>()).on_body(body);
+    error::lock(&app.state::<Mutex<stream::StreamBuffer>>()).on_body(body);
+    error::lock(&app.state::<Mutex<fft_forward::FFTForwarder>>()).on_body(body);
+    error::lock(&app.state::<Mutex<HttpServer>>()).publish_body(body);
+    error::lock(&app.state::<Mutex<scrobble::Scrobbler>>()).on_body(body);
+    error::lock(&app.state::<Mutex<scripting::Scripting>>()).on_body(body);
+    tray::on_body(app, body);
+    error::lock(&app.state::<Mutex<power::PowerInhibitor>>()).on_body(app, body);
+    error::lock(&app.state::<Mutex<watchdog::Watchdog>>()).on_body(app, body);
+    error::lock(&app.state::<Mutex<discord::DiscordPresence>>()).on_body(app, body);
+    #[cfg(windows)]
+    if let Some(smtc) = app.try_state::<Mutex<smtc::Smtc>>() {
+        error::lock(&smtc).on_body(body);
+    }
+    #[cfg(windows)]
+    taskbar::on_body(app, body);
+    #[cfg(target_os = "linux")]
+    if let Some(mpris) = app.try_state::<Mutex<mpris::Mpris>>() {
+        error::lock(&mpris).on_body(app, body);
+    }
+}
+
 fn main() {
-    tauri::Builder::default()
+    // 命令行控制命令会发送给正在运行的实例，不会启动播放器
+    if let Some(code) = cli::run() {
+        std::process::exit(code);
+    }
+    // 已经有实例在运行时会把链接或者文件转发过去并退出
+    tauri_plugin_deep_link::prepare(deep_link::IDENTIFIER);
+    let headless = headless::is_headless();
+    let mut builder = tauri::Builder::default();
+    // 无窗口模式下没有可以打开的窗口，托盘图标也没有意义
+    if !headless {
+        builder = builder
+            .system_tray(tray::system_tray())
+            .on_system_tray_event(tray::on_tray_event);
+    }
+    builder
         .invoke_handler(tauri::generate_handler![
             reopen_connection,
+            ws_listen,
+            ws_get_listeners,
+            ws_listen_local,
             get_connections,
-            boardcast_message
+            boardcast_message,
+            ws_send_to,
+            ws_get_stats,
+            ws_get_token,
+            ws_set_token,
+            ws_rotate_token,
+            ws_get_read_only_token,
+            ws_set_read_only_token,
+            ws_rotate_read_only_token,
+            ws_set_client_role,
+            ws_set_tls,
+            ws_get_tls_info,
+            ws_client::ws_connect_to,
+            ws_client::ws_disconnect_remote,
+            ws_client::ws_get_remote_status,
+            ws_client::ws_send_remote,
+            ws_mdns::ws_discover,
+            http_server::http_reopen_server,
+            http_server::http_set_allowed_origins,
+            media_session::media_session_set_enabled,
+            media_session::media_session_is_enabled,
+            dlna::dlna_set_enabled,
+            dlna::dlna_is_enabled,
+            dlna::dlna_set_name,
+            dlna::dlna_report_position,
+            hotkeys::hotkeys_get,
+            hotkeys::hotkeys_bind,
+            discord::discord_get_config,
+            discord::discord_set_config,
+            power::power_get_config,
+            power::power_set_config,
+            playback_clock::playback_get_position,
+            formats::get_supported_formats,
+            fft_forward::fft_forward_get_config,
+            fft_forward::fft_forward_set_config,
+            open_files::take_pending_open_files,
+            scrobble::scrobble_get_status,
+            scrobble::scrobble_get_pending,
+            scrobble::scrobble_flush,
+            scrobble::scrobble_logout,
+            scrobble::scrobble_lastfm_begin_login,
+            scrobble::scrobble_lastfm_complete_login,
+            scrobble::scrobble_listenbrainz_login,
+            remote_library::remote_library_list_servers,
+            remote_library::remote_library_add_server,
+            remote_library::remote_library_remove_server,
+            remote_library::remote_library_search,
+            remote_library::remote_library_albums,
+            remote_library::remote_library_album_songs,
+            remote_library::remote_library_stream_url,
+            download::download_get_config,
+            download::download_set_config,
+            download::download_enqueue,
+            download::download_list,
+            download::download_pause,
+            download::download_resume,
+            download::download_cancel,
+            plugin::plugins_list,
+            plugin::plugins_install,
+            plugin::plugins_uninstall,
+            plugin::plugins_set_enabled,
+            scripting::scripts_list,
+            scripting::scripts_reload,
+            cover::get_cover_thumbnail,
+            cover_fetch::fetch_cover,
+            waveform::generate_waveform,
+            stream::stream_set_high_rate_events,
+            stream::stream_set_progress_interval,
+            lyric_fetch::search_lyrics,
+            lyric_format::parse_lyric,
+            lyric_format::convert_lyric,
+            lyric_format::convert_lyric_files,
+            lyric_format::parse_lrc_lyric,
+            lyric_format::stringify_lrc_lyric,
+            lyric_format::parse_ttml_lyric,
+            lyric_format::stringify_ttml_lyric,
+            lyric_format::boardcast_lrc_lyric,
+            lyric_store::lyric_store_get,
+            lyric_store::lyric_store_put,
+            lyric_store::lyric_store_purge,
+            lyric_sync::set_lyric_sync_latency,
+            lyric_sync::get_lyric_offset,
+            lyric_sync::set_lyric_offset,
+            romanize::romanize_lyric,
+            romanize::romanize_lyric_text,
+            fingerprint::fingerprint_music_file,
+            fingerprint::acoustid_lookup,
+            musicbrainz::musicbrainz_lookup_file,
+            musicbrainz::musicbrainz_lookup_folder,
+            history::get_play_history,
+            history::get_play_counts,
+            playlist::import_playlist_file,
+            playlist::export_playlist_file,
+            metadata::read_local_music_metadata,
+            metadata::scan_music_files,
+            metadata::get_artist_separators,
+            metadata::set_artist_separators,
+            metadata::sidecar::get_lyric_sidecar_templates,
+            metadata::sidecar::set_lyric_sidecar_templates,
+            metadata::encoding::get_tag_encoding_override,
+            metadata::encoding::set_tag_encoding_override,
+            tag_writer::write_music_metadata,
+            tag_writer::embed_music_lyric,
+            tag_writer::embed_music_cover,
+            library::library_get_folders,
+            library::library_add_folder,
+            library::library_add_network_folder,
+            library::library_remove_folder,
+            library::library_scan,
+            library::library_query,
+            library::library_search,
+            library::library_albums,
+            library::library_artists,
+            library::browse_music_folder,
+            library::smart_playlist_create,
+            library::smart_playlist_list,
+            library::smart_playlist_delete,
+            library::smart_playlist_evaluate,
         ])
+        .register_uri_scheme_protocol(COVER_PROTOCOL, cover::handle_cover_protocol)
+        .register_uri_scheme_protocol(stream::STREAM_PROTOCOL, stream::handle_stream_protocol)
+        .register_uri_scheme_protocol(
+            library::webdav::SHARE_PROTOCOL,
+            library::webdav::handle_share_protocol,
+        )
         .setup(|app| {
-            app.manage(Mutex::new(AMLLWebSocketServer::new(app.handle())));
+            let cache_dir = app
+                .path_resolver()
+                .app_cache_dir()
+                .unwrap_or_else(|| std::env::temp_dir().join("amll-player"));
+            app.manage(CoverCache::new(cache_dir.join("covers")));
+            app.manage(MusicBrainzClient::new(cache_dir.join("musicbrainz")));
+            app.manage(CoverFetchCache::new(cache_dir.join("cover-fetch")));
+            app.manage(LyricCache::new(cache_dir.join("lyrics")));
+            app.manage(waveform::WaveformCache::new(cache_dir.join("waveforms")));
+            app.manage(Mutex::new(stream::StreamBuffer::default()));
+            let data_dir = app.path_resolver().app_data_dir();
+            metadata::load_artist_separators(
+                data_dir.as_ref().map(|x| x.join("artist-separators.json")),
+            );
+            metadata::encoding::load_encoding_overrides(
+                data_dir.as_ref().map(|x| x.join("tag-encodings.json")),
+            );
+            app.manage(Mutex::new(PlayHistory::load(
+                data_dir.as_ref().map(|x| x.join("play-history.json")),
+            )));
+            let library = match &data_dir {
+                Some(data_dir) => MusicLibrary::open(&data_dir.join("library.db")),
+                None => MusicLibrary::open_in_memory(),
+            };
+            let library = library.or_else(|err| {
+                println!("音乐库数据库打开失败，将使用内存数据库: {err:?}");
+                MusicLibrary::open_in_memory()
+            })?;
+            let mut watcher = LibraryWatcher::new(app.handle());
+            watcher.sync_folders(&library.folders()?);
+            app.manage(Mutex::new(library));
+            let lyric_store = match &data_dir {
+                Some(data_dir) => LyricStore::open(&data_dir.join("lyrics.db")),
+                None => LyricStore::open_in_memory(),
+            };
+            let lyric_store = lyric_store.or_else(|err| {
+                println!("歌词存储数据库打开失败，将使用内存数据库: {err:?}");
+                LyricStore::open_in_memory()
+            })?;
+            app.manage(Mutex::new(lyric_store));
+            app.manage(Mutex::new(watcher));
+            let clock = playback_clock::PlaybackClock::default();
+            app.manage(clock.clone());
+            app.manage(Mutex::new(LyricSync::new(
+                app.handle(),
+                clock.clone(),
+                data_dir.as_ref().map(|x| x.join("lyric-offsets.json")),
+            )));
+            app.manage(Mutex::new(AMLLWebSocketClient::new(app.handle())));
+            app.manage(Mutex::new(MdnsService::default()));
+            app.manage(Mutex::new(media_session::MediaSessionListener::default()));
+            app.manage(Mutex::new(dlna::DlnaRenderer::load(
+                data_dir.as_ref().map(|x| x.join("dlna.json")),
+            )));
+            let ws_auth = Arc::new(WsAuth::load(
+                data_dir.as_ref().map(|x| x.join("ws-auth.json")),
+            ));
+            app.manage(Mutex::new(NowPlaying::new(clock.clone())));
+            app.manage(Mutex::new(plugin::PluginManager::load(data_dir.clone())));
+            app.manage(Mutex::new(remote_library::RemoteLibraries::load(
+                data_dir.as_ref().map(|x| x.join("remote-libraries.json")),
+            )));
+            app.manage(Mutex::new(download::DownloadManager::load(
+                data_dir.as_ref().map(|x| x.join("download.json")),
+            )));
+            app.manage(Mutex::new(scripting::Scripting::new(
+                app.handle(),
+                data_dir.as_ref().map(|x| x.join("scripts")),
+            )));
+            app.manage(Mutex::new(scrobble::Scrobbler::load(
+                app.handle(),
+                data_dir.clone(),
+            )));
+            app.manage(Mutex::new(discord::DiscordPresence::load(
+                data_dir.as_ref().map(|x| x.join("discord.json")),
+            )));
+            app.manage(Mutex::new(power::PowerInhibitor::load(
+                data_dir.as_ref().map(|x| x.join("power.json")),
+            )));
+            app.manage(Mutex::new(watchdog::Watchdog::new(app.handle())));
+            app.manage(Mutex::new(HttpServer::new(app.handle(), ws_auth.clone())));
+            app.manage(Mutex::new(AMLLWebSocketServer::new(app.handle(), ws_auth)));
+            app.manage(Mutex::new(fft_forward::FFTForwarder::load(
+                app.handle(),
+                data_dir.as_ref().map(|x| x.join("fft-forward.json")),
+            )));
+            let hotkeys = hotkeys::Hotkeys::load(
+                app.handle(),
+                data_dir.as_ref().map(|x| x.join("hotkeys.json")),
+            );
+            hotkeys.register_all();
+            app.manage(Mutex::new(hotkeys));
+            output_device::watch(app.handle());
+            app.manage(Mutex::new(open_files::OpenFiles::default()));
+            open_files::open_args(app.handle());
+            deep_link::register(&app.handle());
+            cli::listen(app.handle());
+            #[cfg(windows)]
+            match smtc::Smtc::new(app.handle()) {
+                Ok(smtc) => {
+                    app.manage(Mutex::new(smtc));
+                }
+                Err(err) => println!("系统媒体传输控件初始化失败: {err:?}"),
+            }
+            #[cfg(windows)]
+            if let Err(err) = taskbar::init(&app.handle()) {
+                println!("任务栏集成初始化失败: {err:?}");
+            }
+            #[cfg(target_os = "linux")]
+            match tauri::async_runtime::block_on(mpris::Mpris::new(app.handle())) {
+                Ok(mpris) => {
+                    app.manage(Mutex::new(mpris));
+                }
+                Err(err) => println!("MPRIS 接口注册失败: {err:?}"),
+            }
+            if headless {
+                headless::start(app);
+            }
             Ok(())
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(move |app, event| match event {
+            // 无窗口模式下关闭主窗口后继续运行
+            RunEvent::ExitRequested { api, .. } if headless => api.prevent_exit(),
+            RunEvent::Exit => {
+                error::lock(&app.state::<Mutex<PlayHistory>>()).finish_current();
+            }
+            #[cfg(target_os = "macos")]
+            RunEvent::Opened { urls } => {
+                let paths = urls
+                    .into_iter()
+                    .filter_map(|x| x.to_file_path().ok())
+                    .collect();
+                open_files::open(app.clone(), paths);
+            }
+            _ => {}
+        });
 }
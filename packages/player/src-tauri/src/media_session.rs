@@ -0,0 +1,426 @@
+//! 系统媒体会话监听模式
+//!
+//! 开启后会定时读取系统中其它播放器的播放状态（Windows 上为 SMTC 会话，Linux 上为 MPRIS），
+//! 转换成 WebSocket 协议的信息后按照客户端发送的信息处理，
+//! 使 AMLL Player 可以在不自己播放的情况下作为任意播放器的歌词显示器使用。
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use tauri::{AppHandle, State};
+use ws_protocol::Body;
+
+/// 读取播放状态的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 会话的专辑封面
+#[derive(Debug, Clone, PartialEq)]
+enum SessionCover {
+    Url(String),
+    Data(Vec<u8>),
+}
+
+/// 从系统读取到的其它播放器的播放状态
+#[derive(Debug, Clone, Default)]
+struct SessionState {
+    /// 播放器的标识，Windows 上为应用的 AUMID，Linux 上为 D-Bus 名称
+    source: String,
+    title: String,
+    artists: Vec<String>,
+    album: String,
+    /// 单位为毫秒
+    duration: u64,
+    /// 单位为毫秒
+    position: f64,
+    playing: bool,
+}
+
+impl SessionState {
+    fn same_track(&self, other: &Self) -> bool {
+        self.source == other.source
+            && self.title == other.title
+            && self.artists == other.artists
+            && self.album == other.album
+    }
+}
+
+#[cfg(windows)]
+mod backend {
+    use windows::{
+        Media::Control::{
+            GlobalSystemMediaTransportControlsSession,
+            GlobalSystemMediaTransportControlsSessionManager,
+            GlobalSystemMediaTransportControlsSessionPlaybackStatus,
+        },
+        Storage::Streams::DataReader,
+    };
+
+    use super::{SessionCover, SessionState};
+
+    /// `DateTime` 的起点（1601 年）与 UNIX 纪元之间的 100 纳秒数
+    const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+
+    pub struct Backend {
+        manager: GlobalSystemMediaTransportControlsSessionManager,
+        /// 本应用自身注册的 SMTC 的 AUMID，需要排除以免读取到自己同步过去的信息
+        own_id: Option<String>,
+    }
+
+    impl Backend {
+        pub fn new() -> anyhow::Result<Self> {
+            Ok(Self {
+                manager: GlobalSystemMediaTransportControlsSessionManager::RequestAsync()?.get()?,
+                own_id: std::env::current_exe()
+                    .ok()
+                    .and_then(|x| x.file_name().map(|x| x.to_string_lossy().into_owned())),
+            })
+        }
+
+        fn session(&self) -> Option<GlobalSystemMediaTransportControlsSession> {
+            let session = self.manager.GetCurrentSession().ok()?;
+            let source = session.SourceAppUserModelId().ok()?.to_string();
+            match &self.own_id {
+                Some(own_id) if source.eq_ignore_ascii_case(own_id) => None,
+                _ => Some(session),
+            }
+        }
+
+        pub fn query(&mut self) -> anyhow::Result<Option<SessionState>> {
+            let Some(session) = self.session() else {
+                return Ok(None);
+            };
+            let props = session.TryGetMediaPropertiesAsync()?.get()?;
+            let timeline = session.GetTimelineProperties()?;
+            let playing = session.GetPlaybackInfo()?.PlaybackStatus()?
+                == GlobalSystemMediaTransportControlsSessionPlaybackStatus::Playing;
+            let mut position = timeline.Position()?.Duration as f64 / 10_000.0;
+            // 时间线只在播放器主动更新时变化，播放中需要加上距离上次更新经过的时间
+            if playing {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|x| x.as_nanos() as i64 / 100 + UNIX_EPOCH_TICKS)
+                    .unwrap_or_default();
+                let updated_at = timeline.LastUpdatedTime()?.UniversalTime;
+                position += (now - updated_at).max(0) as f64 / 10_000.0;
+            }
+            let duration = (timeline.EndTime()?.Duration - timeline.StartTime()?.Duration).max(0);
+            Ok(Some(SessionState {
+                source: session.SourceAppUserModelId()?.to_string(),
+                title: props.Title()?.to_string(),
+                artists: super::split_artists(&props.Artist()?.to_string()),
+                album: props.AlbumTitle()?.to_string(),
+                duration: (duration / 10_000) as u64,
+                position,
+                playing,
+            }))
+        }
+
+        pub fn cover(&mut self) -> anyhow::Result<Option<SessionCover>> {
+            let Some(session) = self.session() else {
+                return Ok(None);
+            };
+            let Ok(thumbnail) = session.TryGetMediaPropertiesAsync()?.get()?.Thumbnail() else {
+                return Ok(None);
+            };
+            let stream = thumbnail.OpenReadAsync()?.get()?;
+            let size = stream.Size()? as u32;
+            let reader = DataReader::CreateDataReader(&stream)?;
+            reader.LoadAsync(size)?.get()?;
+            let mut data = vec![0; size as usize];
+            reader.ReadBytes(&mut data)?;
+            Ok(Some(SessionCover::Data(data)))
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod backend {
+    use std::collections::HashMap;
+
+    use zbus::{
+        blocking::{fdo::DBusProxy, Connection, Proxy},
+        zvariant::{OwnedValue, Value},
+    };
+
+    use super::{SessionCover, SessionState};
+
+    const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+    /// 本应用自身注册的 MPRIS 名称，需要排除以免读取到自己同步过去的信息
+    const OWN_NAME: &str = "org.mpris.MediaPlayer2.amll";
+
+    fn string_of(metadata: &HashMap<String, OwnedValue>, key: &str) -> String {
+        match metadata.get(key).map(|x| &**x) {
+            Some(Value::Str(x)) => x.to_string(),
+            _ => String::new(),
+        }
+    }
+
+    fn strings_of(metadata: &HashMap<String, OwnedValue>, key: &str) -> Vec<String> {
+        match metadata.get(key).map(|x| &**x) {
+            Some(Value::Array(x)) => x
+                .get()
+                .iter()
+                .filter_map(|x| match x {
+                    Value::Str(x) => Some(x.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            Some(Value::Str(x)) => super::split_artists(x),
+            _ => Vec::new(),
+        }
+    }
+
+    fn micros_of(metadata: &HashMap<String, OwnedValue>, key: &str) -> i64 {
+        match metadata.get(key).map(|x| &**x) {
+            Some(Value::I64(x)) => *x,
+            Some(Value::U64(x)) => *x as i64,
+            Some(Value::I32(x)) => *x as i64,
+            Some(Value::U32(x)) => *x as i64,
+            _ => 0,
+        }
+    }
+
+    pub struct Backend {
+        connection: Connection,
+        art_url: String,
+    }
+
+    impl Backend {
+        pub fn new() -> anyhow::Result<Self> {
+            Ok(Self {
+                connection: Connection::session()?,
+                art_url: String::new(),
+            })
+        }
+
+        fn player(&self, name: &str) -> zbus::Result<Proxy<'static>> {
+            Proxy::new(
+                &self.connection,
+                name.to_string(),
+                "/org/mpris/MediaPlayer2",
+                "org.mpris.MediaPlayer2.Player",
+            )
+        }
+
+        /// 选择正在播放的播放器，都没有播放时选择第一个暂停的播放器
+        fn active_player(&self) -> anyhow::Result<Option<(String, Proxy<'static>, String)>> {
+            let names = DBusProxy::new(&self.connection)?.list_names()?;
+            let mut paused = None;
+            for name in names {
+                let name = name.to_string();
+                if !name.starts_with(MPRIS_PREFIX) || name == OWN_NAME {
+                    continue;
+                }
+                let Ok(player) = self.player(&name) else {
+                    continue;
+                };
+                let Ok(status) = player.get_property::<String>("PlaybackStatus") else {
+                    continue;
+                };
+                match status.as_str() {
+                    "Playing" => return Ok(Some((name, player, status))),
+                    "Paused" if paused.is_none() => paused = Some((name, player, status)),
+                    _ => {}
+                }
+            }
+            Ok(paused)
+        }
+
+        pub fn query(&mut self) -> anyhow::Result<Option<SessionState>> {
+            let Some((name, player, status)) = self.active_player()? else {
+                return Ok(None);
+            };
+            let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata")?;
+            let position = player.get_property::<i64>("Position").unwrap_or_default();
+            self.art_url = string_of(&metadata, "mpris:artUrl");
+            Ok(Some(SessionState {
+                source: name,
+                title: string_of(&metadata, "xesam:title"),
+                artists: strings_of(&metadata, "xesam:artist"),
+                album: string_of(&metadata, "xesam:album"),
+                duration: (micros_of(&metadata, "mpris:length").max(0) / 1000) as u64,
+                position: position.max(0) as f64 / 1000.0,
+                playing: status == "Playing",
+            }))
+        }
+
+        /// 本地文件的封面读取后以图片数据发送，其它封面直接使用 URL
+        pub fn cover(&mut self) -> anyhow::Result<Option<SessionCover>> {
+            if self.art_url.is_empty() {
+                return Ok(None);
+            }
+            match self.art_url.strip_prefix("file://") {
+                Some(path) => {
+                    let path = percent_encoding::percent_decode_str(path).decode_utf8_lossy();
+                    Ok(Some(SessionCover::Data(std::fs::read(&*path)?)))
+                }
+                None => Ok(Some(SessionCover::Url(self.art_url.clone()))),
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod backend {
+    use super::{SessionCover, SessionState};
+
+    pub struct Backend;
+
+    impl Backend {
+        pub fn new() -> anyhow::Result<Self> {
+            anyhow::bail!("当前平台暂不支持监听系统媒体会话")
+        }
+
+        pub fn query(&mut self) -> anyhow::Result<Option<SessionState>> {
+            Ok(None)
+        }
+
+        pub fn cover(&mut self) -> anyhow::Result<Option<SessionCover>> {
+            Ok(None)
+        }
+    }
+}
+
+/// 拆分以常见分隔符连接的多个歌手名称
+fn split_artists(artists: &str) -> Vec<String> {
+    artists
+        .split(['/', ';', '、'])
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// 将播放状态的变化转换成 WebSocket 协议的信息
+fn diff_bodies(last: Option<&SessionState>, state: &SessionState, new_track: bool) -> Vec<Body> {
+    let mut bodies = Vec::new();
+    if new_track {
+        bodies.push(Body::SetMusicId {
+            id: format!("{}:{}", state.source, state.title).into(),
+            name: state.title.as_str().into(),
+            duration: state.duration,
+        });
+        bodies.push(Body::SetMusicAlbum {
+            id: "".into(),
+            name: state.album.as_str().into(),
+        });
+        bodies.push(Body::SetMusicArtists {
+            artists: state
+                .artists
+                .iter()
+                .map(|x| ws_protocol::Artist {
+                    id: "".into(),
+                    name: x.as_str().into(),
+                })
+                .collect(),
+        });
+    }
+    if new_track || last.map(|x| x.playing) != Some(state.playing) {
+        bodies.push(if state.playing {
+            Body::OnResumed
+        } else {
+            Body::OnPaused
+        });
+    }
+    bodies.push(Body::OnPlayProgress {
+        progress: state.position,
+    });
+    bodies
+}
+
+fn cover_body(cover: SessionCover) -> Body {
+    match cover {
+        SessionCover::Url(img_url) => Body::SetMusicAlbumCoverImageURL {
+            img_url: img_url.into(),
+        },
+        SessionCover::Data(data) => Body::SetMusicAlbumCoverImageData { data },
+    }
+}
+
+fn run(app: AppHandle, stop: Arc<AtomicBool>) {
+    let mut backend = match backend::Backend::new() {
+        Ok(backend) => backend,
+        Err(err) => {
+            println!("系统媒体会话监听开启失败: {err:?}");
+            return;
+        }
+    };
+    println!("已开启系统媒体会话监听");
+    let mut last: Option<SessionState> = None;
+    while !stop.load(Ordering::Relaxed) {
+        match backend.query() {
+            Ok(Some(state)) => {
+                let new_track = !last.as_ref().is_some_and(|x| x.same_track(&state));
+                for body in diff_bodies(last.as_ref(), &state, new_track) {
+                    crate::on_local_body(&app, body);
+                }
+                if new_track {
+                    println!("系统媒体会话切换到歌曲: {} ({})", state.title, state.source);
+                    match backend.cover() {
+                        Ok(Some(cover)) => crate::on_local_body(&app, cover_body(cover)),
+                        Ok(None) => {}
+                        Err(err) => println!("系统媒体会话封面读取失败: {err:?}"),
+                    }
+                }
+                last = Some(state);
+            }
+            Ok(None) => {
+                if last.take().is_some() {
+                    crate::on_local_body(&app, Body::OnPaused);
+                }
+            }
+            Err(err) => {
+                println!("系统媒体会话读取失败: {err:?}");
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    println!("已关闭系统媒体会话监听");
+}
+
+#[derive(Default)]
+pub struct MediaSessionListener {
+    task: Option<(JoinHandle<()>, Arc<AtomicBool>)>,
+}
+
+impl MediaSessionListener {
+    pub fn is_enabled(&self) -> bool {
+        self.task.is_some()
+    }
+
+    pub fn set_enabled(&mut self, app: &AppHandle, enabled: bool) {
+        if let Some((task, stop)) = self.task.take() {
+            stop.store(true, Ordering::Relaxed);
+            let _ = task.join();
+        }
+        if enabled {
+            let stop = Arc::new(AtomicBool::new(false));
+            let app = app.clone();
+            let task = std::thread::spawn({
+                let stop = stop.clone();
+                move || run(app, stop)
+            });
+            self.task = Some((task, stop));
+        }
+    }
+}
+
+/// 开启或关闭系统媒体会话监听模式
+#[tauri::command]
+pub fn media_session_set_enabled(
+    app: AppHandle,
+    listener: State<std::sync::Mutex<MediaSessionListener>>,
+    enabled: bool,
+) {
+    listener.lock().unwrap().set_enabled(&app, enabled);
+}
+
+/// 系统媒体会话监听模式是否已开启
+#[tauri::command]
+pub fn media_session_is_enabled(listener: State<std::sync::Mutex<MediaSessionListener>>) -> bool {
+    listener.lock().unwrap().is_enabled()
+}
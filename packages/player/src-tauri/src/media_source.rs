@@ -0,0 +1,55 @@
+//! 本地音频文件的读取源
+//!
+//! 用户主动要求解码整个文件（生成波形和音频指纹）时优先将文件映射到内存中读取，
+//! Symphonia 的每次读取和跳转都只是内存访问，不再需要系统调用，解码大型 FLAC 文件时跳转也会快很多。
+//! 文件无法映射（例如空文件或者不支持映射的文件系统）时退回到普通的文件读取。
+//!
+//! 映射期间文件被其它程序截断时，访问映射会导致进程因为 SIGBUS 崩溃，
+//! 所以扫描音乐库、监听文件变化这类会在后台批量读取文件（包括网络共享上的文件）的场景只使用普通的文件读取。
+//!
+//! 视频文件也可以作为音频读取，此时需要跳过视频轨道选择其中的音轨。
+use std::{fs::File, io::Cursor, path::Path};
+
+use memmap2::Mmap;
+use symphonia::core::{
+    codecs::CODEC_TYPE_NULL,
+    formats::{FormatReader, Track},
+    io::MediaSource,
+};
+
+/// 打开本地文件作为 Symphonia 的读取源
+pub fn open(path: &Path) -> std::io::Result<Box<dyn MediaSource>> {
+    Ok(Box::new(File::open(path)?))
+}
+
+/// 将本地文件映射到内存中作为 Symphonia 的读取源，只应当用于用户主动要求的一次性解码
+pub fn open_mapped(path: &Path) -> std::io::Result<Box<dyn MediaSource>> {
+    let file = File::open(path)?;
+    // 空文件无法映射，直接读取即可
+    if file.metadata()?.len() == 0 {
+        return Ok(Box::new(file));
+    }
+    // SAFETY: 映射是只读的，解码期间文件被其它程序截断时访问映射可能会导致进程崩溃，
+    // 这里的解码都是一次性读取整个文件的短时间操作，这个风险是可以接受的
+    match unsafe { Mmap::map(&file) } {
+        Ok(map) => Ok(Box::new(Cursor::new(map))),
+        Err(err) => {
+            println!(
+                "文件 {} 无法映射到内存，改为直接读取: {err:?}",
+                path.display()
+            );
+            Ok(Box::new(file))
+        }
+    }
+}
+
+/// 选择要解码的音轨，默认轨道不是音轨（例如视频文件的视频轨道）时使用第一条音轨
+pub fn audio_track(format: &dyn FormatReader) -> Option<&Track> {
+    let is_audio = |track: &&Track| {
+        track.codec_params.codec != CODEC_TYPE_NULL && track.codec_params.sample_rate.is_some()
+    };
+    format
+        .default_track()
+        .filter(is_audio)
+        .or_else(|| format.tracks().iter().find(is_audio))
+}
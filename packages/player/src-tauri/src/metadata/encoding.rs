@@ -0,0 +1,219 @@
+//! 标签文本编码修正
+//!
+//! ID3v1 和 ID3v2.3 等格式的文本标签经常以 GBK、Shift-JIS 等本地编码保存，
+//! 但会被当作 ISO-8859-1 解码成乱码。这里会将这类文本还原成原始字节，
+//! 使用 chardetng 推测编码后重新解码，也可以为单个文件手动指定编码。
+//!
+//! 只有来自 ID3v1 标签以及 ID3v2.3 及更早版本中编码标记为 ISO-8859-1 的文本帧的文本会被修正，
+//! FLAC、Vorbis 注释和 UTF 编码的文本帧中的 “Beyoncé” 这类正常的西欧文本不会被改动。
+//! 网络文件夹中的文件无法直接读取原始标签，不会进行修正。
+//! 手动指定的编码保存在应用数据文件夹中，重启后仍然有效。
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, WINDOWS_1252};
+
+use super::{id3, MusicMetadata};
+use crate::error::{CommandError, CommandResult, ErrorCode};
+
+/// 用户为单个文件手动指定的标签编码
+static ENCODING_OVERRIDES: RwLock<BTreeMap<PathBuf, &'static Encoding>> =
+    RwLock::new(BTreeMap::new());
+/// 手动指定的标签编码的保存位置
+static ENCODING_OVERRIDES_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// ID3v1 标签中的文本字段，位于文件末尾的 128 个字节中
+fn read_id3v1_texts(path: &Path) -> Option<Vec<String>> {
+    let mut file = File::open(path).ok()?;
+    file.seek(SeekFrom::End(-128)).ok()?;
+    let mut tag = [0u8; 128];
+    file.read_exact(&mut tag).ok()?;
+    if &tag[0..3] != b"TAG" {
+        return None;
+    }
+    // 标题、艺术家、专辑和注释，ID3v1.1 的注释最后两个字节为音轨号，截取到空字符即可
+    Some(
+        [&tag[3..33], &tag[33..63], &tag[63..93], &tag[97..127]]
+            .iter()
+            .map(|field| {
+                let end = field.iter().position(|x| *x == 0).unwrap_or(field.len());
+                field[..end].iter().map(|x| *x as char).collect()
+            })
+            .collect(),
+    )
+}
+
+/// ID3v2.3 及更早版本中编码标记为 ISO-8859-1 的文本帧、注释帧和歌词帧的内容
+fn read_id3v2_latin1_texts(path: &Path) -> Vec<String> {
+    let Some((version, frames)) = id3::read_frames(path, |id| {
+        id.starts_with(b"T") || matches!(id, b"COMM" | b"USLT" | b"COM" | b"ULT")
+    }) else {
+        return Vec::new();
+    };
+    if version > 3 {
+        return Vec::new();
+    }
+    let mut result = Vec::new();
+    for (id, data) in frames {
+        let Some((&encoding, data)) = data.split_first() else {
+            continue;
+        };
+        if encoding != 0 {
+            continue;
+        }
+        let mut big_endian = false;
+        // 注释和歌词帧的文本前面有语言代码和描述，用户自定义文本帧的值前面有描述
+        let (skip, has_description) = match id.as_slice() {
+            b"TXXX" | b"TXX" => (0, true),
+            b"COMM" | b"USLT" | b"COM" | b"ULT" => (3, true),
+            _ => (0, false),
+        };
+        let mut data = data.get(skip..).unwrap_or_default();
+        if has_description {
+            match id3::read_string(data, encoding, &mut big_endian) {
+                Some((_, len)) => data = &data[len..],
+                None => continue,
+            }
+        }
+        if let Some((text, _)) = id3::read_string(data, encoding, &mut big_endian) {
+            result.push(text);
+        }
+    }
+    result
+}
+
+/// 文件中可能以本地编码保存的原始文本
+fn legacy_texts(path: &Path) -> Vec<String> {
+    let mut result = read_id3v2_latin1_texts(path);
+    result.extend(read_id3v1_texts(path).unwrap_or_default());
+    result
+        .into_iter()
+        .map(|x| x.trim_matches(['\0', ' ']).to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// 如果文本中的所有字符都在 ISO-8859-1 范围内且包含非 ASCII 字符，则返回其原始字节
+fn latin1_bytes(text: &str) -> Option<Vec<u8>> {
+    let mut has_high = false;
+    let bytes = text
+        .chars()
+        .map(|c| {
+            let c = u32::from(c);
+            has_high |= c >= 0x80;
+            u8::try_from(c).ok()
+        })
+        .collect::<Option<Vec<u8>>>()?;
+    has_high.then_some(bytes)
+}
+
+/// 修正元数据中被错误解码的文本标签
+pub(super) fn fix_tag_encoding(metadata: &mut MusicMetadata, path: &Path) {
+    let encoding_override = ENCODING_OVERRIDES.read().unwrap().get(path).copied();
+    let legacy_texts = legacy_texts(path);
+    if legacy_texts.is_empty() {
+        return;
+    }
+    let mut fields = vec![
+        &mut metadata.name,
+        &mut metadata.artist,
+        &mut metadata.album,
+        &mut metadata.lyric,
+        &mut metadata.genre,
+        &mut metadata.album_artist,
+        &mut metadata.composer,
+        &mut metadata.comment,
+    ];
+    fields.extend(metadata.artists.iter_mut());
+
+    let candidates: Vec<(usize, Vec<u8>)> = fields
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| {
+            let field = field.trim_matches(['\0', ' ']);
+            legacy_texts.iter().any(|x| x == field)
+        })
+        .filter_map(|(i, field)| latin1_bytes(field).map(|bytes| (i, bytes)))
+        .collect();
+    if candidates.is_empty() {
+        return;
+    }
+
+    let encoding = match encoding_override {
+        Some(encoding) => encoding,
+        None => {
+            // 单个标签的文本通常很短，合并所有标签一起推测编码更准确
+            let mut detector = EncodingDetector::new();
+            for (_, bytes) in &candidates {
+                detector.feed(bytes, false);
+            }
+            detector.feed(&[], true);
+            let encoding = detector.guess(None, true);
+            // 推测结果为西欧编码时说明原文本很可能就是正确的
+            if encoding == WINDOWS_1252 {
+                return;
+            }
+            encoding
+        }
+    };
+
+    for (i, bytes) in candidates {
+        let (text, had_errors) = encoding.decode_without_bom_handling(&bytes);
+        if !had_errors {
+            *fields[i] = text.into_owned();
+        }
+    }
+}
+
+/// 读取保存的手动指定的标签编码，启动时调用
+pub fn load_encoding_overrides(path: Option<PathBuf>) {
+    let overrides: BTreeMap<PathBuf, String> =
+        crate::config::load_json(path.as_deref(), "标签编码设置").unwrap_or_default();
+    *ENCODING_OVERRIDES.write().unwrap() = overrides
+        .into_iter()
+        .filter_map(|(path, label)| Some((path, Encoding::for_label(label.as_bytes())?)))
+        .collect();
+    *ENCODING_OVERRIDES_PATH.write().unwrap() = path;
+}
+
+fn save_encoding_overrides(overrides: &BTreeMap<PathBuf, &'static Encoding>) {
+    let path = ENCODING_OVERRIDES_PATH.read().unwrap();
+    let overrides: BTreeMap<&PathBuf, &str> =
+        overrides.iter().map(|(k, v)| (k, v.name())).collect();
+    crate::config::save_json(path.as_deref(), &overrides, "标签编码设置");
+}
+
+/// 获取为文件手动指定的标签编码名称
+#[tauri::command]
+pub fn get_tag_encoding_override(path: PathBuf) -> Option<String> {
+    ENCODING_OVERRIDES
+        .read()
+        .unwrap()
+        .get(&path)
+        .map(|x| x.name().to_string())
+}
+
+/// 为文件手动指定标签编码，例如 `gbk`、`shift_jis`、`big5`，传入空值时恢复为自动检测
+#[tauri::command]
+pub fn set_tag_encoding_override(path: PathBuf, encoding: Option<String>) -> CommandResult<()> {
+    let mut overrides = ENCODING_OVERRIDES.write().unwrap();
+    match encoding {
+        Some(label) => {
+            let encoding = Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| {
+                CommandError::new(ErrorCode::InvalidInput, format!("未知的文本编码: {label}"))
+            })?;
+            overrides.insert(path, encoding);
+        }
+        None => {
+            overrides.remove(&path);
+        }
+    }
+    save_encoding_overrides(&overrides);
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! 快速元数据读取
+//!
+//! 对于标签位于文件头部的格式，只读取标签所在的数据块，并根据文件头中的信息估算时长，
+//! 避免创建完整的格式读取器。目前支持 FLAC（STREAMINFO、Vorbis Comment 和图片块）
+//! 以及带有 ID3v2 标签的 MP3（Xing / Info / VBRI 头或按固定码率估算时长）。
+//! 无法使用快速路径的文件会返回 `None`，由调用方回退到完整的探测流程。
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use symphonia::core::{io::BufReader, meta::MetadataBuilder};
+use symphonia_metadata::{flac, id3v2};
+
+use super::MusicMetadata;
+
+/// 在 ID3v2 标签之后寻找第一个 MPEG 音频帧时最多读取的字节数
+const MPEG_SYNC_SEARCH_LEN: usize = 64 * 1024;
+
+pub(super) fn read_fast_metadata(path: &Path) -> Option<MusicMetadata> {
+    let ext = path
+        .extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mut file = File::open(path).ok()?;
+    let result = match ext.as_str() {
+        "flac" => read_flac(&mut file),
+        "mp3" => read_mp3(&mut file),
+        _ => None,
+    };
+    // 时长未知时需要完整探测才能得到准确的结果
+    result.filter(|x| x.duration > 0.0)
+}
+
+fn read_flac(file: &mut File) -> Option<MusicMetadata> {
+    let mut marker = [0u8; 4];
+    file.read_exact(&mut marker).ok()?;
+    if &marker != b"fLaC" {
+        return None;
+    }
+
+    let mut result = MusicMetadata::default();
+    let mut builder = MetadataBuilder::new();
+    loop {
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header).ok()?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        match block_type {
+            // STREAMINFO
+            0 => {
+                let mut block = vec![0u8; len];
+                file.read_exact(&mut block).ok()?;
+                if block.len() < 18 {
+                    return None;
+                }
+                let packed = u64::from_be_bytes(block[10..18].try_into().ok()?);
+                let sample_rate = (packed >> 44) as u32;
+                let total_samples = packed & 0xF_FFFF_FFFF;
+                if sample_rate > 0 {
+                    result.duration = total_samples as f64 / sample_rate as f64;
+                }
+            }
+            // VORBIS_COMMENT
+            4 => {
+                let mut block = vec![0u8; len];
+                file.read_exact(&mut block).ok()?;
+                flac::read_comment_block(&mut BufReader::new(&block), &mut builder).ok()?;
+            }
+            // PICTURE
+            6 => {
+                let mut block = vec![0u8; len];
+                file.read_exact(&mut block).ok()?;
+                flac::read_picture_block(&mut BufReader::new(&block), &mut builder).ok()?;
+            }
+            _ => {
+                file.seek(SeekFrom::Current(len as i64)).ok()?;
+            }
+        }
+        if is_last {
+            break;
+        }
+    }
+    result.apply_revision(&builder.metadata());
+    Some(result)
+}
+
+fn read_mp3(file: &mut File) -> Option<MusicMetadata> {
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header).ok()?;
+    if &header[0..3] != b"ID3" {
+        return None;
+    }
+    let size = header[6..10]
+        .iter()
+        .fold(0usize, |acc, x| (acc << 7) | (*x & 0x7F) as usize);
+    // 带有页脚的标签会多出 10 个字节
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    let mut tag = vec![0u8; 10 + size];
+    tag[..10].copy_from_slice(&header);
+    file.read_exact(&mut tag[10..]).ok()?;
+    let audio_start = (10 + size + footer) as u64;
+
+    let mut result = MusicMetadata::default();
+    let mut builder = MetadataBuilder::new();
+    id3v2::read_id3v2(&mut BufReader::new(&tag), &mut builder).ok()?;
+    result.apply_revision(&builder.metadata());
+
+    let file_len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(audio_start)).ok()?;
+    let mut buf = Vec::with_capacity(MPEG_SYNC_SEARCH_LEN);
+    file.take(MPEG_SYNC_SEARCH_LEN as u64)
+        .read_to_end(&mut buf)
+        .ok()?;
+    let (offset, frame) = (0..buf.len().saturating_sub(4))
+        .find_map(|i| MpegFrameHeader::parse(&buf[i..i + 4]).map(|x| (i, x)))?;
+    result.duration =
+        frame.estimate_duration(&buf[offset..], file_len - audio_start - offset as u64)?;
+    Some(result)
+}
+
+struct MpegFrameHeader {
+    is_mpeg1: bool,
+    is_mono: bool,
+    /// 码率，单位为 kbps
+    bitrate: u32,
+    sample_rate: u32,
+}
+
+impl MpegFrameHeader {
+    /// 只解析 Layer III 的帧头，其它层交给完整的探测流程处理
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data[0] != 0xFF || data[1] & 0xE0 != 0xE0 {
+            return None;
+        }
+        let version = (data[1] >> 3) & 0b11;
+        let layer = (data[1] >> 1) & 0b11;
+        if version == 0b01 || layer != 0b01 {
+            return None;
+        }
+        let is_mpeg1 = version == 0b11;
+        let bitrate_index = (data[2] >> 4) as usize;
+        let sample_rate_index = ((data[2] >> 2) & 0b11) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+        const MPEG1_BITRATES: [u32; 15] = [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ];
+        const MPEG2_BITRATES: [u32; 15] =
+            [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+        let (bitrate, sample_rate) = match version {
+            0b11 => (
+                MPEG1_BITRATES[bitrate_index],
+                [44100, 48000, 32000][sample_rate_index],
+            ),
+            0b10 => (
+                MPEG2_BITRATES[bitrate_index],
+                [22050, 24000, 16000][sample_rate_index],
+            ),
+            _ => (
+                MPEG2_BITRATES[bitrate_index],
+                [11025, 12000, 8000][sample_rate_index],
+            ),
+        };
+        Some(Self {
+            is_mpeg1,
+            is_mono: data[3] >> 6 == 0b11,
+            bitrate,
+            sample_rate,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        if self.is_mpeg1 {
+            1152
+        } else {
+            576
+        }
+    }
+
+    /// 优先使用 Xing / Info 或 VBRI 头中记录的帧数，没有时按照固定码率估算
+    fn estimate_duration(&self, frame: &[u8], audio_len: u64) -> Option<f64> {
+        let side_info_len = match (self.is_mpeg1, self.is_mono) {
+            (true, true) => 17,
+            (true, false) => 32,
+            (false, true) => 9,
+            (false, false) => 17,
+        };
+        let read_u32 = |offset: usize| {
+            frame
+                .get(offset..offset + 4)
+                .map(|x| u32::from_be_bytes([x[0], x[1], x[2], x[3]]))
+        };
+
+        let xing = 4 + side_info_len;
+        let frames = match frame.get(xing..xing + 4) {
+            Some(b"Xing" | b"Info") if read_u32(xing + 4)? & 1 != 0 => read_u32(xing + 8),
+            _ => match frame.get(36..40) {
+                Some(b"VBRI") => read_u32(36 + 14),
+                _ => None,
+            },
+        };
+        match frames {
+            Some(frames) => {
+                Some(frames as f64 * self.samples_per_frame() as f64 / self.sample_rate as f64)
+            }
+            None => Some(audio_len as f64 * 8.0 / (self.bitrate as f64 * 1000.0)),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! ID3v2 标签的底层解析
+//!
+//! Symphonia 不会提供 SYLT 帧以及文本帧的原始编码等信息，需要时直接解析文件头部的 ID3v2 标签。
+use std::{fs::File, io::Read, path::Path};
+
+/// 去除 ID3v2 的反同步处理，即将 `FF 00` 还原为 `FF`
+fn remove_unsync(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        result.push(data[i]);
+        if data[i] == 0xFF && data.get(i + 1) == Some(&0) {
+            i += 1;
+        }
+        i += 1;
+    }
+    result
+}
+
+fn syncsafe(data: &[u8]) -> usize {
+    data.iter()
+        .fold(0usize, |acc, x| (acc << 7) | (*x & 0x7F) as usize)
+}
+
+/// 读取文件头部 ID3v2 标签中 `wanted` 返回 `true` 的帧，返回标签的版本号以及每一帧的 ID 和内容
+pub(super) fn read_frames(
+    path: &Path,
+    wanted: impl Fn(&[u8]) -> bool,
+) -> Option<(u8, Vec<(Vec<u8>, Vec<u8>)>)> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header).ok()?;
+    if &header[0..3] != b"ID3" {
+        return None;
+    }
+    let version = header[3];
+    let flags = header[5];
+    let mut tag = vec![0u8; syncsafe(&header[6..10])];
+    file.read_exact(&mut tag).ok()?;
+    // ID3v2.4 的反同步处理是针对每一帧的
+    if version < 4 && flags & 0x80 != 0 {
+        tag = remove_unsync(&tag);
+    }
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 {
+        pos = match version {
+            3 => 4 + u32::from_be_bytes(tag.get(0..4)?.try_into().ok()?) as usize,
+            4 => syncsafe(tag.get(0..4)?),
+            _ => 0,
+        };
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+    let mut frames = Vec::new();
+    while pos + header_len <= tag.len() {
+        let frame_header = &tag[pos..pos + header_len];
+        // 遇到填充区域时结束
+        if frame_header[0] == 0 {
+            break;
+        }
+        let id = &frame_header[..id_len];
+        let size = match version {
+            2 => {
+                u32::from_be_bytes([0, frame_header[3], frame_header[4], frame_header[5]]) as usize
+            }
+            3 => u32::from_be_bytes(frame_header[4..8].try_into().ok()?) as usize,
+            _ => syncsafe(&frame_header[4..8]),
+        };
+        let start = pos + header_len;
+        let end = (start + size).min(tag.len());
+        pos = start + size;
+        if !wanted(id) {
+            continue;
+        }
+        let mut data = &tag[start..end];
+        let format_flags = if version == 2 { 0 } else { frame_header[9] };
+        // 压缩或加密的帧无法读取
+        let unsupported = match version {
+            3 => format_flags & 0xC0 != 0,
+            4 => format_flags & 0x0C != 0,
+            _ => false,
+        };
+        if unsupported {
+            continue;
+        }
+        // ID3v2.4 中带有数据长度指示的帧在内容前面有 4 个字节的长度
+        if version == 4 && format_flags & 0x01 != 0 {
+            data = data.get(4..).unwrap_or_default();
+        }
+        let data = if version == 4 && (format_flags & 0x02 != 0 || flags & 0x80 != 0) {
+            remove_unsync(data)
+        } else {
+            data.to_vec()
+        };
+        frames.push((id.to_vec(), data));
+    }
+    Some((version, frames))
+}
+
+/// 读取以空字符结尾的字符串，返回字符串和剩余的数据
+pub(super) fn read_string(
+    data: &[u8],
+    encoding: u8,
+    big_endian: &mut bool,
+) -> Option<(String, usize)> {
+    match encoding {
+        // UTF-16，每个字符串都可能带有自己的字节顺序标记
+        1 | 2 => {
+            let mut i = 0;
+            while i + 1 < data.len() && (data[i] != 0 || data[i + 1] != 0) {
+                i += 2;
+            }
+            let mut text = &data[..i.min(data.len())];
+            match text {
+                [0xFE, 0xFF, ..] => {
+                    *big_endian = true;
+                    text = &text[2..];
+                }
+                [0xFF, 0xFE, ..] => {
+                    *big_endian = false;
+                    text = &text[2..];
+                }
+                _ => {}
+            }
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|x| {
+                    if *big_endian || encoding == 2 {
+                        u16::from_be_bytes([x[0], x[1]])
+                    } else {
+                        u16::from_le_bytes([x[0], x[1]])
+                    }
+                })
+                .collect();
+            Some((String::from_utf16_lossy(&units), (i + 2).min(data.len())))
+        }
+        _ => {
+            let i = data.iter().position(|x| *x == 0).unwrap_or(data.len());
+            let text = if encoding == 3 {
+                String::from_utf8_lossy(&data[..i]).into_owned()
+            } else {
+                data[..i].iter().map(|x| *x as char).collect()
+            };
+            Some((text, (i + 1).min(data.len())))
+        }
+    }
+}
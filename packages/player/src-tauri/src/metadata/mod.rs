@@ -0,0 +1,661 @@
+//! 本地音乐文件元数据读取模块
+//!
+//! 使用 Symphonia 探测音频文件，读取其中的标签、封面图片和时长信息。
+//! 批量读取时会在 rayon 线程池中并行处理，并通过事件报告进度和分批返回结果。
+//! 被错误解码的本地编码文本标签会被自动修正，艺术家标签会按照可配置的分隔符拆分成多个艺术家。
+//! 文件中内嵌的所有图片都会被读取出来，没有标记为封面的图片时会以第一张图片作为封面。
+//! 内嵌的歌词标签不是同步歌词时，会尝试读取 ID3v2 中的 SYLT 同步歌词帧。
+//! 加载单个音乐文件时还会查找外置的歌词文件（详见 [`sidecar`] 模块）以及歌词存储中保存的歌词。
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSource, MediaSourceStream},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Value},
+    probe::Hint,
+};
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    cover::CoverCache,
+    error::{CommandResult, ErrorCode},
+    lyric_format::LyricFormat,
+    lyric_store::{LyricStore, TrackIdentity},
+};
+
+pub mod encoding;
+mod fast;
+mod id3;
+pub mod sidecar;
+mod sylt;
+
+/// 批量读取时每批返回的结果数量
+const SCAN_BATCH_SIZE: usize = 64;
+/// 批量读取时进度事件的最小发送间隔
+const SCAN_PROGRESS_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 默认的艺术家分隔符，匹配时不区分英文大小写
+///
+/// 不包括 `/`，因为 AC/DC 这样的艺术家名称中本身就带有 `/`，需要时可以由用户自行添加。
+const DEFAULT_ARTIST_SEPARATORS: &[&str] =
+    &[";", "、", " feat. ", " feat ", " ft. ", " featuring "];
+
+/// 用户配置的艺术家分隔符，为空时使用 [`DEFAULT_ARTIST_SEPARATORS`]
+static ARTIST_SEPARATORS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+/// 艺术家分隔符设置的保存位置
+static ARTIST_SEPARATORS_PATH: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// 支持读取的音频文件扩展名
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "wav", "ogg", "oga", "opus", "m4a", "aac", "aiff", "aif", "caf", "mka",
+];
+
+/// 可以只读取其中音轨的视频文件扩展名，例如演唱会的录像，不会被音乐库扫描
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "mkv", "webm"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .map(|x| extensions.contains(&x.as_str()))
+        .unwrap_or(false)
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    has_extension(path, AUDIO_EXTENSIONS)
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    has_extension(path, VIDEO_EXTENSIONS)
+}
+
+/// 是否为可以作为音频播放的文件，包括音频文件和视频文件
+pub fn is_playable_file(path: &Path) -> bool {
+    is_audio_file(path) || is_video_file(path)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MusicInfo {
+    pub name: String,
+    /// 原始的艺术家标签，有多个艺术家标签时以 `; ` 连接
+    pub artist: String,
+    /// 按照分隔符拆分后的艺术家列表
+    pub artists: Vec<String>,
+    pub album: String,
+    pub lyric: String,
+    /// 歌词的格式，来自内嵌歌词标签时格式未知，为空
+    pub lyric_format: Option<LyricFormat>,
+    /// 封面图片的 `amll-cover` 协议 URL，没有封面时为空字符串
+    pub cover: String,
+    /// 文件中内嵌的所有图片
+    pub pictures: Vec<MusicPicture>,
+    /// 时长，单位为秒
+    pub duration: f64,
+    pub genre: String,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    /// 标签中记录的原始日期字符串，格式因文件而异
+    pub date: String,
+    /// 从日期中解析出来的年份
+    pub year: Option<u32>,
+    pub album_artist: String,
+    pub composer: String,
+    pub comment: String,
+    pub isrc: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MusicPicture {
+    /// 图片的用途，例如 `frontCover`、`backCover`、`artistPerformer` 等，未标记时为 `other`
+    pub usage: String,
+    pub media_type: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// 图片的 `amll-cover` 协议 URL
+    pub url: String,
+}
+
+/// 从音频文件中读取出来的内嵌图片，保留原始数据
+#[derive(Debug, Clone)]
+pub struct Picture {
+    pub usage: Option<StandardVisualKey>,
+    pub media_type: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub data: Vec<u8>,
+}
+
+/// 从音频文件中读取出来的元数据，封面图片保留原始数据
+#[derive(Debug, Clone, Default)]
+pub struct MusicMetadata {
+    pub name: String,
+    pub artist: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    pub lyric: String,
+    pub lyric_format: Option<LyricFormat>,
+    pub cover: Option<Vec<u8>>,
+    pub pictures: Vec<Picture>,
+    pub duration: f64,
+    pub genre: String,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub date: String,
+    pub album_artist: String,
+    pub composer: String,
+    pub comment: String,
+    pub isrc: Option<String>,
+}
+
+/// 部分格式（例如 RIFF INFO、ID3v2）的文本标签会带有结尾的空字符，需要去除
+fn tag_value_to_string(value: &Value) -> String {
+    value.to_string().trim_end_matches('\0').to_string()
+}
+
+/// 解析音轨号或碟片号，标签中的值可能是 `3` 或者 `3/12` 这样的形式
+fn parse_index(value: &Value) -> Option<u32> {
+    match value {
+        Value::UnsignedInt(x) => u32::try_from(*x).ok(),
+        Value::SignedInt(x) => u32::try_from(*x).ok(),
+        value => tag_value_to_string(value)
+            .split('/')
+            .next()
+            .and_then(|x| x.trim().parse().ok()),
+    }
+    .filter(|x| *x > 0)
+}
+
+/// 从形如 `2023`、`2023-05-01` 或 `2023-05-01T00:00:00` 的日期中解析年份
+pub(crate) fn parse_year(date: &str) -> Option<u32> {
+    let date = date.trim();
+    let digits = date
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(date.len());
+    if digits == 4 {
+        date[..4].parse().ok()
+    } else {
+        None
+    }
+}
+
+/// 读取保存的艺术家分隔符设置，启动时调用
+pub fn load_artist_separators(path: Option<PathBuf>) {
+    let separators =
+        crate::config::load_json(path.as_deref(), "艺术家分隔符设置").unwrap_or_default();
+    *ARTIST_SEPARATORS.write().unwrap() = separators;
+    *ARTIST_SEPARATORS_PATH.write().unwrap() = path;
+}
+
+fn save_artist_separators(separators: &[String]) {
+    let path = ARTIST_SEPARATORS_PATH.read().unwrap();
+    crate::config::save_json(path.as_deref(), separators, "艺术家分隔符设置");
+}
+
+pub fn artist_separators() -> Vec<String> {
+    let separators = ARTIST_SEPARATORS.read().unwrap();
+    if separators.is_empty() {
+        DEFAULT_ARTIST_SEPARATORS
+            .iter()
+            .map(|x| x.to_string())
+            .collect()
+    } else {
+        separators.clone()
+    }
+}
+
+/// 按照分隔符拆分艺术家，结果会去除空白和重复项
+pub fn split_artists(values: &[String], separators: &[String]) -> Vec<String> {
+    let separators: Vec<String> = separators
+        .iter()
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_ascii_lowercase())
+        .collect();
+    let mut result: Vec<String> = Vec::new();
+    for value in values {
+        // 只转换 ASCII 字符的大小写，保证字节位置不变
+        let lower = value.to_ascii_lowercase();
+        let mut start = 0;
+        let mut i = 0;
+        while i < value.len() {
+            match separators
+                .iter()
+                .find(|x| lower[i..].starts_with(x.as_str()))
+            {
+                Some(separator) => {
+                    result.push(value[start..i].to_string());
+                    i += separator.len();
+                    start = i;
+                }
+                None => {
+                    i += lower[i..].chars().next().map(char::len_utf8).unwrap_or(1);
+                }
+            }
+        }
+        result.push(value[start..].to_string());
+    }
+    let mut artists: Vec<String> = Vec::with_capacity(result.len());
+    for artist in result {
+        let artist = artist.trim();
+        if !artist.is_empty() && !artists.iter().any(|x| x == artist) {
+            artists.push(artist.to_string());
+        }
+    }
+    artists
+}
+
+fn visual_usage_name(usage: Option<StandardVisualKey>) -> &'static str {
+    match usage {
+        None => "other",
+        Some(StandardVisualKey::FileIcon) => "fileIcon",
+        Some(StandardVisualKey::OtherIcon) => "otherIcon",
+        Some(StandardVisualKey::FrontCover) => "frontCover",
+        Some(StandardVisualKey::BackCover) => "backCover",
+        Some(StandardVisualKey::Leaflet) => "leaflet",
+        Some(StandardVisualKey::Media) => "media",
+        Some(StandardVisualKey::LeadArtistPerformerSoloist) => "leadArtistPerformerSoloist",
+        Some(StandardVisualKey::ArtistPerformer) => "artistPerformer",
+        Some(StandardVisualKey::Conductor) => "conductor",
+        Some(StandardVisualKey::BandOrchestra) => "bandOrchestra",
+        Some(StandardVisualKey::Composer) => "composer",
+        Some(StandardVisualKey::Lyricist) => "lyricist",
+        Some(StandardVisualKey::RecordingLocation) => "recordingLocation",
+        Some(StandardVisualKey::RecordingSession) => "recordingSession",
+        Some(StandardVisualKey::Performance) => "performance",
+        Some(StandardVisualKey::ScreenCapture) => "screenCapture",
+        Some(StandardVisualKey::Illustration) => "illustration",
+        Some(StandardVisualKey::BandArtistLogo) => "bandArtistLogo",
+        Some(StandardVisualKey::PublisherStudioLogo) => "publisherStudioLogo",
+    }
+}
+
+/// 从 PNG 或 JPEG 图片数据的头部读取图片尺寸，标签中记录的尺寸经常缺失或者不准确
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") && data.len() >= 24 {
+        let width = u32::from_be_bytes(data[16..20].try_into().ok()?);
+        let height = u32::from_be_bytes(data[20..24].try_into().ok()?);
+        return Some((width, height));
+    }
+    if data.starts_with(&[0xFF, 0xD8]) {
+        let mut i = 2;
+        while i + 9 < data.len() {
+            if data[i] != 0xFF {
+                return None;
+            }
+            let marker = data[i + 1];
+            let len = u16::from_be_bytes([data[i + 2], data[i + 3]]) as usize;
+            // SOF0 ~ SOF15 中除了 DHT、JPG、DAC 以外的段记录了图片尺寸
+            if (0xC0..=0xCF).contains(&marker) && !matches!(marker, 0xC4 | 0xC8 | 0xCC) {
+                let height = u16::from_be_bytes([data[i + 5], data[i + 6]]) as u32;
+                let width = u16::from_be_bytes([data[i + 7], data[i + 8]]) as u32;
+                return Some((width, height));
+            }
+            i += 2 + len;
+        }
+    }
+    None
+}
+
+impl Picture {
+    pub fn into_music_picture(self, covers: &CoverCache) -> MusicPicture {
+        MusicPicture {
+            usage: visual_usage_name(self.usage).to_string(),
+            media_type: self.media_type,
+            width: self.width,
+            height: self.height,
+            url: covers.store_url(&self.data),
+        }
+    }
+}
+
+impl MusicMetadata {
+    fn apply_revision(&mut self, rev: &MetadataRevision) {
+        // 部分格式（例如 ID3v2.4、Vorbis Comment）允许存在多个艺术家标签
+        let mut artists = Vec::new();
+        for tag in rev.tags() {
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => self.name = tag_value_to_string(&tag.value),
+                Some(StandardTagKey::Artist) => artists.push(tag_value_to_string(&tag.value)),
+                Some(StandardTagKey::Album) => self.album = tag_value_to_string(&tag.value),
+                Some(StandardTagKey::Lyrics) => self.lyric = tag_value_to_string(&tag.value),
+                Some(StandardTagKey::Genre) => self.genre = tag_value_to_string(&tag.value),
+                Some(StandardTagKey::TrackNumber) => self.track_number = parse_index(&tag.value),
+                Some(StandardTagKey::DiscNumber) => self.disc_number = parse_index(&tag.value),
+                Some(StandardTagKey::Date | StandardTagKey::ReleaseDate) => {
+                    self.date = tag_value_to_string(&tag.value)
+                }
+                // 只有在没有其他日期时才使用原始发行日期
+                Some(StandardTagKey::OriginalDate) if self.date.is_empty() => {
+                    self.date = tag_value_to_string(&tag.value)
+                }
+                Some(StandardTagKey::AlbumArtist) => {
+                    self.album_artist = tag_value_to_string(&tag.value)
+                }
+                Some(StandardTagKey::Composer) => self.composer = tag_value_to_string(&tag.value),
+                Some(StandardTagKey::Comment) => self.comment = tag_value_to_string(&tag.value),
+                Some(StandardTagKey::IdentIsrc) => {
+                    self.isrc = Some(tag_value_to_string(&tag.value)).filter(|x| !x.is_empty())
+                }
+                _ => {}
+            }
+        }
+        if !artists.is_empty() {
+            self.artist = artists.join("; ");
+            self.artists = artists;
+        }
+        if !rev.visuals().is_empty() {
+            self.pictures = rev
+                .visuals()
+                .iter()
+                .map(|visual| {
+                    let (width, height) = match image_dimensions(&visual.data) {
+                        Some((width, height)) => (Some(width), Some(height)),
+                        None => (
+                            visual.dimensions.map(|x| x.width),
+                            visual.dimensions.map(|x| x.height),
+                        ),
+                    };
+                    Picture {
+                        usage: visual.usage,
+                        media_type: visual.media_type.clone(),
+                        width,
+                        height,
+                        data: visual.data.to_vec(),
+                    }
+                })
+                .collect();
+        }
+    }
+
+    /// 转换成发送给前端的音乐信息，图片会被保存到封面缓存中并以 URL 的形式返回
+    pub fn into_music_info(self, covers: &CoverCache) -> MusicInfo {
+        MusicInfo {
+            name: self.name,
+            artist: self.artist,
+            artists: self.artists,
+            album: self.album,
+            lyric: self.lyric,
+            lyric_format: self.lyric_format,
+            cover: self.cover.map(|x| covers.store_url(&x)).unwrap_or_default(),
+            pictures: self
+                .pictures
+                .into_iter()
+                .map(|x| x.into_music_picture(covers))
+                .collect(),
+            duration: self.duration,
+            genre: self.genre,
+            track_number: self.track_number,
+            disc_number: self.disc_number,
+            year: parse_year(&self.date),
+            date: self.date,
+            album_artist: self.album_artist,
+            composer: self.composer,
+            comment: self.comment,
+            isrc: self.isrc,
+        }
+    }
+}
+
+/// 使用 Symphonia 完整探测音频文件并读取元数据
+fn probe_music_metadata(path: &Path) -> anyhow::Result<MusicMetadata> {
+    probe_source_metadata(crate::media_source::open(path)?, path)
+}
+
+/// 使用 Symphonia 完整探测读取源并读取元数据，`path` 只用于提供格式提示
+fn probe_source_metadata(
+    source: Box<dyn MediaSource>,
+    path: &Path,
+) -> anyhow::Result<MusicMetadata> {
+    let mss = MediaSourceStream::new(source, Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|x| x.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut result = MusicMetadata::default();
+
+    // 部分格式（例如 MP3 的 ID3 标签）的元数据是在探测阶段读取的
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(rev) = metadata.current() {
+            result.apply_revision(rev);
+        }
+    }
+    if let Some(rev) = probed.format.metadata().current() {
+        result.apply_revision(rev);
+    }
+
+    if let Some(track) = crate::media_source::audio_track(probed.format.as_ref()) {
+        let params = &track.codec_params;
+        if let (Some(n_frames), Some(time_base)) = (params.n_frames, params.time_base) {
+            let time = time_base.calc_time(n_frames);
+            result.duration = time.seconds as f64 + time.frac;
+        }
+    }
+
+    Ok(result)
+}
+
+/// 读取音频文件的元数据，支持的格式会优先使用只读取标签的快速路径
+pub fn read_music_metadata(path: &Path) -> anyhow::Result<MusicMetadata> {
+    let mut result = match fast::read_fast_metadata(path) {
+        Some(result) => result,
+        None => probe_music_metadata(path)?,
+    };
+
+    encoding::fix_tag_encoding(&mut result, path);
+    // 内嵌的歌词标签可能只是纯文本，此时使用 SYLT 帧中的同步歌词
+    if lyric::lrc::parse_lrc(&result.lyric).is_empty() {
+        if let Some(lines) = sylt::read_sylt_lyric(path) {
+            // 逐词的 SYLT 歌词需要增强 LRC 格式才能保留单词的时间
+            result.lyric = lyric::lrc::stringify_enhanced_lrc(&lines);
+            result.lyric_format = Some(LyricFormat::EnhancedLrc);
+        }
+    }
+    finish_metadata(&mut result, path);
+    Ok(result)
+}
+
+/// 读取网络文件等无法直接访问的读取源的元数据，只能使用 Symphonia 完整探测，
+/// `path` 用于提供格式提示以及在缺少标题时使用文件名作为标题
+pub fn read_source_metadata(
+    source: Box<dyn MediaSource>,
+    path: &Path,
+) -> anyhow::Result<MusicMetadata> {
+    let mut result = probe_source_metadata(source, path)?;
+    encoding::fix_tag_encoding(&mut result, path);
+    finish_metadata(&mut result, path);
+    Ok(result)
+}
+
+/// 拆分艺术家、选出封面并补全标题
+fn finish_metadata(result: &mut MusicMetadata, path: &Path) {
+    result.artists = split_artists(&result.artists, &artist_separators());
+    // 很多文件会把封面图片标记为“其他”，此时以第一张图片作为封面
+    result.cover = result
+        .pictures
+        .iter()
+        .find(|x| x.usage == Some(StandardVisualKey::FrontCover))
+        .or_else(|| result.pictures.first())
+        .map(|x| x.data.clone());
+
+    if result.name.is_empty() {
+        result.name = path
+            .file_stem()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+    }
+}
+
+/// 读取单个音乐文件的元数据，存在外置歌词文件或者歌词存储中有保存的歌词时会代替内嵌的歌词
+#[tauri::command]
+pub async fn read_local_music_metadata(
+    app: AppHandle,
+    file_path: PathBuf,
+) -> CommandResult<MusicInfo> {
+    let info = tauri::async_runtime::spawn_blocking(move || {
+        let mut metadata = read_music_metadata(&file_path)?;
+        let mut source = sidecar::attach_sidecar_lyric(&mut metadata, &file_path);
+        // 没有外置歌词文件时，用户覆盖过的歌词或者在文件没有内嵌歌词时保存的歌词优先
+        if source.source != sidecar::LyricSourceKind::Sidecar {
+            let stored = app.state::<Mutex<LyricStore>>().lock().unwrap().get(
+                &source.file_path,
+                &TrackIdentity::of_metadata(&metadata).tag_hash(),
+            );
+            match stored {
+                Ok(Some(stored)) if stored.overridden || metadata.lyric.trim().is_empty() => {
+                    metadata.lyric = stored.lyric;
+                    metadata.lyric_format = stored.format;
+                    source.source = sidecar::LyricSourceKind::Store;
+                    source.format = stored.format;
+                }
+                Ok(_) => {}
+                Err(err) => println!("歌词存储查询失败: {err:?}"),
+            }
+        }
+        if let Err(err) = app.emit_all("music-lyric-source", source) {
+            println!("歌词来源事件发送失败: {err:?}");
+        }
+        anyhow::Ok(metadata.into_music_info(&app.state::<CoverCache>()))
+    })
+    .await??;
+    Ok(info)
+}
+
+#[tauri::command]
+pub fn get_artist_separators() -> Vec<String> {
+    artist_separators()
+}
+
+/// 设置艺术家分隔符，传入空列表时恢复为默认分隔符
+#[tauri::command]
+pub fn set_artist_separators(separators: Vec<String>) {
+    save_artist_separators(&separators);
+    *ARTIST_SEPARATORS.write().unwrap() = separators;
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    pub done: usize,
+    pub total: usize,
+    pub current: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanMusicResult {
+    pub file_path: String,
+    pub info: Option<MusicInfo>,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanMusicSummary {
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+fn emit_scan_batch(app: &AppHandle, results: Vec<ScanMusicResult>) {
+    if results.is_empty() {
+        return;
+    }
+    if let Err(err) = app.emit_all("scan-music-files-batch", results) {
+        println!("音乐文件扫描结果发送失败: {err:?}");
+    }
+}
+
+/// 并行读取多个音乐文件的元数据
+///
+/// 读取进度会通过 `scan-music-files-progress` 事件发送，
+/// 读取结果会每 [`SCAN_BATCH_SIZE`] 个一批通过 `scan-music-files-batch` 事件发送。
+#[tauri::command]
+pub async fn scan_music_files(
+    app: AppHandle,
+    paths: Vec<PathBuf>,
+) -> CommandResult<ScanMusicSummary> {
+    Ok(tauri::async_runtime::spawn_blocking(move || {
+        let total = paths.len();
+        let done = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+        let last_progress = Mutex::new(Instant::now());
+        let batch = Mutex::new(Vec::with_capacity(SCAN_BATCH_SIZE));
+        let covers = app.state::<CoverCache>();
+
+        paths.par_iter().for_each(|path| {
+            let file_path = path.to_string_lossy().into_owned();
+            let result = match read_music_metadata(path) {
+                Ok(metadata) => ScanMusicResult {
+                    file_path: file_path.clone(),
+                    info: Some(metadata.into_music_info(&covers)),
+                    error: None,
+                    error_code: None,
+                },
+                Err(err) => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    ScanMusicResult {
+                        file_path: file_path.clone(),
+                        info: None,
+                        error: Some(err.to_string()),
+                        error_code: Some(ErrorCode::of(&err)),
+                    }
+                }
+            };
+
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            {
+                let mut last_progress = last_progress.lock().unwrap();
+                if done == total || last_progress.elapsed() >= SCAN_PROGRESS_INTERVAL {
+                    *last_progress = Instant::now();
+                    let progress = ScanProgress {
+                        done,
+                        total,
+                        current: file_path,
+                    };
+                    if let Err(err) = app.emit_all("scan-music-files-progress", progress) {
+                        println!("音乐文件扫描进度发送失败: {err:?}");
+                    }
+                }
+            }
+
+            let full_batch = {
+                let mut batch = batch.lock().unwrap();
+                batch.push(result);
+                if batch.len() >= SCAN_BATCH_SIZE {
+                    std::mem::take(&mut *batch)
+                } else {
+                    Vec::new()
+                }
+            };
+            emit_scan_batch(&app, full_batch);
+        });
+
+        emit_scan_batch(&app, batch.into_inner().unwrap());
+
+        let failed = failed.into_inner();
+        ScanMusicSummary {
+            total,
+            succeeded: total - failed,
+            failed,
+        }
+    })
+    .await?)
+}
@@ -0,0 +1,189 @@
+//! 外置歌词文件的查找
+//!
+//! 加载音乐文件时会按照路径模板在音乐文件附近查找同名的歌词文件，
+//! 例如 `歌曲.ttml`、`lyrics/歌曲.lrc`，找到可以解析的歌词文件时会代替内嵌的歌词标签。
+//! 路径模板相对于音乐文件所在的文件夹，不包含扩展名，支持以下占位符：
+//! `{stem}` 为音乐文件名（不含扩展名），`{title}`、`{artist}`、`{album}` 为对应的标签。
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use chardetng::EncodingDetector;
+use serde::Serialize;
+
+use super::MusicMetadata;
+use crate::lyric_format::{parse_lyric_lines, LyricFormat};
+
+/// 默认的外置歌词文件路径模板
+const DEFAULT_TEMPLATES: &[&str] = &[
+    "{stem}",
+    "lyrics/{stem}",
+    "Lyrics/{stem}",
+    "{artist} - {title}",
+];
+
+/// 同一个路径模板下有多种格式的歌词文件时，按照此顺序优先选择信息更丰富的格式
+const FORMAT_PRIORITY: &[LyricFormat] = &[
+    LyricFormat::Ttml,
+    LyricFormat::Lys,
+    LyricFormat::Qrc,
+    LyricFormat::Yrc,
+    LyricFormat::Lrc,
+];
+
+/// 用户配置的路径模板，为空时使用 [`DEFAULT_TEMPLATES`]
+static SIDECAR_TEMPLATES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum LyricSourceKind {
+    /// 外置的歌词文件
+    Sidecar,
+    /// 音乐文件中内嵌的歌词标签
+    Embedded,
+    /// 歌词存储中保存的歌词
+    Store,
+    None,
+}
+
+/// 加载音乐文件时选用的歌词来源，会通过 `music-lyric-source` 事件发送给前端
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricSource {
+    pub file_path: String,
+    pub source: LyricSourceKind,
+    /// 外置歌词文件的路径
+    pub lyric_path: Option<String>,
+    pub format: Option<LyricFormat>,
+}
+
+pub fn sidecar_templates() -> Vec<String> {
+    let templates = SIDECAR_TEMPLATES.read().unwrap();
+    if templates.is_empty() {
+        DEFAULT_TEMPLATES.iter().map(|x| x.to_string()).collect()
+    } else {
+        templates.clone()
+    }
+}
+
+/// 文件名中不能出现的字符会被替换成下划线
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// 展开路径模板，模板中用到的标签为空时返回空
+fn expand_template(template: &str, stem: &str, metadata: &MusicMetadata) -> Option<String> {
+    let mut result = template.to_string();
+    for (key, value) in [
+        ("{stem}", stem),
+        ("{title}", metadata.name.as_str()),
+        ("{artist}", metadata.artist.as_str()),
+        ("{album}", metadata.album.as_str()),
+    ] {
+        if result.contains(key) {
+            if value.trim().is_empty() {
+                return None;
+            }
+            result = result.replace(key, &sanitize(value.trim()));
+        }
+    }
+    Some(result)
+}
+
+/// 读取歌词文件，非 UTF-8 编码的文件会推测其编码后解码
+fn read_lyric_file(path: &Path) -> std::io::Result<String> {
+    let data = std::fs::read(path)?;
+    if let Ok(text) = std::str::from_utf8(&data) {
+        return Ok(text.trim_start_matches('\u{feff}').to_string());
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(&data, true);
+    let (text, _, _) = detector.guess(None, true).decode(&data);
+    Ok(text.into_owned())
+}
+
+/// 查找音乐文件的外置歌词文件，返回第一个可以解析出歌词行的文件
+pub fn find_sidecar_lyric(
+    path: &Path,
+    metadata: &MusicMetadata,
+) -> Option<(PathBuf, LyricFormat, String)> {
+    let dir = path.parent()?;
+    let stem = path.file_stem()?.to_string_lossy();
+    for template in sidecar_templates() {
+        let Some(relative) = expand_template(&template, &stem, metadata) else {
+            continue;
+        };
+        for format in FORMAT_PRIORITY {
+            let ext = format.extension();
+            // 在区分大小写的文件系统中扩展名也可能是大写的
+            for ext in [ext.to_string(), ext.to_ascii_uppercase()] {
+                let candidate = dir.join(format!("{relative}.{ext}"));
+                if !candidate.is_file() {
+                    continue;
+                }
+                let content = match read_lyric_file(&candidate) {
+                    Ok(content) => content,
+                    Err(err) => {
+                        println!("外置歌词文件 {} 读取失败: {err:?}", candidate.display());
+                        continue;
+                    }
+                };
+                match parse_lyric_lines(&content, *format) {
+                    Ok(lines) if !lines.is_empty() => return Some((candidate, *format, content)),
+                    Ok(_) => {}
+                    Err(err) => {
+                        println!(
+                            "外置歌词文件 {} 解析失败: {}",
+                            candidate.display(),
+                            err.message
+                        );
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// 为音乐元数据关联外置歌词文件，找到时会代替内嵌的歌词，返回最终选用的歌词来源
+pub fn attach_sidecar_lyric(metadata: &mut MusicMetadata, path: &Path) -> LyricSource {
+    let file_path = path.to_string_lossy().into_owned();
+    if let Some((lyric_path, format, content)) = find_sidecar_lyric(path, metadata) {
+        metadata.lyric = content;
+        metadata.lyric_format = Some(format);
+        return LyricSource {
+            file_path,
+            source: LyricSourceKind::Sidecar,
+            lyric_path: Some(lyric_path.to_string_lossy().into_owned()),
+            format: Some(format),
+        };
+    }
+    LyricSource {
+        file_path,
+        source: if metadata.lyric.trim().is_empty() {
+            LyricSourceKind::None
+        } else {
+            LyricSourceKind::Embedded
+        },
+        lyric_path: None,
+        format: metadata.lyric_format,
+    }
+}
+
+#[tauri::command]
+pub fn get_lyric_sidecar_templates() -> Vec<String> {
+    sidecar_templates()
+}
+
+/// 设置外置歌词文件的路径模板，传入空列表时恢复为默认模板
+#[tauri::command]
+pub fn set_lyric_sidecar_templates(templates: Vec<String>) {
+    *SIDECAR_TEMPLATES.write().unwrap() = templates;
+}
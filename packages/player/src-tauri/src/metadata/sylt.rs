@@ -0,0 +1,81 @@
+//! ID3v2 同步歌词帧（SYLT）的读取
+//!
+//! Symphonia 会忽略 SYLT 帧，这里直接解析文件头部的 ID3v2 标签，
+//! 将其中以毫秒为时间单位的同步歌词转换为歌词行。
+//! 以换行符开头的文本会被当作新的一行，其余的文本会作为上一行的单词，
+//! 因此逐行和逐词的同步歌词都可以被正确读取。
+use std::{borrow::Cow, path::Path};
+
+use lyric::{LyricLine, LyricWord};
+
+use super::id3::{read_frames, read_string};
+
+/// SYLT 帧中以毫秒为单位的时间戳格式
+const TIMESTAMP_FORMAT_MS: u8 = 2;
+/// SYLT 帧中表示歌词的内容类型，部分软件会写入“其他”类型
+const CONTENT_TYPES: &[u8] = &[1, 0];
+
+/// 将 SYLT 帧解析为带有时间戳的文本，不是以毫秒为单位的帧会被忽略
+fn parse_sylt_frame(data: &[u8]) -> Option<(u8, Vec<(String, usize)>)> {
+    let encoding = *data.first()?;
+    let timestamp_format = *data.get(4)?;
+    let content_type = *data.get(5)?;
+    if timestamp_format != TIMESTAMP_FORMAT_MS {
+        return None;
+    }
+    let mut big_endian = false;
+    let (_, len) = read_string(data.get(6..)?, encoding, &mut big_endian)?;
+    let mut pos = 6 + len;
+    let mut result = Vec::new();
+    while pos < data.len() {
+        let (text, len) = read_string(&data[pos..], encoding, &mut big_endian)?;
+        pos += len;
+        let time = u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().ok()?);
+        pos += 4;
+        result.push((text, time as usize));
+    }
+    Some((content_type, result))
+}
+
+/// 将带有时间戳的文本组合成歌词行，每个单词会持续到下一个单词开始
+fn build_lines(entries: &[(String, usize)]) -> Vec<LyricLine<'static>> {
+    let has_newline = entries
+        .iter()
+        .any(|(text, _)| text.starts_with(['\n', '\r']));
+    let mut lines: Vec<LyricLine<'static>> = Vec::new();
+    for (i, (text, time)) in entries.iter().enumerate() {
+        let end_time = entries.get(i + 1).map(|x| x.1.max(*time)).unwrap_or(*time);
+        let new_line = !has_newline || text.starts_with(['\n', '\r']) || lines.is_empty();
+        let text = text.trim_start_matches(['\n', '\r']);
+        if new_line {
+            lines.push(LyricLine::default());
+        }
+        if text.is_empty() {
+            continue;
+        }
+        if let Some(line) = lines.last_mut() {
+            line.words.push(LyricWord {
+                start_time: *time,
+                end_time,
+                word: Cow::Owned(text.to_string()),
+            });
+        }
+    }
+    lines.retain(|x| !x.words.is_empty());
+    lines
+}
+
+/// 读取音乐文件中的同步歌词，优先使用内容类型为歌词的 SYLT 帧
+pub fn read_sylt_lyric(path: &Path) -> Option<Vec<LyricLine<'static>>> {
+    let (_, frames) = read_frames(path, |id| id == b"SYLT" || id == b"SLT")?;
+    let frames: Vec<(u8, Vec<(String, usize)>)> = frames
+        .iter()
+        .filter_map(|(_, data)| parse_sylt_frame(data))
+        .filter(|x| !x.1.is_empty())
+        .collect();
+    let entries = CONTENT_TYPES
+        .iter()
+        .find_map(|content_type| frames.iter().find(|x| x.0 == *content_type))
+        .or_else(|| frames.first())?;
+    Some(build_lines(&entries.1)).filter(|x| !x.is_empty())
+}
@@ -0,0 +1,390 @@
+//! Linux 上的 MPRIS D-Bus 接口
+//!
+//! 以 `org.mpris.MediaPlayer2.amll` 的名称注册到会话总线上，
+//! 使 GNOME 和 KDE 的媒体控件、playerctl 和 KDE Connect 等工具可以显示和控制当前播放的歌曲。
+//! 歌曲信息和播放进度来自 [`NowPlaying`]，播放、暂停、切歌、调整进度和音量等操作
+//! 会被转换成对应的控制指令广播给播放源。
+use std::{collections::HashMap, time::Instant};
+
+use tauri::{AppHandle, Manager};
+use ws_protocol::Body;
+use zbus::{
+    dbus_interface,
+    zvariant::{ObjectPath, OwnedValue, Value},
+    Connection, ConnectionBuilder, SignalContext,
+};
+
+use crate::{cover::CoverCache, now_playing::NowPlaying};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.amll";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+/// 播放进度与推算的进度相差超过此值时视为发生了跳转，单位为毫秒
+const SEEK_THRESHOLD: f64 = 1000.0;
+
+/// 将毫秒转换为 MPRIS 使用的微秒
+fn to_micros(millis: f64) -> i64 {
+    (millis * 1000.0) as i64
+}
+
+/// 由歌曲 ID 生成 MPRIS 的曲目 ID，对象路径中只能包含字母、数字和下划线
+fn track_id(music_id: &str) -> String {
+    let id: String = music_id
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+        .collect();
+    if id.is_empty() {
+        "/org/mpris/MediaPlayer2/TrackList/NoTrack".to_string()
+    } else {
+        format!("/org/amll/player/track/{id}")
+    }
+}
+
+struct RootInterface;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl RootInterface {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str {
+        "AMLL Player"
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct PlayerInterface {
+    app: AppHandle,
+    /// 封面图片数据在本地缓存中的路径，客户端只发送了图片数据时用作封面的 URL
+    cover_path: Option<String>,
+}
+
+impl PlayerInterface {
+    fn send(&self, body: Body) {
+        crate::send_control(&self.app, body);
+    }
+
+    fn now_playing(&self) -> crate::now_playing::NowPlayingStatus {
+        self.app
+            .state::<std::sync::Mutex<NowPlaying>>()
+            .lock()
+            .unwrap()
+            .status()
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl PlayerInterface {
+    fn next(&self) {
+        self.send(Body::ForwardSong);
+    }
+
+    fn previous(&self) {
+        self.send(Body::BackwardSong);
+    }
+
+    fn pause(&self) {
+        self.send(Body::Pause);
+    }
+
+    fn play_pause(&self) {
+        if self.now_playing().paused {
+            self.send(Body::Resume);
+        } else {
+            self.send(Body::Pause);
+        }
+    }
+
+    fn stop(&self) {
+        self.send(Body::Pause);
+    }
+
+    fn play(&self) {
+        self.send(Body::Resume);
+    }
+
+    /// 相对当前进度跳转，单位为微秒
+    fn seek(&self, offset: i64) {
+        let status = self.now_playing();
+        let progress = (status.position + offset as f64 / 1000.0).max(0.0);
+        if status.duration > 0 && progress > status.duration as f64 {
+            self.send(Body::ForwardSong);
+        } else {
+            self.send(Body::SetPlayProgress { progress });
+        }
+    }
+
+    /// 跳转到指定的进度，单位为微秒，曲目 ID 与当前曲目不同时忽略
+    fn set_position(&self, track: ObjectPath<'_>, position: i64) {
+        let status = self.now_playing();
+        if track.as_str() != track_id(&status.music_id) || position < 0 {
+            return;
+        }
+        let progress = position as f64 / 1000.0;
+        if status.duration == 0 || progress <= status.duration as f64 {
+            self.send(Body::SetPlayProgress { progress });
+        }
+    }
+
+    fn open_uri(&self, _uri: &str) {}
+
+    #[dbus_interface(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        let status = self.now_playing();
+        if status.music_id.is_empty() {
+            "Stopped"
+        } else if status.paused {
+            "Paused"
+        } else {
+            "Playing"
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let status = self.now_playing();
+        let mut metadata = HashMap::new();
+        let mut insert = |key: &str, value: Value<'_>| {
+            metadata.insert(key.to_string(), OwnedValue::from(value));
+        };
+        if let Ok(path) = ObjectPath::try_from(track_id(&status.music_id)) {
+            insert("mpris:trackid", Value::from(path));
+        }
+        insert(
+            "mpris:length",
+            Value::from(to_micros(status.duration as f64)),
+        );
+        insert("xesam:title", Value::from(status.music_name));
+        insert("xesam:album", Value::from(status.album_name));
+        insert("xesam:artist", Value::from(status.artists));
+        let art_url = status
+            .cover_url
+            .filter(|x| x.starts_with("http://") || x.starts_with("https://"))
+            .or_else(|| self.cover_path.clone());
+        if let Some(art_url) = art_url {
+            insert("mpris:artUrl", Value::from(art_url));
+        }
+        metadata
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.now_playing().volume.unwrap_or(1.0)
+    }
+
+    #[dbus_interface(property)]
+    fn set_volume(&self, volume: f64) {
+        self.send(Body::SetVolume {
+            volume: volume.clamp(0.0, 1.0),
+        });
+    }
+
+    /// 当前的播放进度，单位为微秒，按照规范不会发出属性变化的信号
+    #[dbus_interface(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        to_micros(self.now_playing().position)
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// 需要通知 D-Bus 客户端的属性变化
+enum Change {
+    Metadata,
+    PlaybackStatus,
+    Volume,
+    Seeked(i64),
+}
+
+pub struct Mpris {
+    connection: Connection,
+    /// 最近一次收到的播放进度和收到的时间，用于检测进度跳转
+    progress: Option<(f64, Instant)>,
+    paused: bool,
+}
+
+impl Mpris {
+    pub async fn new(app: AppHandle) -> zbus::Result<Self> {
+        let player = PlayerInterface {
+            app,
+            cover_path: None,
+        };
+        let connection = ConnectionBuilder::session()?
+            .name(BUS_NAME)?
+            .serve_at(OBJECT_PATH, RootInterface)?
+            .serve_at(OBJECT_PATH, player)?
+            .build()
+            .await?;
+        println!("已注册 MPRIS 接口: {BUS_NAME}");
+        Ok(Self {
+            connection,
+            progress: None,
+            paused: false,
+        })
+    }
+
+    pub fn on_body(&mut self, app: &AppHandle, body: &Body) {
+        let change = match body {
+            Body::SetMusicId { .. } => {
+                self.progress = None;
+                Change::Metadata
+            }
+            Body::SetMusicAlbum { .. }
+            | Body::SetMusicArtists { .. }
+            | Body::SetMusicAlbumCoverImageURL { .. } => Change::Metadata,
+            Body::SetMusicAlbumCoverImageData { data } => {
+                let cover_path = Some(data)
+                    .filter(|x| !x.is_empty())
+                    .and_then(|data| match app.state::<CoverCache>().store(data) {
+                        Ok(hash) => app.state::<CoverCache>().file_path(&hash),
+                        Err(err) => {
+                            println!("MPRIS 封面图片缓存失败: {err:?}");
+                            None
+                        }
+                    })
+                    .map(|x| format!("file://{}", x.display()));
+                let connection = self.connection.clone();
+                async_std::task::spawn(async move {
+                    let object_server = connection.object_server();
+                    if let Ok(iface) = object_server
+                        .interface::<_, PlayerInterface>(OBJECT_PATH)
+                        .await
+                    {
+                        iface.get_mut().await.cover_path = cover_path;
+                    }
+                    Self::notify(&connection, Change::Metadata).await
+                });
+                return;
+            }
+            Body::OnPlayProgress { progress } => {
+                let expected = self.progress.map(|(position, at)| {
+                    if self.paused {
+                        position
+                    } else {
+                        position + at.elapsed().as_secs_f64() * 1000.0
+                    }
+                });
+                self.progress = Some((*progress, Instant::now()));
+                match expected {
+                    Some(expected) if (expected - progress).abs() > SEEK_THRESHOLD => {
+                        Change::Seeked(to_micros(*progress))
+                    }
+                    _ => return,
+                }
+            }
+            Body::OnPaused | Body::OnResumed => {
+                self.paused = matches!(body, Body::OnPaused);
+                if let Some((position, at)) = &mut self.progress {
+                    if self.paused {
+                        *position += at.elapsed().as_secs_f64() * 1000.0;
+                    }
+                    *at = Instant::now();
+                }
+                Change::PlaybackStatus
+            }
+            Body::SetVolume { .. } => Change::Volume,
+            _ => return,
+        };
+        let connection = self.connection.clone();
+        async_std::task::spawn(async move { Self::notify(&connection, change).await });
+    }
+
+    async fn notify(connection: &Connection, change: Change) {
+        let result: zbus::Result<()> = async {
+            let iface = connection
+                .object_server()
+                .interface::<_, PlayerInterface>(OBJECT_PATH)
+                .await?;
+            let ctxt = iface.signal_context();
+            let player = iface.get().await;
+            match change {
+                Change::Metadata => {
+                    player.metadata_changed(ctxt).await?;
+                    player.playback_status_changed(ctxt).await?;
+                }
+                Change::PlaybackStatus => player.playback_status_changed(ctxt).await?,
+                Change::Volume => player.volume_changed(ctxt).await?,
+                Change::Seeked(position) => PlayerInterface::seeked(ctxt, position).await?,
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(err) = result {
+            println!("MPRIS 属性变化通知失败: {err:?}");
+        }
+    }
+}
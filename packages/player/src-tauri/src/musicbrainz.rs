@@ -0,0 +1,396 @@
+//! MusicBrainz 元数据查询模块
+//!
+//! 根据音乐文件的标签或者 AcoustID 指纹查询 MusicBrainz，返回候选的发行版本，
+//! 用于标签编辑器一键修正标签。MusicBrainz 要求每秒最多发送一个请求，
+//! 因此所有请求会在这里排队发送，查询结果也会缓存到磁盘上避免重复请求。
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tauri::{
+    api::http::{ClientBuilder, HttpRequestBuilder, ResponseType},
+    AppHandle, Manager,
+};
+
+use crate::{
+    error::CommandResult,
+    fingerprint::{fingerprint_file, lookup_acoustid},
+    metadata::{is_audio_file, read_music_metadata},
+};
+
+const MUSICBRAINZ_API_URL: &str = "https://musicbrainz.org/ws/2";
+/// MusicBrainz 要求请求带有能够标识应用的 User-Agent
+const USER_AGENT: &str = concat!(
+    "AMLLPlayer/",
+    env!("CARGO_PKG_VERSION"),
+    " ( https://github.com/Steve-xmh/applemusic-like-lyrics )"
+);
+/// 两次请求之间的最小间隔
+const REQUEST_INTERVAL: Duration = Duration::from_millis(1100);
+/// 每次查询最多返回的候选数量
+const MAX_CANDIDATES: usize = 10;
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReleaseCandidate {
+    pub release_id: String,
+    pub release_group_id: String,
+    pub title: String,
+    pub artist: String,
+    pub date: String,
+    pub country: String,
+    pub track_count: usize,
+    /// 匹配的录音信息，按专辑查询时为空
+    pub recording_id: Option<String>,
+    pub recording_title: Option<String>,
+    pub track_number: Option<String>,
+    /// 匹配程度，范围为 0 ~ 100
+    pub score: u32,
+}
+
+pub struct MusicBrainzClient {
+    cache_dir: PathBuf,
+    last_request: async_std::sync::Mutex<Option<Instant>>,
+}
+
+fn artist_credit(value: &Value) -> String {
+    value["artist-credit"]
+        .as_array()
+        .map(|credits| {
+            credits
+                .iter()
+                .map(|x| {
+                    format!(
+                        "{}{}",
+                        x["name"].as_str().unwrap_or_default(),
+                        x["joinphrase"].as_str().unwrap_or_default()
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn str_field(value: &Value, key: &str) -> String {
+    value[key].as_str().unwrap_or_default().to_string()
+}
+
+/// 转义 Lucene 查询语法中的特殊字符
+fn escape_query(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "+-&|!(){}[]^\"~*?:\\/".contains(c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn release_candidate(release: &Value, score: u32) -> ReleaseCandidate {
+    ReleaseCandidate {
+        release_id: str_field(release, "id"),
+        release_group_id: str_field(&release["release-group"], "id"),
+        title: str_field(release, "title"),
+        artist: artist_credit(release),
+        date: str_field(release, "date"),
+        country: str_field(release, "country"),
+        track_count: release["track-count"]
+            .as_u64()
+            .or_else(|| {
+                release["media"]
+                    .as_array()
+                    .map(|x| x.iter().filter_map(|x| x["track-count"].as_u64()).sum())
+            })
+            .unwrap_or_default() as usize,
+        score,
+        ..Default::default()
+    }
+}
+
+/// 将录音信息展开成其所在的每个发行版本
+fn recording_candidates(recording: &Value, score: u32) -> Vec<ReleaseCandidate> {
+    let recording_id = str_field(recording, "id");
+    let recording_title = str_field(recording, "title");
+    recording["releases"]
+        .as_array()
+        .map(|releases| {
+            releases
+                .iter()
+                .map(|release| {
+                    let track_number = release["media"]
+                        .as_array()
+                        .and_then(|x| x.first())
+                        .and_then(|x| x["track"].as_array())
+                        .and_then(|x| x.first())
+                        .map(|x| str_field(x, "number"));
+                    let mut candidate = release_candidate(release, score);
+                    if candidate.artist.is_empty() {
+                        candidate.artist = artist_credit(recording);
+                    }
+                    ReleaseCandidate {
+                        recording_id: Some(recording_id.clone()),
+                        recording_title: Some(recording_title.clone()),
+                        track_number,
+                        ..candidate
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl MusicBrainzClient {
+    pub fn new(cache_dir: PathBuf) -> Self {
+        Self {
+            cache_dir,
+            last_request: async_std::sync::Mutex::new(None),
+        }
+    }
+
+    fn cache_path(&self, path: &str, query: &BTreeMap<String, String>) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(path);
+        for (key, value) in query {
+            hasher.update([0]);
+            hasher.update(key);
+            hasher.update([0]);
+            hasher.update(value);
+        }
+        self.cache_dir.join(format!("{:x}.json", hasher.finalize()))
+    }
+
+    /// 请求 MusicBrainz 接口，`path` 为 `/ws/2` 之后的部分
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> anyhow::Result<Value> {
+        let mut query: BTreeMap<String, String> = query
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        query.insert("fmt".into(), "json".into());
+
+        let cache_path = self.cache_path(path, &query);
+        if let Ok(data) = std::fs::read(&cache_path) {
+            if let Ok(value) = serde_json::from_slice(&data) {
+                return Ok(value);
+            }
+        }
+
+        let response = {
+            let mut last_request = self.last_request.lock().await;
+            if let Some(elapsed) = last_request.map(|x| x.elapsed()) {
+                if elapsed < REQUEST_INTERVAL {
+                    async_std::task::sleep(REQUEST_INTERVAL - elapsed).await;
+                }
+            }
+            let client = ClientBuilder::new().build()?;
+            let request = HttpRequestBuilder::new("GET", format!("{MUSICBRAINZ_API_URL}{path}"))?
+                .query(query.into_iter().collect::<HashMap<_, _>>())
+                .headers(HashMap::from([(
+                    "User-Agent".to_string(),
+                    USER_AGENT.to_string(),
+                )]))
+                .response_type(ResponseType::Json);
+            let response = client.send(request).await;
+            *last_request = Some(Instant::now());
+            response?.read().await?
+        };
+        if response.status != 200 {
+            anyhow::bail!(
+                "MusicBrainz 请求失败 ({}): {}",
+                response.status,
+                response.data["error"]
+            );
+        }
+
+        if let Err(err) = std::fs::create_dir_all(&self.cache_dir)
+            .and_then(|_| std::fs::write(&cache_path, response.data.to_string()))
+        {
+            println!("MusicBrainz 查询结果缓存失败: {err:?}");
+        }
+        Ok(response.data)
+    }
+
+    /// 根据录音的标题、艺术家和专辑搜索
+    pub async fn search_recordings(
+        &self,
+        title: &str,
+        artist: &str,
+        album: &str,
+    ) -> anyhow::Result<Vec<ReleaseCandidate>> {
+        let mut conditions = vec![format!("recording:\"{}\"", escape_query(title))];
+        if !artist.is_empty() {
+            conditions.push(format!("artist:\"{}\"", escape_query(artist)));
+        }
+        if !album.is_empty() {
+            conditions.push(format!("release:\"{}\"", escape_query(album)));
+        }
+        let limit = MAX_CANDIDATES.to_string();
+        let result = self
+            .get(
+                "/recording",
+                &[("query", &conditions.join(" AND ")), ("limit", &limit)],
+            )
+            .await?;
+        Ok(result["recordings"]
+            .as_array()
+            .map(|recordings| {
+                recordings
+                    .iter()
+                    .flat_map(|x| recording_candidates(x, x["score"].as_u64().unwrap_or(0) as u32))
+                    .take(MAX_CANDIDATES)
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// 根据 MusicBrainz 录音 ID 获取其所在的发行版本
+    pub async fn lookup_recording(
+        &self,
+        recording_id: &str,
+        score: u32,
+    ) -> anyhow::Result<Vec<ReleaseCandidate>> {
+        let result = self
+            .get(
+                &format!("/recording/{recording_id}"),
+                &[("inc", "releases+artist-credits+media")],
+            )
+            .await?;
+        Ok(recording_candidates(&result, score))
+    }
+
+    /// 根据专辑名称、艺术家和曲目数量搜索发行版本
+    pub async fn search_releases(
+        &self,
+        album: &str,
+        artist: &str,
+        track_count: usize,
+    ) -> anyhow::Result<Vec<ReleaseCandidate>> {
+        let mut conditions = vec![format!("release:\"{}\"", escape_query(album))];
+        if !artist.is_empty() {
+            conditions.push(format!("artist:\"{}\"", escape_query(artist)));
+        }
+        if track_count > 0 {
+            conditions.push(format!("tracks:{track_count}"));
+        }
+        let limit = MAX_CANDIDATES.to_string();
+        let result = self
+            .get(
+                "/release",
+                &[("query", &conditions.join(" AND ")), ("limit", &limit)],
+            )
+            .await?;
+        Ok(result["releases"]
+            .as_array()
+            .map(|releases| {
+                releases
+                    .iter()
+                    .map(|x| release_candidate(x, x["score"].as_u64().unwrap_or(0) as u32))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+}
+
+/// 出现次数最多的非空值
+fn most_common(values: impl Iterator<Item = String>) -> String {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for value in values.filter(|x| !x.is_empty()) {
+        *counts.entry(value).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|x| x.0)
+        .unwrap_or_default()
+}
+
+/// 查询音乐文件对应的候选发行版本
+///
+/// 提供了 AcoustID 的客户端密钥时会优先使用音频指纹识别，否则根据文件的标签搜索。
+#[tauri::command]
+pub async fn musicbrainz_lookup_file(
+    app: AppHandle,
+    path: PathBuf,
+    acoustid_client_key: Option<String>,
+) -> CommandResult<Vec<ReleaseCandidate>> {
+    let client = app.state::<MusicBrainzClient>();
+    let result: anyhow::Result<Vec<ReleaseCandidate>> = async {
+        if let Some(client_key) = acoustid_client_key.filter(|x| !x.is_empty()) {
+            let fingerprint_path = path.clone();
+            let fingerprint =
+                tauri::async_runtime::spawn_blocking(move || fingerprint_file(&fingerprint_path))
+                    .await??;
+            let results = lookup_acoustid(&client_key, &fingerprint).await?;
+            let mut candidates = Vec::new();
+            for result in results.as_array().into_iter().flatten() {
+                let score = (result["score"].as_f64().unwrap_or(0.0) * 100.0) as u32;
+                for recording in result["recordings"].as_array().into_iter().flatten() {
+                    if let Some(id) = recording["id"].as_str() {
+                        candidates.extend(client.lookup_recording(id, score).await?);
+                    }
+                    if candidates.len() >= MAX_CANDIDATES {
+                        return Ok(candidates);
+                    }
+                }
+            }
+            if !candidates.is_empty() {
+                return Ok(candidates);
+            }
+        }
+
+        let metadata_path = path.clone();
+        let metadata =
+            tauri::async_runtime::spawn_blocking(move || read_music_metadata(&metadata_path))
+                .await??;
+        client
+            .search_recordings(&metadata.name, &metadata.artist, &metadata.album)
+            .await
+    }
+    .await;
+    Ok(result?)
+}
+
+fn read_folder_tags(path: &Path) -> anyhow::Result<(String, String, usize)> {
+    let mut albums = Vec::new();
+    let mut artists = Vec::new();
+    let mut track_count = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry_path = entry?.path();
+        if !entry_path.is_file() || !is_audio_file(&entry_path) {
+            continue;
+        }
+        track_count += 1;
+        if let Ok(metadata) = read_music_metadata(&entry_path) {
+            albums.push(metadata.album);
+            if metadata.album_artist.is_empty() {
+                artists.push(metadata.artist);
+            } else {
+                artists.push(metadata.album_artist);
+            }
+        }
+    }
+    let album = most_common(albums.into_iter());
+    if album.is_empty() {
+        anyhow::bail!("文件夹中的音乐文件没有专辑信息");
+    }
+    Ok((album, most_common(artists.into_iter()), track_count))
+}
+
+/// 根据文件夹内音乐文件的专辑标签查询候选发行版本
+#[tauri::command]
+pub async fn musicbrainz_lookup_folder(
+    app: AppHandle,
+    path: PathBuf,
+) -> CommandResult<Vec<ReleaseCandidate>> {
+    let (album, artist, track_count) =
+        tauri::async_runtime::spawn_blocking(move || read_folder_tags(&path)).await??;
+    Ok(app
+        .state::<MusicBrainzClient>()
+        .search_releases(&album, &artist, track_count)
+        .await?)
+}
@@ -0,0 +1,113 @@
+//! 当前的播放状态
+//!
+//! 根据 WebSocket 客户端发送过来的信息记录当前播放的歌曲和进度，
+//! 供 HTTP 控制接口和直播歌词叠加层等无法从前端获取播放状态的模块使用。
+//! 播放进度由共享的 [`PlaybackClock`] 推算。
+use serde::Serialize;
+use ws_protocol::Body;
+
+use crate::playback_clock::PlaybackClock;
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct NowPlayingStatus {
+    pub music_id: String,
+    pub music_name: String,
+    pub album_name: String,
+    pub artists: Vec<String>,
+    /// 歌曲的总时长，单位为毫秒
+    pub duration: u64,
+    /// 当前的播放进度，单位为毫秒
+    pub position: f64,
+    /// 播放源已经加载（缓冲）到的位置，单位为毫秒，播放源没有发送加载进度时为空
+    pub load_position: Option<f64>,
+    pub paused: bool,
+    pub volume: Option<f64>,
+    pub cover_url: Option<String>,
+    /// 客户端是否发送了专辑封面的图片数据
+    pub has_cover_data: bool,
+}
+
+pub struct NowPlaying {
+    status: NowPlayingStatus,
+    clock: PlaybackClock,
+    cover_data: Option<Vec<u8>>,
+    lyric: Vec<ws_protocol::LyricLine>,
+}
+
+impl NowPlaying {
+    pub fn new(clock: PlaybackClock) -> Self {
+        Self {
+            status: NowPlayingStatus::default(),
+            clock,
+            cover_data: None,
+            lyric: Vec::new(),
+        }
+    }
+
+    pub fn on_body(&mut self, body: &Body) {
+        let status = &mut self.status;
+        match body {
+            Body::SetMusicId { id, name, duration } => {
+                status.music_id = id.to_string();
+                status.music_name = name.to_string();
+                status.duration = *duration;
+                status.load_position = None;
+                self.lyric.clear();
+            }
+            Body::SetMusicAlbum { name, .. } => {
+                status.album_name = name.to_string();
+            }
+            Body::SetMusicArtists { artists } => {
+                status.artists = artists.iter().map(|x| x.name.to_string()).collect();
+            }
+            Body::SetMusicAlbumCoverImageURL { img_url } => {
+                status.cover_url = Some(img_url.to_string()).filter(|x| !x.is_empty());
+            }
+            Body::SetMusicAlbumCoverImageData { data } => {
+                status.has_cover_data = !data.is_empty();
+                self.cover_data = Some(data.clone()).filter(|x| !x.is_empty());
+            }
+            Body::SetLyric { data } => {
+                self.lyric = data.clone();
+            }
+            Body::OnLoadProgress { progress } => {
+                status.load_position = Some(*progress);
+            }
+            Body::OnPaused => {
+                status.paused = true;
+            }
+            Body::OnResumed => {
+                status.paused = false;
+            }
+            Body::SetVolume { volume } => {
+                status.volume = Some(*volume);
+            }
+            _ => {}
+        }
+    }
+
+    /// 根据播放时钟推算当前的播放进度，不会超过歌曲的总时长
+    fn position(&self) -> f64 {
+        let mut position = self.clock.position().unwrap_or(0.0);
+        if self.status.duration > 0 {
+            position = position.min(self.status.duration as f64);
+        }
+        position
+    }
+
+    pub fn cover_data(&self) -> Option<&[u8]> {
+        self.cover_data.as_deref()
+    }
+
+    pub fn lyric(&self) -> &[ws_protocol::LyricLine] {
+        &self.lyric
+    }
+
+    pub fn status(&self) -> NowPlayingStatus {
+        NowPlayingStatus {
+            position: self.position(),
+            ..self.status.clone()
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! 打开方式和文件关联
+//!
+//! 在文件管理器中双击关联的音乐文件或者播放列表时，文件路径会作为启动参数传入，
+//! macOS 下则会通过打开文件事件传入。这些文件会被读取为歌曲列表后交给前端替换播放列表并开始播放。
+//! 前端还没有加载完成时收到的文件会先保存起来，前端加载完成后通过 [`take_pending_open_files`] 取出。
+//! 通过命令行添加的文件则会追加到播放列表末尾。
+use std::{path::PathBuf, sync::Mutex};
+
+use tauri::{AppHandle, Manager, State};
+
+use crate::{
+    metadata,
+    playlist::{self, SongData},
+};
+
+#[derive(Default)]
+pub struct OpenFiles {
+    pending: Vec<SongData>,
+    /// 前端是否已经取出过文件，之后只通过事件通知
+    ready: bool,
+}
+
+/// 读取文件为歌曲列表，播放列表文件会被展开，其它不支持的文件会被忽略
+fn collect_songs(paths: Vec<PathBuf>) -> Vec<SongData> {
+    let mut songs = Vec::new();
+    for path in paths {
+        if playlist::is_playlist_file(&path) {
+            match playlist::import_playlist(&path) {
+                Ok(x) => songs.extend(x),
+                Err(err) => println!("播放列表 {} 导入失败: {err:?}", path.display()),
+            }
+        } else if metadata::is_playable_file(&path) {
+            // 元数据读取失败时仍然尝试播放，由前端显示文件名
+            let metadata = metadata::read_music_metadata(&path)
+                .map_err(|err| println!("音乐文件 {} 读取失败: {err:?}", path.display()))
+                .ok();
+            songs.push(SongData::Local {
+                file_path: path.to_string_lossy().into_owned(),
+                title: metadata.as_ref().map(|x| x.name.clone()),
+                artist: metadata
+                    .as_ref()
+                    .map(|x| x.artists.join(" / "))
+                    .filter(|x| !x.is_empty()),
+                duration: metadata
+                    .as_ref()
+                    .filter(|x| x.duration > 0.0)
+                    .map(|x| (x.duration * 1000.0) as u64),
+            });
+        }
+    }
+    songs
+}
+
+/// 在后台读取文件，读取到歌曲时交给 `callback` 处理
+fn read_in_background(paths: Vec<PathBuf>, callback: impl FnOnce(Vec<SongData>) + Send + 'static) {
+    let paths: Vec<_> = paths.into_iter().filter(|x| x.is_file()).collect();
+    if paths.is_empty() {
+        return;
+    }
+    // 读取元数据可能比较慢，不阻塞启动和事件循环
+    std::thread::spawn(move || {
+        let songs = collect_songs(paths);
+        if !songs.is_empty() {
+            callback(songs);
+        }
+    });
+}
+
+/// 打开传入的文件，读取完成后通过 `on-open-files` 事件通知前端
+pub fn open(app: AppHandle, paths: Vec<PathBuf>) {
+    read_in_background(paths, move |songs| {
+        println!("通过打开方式打开了 {} 首歌曲", songs.len());
+        {
+            let state = app.state::<Mutex<OpenFiles>>();
+            let mut open_files = state.lock().unwrap();
+            if !open_files.ready {
+                open_files.pending.clone_from(&songs);
+            }
+        }
+        let _ = app.emit_all("on-open-files", songs);
+    });
+}
+
+/// 把传入的文件添加到播放列表末尾，读取完成后通过 `on-add-files` 事件通知前端
+pub fn add(app: AppHandle, paths: Vec<PathBuf>) {
+    read_in_background(paths, move |songs| {
+        println!("添加了 {} 首歌曲到播放列表", songs.len());
+        let _ = app.emit_all("on-add-files", songs);
+    });
+}
+
+/// 打开启动参数中传入的文件
+pub fn open_args(app: AppHandle) {
+    open(
+        app,
+        std::env::args_os().skip(1).map(PathBuf::from).collect(),
+    );
+}
+
+/// 取出还没有被前端处理的文件，前端加载完成后调用一次
+#[tauri::command]
+pub fn take_pending_open_files(open_files: State<Mutex<OpenFiles>>) -> Vec<SongData> {
+    let mut open_files = open_files.lock().unwrap();
+    open_files.ready = true;
+    std::mem::take(&mut open_files.pending)
+}
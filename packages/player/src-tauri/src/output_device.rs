@@ -0,0 +1,76 @@
+//! 音频输出设备监听
+//!
+//! 播放器本身不输出音频，但播放源通常运行在同一台电脑上。
+//! 定时检查系统的默认输出设备，正在使用的设备被移除（例如蓝牙耳机断开）时自动暂停播放，
+//! 避免声音突然从扬声器外放，同时通过 `on-output-device-removed` 事件通知前端暂停的原因。
+//!
+//! 目前只支持 Windows 和 macOS。Linux 上通过 ALSA 获取的默认设备名称始终是 `default`，
+//! 拔出耳机时名称不会变化，需要监听 PulseAudio / PipeWire 的设备事件才能实现，因此不会开始监听。
+use std::{sync::Mutex, time::Duration};
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use ws_protocol::Body;
+
+use crate::now_playing::NowPlaying;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputDeviceRemoved {
+    /// 被移除的设备名称
+    pub device: String,
+    /// 是否因此暂停了播放
+    pub paused: bool,
+}
+
+fn default_output_device(host: &cpal::Host) -> Option<String> {
+    host.default_output_device().and_then(|x| x.name().ok())
+}
+
+fn has_output_device(host: &cpal::Host, name: &str) -> bool {
+    match host.output_devices() {
+        Ok(mut devices) => devices.any(|x| x.name().is_ok_and(|x| x == name)),
+        // 无法列出设备时当作设备仍然存在，避免误暂停
+        Err(_) => true,
+    }
+}
+
+fn run(app: AppHandle) {
+    let host = cpal::default_host();
+    let mut current = default_output_device(&host);
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        let device = default_output_device(&host);
+        if device == current {
+            continue;
+        }
+        // 默认设备变化可能只是用户切换了设备，只有原来的设备消失时才暂停
+        if let Some(removed) = current.filter(|x| !has_output_device(&host, x)) {
+            let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+            let paused = !status.paused && !status.music_id.is_empty();
+            if paused {
+                println!("音频输出设备 {removed} 已被移除，暂停播放");
+                crate::send_control(&app, Body::Pause);
+            }
+            let _ = app.emit_all(
+                "on-output-device-removed",
+                OutputDeviceRemoved {
+                    device: removed,
+                    paused,
+                },
+            );
+        }
+        current = device;
+    }
+}
+
+/// 在后台线程中开始监听默认输出设备的变化，Linux 上不支持
+pub fn watch(app: AppHandle) {
+    if cfg!(target_os = "linux") {
+        return;
+    }
+    std::thread::spawn(move || run(app));
+}
@@ -0,0 +1,81 @@
+//! 共享的播放时钟
+//!
+//! 播放源每隔一段时间才会发送一次播放进度，之前当前状态和歌词同步模块各自记录最近一次的播放进度并推算位置，
+//! 现在统一由此时钟记录基准进度、收到基准进度的时间和播放速率，任何时候读取都可以推算出当前的位置。
+//! 时钟的状态整体存放在 [`ArcSwap`] 中，读取时不需要加锁，也不会读到更新了一半的状态。
+use std::{sync::Arc, time::Instant};
+
+use arc_swap::ArcSwap;
+use tauri::State;
+use ws_protocol::Body;
+
+#[derive(Debug, Clone, Copy)]
+struct ClockState {
+    /// 最近一次的基准进度（毫秒）及其对应的时间，没有收到过播放进度时为空
+    anchor: Option<(f64, Instant)>,
+    /// 播放速率，暂停时为 0
+    rate: f64,
+}
+
+impl Default for ClockState {
+    fn default() -> Self {
+        Self {
+            anchor: None,
+            // 客户端不一定会发送恢复播放的消息，默认收到播放进度时即为正在播放
+            rate: 1.0,
+        }
+    }
+}
+
+impl ClockState {
+    fn position_at(&self, now: Instant) -> Option<f64> {
+        let (position, at) = self.anchor?;
+        let elapsed = now.saturating_duration_since(at).as_secs_f64() * 1000.0;
+        Some(position + elapsed * self.rate)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PlaybackClock {
+    state: Arc<ArcSwap<ClockState>>,
+}
+
+impl PlaybackClock {
+    fn update(&self, f: impl Fn(&ClockState, Instant) -> ClockState) {
+        self.state.rcu(|state| f(state, Instant::now()));
+    }
+
+    /// 根据播放源发送的信息更新时钟，需要在其它读取时钟的模块处理信息之前调用
+    pub fn on_body(&self, body: &Body) {
+        match body {
+            Body::SetMusicId { .. } => self.update(|state, _| ClockState {
+                anchor: None,
+                ..*state
+            }),
+            Body::OnPlayProgress { progress } => self.update(|state, now| ClockState {
+                anchor: Some((*progress, now)),
+                ..*state
+            }),
+            Body::OnPaused => self.update(|state, now| ClockState {
+                anchor: state.position_at(now).map(|x| (x, now)),
+                rate: 0.0,
+            }),
+            Body::OnResumed => self.update(|state, now| ClockState {
+                anchor: state.anchor.map(|(x, _)| (x, now)),
+                rate: 1.0,
+            }),
+            _ => {}
+        }
+    }
+
+    /// 推算当前的播放进度，单位为毫秒，切歌后还没有收到播放进度时为空
+    pub fn position(&self) -> Option<f64> {
+        self.state.load().position_at(Instant::now())
+    }
+}
+
+/// 获取推算出的当前播放进度，单位为毫秒，没有收到播放进度时为空
+#[tauri::command]
+pub fn playback_get_position(clock: State<PlaybackClock>) -> Option<f64> {
+    clock.position()
+}
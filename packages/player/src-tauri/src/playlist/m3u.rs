@@ -0,0 +1,115 @@
+//! M3U / M3U8 播放列表格式
+//!
+//! 支持扩展 M3U 的 `#EXTINF:<时长>,<艺术家> - <标题>` 信息行，
+//! 其余以 `#` 开头的指令行会被忽略。
+use std::fmt::Write;
+use std::path::Path;
+
+use super::{display_title, relative_location, resolve_location, split_display_title, SongData};
+
+struct ExtInf {
+    duration: Option<u64>,
+    title: Option<String>,
+    artist: Option<String>,
+}
+
+fn parse_extinf(src: &str) -> ExtInf {
+    // 时长后面可能带有 `tvg-id="..."` 之类的属性，以第一个不在引号内的逗号为分隔
+    let mut in_quote = false;
+    let mut split_at = None;
+    for (i, c) in src.char_indices() {
+        match c {
+            '"' => in_quote = !in_quote,
+            ',' if !in_quote => {
+                split_at = Some(i);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (info, display) = match split_at {
+        Some(i) => (&src[..i], src[i + 1..].trim()),
+        None => (src, ""),
+    };
+    let duration = info
+        .split_whitespace()
+        .next()
+        .and_then(|x| x.parse::<f64>().ok())
+        .filter(|x| *x > 0.0)
+        .map(|x| (x * 1000.0) as u64);
+    let (artist, title) = split_display_title(display);
+    ExtInf {
+        duration,
+        title,
+        artist,
+    }
+}
+
+pub fn parse_m3u(src: &str, base_dir: &Path) -> Vec<SongData> {
+    let mut result = Vec::new();
+    let mut ext_inf = None;
+
+    for line in src.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            ext_inf = Some(parse_extinf(info));
+            continue;
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        let info = ext_inf.take();
+        match resolve_location(line, base_dir) {
+            Some(file_path) => {
+                let (title, artist, duration) = match info {
+                    Some(info) => (info.title, info.artist, info.duration),
+                    None => (None, None, None),
+                };
+                result.push(SongData::Local {
+                    file_path: file_path.to_string_lossy().into_owned(),
+                    title,
+                    artist,
+                    duration,
+                });
+            }
+            None => {
+                println!("已跳过播放列表中的非本地歌曲: {line}");
+            }
+        }
+    }
+
+    result
+}
+
+pub fn stringify_m3u(songs: &[SongData], base_dir: &Path) -> String {
+    let mut result = String::with_capacity(songs.len() * 128 + 8);
+    result.push_str("#EXTM3U\n");
+
+    for song in songs {
+        match song {
+            SongData::Local {
+                file_path,
+                title,
+                artist,
+                duration,
+            } => {
+                if title.is_some() || artist.is_some() || duration.is_some() {
+                    let duration = duration.map(|x| (x / 1000) as i64).unwrap_or(-1);
+                    writeln!(
+                        result,
+                        "#EXTINF:{duration},{}",
+                        display_title(artist, title)
+                    )
+                    .unwrap();
+                }
+                result.push_str(&relative_location(file_path, base_dir));
+                result.push('\n');
+            }
+        }
+    }
+
+    result
+}
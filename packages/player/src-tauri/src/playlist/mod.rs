@@ -0,0 +1,187 @@
+//! 播放列表文件的导入导出模块
+//!
+//! 根据文件扩展名判断播放列表格式，目前支持 M3U / M3U8、XSPF 和 PLS 格式。
+//! 播放列表中的相对路径会以播放列表文件所在的文件夹为基准解析，
+//! 导出时如果歌曲位于播放列表所在文件夹内则会写出相对路径。
+//! M3U8 文件固定为 UTF-8 编码，M3U 文件通常使用系统的旧编码（例如 GBK、Shift-JIS），导入时会推测其编码。
+use std::path::{Path, PathBuf};
+
+use chardetng::EncodingDetector;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+
+use crate::error::CommandResult;
+
+mod m3u;
+mod pls;
+mod xspf;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum SongData {
+    #[serde(rename_all = "camelCase")]
+    Local {
+        file_path: String,
+        #[serde(default)]
+        title: Option<String>,
+        #[serde(default)]
+        artist: Option<String>,
+        /// 歌曲时长，单位为毫秒
+        #[serde(default)]
+        duration: Option<u64>,
+    },
+}
+
+fn is_remote_location(location: &str) -> bool {
+    location.contains("://") && !location.starts_with("file://")
+}
+
+fn encode_percent(src: &str) -> String {
+    let mut result = String::with_capacity(src.len());
+    for b in src.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => {
+                result.push(b as char)
+            }
+            b => {
+                result.push('%');
+                result.push_str(&format!("{b:02X}"));
+            }
+        }
+    }
+    result
+}
+
+/// 将形如 `艺术家 - 标题` 的显示名称拆分成艺术家和标题
+pub(crate) fn split_display_title(display: &str) -> (Option<String>, Option<String>) {
+    let (artist, title) = match display.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim()), Some(title.trim())),
+        None => (None, Some(display.trim())),
+    };
+    (
+        artist.filter(|x| !x.is_empty()).map(str::to_string),
+        title.filter(|x| !x.is_empty()).map(str::to_string),
+    )
+}
+
+/// 将艺术家和标题组合成形如 `艺术家 - 标题` 的显示名称
+pub(crate) fn display_title(artist: &Option<String>, title: &Option<String>) -> String {
+    match (artist, title) {
+        (Some(artist), Some(title)) => format!("{artist} - {title}"),
+        (None, Some(title)) => title.to_owned(),
+        (Some(artist), None) => artist.to_owned(),
+        (None, None) => String::new(),
+    }
+}
+
+/// 将播放列表中的位置解析成本地文件路径，如果是网络地址则返回 `None`
+pub(crate) fn resolve_location(location: &str, base_dir: &Path) -> Option<PathBuf> {
+    let location = location.trim();
+    if location.is_empty() || is_remote_location(location) {
+        return None;
+    }
+    let path = if let Some(path) = location.strip_prefix("file://") {
+        // file:///C:/xxx 在 Windows 下需要去掉开头的斜杠
+        let path = percent_decode_str(path).decode_utf8_lossy().into_owned();
+        if cfg!(windows) {
+            PathBuf::from(path.trim_start_matches('/'))
+        } else {
+            PathBuf::from(path)
+        }
+    } else {
+        PathBuf::from(location)
+    };
+    if path.is_relative() {
+        Some(base_dir.join(path))
+    } else {
+        Some(path)
+    }
+}
+
+/// 将 URI 形式的位置解析成本地文件路径，与 [`uri_location`] 对应，相对位置同样经过了百分号编码
+pub(crate) fn resolve_uri_location(location: &str, base_dir: &Path) -> Option<PathBuf> {
+    let location = location.trim();
+    if location.starts_with("file://") || is_remote_location(location) {
+        return resolve_location(location, base_dir);
+    }
+    resolve_location(&percent_decode_str(location).decode_utf8_lossy(), base_dir)
+}
+
+/// 如果歌曲位于播放列表所在的文件夹内，则返回相对路径，否则返回原路径
+pub(crate) fn relative_location(file_path: &str, base_dir: &Path) -> String {
+    match Path::new(file_path).strip_prefix(base_dir) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => file_path.to_string(),
+    }
+}
+
+/// 将本地文件路径转换成 URI 形式的位置，位于播放列表所在文件夹内的歌曲会使用相对位置
+pub(crate) fn uri_location(file_path: &str, base_dir: &Path) -> String {
+    let path = Path::new(file_path);
+    match path.strip_prefix(base_dir) {
+        Ok(relative) => encode_percent(&relative.to_string_lossy().replace('\\', "/")),
+        Err(_) => {
+            let path = path.to_string_lossy().replace('\\', "/");
+            if path.starts_with('/') {
+                format!("file://{}", encode_percent(&path))
+            } else {
+                format!("file:///{}", encode_percent(&path))
+            }
+        }
+    }
+}
+
+fn playlist_extension(path: &Path) -> String {
+    path.extension()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// 支持导入的播放列表文件扩展名
+pub const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8", "xspf", "pls"];
+
+pub fn is_playlist_file(path: &Path) -> bool {
+    PLAYLIST_EXTENSIONS.contains(&playlist_extension(path).as_str())
+}
+
+pub fn import_playlist(path: &Path) -> anyhow::Result<Vec<SongData>> {
+    let data = std::fs::read(path)?;
+    let extension = playlist_extension(path);
+    let src = if extension == "m3u" {
+        let mut detector = EncodingDetector::new();
+        detector.feed(&data, true);
+        detector.guess(None, true).decode(&data).0
+    } else {
+        String::from_utf8_lossy(&data)
+    };
+    let src = src.trim_start_matches('\u{feff}');
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    match extension.as_str() {
+        "m3u" | "m3u8" => Ok(m3u::parse_m3u(src, base_dir)),
+        "xspf" => xspf::parse_xspf(src, base_dir),
+        "pls" => Ok(pls::parse_pls(src, base_dir)),
+        other => anyhow::bail!("不支持的播放列表格式: {other}"),
+    }
+}
+
+pub fn export_playlist(path: &Path, songs: &[SongData]) -> anyhow::Result<()> {
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    let data = match playlist_extension(path).as_str() {
+        "m3u" | "m3u8" => m3u::stringify_m3u(songs, base_dir),
+        "xspf" => xspf::stringify_xspf(songs, base_dir),
+        "pls" => pls::stringify_pls(songs, base_dir),
+        other => anyhow::bail!("不支持的播放列表格式: {other}"),
+    };
+    std::fs::write(path, data)?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn import_playlist_file(path: PathBuf) -> CommandResult<Vec<SongData>> {
+    Ok(import_playlist(&path)?)
+}
+
+#[tauri::command]
+pub fn export_playlist_file(path: PathBuf, songs: Vec<SongData>) -> CommandResult<()> {
+    Ok(export_playlist(&path, &songs)?)
+}
@@ -0,0 +1,92 @@
+//! PLS 播放列表格式
+//!
+//! 形如 `File1=...`、`Title1=...`、`Length1=...` 的 INI 风格格式，
+//! 其中 `Length` 的单位为秒，`-1` 代表未知时长。
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use super::{display_title, relative_location, resolve_location, split_display_title, SongData};
+
+#[derive(Default)]
+struct PlsEntry<'a> {
+    file: Option<&'a str>,
+    title: Option<&'a str>,
+    length: Option<&'a str>,
+}
+
+pub fn parse_pls(src: &str, base_dir: &Path) -> Vec<SongData> {
+    let mut entries: BTreeMap<usize, PlsEntry> = BTreeMap::new();
+
+    for line in src.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim();
+        let (field, index) =
+            key.split_at(key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len()));
+        let Ok(index) = index.parse::<usize>() else {
+            continue;
+        };
+        let entry = entries.entry(index).or_default();
+        match field {
+            "file" => entry.file = Some(value),
+            "title" => entry.title = Some(value),
+            "length" => entry.length = Some(value),
+            _ => {}
+        }
+    }
+
+    entries
+        .into_values()
+        .filter_map(|entry| {
+            let location = entry.file?;
+            let Some(file_path) = resolve_location(location, base_dir) else {
+                println!("已跳过播放列表中的非本地歌曲: {location}");
+                return None;
+            };
+            let (artist, title) = split_display_title(entry.title.unwrap_or_default());
+            let duration = entry
+                .length
+                .and_then(|x| x.parse::<f64>().ok())
+                .filter(|x| *x > 0.0)
+                .map(|x| (x * 1000.0) as u64);
+            Some(SongData::Local {
+                file_path: file_path.to_string_lossy().into_owned(),
+                title,
+                artist,
+                duration,
+            })
+        })
+        .collect()
+}
+
+pub fn stringify_pls(songs: &[SongData], base_dir: &Path) -> String {
+    let mut result = String::with_capacity(songs.len() * 128 + 32);
+    result.push_str("[playlist]\n");
+
+    for (i, song) in songs.iter().enumerate() {
+        let i = i + 1;
+        match song {
+            SongData::Local {
+                file_path,
+                title,
+                artist,
+                duration,
+            } => {
+                writeln!(result, "File{i}={}", relative_location(file_path, base_dir)).unwrap();
+                if title.is_some() || artist.is_some() {
+                    writeln!(result, "Title{i}={}", display_title(artist, title)).unwrap();
+                }
+                let duration = duration.map(|x| (x / 1000) as i64).unwrap_or(-1);
+                writeln!(result, "Length{i}={duration}").unwrap();
+            }
+        }
+    }
+
+    writeln!(result, "NumberOfEntries={}", songs.len()).unwrap();
+    result.push_str("Version=2\n");
+
+    result
+}
@@ -0,0 +1,140 @@
+//! XSPF（XML Shareable Playlist Format）播放列表格式
+//!
+//! 读取每个 `<track>` 中的 `<location>`、`<title>`、`<creator>` 和 `<duration>`，
+//! 其中 `<duration>` 的单位为毫秒，`<location>` 为 URI。
+use std::fmt::Write;
+use std::path::Path;
+
+use quick_xml::{escape::escape, events::Event, Reader};
+
+use super::{resolve_uri_location, uri_location, SongData};
+
+#[derive(Default)]
+struct XspfTrack {
+    location: Option<String>,
+    title: Option<String>,
+    creator: Option<String>,
+    duration: Option<u64>,
+}
+
+pub fn parse_xspf(src: &str, base_dir: &Path) -> anyhow::Result<Vec<SongData>> {
+    let mut reader = Reader::from_str(src);
+    reader.trim_text(true);
+
+    let mut result = Vec::new();
+    let mut track: Option<XspfTrack> = None;
+    let mut current_field = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = e.local_name();
+                if name.as_ref() == b"track" {
+                    track = Some(XspfTrack::default());
+                } else if track.is_some() {
+                    current_field = name.as_ref().to_vec();
+                }
+            }
+            Event::Text(text) => {
+                if let Some(track) = &mut track {
+                    let text = text.unescape()?.into_owned();
+                    match current_field.as_slice() {
+                        b"location" => {
+                            // 一个 track 中可能有多个 location，只取第一个
+                            if track.location.is_none() {
+                                track.location = Some(text);
+                            }
+                        }
+                        b"title" => track.title = Some(text),
+                        b"creator" => track.creator = Some(text),
+                        b"duration" => track.duration = text.parse().ok(),
+                        _ => {}
+                    }
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"track" {
+                    if let Some(track) = track.take() {
+                        let Some(location) = track.location else {
+                            continue;
+                        };
+                        match resolve_uri_location(&location, base_dir) {
+                            Some(file_path) => result.push(SongData::Local {
+                                file_path: file_path.to_string_lossy().into_owned(),
+                                title: track.title,
+                                artist: track.creator,
+                                duration: track.duration,
+                            }),
+                            None => {
+                                println!("已跳过播放列表中的非本地歌曲: {location}");
+                            }
+                        }
+                    }
+                }
+                current_field.clear();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+pub fn stringify_xspf(songs: &[SongData], base_dir: &Path) -> String {
+    let mut result = String::with_capacity(songs.len() * 256 + 128);
+    result.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    result.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    result.push_str("  <trackList>\n");
+
+    for song in songs {
+        match song {
+            SongData::Local {
+                file_path,
+                title,
+                artist,
+                duration,
+            } => {
+                result.push_str("    <track>\n");
+                writeln!(
+                    result,
+                    "      <location>{}</location>",
+                    escape(&uri_location(file_path, base_dir))
+                )
+                .unwrap();
+                if let Some(title) = title {
+                    writeln!(result, "      <title>{}</title>", escape(title)).unwrap();
+                }
+                if let Some(artist) = artist {
+                    writeln!(result, "      <creator>{}</creator>", escape(artist)).unwrap();
+                }
+                if let Some(duration) = duration {
+                    writeln!(result, "      <duration>{duration}</duration>").unwrap();
+                }
+                result.push_str("    </track>\n");
+            }
+        }
+    }
+
+    result.push_str("  </trackList>\n");
+    result.push_str("</playlist>\n");
+
+    result
+}
+
+#[test]
+fn xspf_round_trip_test() {
+    let base_dir = Path::new("music");
+    let songs = vec![SongData::Local {
+        file_path: base_dir
+            .join("My Song #1.flac")
+            .to_string_lossy()
+            .into_owned(),
+        title: Some("My Song".into()),
+        artist: Some("Artist".into()),
+        duration: Some(180000),
+    }];
+    let src = stringify_xspf(&songs, base_dir);
+    assert!(src.contains("<location>My%20Song%20%231.flac</location>"));
+    assert_eq!(parse_xspf(&src, base_dir).unwrap(), songs);
+}
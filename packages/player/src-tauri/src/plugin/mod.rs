@@ -0,0 +1,264 @@
+//! 插件管理
+//!
+//! 第三方可以通过 WASM 插件提供新的歌词来源，插件安装在应用数据文件夹的 `plugins/<插件 ID>` 中，
+//! 每个插件包含描述插件信息和权限的 `plugin.json` 以及插件本体 `plugin.wasm`。
+//! 新安装的插件默认是禁用的，前端向用户展示插件申请的权限后再由用户启用。
+//! 启用的插件会作为歌词来源参与歌词搜索，来源名称为插件的 ID。
+//!
+//! 插件的调用约定见 [`runtime`]。
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::{error::CommandResult, lyric_fetch::LyricProvider};
+
+mod runtime;
+
+const MANIFEST_FILE: &str = "plugin.json";
+const WASM_FILE: &str = "plugin.wasm";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PluginPermissions {
+    /// 允许访问的网络主机，同时允许访问其子域名
+    pub network: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginManifest {
+    /// 插件的 ID，只能包含小写字母、数字、`-` 和 `_`
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub permissions: PluginPermissions,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginInfo {
+    #[serde(flatten)]
+    pub manifest: PluginManifest,
+    pub enabled: bool,
+    /// 插件加载失败时的错误信息
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+struct PluginConfig {
+    enabled: HashSet<String>,
+}
+
+struct Plugin {
+    manifest: PluginManifest,
+    module: Result<Arc<runtime::PluginModule>, String>,
+}
+
+impl Plugin {
+    fn load(manifest: PluginManifest, wasm: &Path) -> Self {
+        let module =
+            runtime::PluginModule::load(&manifest.id, wasm, manifest.permissions.network.clone())
+                .map(Arc::new)
+                .map_err(|err| {
+                    println!("插件 {} 加载失败: {err:?}", manifest.id);
+                    err.to_string()
+                });
+        Self { manifest, module }
+    }
+}
+
+fn read_manifest(dir: &Path) -> anyhow::Result<PluginManifest> {
+    let manifest: PluginManifest =
+        serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_FILE))?)?;
+    let valid_id = !manifest.id.is_empty()
+        && manifest
+            .id
+            .chars()
+            .all(|x| x.is_ascii_lowercase() || x.is_ascii_digit() || x == '-' || x == '_');
+    if !valid_id {
+        anyhow::bail!("插件 ID 不合法: {}", manifest.id);
+    }
+    Ok(manifest)
+}
+
+pub struct PluginManager {
+    dir: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    config: PluginConfig,
+    plugins: Vec<Plugin>,
+}
+
+impl PluginManager {
+    pub fn load(data_dir: Option<PathBuf>) -> Self {
+        let dir = data_dir.as_ref().map(|x| x.join("plugins"));
+        let config_path = data_dir.as_ref().map(|x| x.join("plugins.json"));
+        let config =
+            crate::config::load_json(config_path.as_deref(), "插件设置").unwrap_or_default();
+        let mut manager = Self {
+            dir,
+            config_path,
+            config,
+            plugins: Vec::new(),
+        };
+        manager.load_plugins();
+        manager
+    }
+
+    fn load_plugins(&mut self) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut dirs: Vec<PathBuf> = entries
+            .filter_map(|x| x.ok().map(|x| x.path()))
+            .filter(|x| x.is_dir())
+            .collect();
+        dirs.sort();
+        for dir in dirs {
+            match read_manifest(&dir) {
+                Ok(manifest) => {
+                    println!("已加载插件 {} ({})", manifest.name, manifest.id);
+                    self.plugins
+                        .push(Plugin::load(manifest, &dir.join(WASM_FILE)));
+                }
+                Err(err) => println!("插件 {} 读取失败: {err:?}", dir.display()),
+            }
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.config_path.as_deref(), &self.config, "插件设置");
+    }
+
+    pub fn plugins(&self) -> Vec<PluginInfo> {
+        self.plugins
+            .iter()
+            .map(|x| PluginInfo {
+                manifest: x.manifest.clone(),
+                enabled: self.config.enabled.contains(&x.manifest.id),
+                error: x.module.as_ref().err().cloned(),
+            })
+            .collect()
+    }
+
+    /// 从包含 `plugin.json` 和 `plugin.wasm` 的文件夹安装插件，已经安装的同名插件会被替换，
+    /// 新安装的插件需要启用后才会生效
+    pub fn install(&mut self, source: &Path) -> anyhow::Result<PluginInfo> {
+        let dir = self
+            .dir
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("无法获取应用数据文件夹"))?;
+        let manifest = read_manifest(source)?;
+        if crate::lyric_fetch::providers()
+            .iter()
+            .any(|x| x.name() == manifest.id)
+        {
+            anyhow::bail!("插件 ID 与内置的歌词来源重复: {}", manifest.id);
+        }
+        // 先编译一次，确认插件可以正常加载再安装
+        runtime::PluginModule::load(&manifest.id, &source.join(WASM_FILE), Vec::new())?;
+        let target = dir.join(&manifest.id);
+        if target.exists() {
+            std::fs::remove_dir_all(&target)?;
+        }
+        std::fs::create_dir_all(&target)?;
+        for file in [MANIFEST_FILE, WASM_FILE] {
+            std::fs::copy(source.join(file), target.join(file))?;
+        }
+        self.config.enabled.remove(&manifest.id);
+        self.save();
+        self.plugins.retain(|x| x.manifest.id != manifest.id);
+        println!("已安装插件 {} ({})", manifest.name, manifest.id);
+        let plugin = Plugin::load(manifest, &target.join(WASM_FILE));
+        let info = PluginInfo {
+            manifest: plugin.manifest.clone(),
+            enabled: false,
+            error: plugin.module.as_ref().err().cloned(),
+        };
+        self.plugins.push(plugin);
+        Ok(info)
+    }
+
+    pub fn uninstall(&mut self, id: &str) -> anyhow::Result<()> {
+        if let Some(dir) = &self.dir {
+            let target = dir.join(id);
+            if target.is_dir() && self.plugins.iter().any(|x| x.manifest.id == id) {
+                std::fs::remove_dir_all(target)?;
+            }
+        }
+        self.plugins.retain(|x| x.manifest.id != id);
+        if self.config.enabled.remove(id) {
+            self.save();
+        }
+        Ok(())
+    }
+
+    pub fn set_enabled(&mut self, id: &str, enabled: bool) -> anyhow::Result<()> {
+        if self.plugins.iter().all(|x| x.manifest.id != id) {
+            anyhow::bail!("插件没有安装: {id}");
+        }
+        if enabled {
+            self.config.enabled.insert(id.to_string());
+        } else {
+            self.config.enabled.remove(id);
+        }
+        self.save();
+        Ok(())
+    }
+
+    /// 已启用的插件提供的歌词来源
+    pub fn lyric_providers(&self) -> Vec<Box<dyn LyricProvider>> {
+        self.plugins
+            .iter()
+            .filter(|x| self.config.enabled.contains(&x.manifest.id))
+            .filter_map(|x| x.module.as_ref().ok())
+            .map(|module| {
+                Box::new(runtime::WasmLyricProvider {
+                    module: module.clone(),
+                }) as Box<dyn LyricProvider>
+            })
+            .collect()
+    }
+}
+
+/// 获取已安装的插件
+#[tauri::command]
+pub fn plugins_list(plugins: State<Mutex<PluginManager>>) -> Vec<PluginInfo> {
+    plugins.lock().unwrap().plugins()
+}
+
+/// 从文件夹安装插件，安装后默认是禁用的
+#[tauri::command]
+pub fn plugins_install(
+    plugins: State<Mutex<PluginManager>>,
+    path: PathBuf,
+) -> CommandResult<PluginInfo> {
+    Ok(plugins.lock().unwrap().install(&path)?)
+}
+
+#[tauri::command]
+pub fn plugins_uninstall(plugins: State<Mutex<PluginManager>>, id: String) -> CommandResult<()> {
+    Ok(plugins.lock().unwrap().uninstall(&id)?)
+}
+
+/// 启用或者禁用插件
+#[tauri::command]
+pub fn plugins_set_enabled(
+    plugins: State<Mutex<PluginManager>>,
+    id: String,
+    enabled: bool,
+) -> CommandResult<()> {
+    Ok(plugins.lock().unwrap().set_enabled(&id, enabled)?)
+}
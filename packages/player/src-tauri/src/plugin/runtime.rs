@@ -0,0 +1,235 @@
+//! WASM 插件的运行环境
+//!
+//! 插件和宿主之间通过 JSON 交换数据，插件需要导出：
+//!
+//! - `memory`：插件的线性内存
+//! - `amll_alloc(len: i32) -> i32`：分配指定长度的内存，返回起始地址，宿主会把参数和请求结果写入其中
+//! - `amll_search(ptr: i32, len: i32) -> i64` 和 `amll_fetch(ptr: i32, len: i32) -> i64`：
+//!   参数为 JSON 数据的地址和长度，返回值的高 32 位为结果的地址，低 32 位为结果的长度
+//!
+//! 宿主在 `amll` 模块中提供以下函数：
+//!
+//! - `log(ptr: i32, len: i32)`：输出日志
+//! - `http_get(ptr: i32, len: i32) -> i64`：请求 URL 并返回响应内容，返回值的格式与上面相同，
+//!   失败或者访问了清单中没有声明的主机时返回 0，跳转后的地址同样需要在清单中声明
+//!
+//! 插件没有文件系统访问能力，每次调用都会创建新的实例，并限制可以执行的指令数量和可以使用的内存大小。
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+use crate::lyric_fetch::{LyricCandidate, LyricContent, LyricProvider, LyricQuery};
+
+/// 每次调用最多消耗的燃料，大致对应执行的指令数量
+const MAX_FUEL: u64 = 1_000_000_000;
+/// 每个实例的线性内存最大大小
+const MAX_MEMORY_SIZE: usize = 256 * 1024 * 1024;
+/// 一次读写插件内存的最大长度
+const MAX_TRANSFER_SIZE: usize = 16 * 1024 * 1024;
+const REQUIRED_EXPORTS: &[&str] = &["memory", "amll_alloc", "amll_search", "amll_fetch"];
+
+struct HostState {
+    id: String,
+    network: Vec<String>,
+    limits: StoreLimits,
+}
+
+/// 检查插件是否声明了访问该 URL 所在主机的权限，声明的主机包括其子域名
+fn check_network(allowed: &[String], url: &url::Url) -> anyhow::Result<()> {
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("只允许 HTTP 请求: {url}");
+    }
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    let permitted = allowed.iter().any(|x| {
+        let x = x.to_lowercase();
+        host == x || host.ends_with(&format!(".{x}"))
+    });
+    if !permitted {
+        anyhow::bail!("插件没有访问 {host} 的权限");
+    }
+    Ok(())
+}
+
+fn memory(caller: &Caller<'_, HostState>) -> anyhow::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .context("插件没有导出内存")
+}
+
+fn transfer_size(len: i32) -> anyhow::Result<usize> {
+    let len = usize::try_from(len)?;
+    if len > MAX_TRANSFER_SIZE {
+        anyhow::bail!("数据过大: {len} 字节");
+    }
+    Ok(len)
+}
+
+fn read_memory(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> anyhow::Result<Vec<u8>> {
+    let mut data = vec![0; transfer_size(len)?];
+    memory(caller)?.read(caller, ptr as u32 as usize, &mut data)?;
+    Ok(data)
+}
+
+/// 在插件中分配内存并写入数据，返回打包后的地址和长度
+fn write_memory(caller: &mut Caller<'_, HostState>, data: &[u8]) -> anyhow::Result<i64> {
+    let len = i32::try_from(data.len())?;
+    transfer_size(len)?;
+    let alloc = caller
+        .get_export("amll_alloc")
+        .and_then(Extern::into_func)
+        .context("插件没有导出 amll_alloc")?
+        .typed::<i32, i32>(&*caller)?;
+    let ptr = alloc.call(&mut *caller, len)?;
+    memory(caller)?.write(&mut *caller, ptr as u32 as usize, data)?;
+    Ok(((ptr as u32 as i64) << 32) | len as i64)
+}
+
+fn unpack(packed: i64) -> (usize, usize) {
+    let packed = packed as u64;
+    ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize)
+}
+
+/// 编译好的插件模块
+pub struct PluginModule {
+    id: String,
+    network: Vec<String>,
+    engine: Engine,
+    module: Module,
+}
+
+impl PluginModule {
+    pub fn load(id: &str, path: &Path, network: Vec<String>) -> anyhow::Result<Self> {
+        let wasm = std::fs::read(path)?;
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &wasm[..])?;
+        for name in REQUIRED_EXPORTS {
+            if module.exports().all(|x| x.name() != *name) {
+                anyhow::bail!("插件没有导出 {name}");
+            }
+        }
+        Ok(Self {
+            id: id.to_string(),
+            network,
+            engine,
+            module,
+        })
+    }
+
+    /// 创建新的实例并调用导出的函数
+    fn call(&self, name: &str, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut store = Store::new(
+            &self.engine,
+            HostState {
+                id: self.id.clone(),
+                network: self.network.clone(),
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(MAX_MEMORY_SIZE)
+                    .build(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.add_fuel(MAX_FUEL)?;
+        let mut linker = <Linker<HostState>>::new(&self.engine);
+        linker.func_wrap(
+            "amll",
+            "log",
+            |caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+                if let Ok(data) = read_memory(&caller, ptr, len) {
+                    println!(
+                        "[插件 {}] {}",
+                        caller.data().id,
+                        String::from_utf8_lossy(&data)
+                    );
+                }
+            },
+        )?;
+        linker.func_wrap(
+            "amll",
+            "http_get",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> i64 {
+                let result = read_memory(&caller, ptr, len)
+                    .and_then(|url| String::from_utf8(url).map_err(anyhow::Error::from))
+                    .and_then(|url| {
+                        let network = &caller.data().network;
+                        tauri::async_runtime::block_on(crate::http::get_bytes_checked(
+                            &url,
+                            MAX_TRANSFER_SIZE,
+                            |url| check_network(network, url),
+                        ))
+                    })
+                    .and_then(|data| write_memory(&mut caller, &data));
+                result.unwrap_or_else(|err| {
+                    println!("插件 {} 的网络请求失败: {err:?}", caller.data().id);
+                    0
+                })
+            },
+        )?;
+        let instance = linker
+            .instantiate(&mut store, &self.module)?
+            .start(&mut store)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .context("插件没有导出内存")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "amll_alloc")?;
+        let func = instance.get_typed_func::<(i32, i32), i64>(&store, name)?;
+        let len = i32::try_from(input.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+        let (ptr, len) = unpack(func.call(&mut store, (ptr, len))?);
+        if len > MAX_TRANSFER_SIZE {
+            anyhow::bail!("插件返回的数据过大: {len} 字节");
+        }
+        let mut output = vec![0; len];
+        memory.read(&store, ptr, &mut output)?;
+        Ok(output)
+    }
+
+    /// 在后台线程上调用，插件执行和网络请求都是阻塞的
+    async fn call_blocking(
+        self: &Arc<Self>,
+        name: &'static str,
+        input: Vec<u8>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let module = self.clone();
+        async_std::task::spawn_blocking(move || module.call(name, &input)).await
+    }
+}
+
+/// 由插件实现的歌词来源，名称为插件的 ID
+pub struct WasmLyricProvider {
+    pub module: Arc<PluginModule>,
+}
+
+#[async_trait]
+impl LyricProvider for WasmLyricProvider {
+    fn name(&self) -> &str {
+        &self.module.id
+    }
+
+    async fn search(&self, query: &LyricQuery) -> anyhow::Result<Vec<LyricCandidate>> {
+        let output = self
+            .module
+            .call_blocking("amll_search", serde_json::to_vec(query)?)
+            .await?;
+        let mut candidates: Vec<LyricCandidate> = serde_json::from_slice(&output)?;
+        for candidate in &mut candidates {
+            candidate.provider = self.module.id.clone();
+        }
+        Ok(candidates)
+    }
+
+    async fn fetch(&self, candidate: &LyricCandidate) -> anyhow::Result<Option<LyricContent>> {
+        let output = self
+            .module
+            .call_blocking("amll_fetch", serde_json::to_vec(candidate)?)
+            .await?;
+        Ok(serde_json::from_slice(&output)?)
+    }
+}
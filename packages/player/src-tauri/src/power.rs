@@ -0,0 +1,306 @@
+//! 播放时阻止系统休眠
+//!
+//! 正在播放时向系统申请电源锁，暂停、停止或者切换到没有歌曲时释放。
+//! 可以选择同时保持屏幕常亮，适合使用桌面歌词的用户。
+//! 电源锁在专门的后台线程上申请和释放，因为 Windows 的执行状态是和线程绑定的。
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{Receiver, Sender},
+        Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::now_playing::NowPlaying;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PowerConfig {
+    /// 播放时阻止系统休眠
+    pub prevent_sleep: bool,
+    /// 播放时同时保持屏幕常亮
+    pub keep_display_awake: bool,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            prevent_sleep: true,
+            keep_display_awake: false,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use windows::Win32::System::Power::{
+        SetThreadExecutionState, ES_CONTINUOUS, ES_DISPLAY_REQUIRED, ES_SYSTEM_REQUIRED,
+    };
+
+    /// 电源锁，释放时恢复默认的执行状态，必须在申请的线程上释放
+    pub struct Assertion;
+
+    impl Assertion {
+        pub fn acquire(keep_display_awake: bool) -> anyhow::Result<Self> {
+            let mut state = ES_CONTINUOUS | ES_SYSTEM_REQUIRED;
+            if keep_display_awake {
+                state |= ES_DISPLAY_REQUIRED;
+            }
+            if unsafe { SetThreadExecutionState(state) }.0 == 0 {
+                anyhow::bail!("SetThreadExecutionState 调用失败");
+            }
+            Ok(Self)
+        }
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            unsafe { SetThreadExecutionState(ES_CONTINUOUS) };
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use core_foundation::{
+        base::TCFType,
+        string::{CFString, CFStringRef},
+    };
+
+    const ASSERTION_LEVEL_ON: u32 = 255;
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPMAssertionCreateWithName(
+            assertion_type: CFStringRef,
+            level: u32,
+            name: CFStringRef,
+            id: *mut u32,
+        ) -> i32;
+        fn IOPMAssertionRelease(id: u32) -> i32;
+    }
+
+    fn create(assertion_type: &str) -> anyhow::Result<u32> {
+        let assertion_type = CFString::new(assertion_type);
+        let name = CFString::new("AMLL Player 正在播放");
+        let mut id = 0;
+        let result = unsafe {
+            IOPMAssertionCreateWithName(
+                assertion_type.as_concrete_TypeRef(),
+                ASSERTION_LEVEL_ON,
+                name.as_concrete_TypeRef(),
+                &mut id,
+            )
+        };
+        if result != 0 {
+            anyhow::bail!("IOPMAssertionCreateWithName 调用失败: {result:#x}");
+        }
+        Ok(id)
+    }
+
+    /// 电源锁，释放时撤销所有申请的 IOPMAssertion
+    pub struct Assertion(Vec<u32>);
+
+    impl Assertion {
+        pub fn acquire(keep_display_awake: bool) -> anyhow::Result<Self> {
+            let mut assertion = Self(vec![create("PreventUserIdleSystemSleep")?]);
+            if keep_display_awake {
+                assertion.0.push(create("PreventUserIdleDisplaySleep")?);
+            }
+            Ok(assertion)
+        }
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            for &id in &self.0 {
+                unsafe { IOPMAssertionRelease(id) };
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use zbus::{blocking::Connection, zvariant::OwnedFd};
+
+    /// 电源锁，持有 logind 返回的文件描述符，关闭描述符时自动释放；
+    /// 保持屏幕常亮时还会通过会话总线的屏保接口阻止屏幕关闭
+    pub struct Assertion {
+        _inhibitor: OwnedFd,
+        screensaver: Option<(Connection, u32)>,
+    }
+
+    fn inhibit_screensaver() -> zbus::Result<(Connection, u32)> {
+        let connection = Connection::session()?;
+        let cookie = connection
+            .call_method(
+                Some("org.freedesktop.ScreenSaver"),
+                "/org/freedesktop/ScreenSaver",
+                Some("org.freedesktop.ScreenSaver"),
+                "Inhibit",
+                &("AMLL Player", "正在显示桌面歌词"),
+            )?
+            .body()?;
+        Ok((connection, cookie))
+    }
+
+    impl Assertion {
+        pub fn acquire(keep_display_awake: bool) -> anyhow::Result<Self> {
+            let inhibitor = Connection::system()?
+                .call_method(
+                    Some("org.freedesktop.login1"),
+                    "/org/freedesktop/login1",
+                    Some("org.freedesktop.login1.Manager"),
+                    "Inhibit",
+                    &("sleep:idle", "AMLL Player", "正在播放", "block"),
+                )?
+                .body()?;
+            // 不是所有桌面环境都提供屏保接口，失败时仍然阻止系统休眠
+            let screensaver = keep_display_awake
+                .then(|| {
+                    inhibit_screensaver()
+                        .map_err(|err| println!("屏幕常亮申请失败: {err:?}"))
+                        .ok()
+                })
+                .flatten();
+            Ok(Self {
+                _inhibitor: inhibitor,
+                screensaver,
+            })
+        }
+    }
+
+    impl Drop for Assertion {
+        fn drop(&mut self) {
+            if let Some((connection, cookie)) = &self.screensaver {
+                let _ = connection.call_method(
+                    Some("org.freedesktop.ScreenSaver"),
+                    "/org/freedesktop/ScreenSaver",
+                    Some("org.freedesktop.ScreenSaver"),
+                    "UnInhibit",
+                    cookie,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+mod platform {
+    pub struct Assertion;
+
+    impl Assertion {
+        pub fn acquire(_keep_display_awake: bool) -> anyhow::Result<Self> {
+            anyhow::bail!("当前系统不支持阻止休眠")
+        }
+    }
+}
+
+/// 发送给后台线程的更新
+enum Update {
+    Playing(bool),
+    Config(PowerConfig),
+}
+
+/// 后台线程，根据播放状态和设置申请或者释放电源锁
+fn run(receiver: Receiver<Update>) {
+    let mut config = PowerConfig::default();
+    let mut playing = false;
+    let mut assertion = None;
+    for update in receiver {
+        match update {
+            Update::Playing(new_playing) if new_playing == playing => continue,
+            Update::Playing(new_playing) => playing = new_playing,
+            Update::Config(new_config) => {
+                if new_config == config {
+                    continue;
+                }
+                config = new_config;
+                // 设置变化时需要按新的设置重新申请
+                assertion = None;
+            }
+        }
+        if !playing || !config.prevent_sleep {
+            if assertion.take().is_some() {
+                println!("已释放电源锁");
+            }
+        } else if assertion.is_none() {
+            match platform::Assertion::acquire(config.keep_display_awake) {
+                Ok(x) => {
+                    println!("已申请电源锁，播放时系统不会休眠");
+                    assertion = Some(x);
+                }
+                Err(err) => println!("电源锁申请失败: {err:?}"),
+            }
+        }
+    }
+}
+
+pub struct PowerInhibitor {
+    path: Option<PathBuf>,
+    config: PowerConfig,
+    sender: Sender<Update>,
+}
+
+impl PowerInhibitor {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let config: PowerConfig =
+            crate::config::load_json(path.as_deref(), "电源设置").unwrap_or_default();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || run(receiver));
+        let _ = sender.send(Update::Config(config.clone()));
+        Self {
+            path,
+            config,
+            sender,
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.path.as_deref(), &self.config, "电源设置");
+    }
+
+    pub fn config(&self) -> PowerConfig {
+        self.config.clone()
+    }
+
+    pub fn set_config(&mut self, config: PowerConfig) {
+        self.config = config;
+        self.save();
+        let _ = self.sender.send(Update::Config(self.config.clone()));
+    }
+
+    /// 播放状态变化时申请或者释放电源锁，需要在 [`NowPlaying`] 处理信息之后调用
+    pub fn on_body(&self, app: &AppHandle, body: &Body) {
+        if matches!(
+            body,
+            Body::SetMusicId { .. } | Body::OnPaused | Body::OnResumed
+        ) {
+            let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+            let playing = !status.paused && !status.music_id.is_empty();
+            let _ = self.sender.send(Update::Playing(playing));
+        }
+    }
+
+    /// 播放源断开连接时释放电源锁
+    pub fn release(&self) {
+        let _ = self.sender.send(Update::Playing(false));
+    }
+}
+
+/// 获取播放时阻止休眠的设置
+#[tauri::command]
+pub fn power_get_config(power: State<Mutex<PowerInhibitor>>) -> PowerConfig {
+    power.lock().unwrap().config()
+}
+
+/// 修改播放时阻止休眠的设置，包括是否保持屏幕常亮
+#[tauri::command]
+pub fn power_set_config(power: State<Mutex<PowerInhibitor>>, config: PowerConfig) {
+    power.lock().unwrap().set_config(config);
+}
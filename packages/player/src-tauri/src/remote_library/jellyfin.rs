@@ -0,0 +1,180 @@
+//! Jellyfin 服务器
+//!
+//! 连接时使用用户名和密码换取访问令牌，之后的请求都通过令牌验证。
+//! 串流使用 `universal` 接口，服务器会根据播放器支持的格式和码率限制决定直接串流还是转码。
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use super::{RemoteAlbum, RemoteClient, RemoteServer, RemoteSong, StreamOptions};
+
+const CLIENT_NAME: &str = "AMLL Player";
+/// 播放器可以直接播放的容器格式
+const DIRECT_CONTAINERS: &str = "flac,mp3,ogg,opus,m4a,aac,wav,webm";
+/// 需要转码但没有指定格式时使用的格式
+const DEFAULT_TRANSCODE_FORMAT: &str = "mp3";
+/// Jellyfin 的时长单位为 100 纳秒
+const TICKS_PER_MILLISECOND: u64 = 10_000;
+
+pub struct JellyfinClient {
+    url: String,
+    /// 设备 ID，使用服务器在播放器中的 ID，保证同一台设备每次连接都相同
+    device_id: String,
+    user_id: String,
+    token: String,
+}
+
+fn authorization(device_id: &str) -> String {
+    format!(
+        "MediaBrowser Client=\"{CLIENT_NAME}\", Device=\"{CLIENT_NAME}\", DeviceId=\"{device_id}\", Version=\"{}\"",
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+fn str_of(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+impl JellyfinClient {
+    pub async fn connect(server: &RemoteServer, password: &str) -> anyhow::Result<Self> {
+        let result = crate::http::post_json(
+            &format!("{}/Users/AuthenticateByName", server.url),
+            json!({ "Username": server.username, "Pw": password }),
+            &[("X-Emby-Authorization", &authorization(&server.id))],
+        )
+        .await?;
+        let (Some(token), Some(user_id)) = (
+            result["AccessToken"].as_str(),
+            result["User"]["Id"].as_str(),
+        ) else {
+            anyhow::bail!("Jellyfin 登录失败，服务器没有返回访问令牌");
+        };
+        Ok(Self {
+            url: server.url.clone(),
+            device_id: server.id.clone(),
+            user_id: user_id.to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    async fn items(&self, query: &[(&str, &str)]) -> anyhow::Result<Vec<Value>> {
+        let mut query = query.to_vec();
+        query.push(("Recursive", "true"));
+        let result = crate::http::get_json_with_headers(
+            &format!("{}/Users/{}/Items", self.url, self.user_id),
+            &query,
+            &[("X-Emby-Token", &self.token)],
+        )
+        .await?;
+        Ok(result["Items"].as_array().cloned().unwrap_or_default())
+    }
+
+    fn cover_url(&self, item: &Value) -> Option<String> {
+        // 歌曲没有自己的封面时使用专辑的封面
+        let id = if item["ImageTags"]["Primary"].is_string() {
+            item["Id"].as_str()?
+        } else {
+            item["AlbumId"]
+                .as_str()
+                .filter(|_| item["AlbumPrimaryImageTag"].is_string())?
+        };
+        Some(format!(
+            "{}/Items/{id}/Images/Primary?api_key={}",
+            self.url, self.token
+        ))
+    }
+
+    fn song(&self, item: &Value) -> Option<RemoteSong> {
+        let artists: Vec<&str> = item["Artists"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        Some(RemoteSong {
+            id: item["Id"].as_str()?.to_string(),
+            title: str_of(&item["Name"]),
+            artist: artists.join("/"),
+            album: str_of(&item["Album"]),
+            album_id: item["AlbumId"].as_str().map(str::to_string),
+            duration: item["RunTimeTicks"]
+                .as_u64()
+                .map(|x| x / TICKS_PER_MILLISECOND),
+            cover_url: self.cover_url(item),
+        })
+    }
+}
+
+#[async_trait]
+impl RemoteClient for JellyfinClient {
+    async fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<RemoteSong>> {
+        let limit = limit.to_string();
+        let items = self
+            .items(&[
+                ("SearchTerm", query),
+                ("IncludeItemTypes", "Audio"),
+                ("Limit", limit.as_str()),
+            ])
+            .await?;
+        Ok(items.iter().filter_map(|x| self.song(x)).collect())
+    }
+
+    async fn albums(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<RemoteAlbum>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let items = self
+            .items(&[
+                ("IncludeItemTypes", "MusicAlbum"),
+                ("SortBy", "SortName"),
+                ("StartIndex", offset.as_str()),
+                ("Limit", limit.as_str()),
+            ])
+            .await?;
+        Ok(items
+            .iter()
+            .filter_map(|x| {
+                Some(RemoteAlbum {
+                    id: x["Id"].as_str()?.to_string(),
+                    name: str_of(&x["Name"]),
+                    artist: str_of(&x["AlbumArtist"]),
+                    song_count: x["ChildCount"].as_u64(),
+                    cover_url: self.cover_url(x),
+                })
+            })
+            .collect())
+    }
+
+    async fn album_songs(&self, album_id: &str) -> anyhow::Result<Vec<RemoteSong>> {
+        let items = self
+            .items(&[
+                ("ParentId", album_id),
+                ("IncludeItemTypes", "Audio"),
+                ("SortBy", "ParentIndexNumber,IndexNumber,SortName"),
+            ])
+            .await?;
+        Ok(items.iter().filter_map(|x| self.song(x)).collect())
+    }
+
+    fn stream_url(&self, song_id: &str, options: &StreamOptions) -> String {
+        let url = format!("{}/Audio/{song_id}/universal", self.url);
+        // 指定了格式时只接受该格式，服务器会在原始格式不同时转码
+        let format = options.format.as_deref();
+        let max_bitrate = options.max_bitrate.map(|x| (x as u64 * 1000).to_string());
+        let mut params = vec![
+            ("UserId", self.user_id.as_str()),
+            ("DeviceId", self.device_id.as_str()),
+            ("api_key", self.token.as_str()),
+            ("Container", format.unwrap_or(DIRECT_CONTAINERS)),
+            (
+                "TranscodingContainer",
+                format.unwrap_or(DEFAULT_TRANSCODE_FORMAT),
+            ),
+            ("AudioCodec", format.unwrap_or(DEFAULT_TRANSCODE_FORMAT)),
+            ("TranscodingProtocol", "http"),
+        ];
+        if let Some(max_bitrate) = &max_bitrate {
+            params.push(("MaxStreamingBitrate", max_bitrate.as_str()));
+        }
+        url::Url::parse_with_params(&url, params)
+            .map(String::from)
+            .unwrap_or(url)
+    }
+}
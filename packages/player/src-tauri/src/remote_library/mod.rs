@@ -0,0 +1,268 @@
+//! 远程音乐库
+//!
+//! 连接用户自建的 Subsonic（包括 Navidrome 等兼容服务器）或者 Jellyfin 服务器，在播放器中浏览、搜索并串流播放其中的歌曲。
+//! 每种服务器都实现了 [`RemoteClient`]，服务器的地址和用户名保存在应用数据文件夹中，
+//! 密码则保存在系统的凭据管理器（Windows 凭据管理器、macOS 钥匙串或者 Secret Service）中，不会写入磁盘上的配置文件。
+//!
+//! 串流地址可以指定最高码率和目标格式，由服务器决定直接串流还是转码。
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::CommandResult;
+
+mod jellyfin;
+mod subsonic;
+
+/// 保存密码时使用的服务名称
+const KEYRING_SERVICE: &str = "net.stevexmh.amllplayer.remote-library";
+const DEFAULT_LIMIT: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ServerKind {
+    Subsonic,
+    Jellyfin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteServer {
+    pub id: String,
+    pub kind: ServerKind,
+    /// 显示给用户的名称
+    pub name: String,
+    pub url: String,
+    pub username: String,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteSong {
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub album_id: Option<String>,
+    /// 歌曲时长，单位为毫秒
+    pub duration: Option<u64>,
+    pub cover_url: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteAlbum {
+    pub id: String,
+    pub name: String,
+    pub artist: String,
+    pub song_count: Option<u64>,
+    pub cover_url: Option<String>,
+}
+
+/// 串流时的转码要求
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StreamOptions {
+    /// 最高码率，单位为 kbps，为空时不限制
+    pub max_bitrate: Option<u32>,
+    /// 需要转码到的格式，例如 `mp3` 或者 `opus`，为空时由服务器决定
+    pub format: Option<String>,
+}
+
+#[async_trait]
+pub trait RemoteClient: Send + Sync {
+    /// 搜索歌曲
+    async fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<RemoteSong>>;
+
+    /// 按照名称排序列出专辑
+    async fn albums(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<RemoteAlbum>>;
+
+    /// 获取专辑中的歌曲
+    async fn album_songs(&self, album_id: &str) -> anyhow::Result<Vec<RemoteSong>>;
+
+    /// 获取歌曲的串流地址，地址中包含身份验证信息，可以直接交给播放器播放
+    fn stream_url(&self, song_id: &str, options: &StreamOptions) -> String;
+}
+
+/// 连接服务器并验证用户名和密码
+async fn connect(server: &RemoteServer, password: &str) -> anyhow::Result<Arc<dyn RemoteClient>> {
+    Ok(match server.kind {
+        ServerKind::Subsonic => {
+            Arc::new(subsonic::SubsonicClient::connect(server, password).await?)
+        }
+        ServerKind::Jellyfin => {
+            Arc::new(jellyfin::JellyfinClient::connect(server, password).await?)
+        }
+    })
+}
+
+fn keyring_entry(server_id: &str) -> anyhow::Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, server_id)?)
+}
+
+/// 去掉地址末尾的 `/`，方便拼接接口路径
+fn normalize_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+/// 生成随机的十六进制字符串，用作服务器 ID 和 Subsonic 的盐值
+fn random_hex(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..len)
+        .map(|_| format!("{:x}", rng.gen_range(0..16)))
+        .collect()
+}
+
+pub struct RemoteLibraries {
+    path: Option<PathBuf>,
+    servers: Vec<RemoteServer>,
+    /// 已经连接的服务器，第一次使用时才会连接
+    clients: HashMap<String, Arc<dyn RemoteClient>>,
+}
+
+impl RemoteLibraries {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let servers =
+            crate::config::load_json(path.as_deref(), "远程音乐库配置").unwrap_or_default();
+        Self {
+            path,
+            servers,
+            clients: HashMap::new(),
+        }
+    }
+
+    fn save(&self) {
+        crate::config::save_json(self.path.as_deref(), &self.servers, "远程音乐库配置");
+    }
+}
+
+/// 获取已经连接的服务器，还没有连接时从凭据管理器中读取密码并连接
+pub(crate) async fn client(
+    app: &AppHandle,
+    server_id: &str,
+) -> anyhow::Result<Arc<dyn RemoteClient>> {
+    let server = {
+        let libraries = app.state::<Mutex<RemoteLibraries>>();
+        let libraries = libraries.lock().unwrap();
+        if let Some(client) = libraries.clients.get(server_id) {
+            return Ok(client.clone());
+        }
+        libraries
+            .servers
+            .iter()
+            .find(|x| x.id == server_id)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("没有找到远程音乐库: {server_id}"))?
+    };
+    let password = keyring_entry(&server.id)?.get_password()?;
+    let client = connect(&server, &password).await?;
+    app.state::<Mutex<RemoteLibraries>>()
+        .lock()
+        .unwrap()
+        .clients
+        .insert(server.id.clone(), client.clone());
+    Ok(client)
+}
+
+/// 获取已添加的远程音乐库
+#[tauri::command]
+pub fn remote_library_list_servers(libraries: State<Mutex<RemoteLibraries>>) -> Vec<RemoteServer> {
+    libraries.lock().unwrap().servers.clone()
+}
+
+/// 添加远程音乐库，会先使用用户名和密码连接一次，连接成功后才会保存
+#[tauri::command]
+pub async fn remote_library_add_server(
+    app: AppHandle,
+    kind: ServerKind,
+    name: String,
+    url: String,
+    username: String,
+    password: String,
+) -> CommandResult<RemoteServer> {
+    let server = RemoteServer {
+        id: random_hex(16),
+        kind,
+        name,
+        url: normalize_url(&url),
+        username: username.trim().to_string(),
+    };
+    let client = connect(&server, &password).await?;
+    keyring_entry(&server.id)?
+        .set_password(&password)
+        .map_err(anyhow::Error::from)?;
+    let libraries = app.state::<Mutex<RemoteLibraries>>();
+    let mut libraries = libraries.lock().unwrap();
+    libraries.servers.push(server.clone());
+    libraries.clients.insert(server.id.clone(), client);
+    libraries.save();
+    println!("已添加远程音乐库 {} ({})", server.name, server.url);
+    Ok(server)
+}
+
+/// 删除远程音乐库，同时删除保存的密码
+#[tauri::command]
+pub fn remote_library_remove_server(libraries: State<Mutex<RemoteLibraries>>, id: String) {
+    let mut libraries = libraries.lock().unwrap();
+    libraries.servers.retain(|x| x.id != id);
+    libraries.clients.remove(&id);
+    libraries.save();
+    if let Err(err) = keyring_entry(&id).and_then(|x| Ok(x.delete_password()?)) {
+        println!("远程音乐库的密码删除失败: {err:?}");
+    }
+}
+
+#[tauri::command]
+pub async fn remote_library_search(
+    app: AppHandle,
+    server_id: String,
+    query: String,
+    limit: Option<usize>,
+) -> CommandResult<Vec<RemoteSong>> {
+    let client = client(&app, &server_id).await?;
+    Ok(client
+        .search(query.trim(), limit.unwrap_or(DEFAULT_LIMIT))
+        .await?)
+}
+
+#[tauri::command]
+pub async fn remote_library_albums(
+    app: AppHandle,
+    server_id: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> CommandResult<Vec<RemoteAlbum>> {
+    let client = client(&app, &server_id).await?;
+    Ok(client
+        .albums(offset.unwrap_or(0), limit.unwrap_or(DEFAULT_LIMIT))
+        .await?)
+}
+
+#[tauri::command]
+pub async fn remote_library_album_songs(
+    app: AppHandle,
+    server_id: String,
+    album_id: String,
+) -> CommandResult<Vec<RemoteSong>> {
+    let client = client(&app, &server_id).await?;
+    Ok(client.album_songs(&album_id).await?)
+}
+
+/// 获取歌曲的串流地址，可以指定最高码率和转码格式
+#[tauri::command]
+pub async fn remote_library_stream_url(
+    app: AppHandle,
+    server_id: String,
+    song_id: String,
+    options: Option<StreamOptions>,
+) -> CommandResult<String> {
+    let client = client(&app, &server_id).await?;
+    Ok(client.stream_url(&song_id, &options.unwrap_or_default()))
+}
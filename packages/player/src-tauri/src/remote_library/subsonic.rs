@@ -0,0 +1,164 @@
+//! Subsonic 服务器
+//!
+//! 使用 Subsonic API 1.13 引入的令牌验证，每次请求都使用新的盐值计算令牌，请求中不会出现明文密码。
+//! Navidrome、Airsonic 和 Gonic 等服务器都兼容此接口。
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{random_hex, RemoteAlbum, RemoteClient, RemoteServer, RemoteSong, StreamOptions};
+
+const API_VERSION: &str = "1.16.1";
+const CLIENT_NAME: &str = "AMLLPlayer";
+const COVER_SIZE: &str = "512";
+
+pub struct SubsonicClient {
+    url: String,
+    username: String,
+    password: String,
+}
+
+impl SubsonicClient {
+    pub async fn connect(server: &RemoteServer, password: &str) -> anyhow::Result<Self> {
+        let client = Self {
+            url: server.url.clone(),
+            username: server.username.clone(),
+            password: password.to_string(),
+        };
+        client.call("ping", &[]).await?;
+        Ok(client)
+    }
+
+    /// 身份验证和通用参数
+    fn auth_params(&self) -> Vec<(&'static str, String)> {
+        let salt = random_hex(12);
+        let token = format!("{:x}", md5::compute(format!("{}{salt}", self.password)));
+        vec![
+            ("u", self.username.clone()),
+            ("t", token),
+            ("s", salt),
+            ("v", API_VERSION.to_string()),
+            ("c", CLIENT_NAME.to_string()),
+        ]
+    }
+
+    /// 拼接带有身份验证信息的接口地址，用于串流和封面等直接交给前端加载的地址
+    fn url_of(&self, method: &str, params: &[(&str, &str)]) -> String {
+        let url = format!("{}/rest/{method}.view", self.url);
+        let auth = self.auth_params();
+        let pairs = auth
+            .iter()
+            .map(|(k, v)| (*k, v.as_str()))
+            .chain(params.iter().copied());
+        url::Url::parse_with_params(&url, pairs)
+            .map(String::from)
+            .unwrap_or(url)
+    }
+
+    async fn call(&self, method: &str, params: &[(&str, &str)]) -> anyhow::Result<Value> {
+        let auth = self.auth_params();
+        let mut query: Vec<(&str, &str)> = auth.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        query.push(("f", "json"));
+        query.extend_from_slice(params);
+        let result =
+            crate::http::get_json(&format!("{}/rest/{method}.view", self.url), &query).await?;
+        let response = &result["subsonic-response"];
+        if response["status"] != "ok" {
+            anyhow::bail!(
+                "Subsonic 请求 {method} 失败: {}",
+                response["error"]["message"].as_str().unwrap_or("未知错误")
+            );
+        }
+        Ok(response.clone())
+    }
+
+    fn cover_url(&self, item: &Value) -> Option<String> {
+        item["coverArt"]
+            .as_str()
+            .map(|id| self.url_of("getCoverArt", &[("id", id), ("size", COVER_SIZE)]))
+    }
+
+    fn song(&self, item: &Value) -> Option<RemoteSong> {
+        Some(RemoteSong {
+            id: item["id"].as_str()?.to_string(),
+            title: item["title"].as_str().unwrap_or_default().to_string(),
+            artist: item["artist"].as_str().unwrap_or_default().to_string(),
+            album: item["album"].as_str().unwrap_or_default().to_string(),
+            album_id: item["albumId"].as_str().map(str::to_string),
+            duration: item["duration"].as_u64().map(|x| x * 1000),
+            cover_url: self.cover_url(item),
+        })
+    }
+
+    fn songs(&self, items: &Value) -> Vec<RemoteSong> {
+        items
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| self.song(x))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl RemoteClient for SubsonicClient {
+    async fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<RemoteSong>> {
+        let limit = limit.to_string();
+        let result = self
+            .call(
+                "search3",
+                &[
+                    ("query", query),
+                    ("songCount", limit.as_str()),
+                    ("albumCount", "0"),
+                    ("artistCount", "0"),
+                ],
+            )
+            .await?;
+        Ok(self.songs(&result["searchResult3"]["song"]))
+    }
+
+    async fn albums(&self, offset: usize, limit: usize) -> anyhow::Result<Vec<RemoteAlbum>> {
+        let (offset, limit) = (offset.to_string(), limit.to_string());
+        let result = self
+            .call(
+                "getAlbumList2",
+                &[
+                    ("type", "alphabeticalByName"),
+                    ("size", limit.as_str()),
+                    ("offset", offset.as_str()),
+                ],
+            )
+            .await?;
+        Ok(result["albumList2"]["album"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|x| {
+                Some(RemoteAlbum {
+                    id: x["id"].as_str()?.to_string(),
+                    name: x["name"].as_str().unwrap_or_default().to_string(),
+                    artist: x["artist"].as_str().unwrap_or_default().to_string(),
+                    song_count: x["songCount"].as_u64(),
+                    cover_url: self.cover_url(x),
+                })
+            })
+            .collect())
+    }
+
+    async fn album_songs(&self, album_id: &str) -> anyhow::Result<Vec<RemoteSong>> {
+        let result = self.call("getAlbum", &[("id", album_id)]).await?;
+        Ok(self.songs(&result["album"]["song"]))
+    }
+
+    fn stream_url(&self, song_id: &str, options: &StreamOptions) -> String {
+        let max_bitrate = options.max_bitrate.map(|x| x.to_string());
+        let mut params = vec![("id", song_id)];
+        if let Some(max_bitrate) = &max_bitrate {
+            params.push(("maxBitRate", max_bitrate.as_str()));
+        }
+        if let Some(format) = &options.format {
+            params.push(("format", format.as_str()));
+        }
+        self.url_of("stream", &params)
+    }
+}
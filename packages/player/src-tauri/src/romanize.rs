@@ -0,0 +1,109 @@
+//! 歌词的罗马音生成
+//!
+//! 为中文歌词生成带声调的拼音，为日文歌词生成罗马音，结果会写入歌词行的音译歌词中。
+//! 日文的汉字读音和分词由 kakasi 完成，没有指定语言时会根据歌词行中的字符自动判断：
+//! 含有假名的行视为日文，只含有汉字的行视为中文。
+use lyric::LyricLine;
+use pinyin::ToPinyin;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RomanizeLanguage {
+    /// 中文，生成拼音
+    Zh,
+    /// 日文，生成罗马音
+    Ja,
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{309F}' | '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}')
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+/// 根据文本中的字符判断语言，不包含中日文字符时返回空
+pub fn detect_language(text: &str) -> Option<RomanizeLanguage> {
+    if text.chars().any(is_kana) {
+        Some(RomanizeLanguage::Ja)
+    } else if text.chars().any(is_han) {
+        Some(RomanizeLanguage::Zh)
+    } else {
+        None
+    }
+}
+
+/// 生成带声调的拼音，每个汉字的拼音之间以空格分隔，其它字符保持原样
+pub fn to_pinyin(text: &str) -> String {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut pending = String::new();
+    for c in text.chars() {
+        match c.to_pinyin() {
+            Some(pinyin) => {
+                if !pending.trim().is_empty() {
+                    tokens.push(pending.trim().to_string());
+                }
+                pending.clear();
+                tokens.push(pinyin.with_tone().to_string());
+            }
+            None => pending.push(c),
+        }
+    }
+    if !pending.trim().is_empty() {
+        tokens.push(pending.trim().to_string());
+    }
+    tokens.join(" ")
+}
+
+/// 生成日文的罗马音，汉字会根据词典转换为读音
+pub fn to_romaji(text: &str) -> String {
+    kakasi::convert(text)
+        .romaji
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 生成文本的罗马音，没有指定语言且无法判断语言时返回空
+pub fn romanize_text(text: &str, language: Option<RomanizeLanguage>) -> Option<String> {
+    match language.or_else(|| detect_language(text))? {
+        RomanizeLanguage::Zh => Some(to_pinyin(text)),
+        RomanizeLanguage::Ja => Some(to_romaji(text)),
+    }
+}
+
+/// 为歌词行生成音译歌词，已有音译歌词的行只有在 `overwrite` 为真时才会被覆盖
+pub fn romanize_lines(
+    lines: &mut [LyricLine],
+    language: Option<RomanizeLanguage>,
+    overwrite: bool,
+) {
+    for line in lines {
+        if !overwrite && !line.roman_lyric.trim().is_empty() {
+            continue;
+        }
+        let text: String = line.words.iter().map(|x| x.word.as_ref()).collect();
+        if let Some(roman) = romanize_text(&text, language) {
+            line.roman_lyric = roman;
+        }
+    }
+}
+
+/// 生成文本的罗马音，没有指定语言时会自动判断
+#[tauri::command]
+pub fn romanize_lyric_text(text: String, language: Option<RomanizeLanguage>) -> String {
+    romanize_text(&text, language).unwrap_or_default()
+}
+
+/// 为歌词行生成音译歌词并返回处理后的歌词行
+#[tauri::command]
+pub fn romanize_lyric(
+    mut lines: Vec<LyricLine<'static>>,
+    language: Option<RomanizeLanguage>,
+    overwrite: Option<bool>,
+) -> Vec<LyricLine<'static>> {
+    romanize_lines(&mut lines, language, overwrite.unwrap_or(false));
+    lines
+}
@@ -0,0 +1,422 @@
+//! 自动化脚本
+//!
+//! 应用数据文件夹中 `scripts` 文件夹里的 `.rhai` 脚本会在启动时加载，脚本可以定义以下函数来响应播放事件：
+//!
+//! - `on_track_change(status)`：切换歌曲后第一次收到播放进度时调用，`status` 和 HTTP 接口 `/status` 的内容相同
+//! - `on_play()` / `on_pause()`：继续或者暂停播放时调用
+//! - `on_lyric_line(line)`：当前歌词行变化时调用，`line` 包含下标 `index` 和歌词文本 `text`
+//!
+//! 脚本只能调用以下受限的接口，可以用来实现自定义的记录、上报或者智能家居联动：
+//!
+//! - `log(message)`：输出日志
+//! - `status()`：获取当前的播放状态
+//! - `control(action)` / `seek(position)`：控制播放，`action` 可以是 `play`、`pause`、`next` 或者 `prev`，`position` 单位为毫秒
+//! - `send_message(event, data)`：通过 `on-script-message` 事件发送给前端，同时推送给 HTTP 事件流
+//! - `write_file(name, content)` / `append_file(name, content)`：写入 `scripts/output` 文件夹中的文件，不能访问其它位置
+//! - `http_get(url)` / `http_post(url, body)`：发送 HTTP 请求并将返回的 JSON 作为结果
+//!
+//! 脚本在单独的后台线程上运行，不会阻塞播放信息的处理，每次调用的运算次数也有上限，避免死循环卡住后台线程。
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::{
+    error::{CommandError, CommandResult, ErrorCode},
+    http_server::HttpServer,
+    lyric_sync::LyricLineChanged,
+    now_playing::{NowPlaying, NowPlayingStatus},
+};
+
+const SCRIPT_EXTENSION: &str = "rhai";
+/// 单次调用最多执行的运算次数
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+const HOOKS: &[&str] = &["on_track_change", "on_play", "on_pause", "on_lyric_line"];
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptInfo {
+    /// 脚本的文件名
+    pub name: String,
+    /// 脚本定义的事件处理函数
+    pub hooks: Vec<String>,
+    /// 脚本编译失败时的错误信息
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptError {
+    pub script: String,
+    pub hook: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ScriptMessage {
+    script: String,
+    event: String,
+    data: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct LyricLineHook {
+    index: Option<i64>,
+    text: String,
+}
+
+/// 发送给后台线程的事件，脚本的值不能跨线程传递，所以参数先转换为 JSON
+enum Event {
+    Hook(&'static str, Vec<serde_json::Value>),
+    Reload(Sender<Vec<ScriptInfo>>),
+}
+
+struct Script {
+    name: String,
+    ast: AST,
+    scope: Scope<'static>,
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn to_dynamic<T: Serialize>(value: &T) -> Dynamic {
+    rhai::serde::to_dynamic(value).unwrap_or_default()
+}
+
+/// 输出文件只能直接放在输出文件夹中，文件名不能包含路径
+fn output_path(output_dir: &Path, name: &str) -> ScriptResult<PathBuf> {
+    if name.is_empty() || Path::new(name).file_name() != Some(name.as_ref()) {
+        return Err(format!("不允许的文件名: {name}").into());
+    }
+    std::fs::create_dir_all(output_dir).map_err(|err| err.to_string())?;
+    Ok(output_dir.join(name))
+}
+
+fn check_url(url: &str) -> ScriptResult<()> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(format!("只允许 HTTP 请求: {url}").into())
+    }
+}
+
+fn control(app: &AppHandle, action: &str) -> ScriptResult<()> {
+    let body = match action {
+        "play" => Body::Resume,
+        "pause" => Body::Pause,
+        "next" => Body::ForwardSong,
+        "prev" => Body::BackwardSong,
+        _ => return Err(format!("未知的控制指令: {action}").into()),
+    };
+    crate::send_control(app, body);
+    Ok(())
+}
+
+/// 创建只能调用受限接口的脚本引擎，`script` 为调用接口的脚本名称，由后台线程在调用前设置
+fn create_engine(app: AppHandle, dir: &Path, script: Arc<Mutex<String>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.disable_symbol("eval");
+    engine.on_print(|message| println!("[脚本] {message}"));
+
+    let name = script.clone();
+    engine.register_fn("log", move |message: &str| {
+        println!("[脚本 {}] {message}", name.lock().unwrap());
+    });
+    let handle = app.clone();
+    engine.register_fn("status", move || {
+        to_dynamic(&handle.state::<Mutex<NowPlaying>>().lock().unwrap().status())
+    });
+    let handle = app.clone();
+    engine.register_fn("control", move |action: &str| control(&handle, action));
+    let handle = app.clone();
+    engine.register_fn("seek", move |position: f64| {
+        crate::send_control(&handle, Body::SetPlayProgress { progress: position });
+    });
+    let handle = app.clone();
+    engine.register_fn("seek", move |position: i64| {
+        crate::send_control(
+            &handle,
+            Body::SetPlayProgress {
+                progress: position as f64,
+            },
+        );
+    });
+    let handle = app;
+    engine.register_fn(
+        "send_message",
+        move |event: &str, data: Dynamic| -> ScriptResult<()> {
+            let message = ScriptMessage {
+                script: script.lock().unwrap().clone(),
+                event: event.to_string(),
+                data: rhai::serde::from_dynamic(&data)?,
+            };
+            if let Some(http) = handle.try_state::<Mutex<HttpServer>>() {
+                http.lock().unwrap().publish("script-message", &message);
+            }
+            handle
+                .emit_all("on-script-message", message)
+                .map_err(|err| err.to_string().into())
+        },
+    );
+    let output_dir = dir.join("output");
+    engine.register_fn(
+        "write_file",
+        move |name: &str, content: &str| -> ScriptResult<()> {
+            std::fs::write(output_path(&output_dir, name)?, content)
+                .map_err(|err| err.to_string().into())
+        },
+    );
+    let output_dir = dir.join("output");
+    engine.register_fn(
+        "append_file",
+        move |name: &str, content: &str| -> ScriptResult<()> {
+            use std::io::Write;
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(output_path(&output_dir, name)?)
+                .and_then(|mut file| file.write_all(content.as_bytes()))
+                .map_err(|err| err.to_string().into())
+        },
+    );
+    engine.register_fn("http_get", |url: &str| -> ScriptResult<Dynamic> {
+        check_url(url)?;
+        tauri::async_runtime::block_on(crate::http::get_json(url, &[]))
+            .map(|x| to_dynamic(&x))
+            .map_err(|err| err.to_string().into())
+    });
+    engine.register_fn(
+        "http_post",
+        |url: &str, body: Dynamic| -> ScriptResult<Dynamic> {
+            check_url(url)?;
+            let body = rhai::serde::from_dynamic(&body)?;
+            tauri::async_runtime::block_on(crate::http::post_json(url, body, &[]))
+                .map(|x| to_dynamic(&x))
+                .map_err(|err| err.to_string().into())
+        },
+    );
+    engine
+}
+
+/// 编译并运行文件夹中所有脚本的顶层代码
+fn load_scripts(
+    engine: &Engine,
+    dir: &Path,
+    current: &Mutex<String>,
+) -> (Vec<Script>, Vec<ScriptInfo>) {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|x| {
+            x.filter_map(|x| x.ok().map(|x| x.path()))
+                .filter(|x| x.is_file())
+                .filter(|x| x.extension().is_some_and(|x| x == SCRIPT_EXTENSION))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    let mut scripts = Vec::new();
+    let mut infos = Vec::new();
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|x| x.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        *current.lock().unwrap() = name.clone();
+        let mut scope = Scope::new();
+        let result = engine
+            .compile_file(path.clone())
+            .and_then(|ast| engine.run_ast_with_scope(&mut scope, &ast).map(|_| ast));
+        match result {
+            Ok(ast) => {
+                let hooks = ast
+                    .iter_functions()
+                    .map(|x| x.name.to_string())
+                    .filter(|x| HOOKS.contains(&x.as_str()))
+                    .collect();
+                println!("已加载脚本 {name}");
+                infos.push(ScriptInfo {
+                    name: name.clone(),
+                    hooks,
+                    error: None,
+                });
+                scripts.push(Script { name, ast, scope });
+            }
+            Err(err) => {
+                println!("脚本 {name} 加载失败: {err}");
+                infos.push(ScriptInfo {
+                    name,
+                    hooks: Vec::new(),
+                    error: Some(err.to_string()),
+                });
+            }
+        }
+    }
+    (scripts, infos)
+}
+
+/// 后台线程，加载脚本并依次调用脚本中的事件处理函数
+fn run(
+    app: AppHandle,
+    dir: PathBuf,
+    infos: Arc<Mutex<Vec<ScriptInfo>>>,
+    receiver: Receiver<Event>,
+) {
+    if let Err(err) = std::fs::create_dir_all(&dir) {
+        println!("脚本文件夹创建失败: {err:?}");
+    }
+    let current = Arc::new(Mutex::new(String::new()));
+    let engine = create_engine(app.clone(), &dir, current.clone());
+    let mut scripts = Vec::new();
+    for event in receiver {
+        match event {
+            Event::Reload(reply) => {
+                let (new_scripts, new_infos) = load_scripts(&engine, &dir, &current);
+                scripts = new_scripts;
+                infos.lock().unwrap().clone_from(&new_infos);
+                let _ = reply.send(new_infos);
+            }
+            Event::Hook(hook, args) => {
+                let args: Vec<Dynamic> = args.iter().map(to_dynamic).collect();
+                for script in &mut scripts {
+                    let defined = script
+                        .ast
+                        .iter_functions()
+                        .any(|x| x.name == hook && x.params.len() == args.len());
+                    if !defined {
+                        continue;
+                    }
+                    *current.lock().unwrap() = script.name.clone();
+                    let options = CallFnOptions::new().eval_ast(false);
+                    let result = engine.call_fn_with_options::<Dynamic>(
+                        options,
+                        &mut script.scope,
+                        &script.ast,
+                        hook,
+                        args.clone(),
+                    );
+                    if let Err(err) = result {
+                        println!("脚本 {} 的 {hook} 执行失败: {err}", script.name);
+                        let error = ScriptError {
+                            script: script.name.clone(),
+                            hook: hook.to_string(),
+                            message: err.to_string(),
+                        };
+                        if let Err(err) = app.emit_all("on-script-error", error) {
+                            println!("脚本错误事件发送失败: {err:?}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub struct Scripting {
+    app: AppHandle,
+    sender: Sender<Event>,
+    infos: Arc<Mutex<Vec<ScriptInfo>>>,
+    /// 切换歌曲后还没有调用 `on_track_change`
+    track_pending: bool,
+}
+
+impl Scripting {
+    pub fn new(app: AppHandle, dir: Option<PathBuf>) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let infos = Arc::new(Mutex::new(Vec::new()));
+        if let Some(dir) = dir {
+            let handle = app.clone();
+            let infos = infos.clone();
+            std::thread::spawn(move || run(handle, dir, infos, receiver));
+            let (reply, _) = std::sync::mpsc::channel();
+            let _ = sender.send(Event::Reload(reply));
+        }
+        Self {
+            app,
+            sender,
+            infos,
+            track_pending: false,
+        }
+    }
+
+    fn call(&self, hook: &'static str, args: Vec<serde_json::Value>) {
+        let _ = self.sender.send(Event::Hook(hook, args));
+    }
+
+    fn status(&self) -> NowPlayingStatus {
+        self.app
+            .state::<Mutex<NowPlaying>>()
+            .lock()
+            .unwrap()
+            .status()
+    }
+
+    pub fn on_body(&mut self, body: &Body) {
+        match body {
+            Body::SetMusicId { .. } => self.track_pending = true,
+            // 歌曲的其它信息在歌曲 ID 之后才会陆续发送，等到开始播放时再通知脚本
+            Body::OnPlayProgress { .. } if self.track_pending => {
+                self.track_pending = false;
+                let status = self.status();
+                self.call("on_track_change", vec![json!(status)]);
+            }
+            Body::OnResumed => self.call("on_play", Vec::new()),
+            Body::OnPaused => self.call("on_pause", Vec::new()),
+            _ => {}
+        }
+    }
+
+    pub fn on_lyric_line(&self, line: &LyricLineChanged) {
+        let text = line
+            .index
+            .and_then(|index| {
+                let now_playing = self.app.state::<Mutex<NowPlaying>>();
+                let now_playing = now_playing.lock().unwrap();
+                now_playing
+                    .lyric()
+                    .get(index)
+                    .map(|x| x.words.iter().map(|x| x.word.to_string()).collect())
+            })
+            .unwrap_or_default();
+        let line = LyricLineHook {
+            index: line.index.map(|x| x as i64),
+            text,
+        };
+        self.call("on_lyric_line", vec![json!(line)]);
+    }
+}
+
+/// 获取已加载的脚本
+#[tauri::command]
+pub fn scripts_list(scripting: State<Mutex<Scripting>>) -> Vec<ScriptInfo> {
+    scripting.lock().unwrap().infos.lock().unwrap().clone()
+}
+
+/// 重新加载脚本文件夹中的所有脚本
+#[tauri::command]
+pub async fn scripts_reload(
+    scripting: State<'_, Mutex<Scripting>>,
+) -> CommandResult<Vec<ScriptInfo>> {
+    let (reply, receiver) = std::sync::mpsc::channel();
+    scripting
+        .lock()
+        .unwrap()
+        .sender
+        .send(Event::Reload(reply))
+        .map_err(|_| CommandError::new(ErrorCode::Other, "无法获取应用数据文件夹，脚本没有启用"))?;
+    async_std::task::spawn_blocking(move || receiver.recv())
+        .await
+        .map_err(|err| CommandError::new(ErrorCode::Other, err.to_string()))
+}
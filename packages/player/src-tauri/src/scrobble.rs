@@ -0,0 +1,561 @@
+//! Last.fm 和 ListenBrainz 的播放记录提交（Scrobble）
+//!
+//! 根据 WebSocket 客户端发送过来的播放信息统计每首歌曲实际播放的时长，
+//! 按照 Last.fm 的规则，时长超过 30 秒的歌曲播放了一半或者 4 分钟（以较短者为准）后提交一次播放记录，
+//! 歌曲开始播放时还会更新“正在播放”的状态。
+//! 提交失败的播放记录会保存在离线队列中并持久化到应用数据文件夹，之后再次提交。
+use std::{
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager, State};
+use ws_protocol::Body;
+
+use crate::{
+    error::{CommandError, CommandResult, ErrorCode},
+    http,
+    now_playing::NowPlaying,
+};
+
+const LASTFM_API: &str = "https://ws.audioscrobbler.com/2.0/";
+const LASTFM_AUTH_URL: &str = "https://www.last.fm/api/auth/";
+/// Last.fm 的 API 密钥需要在编译时通过环境变量指定
+const LASTFM_API_KEY: Option<&str> = option_env!("AMLL_LASTFM_API_KEY");
+const LASTFM_API_SECRET: Option<&str> = option_env!("AMLL_LASTFM_API_SECRET");
+const LISTENBRAINZ_API: &str = "https://api.listenbrainz.org/1";
+/// 短于此时长的歌曲不会被提交，单位为毫秒
+const MIN_TRACK_DURATION: u64 = 30_000;
+/// 播放时长达到此值后一定会被提交，单位为毫秒
+const MAX_SCROBBLE_THRESHOLD: f64 = 240_000.0;
+/// 两次播放进度之间的差距超过该值时视为跳转，不计入播放时长，单位为毫秒
+const MAX_PROGRESS_STEP: f64 = 5000.0;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ScrobbleService {
+    LastFm,
+    ListenBrainz,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Account {
+    username: String,
+    /// Last.fm 的会话密钥或者 ListenBrainz 的用户令牌
+    key: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct ScrobbleConfig {
+    lastfm: Option<Account>,
+    listenbrainz: Option<Account>,
+}
+
+impl ScrobbleConfig {
+    fn account(&self, service: ScrobbleService) -> Option<&Account> {
+        match service {
+            ScrobbleService::LastFm => self.lastfm.as_ref(),
+            ScrobbleService::ListenBrainz => self.listenbrainz.as_ref(),
+        }
+    }
+
+    fn services(&self) -> Vec<ScrobbleService> {
+        [ScrobbleService::LastFm, ScrobbleService::ListenBrainz]
+            .into_iter()
+            .filter(|x| self.account(*x).is_some())
+            .collect()
+    }
+}
+
+/// 等待提交的播放记录
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Scrobble {
+    pub id: u64,
+    pub artist: String,
+    pub track: String,
+    pub album: String,
+    /// 歌曲的总时长，单位为毫秒
+    pub duration: u64,
+    /// 开始播放的时间，为 UNIX 时间戳，单位为秒
+    pub timestamp: u64,
+    /// 还没有提交成功的服务
+    pub services: Vec<ScrobbleService>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScrobbleStatus {
+    /// 已登录的 Last.fm 用户名
+    pub lastfm: Option<String>,
+    /// 已登录的 ListenBrainz 用户名
+    pub listenbrainz: Option<String>,
+    /// 离线队列中等待提交的播放记录数量
+    pub pending: usize,
+}
+
+struct CurrentTrack {
+    music_id: String,
+    duration: u64,
+    started_at: u64,
+    /// 实际播放的时长，单位为毫秒
+    played: f64,
+    last_progress: Option<f64>,
+    now_playing_sent: bool,
+    scrobbled: bool,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+/// 调用 Last.fm 的 API，参数会按照要求进行签名
+async fn lastfm_call(
+    method: &str,
+    params: &[(&str, String)],
+    session_key: Option<&str>,
+) -> anyhow::Result<Value> {
+    let (Some(api_key), Some(secret)) = (LASTFM_API_KEY, LASTFM_API_SECRET) else {
+        anyhow::bail!("没有配置 Last.fm 的 API 密钥");
+    };
+    let mut params: Vec<(&str, String)> = params.to_vec();
+    params.push(("method", method.to_string()));
+    params.push(("api_key", api_key.to_string()));
+    if let Some(session_key) = session_key {
+        params.push(("sk", session_key.to_string()));
+    }
+    params.sort_by(|a, b| a.0.cmp(b.0));
+    let mut signature = String::new();
+    for (key, value) in &params {
+        signature.push_str(key);
+        signature.push_str(value);
+    }
+    signature.push_str(secret);
+    let signature = format!("{:x}", md5::compute(signature));
+    params.push(("api_sig", signature));
+    params.push(("format", "json".to_string()));
+    let form: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    let result = http::post_form(LASTFM_API, &form).await?;
+    if let Some(error) = result.get("error") {
+        anyhow::bail!(
+            "Last.fm 返回错误 {error}: {}",
+            result["message"].as_str().unwrap_or_default()
+        );
+    }
+    Ok(result)
+}
+
+/// 将播放记录转换成 ListenBrainz 的格式
+fn listenbrainz_payload(scrobble: &Scrobble, listened_at: Option<u64>) -> Value {
+    let mut payload = json!({
+        "track_metadata": {
+            "artist_name": scrobble.artist,
+            "track_name": scrobble.track,
+            "additional_info": {
+                "duration_ms": scrobble.duration,
+                "submission_client": "AMLL Player",
+                "submission_client_version": env!("CARGO_PKG_VERSION"),
+            },
+        },
+    });
+    if !scrobble.album.is_empty() {
+        payload["track_metadata"]["release_name"] = json!(scrobble.album);
+    }
+    if let Some(listened_at) = listened_at {
+        payload["listened_at"] = json!(listened_at);
+    }
+    payload
+}
+
+async fn listenbrainz_submit(token: &str, listen_type: &str, payload: Value) -> anyhow::Result<()> {
+    http::post_json(
+        &format!("{LISTENBRAINZ_API}/submit-listens"),
+        json!({ "listen_type": listen_type, "payload": [payload] }),
+        &[("Authorization", &format!("Token {token}"))],
+    )
+    .await?;
+    Ok(())
+}
+
+/// 向指定的服务提交播放记录
+async fn submit(service: ScrobbleService, key: &str, scrobble: &Scrobble) -> anyhow::Result<()> {
+    match service {
+        ScrobbleService::LastFm => {
+            let mut params = vec![
+                ("artist", scrobble.artist.clone()),
+                ("track", scrobble.track.clone()),
+                ("timestamp", scrobble.timestamp.to_string()),
+                ("duration", (scrobble.duration / 1000).to_string()),
+            ];
+            if !scrobble.album.is_empty() {
+                params.push(("album", scrobble.album.clone()));
+            }
+            lastfm_call("track.scrobble", &params, Some(key)).await?;
+        }
+        ScrobbleService::ListenBrainz => {
+            let payload = listenbrainz_payload(scrobble, Some(scrobble.timestamp));
+            listenbrainz_submit(key, "single", payload).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 更新“正在播放”的状态，失败时不会重试
+async fn update_now_playing(
+    service: ScrobbleService,
+    key: &str,
+    scrobble: &Scrobble,
+) -> anyhow::Result<()> {
+    match service {
+        ScrobbleService::LastFm => {
+            let mut params = vec![
+                ("artist", scrobble.artist.clone()),
+                ("track", scrobble.track.clone()),
+                ("duration", (scrobble.duration / 1000).to_string()),
+            ];
+            if !scrobble.album.is_empty() {
+                params.push(("album", scrobble.album.clone()));
+            }
+            lastfm_call("track.updateNowPlaying", &params, Some(key)).await?;
+        }
+        ScrobbleService::ListenBrainz => {
+            let payload = listenbrainz_payload(scrobble, None);
+            listenbrainz_submit(key, "playing_now", payload).await?;
+        }
+    }
+    Ok(())
+}
+
+pub struct Scrobbler {
+    app: AppHandle,
+    config_path: Option<PathBuf>,
+    queue_path: Option<PathBuf>,
+    config: ScrobbleConfig,
+    queue: Vec<Scrobble>,
+    current: Option<CurrentTrack>,
+    /// 正在等待用户在浏览器中授权的 Last.fm 令牌
+    lastfm_token: Option<String>,
+    flushing: bool,
+}
+
+impl Scrobbler {
+    pub fn load(app: AppHandle, dir: Option<PathBuf>) -> Self {
+        let config_path = dir.as_ref().map(|x| x.join("scrobble.json"));
+        let queue_path = dir.as_ref().map(|x| x.join("scrobble-queue.json"));
+        let mut scrobbler = Self {
+            app,
+            config: crate::config::load_json(config_path.as_deref(), "播放记录提交配置")
+                .unwrap_or_default(),
+            queue: crate::config::load_json(queue_path.as_deref(), "播放记录离线队列")
+                .unwrap_or_default(),
+            config_path,
+            queue_path,
+            current: None,
+            lastfm_token: None,
+            flushing: false,
+        };
+        scrobbler.flush();
+        scrobbler
+    }
+
+    fn save_config(&self) {
+        crate::config::save_json(
+            self.config_path.as_deref(),
+            &self.config,
+            "播放记录提交配置",
+        );
+    }
+
+    fn save_queue(&self) {
+        crate::config::save_json(self.queue_path.as_deref(), &self.queue, "播放记录离线队列");
+    }
+
+    pub fn status(&self) -> ScrobbleStatus {
+        ScrobbleStatus {
+            lastfm: self.config.lastfm.as_ref().map(|x| x.username.clone()),
+            listenbrainz: self
+                .config
+                .listenbrainz
+                .as_ref()
+                .map(|x| x.username.clone()),
+            pending: self.queue.len(),
+        }
+    }
+
+    pub fn pending(&self) -> Vec<Scrobble> {
+        self.queue.clone()
+    }
+
+    fn set_account(&mut self, service: ScrobbleService, account: Option<Account>) {
+        match service {
+            ScrobbleService::LastFm => self.config.lastfm = account,
+            ScrobbleService::ListenBrainz => self.config.listenbrainz = account,
+        }
+        self.save_config();
+    }
+
+    /// 退出登录，离线队列中只等待提交到此服务的播放记录会被丢弃
+    pub fn logout(&mut self, service: ScrobbleService) {
+        self.set_account(service, None);
+        for scrobble in &mut self.queue {
+            scrobble.services.retain(|x| *x != service);
+        }
+        self.queue.retain(|x| !x.services.is_empty());
+        self.save_queue();
+    }
+
+    /// 根据当前的播放状态生成播放记录，歌手或者歌曲名称为空时返回空值
+    fn make_scrobble(&self, current: &CurrentTrack) -> Option<Scrobble> {
+        let status = self
+            .app
+            .state::<Mutex<NowPlaying>>()
+            .lock()
+            .unwrap()
+            .status();
+        if status.music_id != current.music_id
+            || status.artists.is_empty()
+            || status.music_name.is_empty()
+        {
+            return None;
+        }
+        Some(Scrobble {
+            id: current.started_at * 1000 + self.queue.len() as u64,
+            artist: status.artists.join(", "),
+            track: status.music_name,
+            album: status.album_name,
+            duration: current.duration,
+            timestamp: current.started_at,
+            services: self.config.services(),
+        })
+    }
+
+    /// 需要在 [`NowPlaying`] 处理信息之后调用
+    pub fn on_body(&mut self, body: &Body) {
+        match body {
+            Body::SetMusicId { id, duration, .. } => {
+                let id = id.to_string();
+                if self.current.as_ref().is_some_and(|x| x.music_id == id) {
+                    return;
+                }
+                self.current = Some(CurrentTrack {
+                    music_id: id,
+                    duration: *duration,
+                    started_at: now_secs(),
+                    played: 0.0,
+                    last_progress: None,
+                    now_playing_sent: false,
+                    scrobbled: false,
+                });
+            }
+            Body::OnPlayProgress { progress } => {
+                if self.config.services().is_empty() {
+                    return;
+                }
+                let Some(current) = &mut self.current else {
+                    return;
+                };
+                if let Some(last_progress) = current.last_progress {
+                    let step = progress - last_progress;
+                    if step > 0.0 && step < MAX_PROGRESS_STEP {
+                        current.played += step;
+                    }
+                }
+                current.last_progress = Some(*progress);
+                let threshold = (current.duration as f64 / 2.0).min(MAX_SCROBBLE_THRESHOLD);
+                let send_now_playing = !current.now_playing_sent;
+                let should_scrobble = !current.scrobbled
+                    && current.duration > MIN_TRACK_DURATION
+                    && current.played >= threshold;
+                if !send_now_playing && !should_scrobble {
+                    return;
+                }
+                let Some(current) = self.current.take() else {
+                    return;
+                };
+                let scrobble = self.make_scrobble(&current);
+                self.current = Some(CurrentTrack {
+                    now_playing_sent: true,
+                    scrobbled: current.scrobbled || should_scrobble,
+                    ..current
+                });
+                let Some(scrobble) = scrobble else {
+                    return;
+                };
+                if send_now_playing {
+                    self.send_now_playing(scrobble.clone());
+                }
+                if should_scrobble {
+                    println!("提交播放记录: {} - {}", scrobble.artist, scrobble.track);
+                    self.queue.push(scrobble);
+                    self.save_queue();
+                    self.flush();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn send_now_playing(&self, scrobble: Scrobble) {
+        let accounts: Vec<_> = scrobble
+            .services
+            .iter()
+            .filter_map(|x| Some((*x, self.config.account(*x)?.key.clone())))
+            .collect();
+        tauri::async_runtime::spawn(async move {
+            for (service, key) in accounts {
+                if let Err(err) = update_now_playing(service, &key, &scrobble).await {
+                    println!("{service:?} 正在播放状态更新失败: {err:?}");
+                }
+            }
+        });
+    }
+
+    /// 在后台提交离线队列中的播放记录，成功提交的记录会从队列中移除
+    pub fn flush(&mut self) {
+        if self.flushing || self.queue.is_empty() {
+            return;
+        }
+        self.flushing = true;
+        let queue = self.queue.clone();
+        let config = self.config.clone();
+        let app = self.app.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut submitted = Vec::new();
+            for scrobble in &queue {
+                for &service in &scrobble.services {
+                    let Some(account) = config.account(service) else {
+                        continue;
+                    };
+                    match submit(service, &account.key, scrobble).await {
+                        Ok(()) => submitted.push((scrobble.id, service)),
+                        Err(err) => println!("{service:?} 播放记录提交失败: {err:?}"),
+                    }
+                }
+            }
+            let scrobbler = app.state::<Mutex<Scrobbler>>();
+            let mut scrobbler = scrobbler.lock().unwrap();
+            for scrobble in &mut scrobbler.queue {
+                scrobble
+                    .services
+                    .retain(|x| !submitted.contains(&(scrobble.id, *x)));
+            }
+            scrobbler.queue.retain(|x| !x.services.is_empty());
+            scrobbler.save_queue();
+            scrobbler.flushing = false;
+        });
+    }
+}
+
+/// 获取各个服务的登录状态和离线队列中的播放记录数量
+#[tauri::command]
+pub fn scrobble_get_status(scrobbler: State<Mutex<Scrobbler>>) -> ScrobbleStatus {
+    scrobbler.lock().unwrap().status()
+}
+
+/// 获取离线队列中等待提交的播放记录
+#[tauri::command]
+pub fn scrobble_get_pending(scrobbler: State<Mutex<Scrobbler>>) -> Vec<Scrobble> {
+    scrobbler.lock().unwrap().pending()
+}
+
+/// 立即尝试提交离线队列中的播放记录
+#[tauri::command]
+pub fn scrobble_flush(scrobbler: State<Mutex<Scrobbler>>) {
+    scrobbler.lock().unwrap().flush();
+}
+
+/// 退出指定服务的登录
+#[tauri::command]
+pub fn scrobble_logout(scrobbler: State<Mutex<Scrobbler>>, service: ScrobbleService) {
+    scrobbler.lock().unwrap().logout(service);
+}
+
+/// 开始登录 Last.fm，返回需要在浏览器中打开的授权页面地址，
+/// 用户授权后调用 [`scrobble_lastfm_complete_login`] 完成登录
+#[tauri::command]
+pub async fn scrobble_lastfm_begin_login(app: AppHandle) -> CommandResult<String> {
+    let result = lastfm_call("auth.getToken", &[], None).await?;
+    let Some(token) = result["token"].as_str() else {
+        return Err(CommandError::new(
+            ErrorCode::NetworkError,
+            "Last.fm 没有返回令牌",
+        ));
+    };
+    app.state::<Mutex<Scrobbler>>().lock().unwrap().lastfm_token = Some(token.to_string());
+    Ok(format!(
+        "{LASTFM_AUTH_URL}?api_key={}&token={token}",
+        LASTFM_API_KEY.unwrap_or_default()
+    ))
+}
+
+/// 完成 Last.fm 的登录并返回用户名
+#[tauri::command]
+pub async fn scrobble_lastfm_complete_login(app: AppHandle) -> CommandResult<String> {
+    let token = app
+        .state::<Mutex<Scrobbler>>()
+        .lock()
+        .unwrap()
+        .lastfm_token
+        .take();
+    let Some(token) = token else {
+        return Err(CommandError::new(
+            ErrorCode::InvalidInput,
+            "没有正在进行的 Last.fm 登录",
+        ));
+    };
+    let result = lastfm_call("auth.getSession", &[("token", token)], None).await?;
+    let session = &result["session"];
+    let (Some(username), Some(key)) = (session["name"].as_str(), session["key"].as_str()) else {
+        return Err(CommandError::new(
+            ErrorCode::NetworkError,
+            "Last.fm 没有返回会话密钥",
+        ));
+    };
+    let account = Account {
+        username: username.to_string(),
+        key: key.to_string(),
+    };
+    app.state::<Mutex<Scrobbler>>()
+        .lock()
+        .unwrap()
+        .set_account(ScrobbleService::LastFm, Some(account));
+    Ok(username.to_string())
+}
+
+/// 使用用户令牌登录 ListenBrainz 并返回用户名
+#[tauri::command]
+pub async fn scrobble_listenbrainz_login(app: AppHandle, token: String) -> CommandResult<String> {
+    let token = token.trim().to_string();
+    let result = http::get_json_with_headers(
+        &format!("{LISTENBRAINZ_API}/validate-token"),
+        &[],
+        &[("Authorization", &format!("Token {token}"))],
+    )
+    .await?;
+    let (true, Some(username)) = (
+        result["valid"].as_bool().unwrap_or_default(),
+        result["user_name"].as_str(),
+    ) else {
+        return Err(CommandError::new(
+            ErrorCode::InvalidInput,
+            "ListenBrainz 令牌无效",
+        ));
+    };
+    let account = Account {
+        username: username.to_string(),
+        key: token,
+    };
+    app.state::<Mutex<Scrobbler>>()
+        .lock()
+        .unwrap()
+        .set_account(ScrobbleService::ListenBrainz, Some(account));
+    Ok(username.to_string())
+}
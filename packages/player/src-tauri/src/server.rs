@@ -1,124 +1,977 @@
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
-use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc};
 
 use async_std::net::{TcpListener, TcpStream};
 use async_std::sync::Mutex;
 use async_std::task::{block_on, JoinHandle};
+use async_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use async_tungstenite::tungstenite::http::StatusCode;
 use async_tungstenite::tungstenite::Message;
-use async_tungstenite::WebSocketStream;
 use futures::prelude::*;
-use futures::stream::SplitSink;
+use futures_rustls::TlsAcceptor;
+use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Manager};
+use ws_protocol::{BodyEncoding, ClientRole};
+
+use crate::cover_chunk::{self, CoverAssembler};
+use crate::error;
+use crate::history::now_millis;
+use crate::power::PowerInhibitor;
+use crate::watchdog::Watchdog;
+use crate::ws_auth::WsAuth;
+use crate::ws_queue::{MessageKind, OutgoingQueue, QueueEvent, QueueLag};
+use crate::ws_stats::{ServerStats, WsStats};
+use crate::ws_tls::TlsInfo;
+
+/// 通过本地套接字连接的客户端没有网络地址，统一使用此地址表示
+pub const LOCAL_ADDR: SocketAddr = SocketAddr::V4(std::net::SocketAddrV4::new(
+    std::net::Ipv4Addr::LOCALHOST,
+    0,
+));
+/// 用于为每个连接分配唯一的 ID
+static CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+/// 监听地址开启失败后第一次重试的间隔，之后每次失败间隔都会翻倍
+const MIN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+/// 监听地址开启失败后重试的最大间隔
+const MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+/// 向客户端发送心跳的间隔
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// 超过此时间没有收到客户端的任何数据（包括心跳的回应）时视为超时并断开连接
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 连接的 ID 和地址，会作为连接和断开事件的内容发送给前端
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionId {
+    pub id: u64,
+    pub addr: SocketAddr,
+}
+
+/// 发送信息的目标客户端，可以是连接的 ID 或者地址
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ConnectionTarget {
+    Id(u64),
+    Addr(SocketAddr),
+}
+
+impl ConnectionTarget {
+    fn matches(self, conn: &ConnectionId) -> bool {
+        match self {
+            Self::Id(id) => conn.id == id,
+            Self::Addr(addr) => conn.addr == addr,
+        }
+    }
+}
+
+/// 客户端连接、断开和超时事件的内容
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionEvent {
+    #[serde(flatten)]
+    pub conn: ConnectionId,
+    /// 客户端在握手时提供的名称
+    pub name: Option<String>,
+}
+
+/// 握手时因为令牌错误而被拒绝的连接，会通过 `on-client-rejected` 事件发送给前端
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectedConnection {
+    pub addr: SocketAddr,
+    pub name: Option<String>,
+    /// 客户端是否提供了令牌
+    pub has_token: bool,
+}
+
+/// 已连接的客户端的信息，名称、版本和功能会在客户端发送 [`ws_protocol::Body::Hello`] 后更新
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionInfo {
+    #[serde(flatten)]
+    pub conn: ConnectionId,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    /// 客户端支持的功能，为 [`ws_protocol::capabilities`] 中各个标志的组合
+    pub capabilities: u32,
+    /// 连接的时间，为 UNIX 时间戳，单位为毫秒
+    pub connected_at: u64,
+    /// 最后一次收到客户端数据的时间，为 UNIX 时间戳，单位为毫秒
+    pub last_seen_at: u64,
+    /// 最近一次心跳的往返延迟，单位为毫秒，客户端还没有回应心跳时为空
+    pub latency: Option<u64>,
+    /// 向客户端发送信息时使用的编码方式
+    pub encoding: BodyEncoding,
+    /// 客户端订阅的信息类别，为 [`ws_protocol::topics`] 中各个标志的组合
+    pub topics: u32,
+    /// 客户端的权限，只读的客户端发送的控制指令会被忽略
+    pub role: ClientRole,
+}
+
+/// 客户端持续落后、发送队列中的信息开始被丢弃时，会通过 `on-client-lagging` 事件发送给前端
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LaggingClient {
+    #[serde(flatten)]
+    pub conn: ConnectionId,
+    /// 发送队列中的信息数量
+    pub queued: usize,
+    /// 发送队列清空前已经丢弃的信息数量
+    pub dropped: usize,
+}
+
+/// 服务端回应客户端的 [`ws_protocol::Body::Hello`] 时发送的功能标志
+const SERVER_CAPABILITIES: u32 = ws_protocol::capabilities::LYRIC
+    | ws_protocol::capabilities::COVER_DATA
+    | ws_protocol::capabilities::AUDIO_DATA
+    | ws_protocol::capabilities::REMOTE_CONTROL
+    | ws_protocol::capabilities::COVER_CHUNK
+    | ws_protocol::capabilities::SPECTRUM;
+
+/// 从客户端接收到的信息，会通过 `on-ws-client-message` 事件转发给前端
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientMessage {
+    #[serde(flatten)]
+    pub from: ConnectionId,
+    pub body: ws_protocol::Body,
+}
+
+/// 客户端的数据流，可能是普通的 TCP 连接或者 TLS 连接
+trait ClientStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClientStream for T {}
+
+type TlsState = Arc<std::sync::RwLock<Option<(TlsAcceptor, TlsInfo)>>>;
+
+struct ClientConnection {
+    id: ConnectionId,
+    queue: OutgoingQueue,
+    encoding: BodyEncoding,
+    topics: u32,
+}
+
+impl ClientConnection {
+    /// 客户端是否订阅了此信息所属的类别
+    fn subscribed(&self, body: &ws_protocol::Body) -> bool {
+        let topic = body.topic();
+        topic == 0 || self.topics & topic != 0
+    }
+}
+
+/// 按照编码方式将信息主体编码为 WebSocket 消息，二进制编码使用二进制帧，JSON 编码使用文本帧
+fn encode_body(body: &ws_protocol::Body, encoding: BodyEncoding) -> anyhow::Result<Message> {
+    Ok(match encoding {
+        BodyEncoding::Binary => Message::Binary(ws_protocol::to_body(body)?),
+        BodyEncoding::Json => Message::Text(ws_protocol::to_body_json(body)?),
+    })
+}
+
+/// 解析客户端发送的消息，二进制帧和文本帧分别按照二进制编码和 JSON 编码解析，
+/// 其它类型的帧返回空值
+fn decode_body(data: Message) -> Option<(anyhow::Result<ws_protocol::Body>, BodyEncoding)> {
+    match data {
+        Message::Binary(data) => Some((ws_protocol::parse_body(&data), BodyEncoding::Binary)),
+        Message::Text(data) => Some((ws_protocol::parse_body_json(&data), BodyEncoding::Json)),
+        _ => None,
+    }
+}
+
+/// 按照编码方式缓存编码后的信息，广播时每种编码方式只需要编码一次
+#[derive(Default)]
+struct EncodedBody {
+    binary: Option<Message>,
+    json: Option<Message>,
+}
+
+impl EncodedBody {
+    fn get(&mut self, body: &ws_protocol::Body, encoding: BodyEncoding) -> anyhow::Result<Message> {
+        let cache = match encoding {
+            BodyEncoding::Binary => &mut self.binary,
+            BodyEncoding::Json => &mut self.json,
+        };
+        if cache.is_none() {
+            *cache = Some(encode_body(body, encoding)?);
+        }
+        Ok(cache.clone().unwrap())
+    }
+}
+
+/// 监听地址开启失败的原因
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindErrorKind {
+    /// 端口已被占用
+    AddrInUse,
+    /// 没有权限监听此端口
+    PermissionDenied,
+    /// 本机没有此地址
+    AddrNotAvailable,
+    /// 地址格式错误或者无法解析
+    InvalidAddr,
+    Other,
+}
+
+impl From<std::io::ErrorKind> for BindErrorKind {
+    fn from(kind: std::io::ErrorKind) -> Self {
+        match kind {
+            std::io::ErrorKind::AddrInUse => Self::AddrInUse,
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied,
+            std::io::ErrorKind::AddrNotAvailable => Self::AddrNotAvailable,
+            std::io::ErrorKind::InvalidInput => Self::InvalidAddr,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// 监听地址开启失败时通过 `ws-server-error` 事件发送给前端的内容
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerError {
+    pub addr: String,
+    pub kind: BindErrorKind,
+    pub message: String,
+    /// 距离下一次重试的时间，单位为毫秒
+    pub retry_in: u64,
+}
+
+/// 监听地址的状态，状态变化时会通过 `on-ws-listener-status` 事件发送给前端
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerStatus {
+    pub addr: String,
+    /// 是否正在监听此地址
+    pub listening: bool,
+    /// 实际监听的地址，端口为 0 或者自动选择了空闲端口时可以从中得知实际的端口
+    pub local_addr: Option<SocketAddr>,
+    /// 最近一次开启失败的原因
+    pub error: Option<String>,
+    pub error_kind: Option<BindErrorKind>,
+}
+
+impl ListenerStatus {
+    fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            listening: false,
+            local_addr: None,
+            error: None,
+            error_kind: None,
+        }
+    }
+}
+
+type ListenerStatuses = Arc<std::sync::Mutex<Vec<ListenerStatus>>>;
+
+/// 更新监听地址的状态，状态发生变化时通知前端
+fn set_listener_status(app: &AppHandle, statuses: &ListenerStatuses, status: ListenerStatus) {
+    let mut statuses = statuses.lock().unwrap();
+    let Some(current) = statuses.iter_mut().find(|x| x.addr == status.addr) else {
+        return;
+    };
+    if *current != status {
+        *current = status.clone();
+        let _ = app.emit_all("on-ws-listener-status", status);
+    }
+}
+
+/// 监听地址，`pick_free_port` 为真且端口被占用时会改为监听同一主机上由系统分配的空闲端口
+async fn bind_listener(addr: &str, pick_free_port: bool) -> std::io::Result<TcpListener> {
+    match TcpListener::bind(addr).await {
+        Err(err) if pick_free_port && err.kind() == std::io::ErrorKind::AddrInUse => {
+            let host = addr.rsplit_once(':').map(|x| x.0).unwrap_or(addr);
+            println!("WebSocket 服务器地址 {addr} 的端口已被占用，将使用空闲端口");
+            TcpListener::bind(format!("{host}:0")).await
+        }
+        result => result,
+    }
+}
+
+type Connections = Arc<Mutex<Vec<ClientConnection>>>;
+type ConnectionInfos = Arc<std::sync::Mutex<Vec<ConnectionInfo>>>;
+
+/// 接受连接时需要的服务器状态
+#[derive(Clone)]
+struct ServerContext {
+    app: AppHandle,
+    connections: Connections,
+    connection_infos: ConnectionInfos,
+    auth: Arc<WsAuth>,
+    tls: TlsState,
+    stats: Arc<WsStats>,
+}
 
-type Connections = Arc<Mutex<Vec<SplitSink<WebSocketStream<TcpStream>, Message>>>>;
-type ConnectionAddrs = Arc<std::sync::Mutex<HashSet<SocketAddr>>>;
 pub struct AMLLWebSocketServer {
     app: AppHandle,
-    server_handle: Option<JoinHandle<()>>,
+    listeners: Vec<JoinHandle<()>>,
+    local_listener: Option<JoinHandle<()>>,
+    listener_statuses: ListenerStatuses,
     connections: Connections,
-    connection_addrs: ConnectionAddrs,
+    connection_infos: ConnectionInfos,
+    auth: Arc<WsAuth>,
+    tls: TlsState,
+    stats: Arc<WsStats>,
 }
 
 impl AMLLWebSocketServer {
-    pub fn new(app: AppHandle) -> Self {
+    pub fn new(app: AppHandle, auth: Arc<WsAuth>) -> Self {
         Self {
             app,
-            server_handle: None,
+            auth,
+            tls: Arc::new(std::sync::RwLock::new(None)),
+            stats: Arc::new(WsStats::default()),
+            listeners: Vec::new(),
+            local_listener: None,
+            listener_statuses: Arc::new(std::sync::Mutex::new(Vec::new())),
             connections: Arc::new(Mutex::new(Vec::with_capacity(8))),
-            connection_addrs: Arc::new(std::sync::Mutex::new(HashSet::with_capacity(8))),
+            connection_infos: Arc::new(std::sync::Mutex::new(Vec::with_capacity(8))),
         }
     }
+
+    fn context(&self) -> ServerContext {
+        ServerContext {
+            app: self.app.clone(),
+            connections: self.connections.clone(),
+            connection_infos: self.connection_infos.clone(),
+            auth: self.auth.clone(),
+            tls: self.tls.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// 只监听一个地址，地址为空时关闭服务器
     pub fn reopen(&mut self, addr: String) {
-        block_on(async move {
-            if let Some(task) = self.server_handle.take() {
+        self.listen(vec![addr], false);
+    }
+
+    /// 同时监听多个地址，之前的监听会被关闭，已连接的客户端不受影响，
+    /// 开启失败时会以指数退避的间隔重试，`pick_free_port` 为真时端口被占用会自动改用空闲端口
+    pub fn listen(&mut self, addrs: Vec<String>, pick_free_port: bool) {
+        block_on(async {
+            for task in self.listeners.drain(..) {
                 task.cancel().await;
             }
+        });
+        let mut unique_addrs: Vec<String> = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let addr = addr.trim().to_string();
+            if !addr.is_empty() && !unique_addrs.contains(&addr) {
+                unique_addrs.push(addr);
+            }
+        }
+        *self.listener_statuses.lock().unwrap() = unique_addrs
+            .iter()
+            .map(|addr| ListenerStatus::new(addr))
+            .collect();
+        for addr in unique_addrs {
+            let ctx = self.context();
             let app = self.app.clone();
-            let connections = self.connections.clone();
-            let conn_addrs = self.connection_addrs.clone();
-            self.server_handle = Some(async_std::task::spawn(async move {
+            let statuses = self.listener_statuses.clone();
+            self.listeners.push(async_std::task::spawn(async move {
+                let mut retry_interval = MIN_RETRY_INTERVAL;
                 loop {
                     println!("正在开启 WebSocket 服务器到 {addr}");
-                    let listener = TcpListener::bind(&addr).await;
-                    match listener {
+                    match bind_listener(&addr, pick_free_port).await {
                         Ok(listener) => {
-                            println!("已开启 WebSocket 服务器到 {addr}");
+                            retry_interval = MIN_RETRY_INTERVAL;
+                            let local_addr = listener.local_addr().ok();
+                            println!("已开启 WebSocket 服务器到 {addr} ({local_addr:?})");
+                            set_listener_status(
+                                &app,
+                                &statuses,
+                                ListenerStatus {
+                                    listening: true,
+                                    local_addr,
+                                    ..ListenerStatus::new(&addr)
+                                },
+                            );
                             while let Ok((stream, _)) = listener.accept().await {
-                                async_std::task::spawn(Self::accept_conn(
-                                    stream,
-                                    app.clone(),
-                                    connections.clone(),
-                                    conn_addrs.clone(),
-                                ));
+                                async_std::task::spawn(Self::accept_tcp(stream, ctx.clone()));
                             }
+                            set_listener_status(&app, &statuses, ListenerStatus::new(&addr));
                             break;
                         }
                         Err(err) => {
-                            println!("WebSocket 服务器 {addr} 开启失败: {err:?}");
+                            println!(
+                                "WebSocket 服务器 {addr} 开启失败，将在 {retry_interval:?} 后重试: {err:?}"
+                            );
+                            let kind = BindErrorKind::from(err.kind());
+                            let _ = app.emit_all(
+                                "ws-server-error",
+                                ServerError {
+                                    addr: addr.clone(),
+                                    kind,
+                                    message: err.to_string(),
+                                    retry_in: retry_interval.as_millis() as u64,
+                                },
+                            );
+                            set_listener_status(
+                                &app,
+                                &statuses,
+                                ListenerStatus {
+                                    error: Some(err.to_string()),
+                                    error_kind: Some(kind),
+                                    ..ListenerStatus::new(&addr)
+                                },
+                            );
                         }
                     }
-                    async_std::task::sleep(Duration::from_secs(1)).await;
+                    async_std::task::sleep(retry_interval).await;
+                    retry_interval = (retry_interval * 2).min(MAX_RETRY_INTERVAL);
                 }
             }));
-        });
+        }
+    }
+
+    /// 在本地套接字上提供相同的 WebSocket 服务，供不希望开放网络端口的本地工具使用，
+    /// 传入空值时关闭。Unix 上使用 Unix 域套接字，套接字文件只有当前用户可以访问，
+    /// 连接的地址统一为 [`LOCAL_ADDR`]
+    #[cfg(unix)]
+    pub fn listen_local(&mut self, path: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+        if let Some(task) = self.local_listener.take() {
+            block_on(task.cancel());
+        }
+        let Some(path) = path else {
+            return Ok(());
+        };
+        let listener =
+            async_std::os::unix::net::UnixListener::from(crate::local_socket::bind(&path)?);
+        println!("已开启本地 WebSocket 服务器到 {}", path.display());
+        let ctx = self.context();
+        self.local_listener = Some(async_std::task::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                async_std::task::spawn(Self::accept_conn(
+                    Box::new(stream),
+                    LOCAL_ADDR,
+                    ctx.clone(),
+                ));
+            }
+        }));
+        Ok(())
     }
 
-    pub fn get_connections(&self) -> Vec<SocketAddr> {
-        let conns = self
-            .connection_addrs
+    /// Windows 上应当使用命名管道提供本地 WebSocket 服务，但目前还没有实现，开启时总是返回错误
+    #[cfg(not(unix))]
+    pub fn listen_local(&mut self, path: Option<std::path::PathBuf>) -> anyhow::Result<()> {
+        match path {
+            Some(_) => anyhow::bail!("当前平台暂不支持本地套接字，Windows 的命名管道尚未实现"),
+            None => Ok(()),
+        }
+    }
+
+    /// 获取各个监听地址的状态
+    pub fn listener_statuses(&self) -> Vec<ListenerStatus> {
+        self.listener_statuses.lock().unwrap().clone()
+    }
+
+    pub fn auth(&self) -> &WsAuth {
+        &self.auth
+    }
+
+    /// 设置 TLS 接受器，之后的新连接都需要使用 `wss://` 连接，传入空值时关闭 TLS
+    pub fn set_tls(&self, tls: Option<(TlsAcceptor, TlsInfo)>) {
+        *self.tls.write().unwrap() = tls;
+    }
+
+    pub fn tls_info(&self) -> Option<TlsInfo> {
+        self.tls.read().unwrap().as_ref().map(|x| x.1.clone())
+    }
+
+    /// 获取服务器的统计信息
+    pub async fn stats(&self) -> ServerStats {
+        let clients = self
+            .connections
             .lock()
-            .unwrap()
+            .await
             .iter()
-            .copied()
+            .map(|x| (x.id, x.queue.queued(), x.queue.total_dropped()))
             .collect();
-        conns
+        self.stats.snapshot(clients)
     }
 
+    pub fn get_connections(&self) -> Vec<ConnectionInfo> {
+        self.connection_infos.lock().unwrap().clone()
+    }
+
+    /// 修改已连接的客户端的权限，客户端不存在时返回错误
+    pub fn set_role(&self, target: ConnectionTarget, role: ClientRole) -> anyhow::Result<()> {
+        let mut conn_infos = self.connection_infos.lock().unwrap();
+        let Some(info) = conn_infos.iter_mut().find(|x| target.matches(&x.conn)) else {
+            anyhow::bail!("WebSocket 客户端 {target:?} 不存在");
+        };
+        info.role = role;
+        println!(
+            "WebSocket 客户端 {} 的权限已修改为 {role:?}",
+            info.conn.addr
+        );
+        Ok(())
+    }
+
+    /// 获取声明支持指定功能的客户端
+    pub fn connections_with(&self, capability: u32) -> Vec<ConnectionId> {
+        self.connection_infos
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.capabilities & capability != 0)
+            .map(|x| x.conn)
+            .collect()
+    }
+
+    /// 向所有客户端广播信息，较大的封面图片会以分块传输的方式发送给支持的客户端，
+    /// 频谱数据只会发送给支持的客户端
     pub async fn boardcast_message(&mut self, data: ws_protocol::Body) {
-        let mut conns = self.connections.lock().await;
-        let mut i = 0;
-        while i < conns.len() {
-            if let Err(err) = conns[i]
-                .send(Message::Binary(ws_protocol::to_body(&data).unwrap()))
-                .await
-            {
-                println!("WebSocket 客户端 {:?} 发送失败: {err:?}", conns[i]);
-                conns.remove(i);
-            } else {
-                i += 1;
+        if let ws_protocol::Body::SetMusicAlbumCoverImageData { data: cover } = &data {
+            if cover.len() > cover_chunk::CHUNK_SIZE {
+                let chunked = self.connections_with(ws_protocol::capabilities::COVER_CHUNK);
+                if !chunked.is_empty() {
+                    self.boardcast_to(&data, |id| !chunked.contains(id)).await;
+                    // 每个分块发送后都会释放连接列表的锁，使其它信息可以在分块之间发送
+                    for chunk in cover_chunk::split_cover(cover) {
+                        self.boardcast_to(&chunk, |id| chunked.contains(id)).await;
+                    }
+                    return;
+                }
             }
         }
+        if let ws_protocol::Body::OnFFTData { .. } = &data {
+            let supported = self.connections_with(ws_protocol::capabilities::SPECTRUM);
+            self.boardcast_to(&data, |id| supported.contains(id)).await;
+            return;
+        }
+        self.boardcast_to(&data, |_| true).await;
     }
 
-    async fn accept_conn(
-        stream: TcpStream,
-        app: AppHandle,
-        conns: Connections,
-        conn_addrs: ConnectionAddrs,
+    /// 向满足条件且订阅了此信息类别的客户端广播信息
+    async fn boardcast_to(&self, data: &ws_protocol::Body, filter: impl Fn(&ConnectionId) -> bool) {
+        let mut encoded = EncodedBody::default();
+        let kind = MessageKind::of(data);
+        let conns = self.connections.lock().await;
+        for conn in conns.iter() {
+            if !filter(&conn.id) || !conn.subscribed(data) {
+                continue;
+            }
+            let msg = match encoded.get(data, conn.encoding) {
+                Ok(msg) => msg,
+                Err(err) => {
+                    println!("WebSocket 信息序列化失败: {err:?}");
+                    return;
+                }
+            };
+            self.stats.on_sent(conn.id, data, msg.len());
+            if let Some(event) = conn.queue.push(msg, kind) {
+                self.on_queue_event(conn.id, event);
+            }
+        }
+    }
+
+    fn on_queue_event(&self, id: ConnectionId, event: QueueEvent) {
+        match event {
+            QueueEvent::Lagging(lag) => self.on_lagging(id, lag),
+            QueueEvent::Overflowed => {
+                println!("WebSocket 客户端 {} 的发送队列已满，正在断开连接", id.addr);
+            }
+        }
+    }
+
+    fn on_lagging(&self, id: ConnectionId, lag: QueueLag) {
+        println!(
+            "WebSocket 客户端 {} 持续落后，队列中有 {} 条信息，已丢弃 {} 条信息",
+            id.addr, lag.queued, lag.dropped
+        );
+        let _ = self.app.emit_all(
+            "on-client-lagging",
+            LaggingClient {
+                conn: id,
+                queued: lag.queued,
+                dropped: lag.dropped,
+            },
+        );
+    }
+
+    /// 向指定的客户端发送信息，不受客户端订阅的信息类别限制，客户端不存在时返回错误
+    pub async fn send_to(
+        &mut self,
+        target: ConnectionTarget,
+        data: ws_protocol::Body,
     ) -> anyhow::Result<()> {
+        let conns = self.connections.lock().await;
+        let Some(conn) = conns.iter().find(|x| target.matches(&x.id)) else {
+            anyhow::bail!("WebSocket 客户端 {target:?} 不存在");
+        };
+        let msg = encode_body(&data, conn.encoding)?;
+        self.stats.on_sent(conn.id, &data, msg.len());
+        if let Some(event) = conn.queue.push(msg, MessageKind::of(&data)) {
+            self.on_queue_event(conn.id, event);
+        }
+        Ok(())
+    }
+
+    /// 定时向客户端发送心跳，客户端会自动回应，连接被移除后结束
+    async fn heartbeat(id: ConnectionId, conns: Connections) {
+        loop {
+            async_std::task::sleep(HEARTBEAT_INTERVAL).await;
+            let conns = conns.lock().await;
+            let Some(conn) = conns.iter().find(|x| x.id == id) else {
+                break;
+            };
+            // 心跳中包含发送的时间，用于在收到回应时计算往返延迟
+            let payload = now_millis().to_le_bytes().to_vec();
+            conn.queue
+                .push(Message::Ping(payload), MessageKind::Reliable);
+        }
+    }
+
+    /// 记录收到客户端数据的时间，收到心跳的回应时同时更新往返延迟
+    fn on_activity(conn_infos: &ConnectionInfos, id: ConnectionId, data: &Message) {
+        let now = now_millis();
+        let mut conn_infos = conn_infos.lock().unwrap();
+        let Some(info) = conn_infos.iter_mut().find(|x| x.conn == id) else {
+            return;
+        };
+        info.last_seen_at = now;
+        if let Message::Pong(payload) = data {
+            if let Ok(sent_at) = <[u8; 8]>::try_from(payload.as_slice()) {
+                info.latency = Some(now.saturating_sub(u64::from_le_bytes(sent_at)));
+            }
+        }
+    }
+
+    /// 记录客户端在 [`ws_protocol::Body::Hello`] 中提供的信息，返回更新后的连接信息
+    fn on_hello(
+        conn_infos: &ConnectionInfos,
+        id: ConnectionId,
+        name: String,
+        version: String,
+        capabilities: u32,
+    ) -> Option<ConnectionInfo> {
+        let mut conn_infos = conn_infos.lock().unwrap();
+        let info = conn_infos.iter_mut().find(|x| x.conn == id)?;
+        // 名称为空时保留握手请求中提供的名称
+        if !name.trim().is_empty() {
+            info.name = Some(name.trim().to_string());
+        }
+        info.version = Some(version).filter(|x| !x.is_empty());
+        info.capabilities = capabilities;
+        println!("WebSocket 客户端 {} 的信息: {info:?}", id.addr);
+        Some(info.clone())
+    }
+
+    /// 修改向客户端发送信息时使用的编码方式
+    async fn set_encoding(
+        conns: &Connections,
+        conn_infos: &ConnectionInfos,
+        id: ConnectionId,
+        encoding: BodyEncoding,
+    ) {
+        if let Some(conn) = conns.lock().await.iter_mut().find(|x| x.id == id) {
+            conn.encoding = encoding;
+        }
+        if let Some(info) = conn_infos.lock().unwrap().iter_mut().find(|x| x.conn == id) {
+            info.encoding = encoding;
+        }
+    }
+
+    /// 修改客户端订阅的信息类别
+    async fn set_topics(
+        conns: &Connections,
+        conn_infos: &ConnectionInfos,
+        id: ConnectionId,
+        topics: u32,
+    ) {
+        if let Some(conn) = conns.lock().await.iter_mut().find(|x| x.id == id) {
+            conn.topics = topics;
+        }
+        if let Some(info) = conn_infos.lock().unwrap().iter_mut().find(|x| x.conn == id) {
+            info.topics = topics;
+        }
+        println!("WebSocket 客户端 {} 订阅的信息类别: {topics:#b}", id.addr);
+    }
+
+    /// 客户端当前的权限，客户端不存在时视为只读
+    fn role(conn_infos: &ConnectionInfos, id: ConnectionId) -> ClientRole {
+        conn_infos
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|x| x.conn == id)
+            .map(|x| x.role)
+            .unwrap_or(ClientRole::ReadOnly)
+    }
+
+    /// 向客户端回应服务端自身的信息
+    async fn send_hello(conns: &Connections, stats: &WsStats, id: ConnectionId) {
+        let hello = ws_protocol::Body::Hello {
+            client_name: "AMLL Player".into(),
+            version: env!("CARGO_PKG_VERSION").into(),
+            capabilities: SERVER_CAPABILITIES,
+        };
+        let conns = conns.lock().await;
+        if let Some(conn) = conns.iter().find(|x| x.id == id) {
+            match encode_body(&hello, conn.encoding) {
+                Ok(msg) => {
+                    stats.on_sent(id, &hello, msg.len());
+                    conn.queue.push(msg, MessageKind::Reliable);
+                }
+                Err(err) => {
+                    println!("WebSocket 服务端信息序列化失败: {err:?}");
+                }
+            }
+        }
+    }
+
+    /// 接受 TCP 连接，开启了 TLS 时先进行 TLS 握手
+    async fn accept_tcp(stream: TcpStream, ctx: ServerContext) -> anyhow::Result<()> {
         let addr = stream.peer_addr()?;
         println!("已接受套接字连接: {addr}");
+        let acceptor = ctx.tls.read().unwrap().as_ref().map(|x| x.0.clone());
+        let stream: Box<dyn ClientStream> = match acceptor {
+            Some(acceptor) => Box::new(acceptor.accept(stream).await?),
+            None => Box::new(stream),
+        };
+        Self::accept_conn(stream, addr, ctx).await
+    }
 
-        let wss = async_tungstenite::accept_async(stream).await?;
-        println!("已连接 WebSocket 客户端: {addr}");
-        app.emit_all("on-client-connected", addr)?;
-        conn_addrs.lock().unwrap().insert(addr.to_owned());
+    async fn accept_conn(
+        stream: Box<dyn ClientStream>,
+        addr: SocketAddr,
+        ctx: ServerContext,
+    ) -> anyhow::Result<()> {
+        let ServerContext {
+            app,
+            connections: conns,
+            connection_infos: conn_infos,
+            auth,
+            stats,
+            ..
+        } = ctx;
 
-        let (write, read) = wss.split();
+        let mut name = None;
+        let mut encoding = None;
+        let mut topics = None;
+        let mut role = ClientRole::ReadOnly;
+        let mut rejected = None;
+        let mut locked_out = None;
+        let wss = async_tungstenite::accept_hdr_async(
+            stream,
+            |req: &Request, res: Response| -> Result<Response, ErrorResponse> {
+                name = client_name(req);
+                encoding = client_encoding(req);
+                topics = client_topics(req);
+                if let Some(remaining) = auth.locked_out(addr.ip()) {
+                    locked_out = Some(remaining);
+                    let mut res = ErrorResponse::new(Some("错误次数过多，请稍后再试".to_string()));
+                    *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    res.headers_mut()
+                        .insert("Retry-After", remaining.as_secs().max(1).into());
+                    return Err(res);
+                }
+                let token = client_token(req);
+                if let Some(granted) = auth.verify(addr.ip(), token.as_deref()) {
+                    // 客户端可以主动要求更低的权限，但不能超过令牌授予的权限
+                    role = client_role(req).map_or(granted, |x| x.min(granted));
+                    return Ok(res);
+                }
+                rejected = Some(RejectedConnection {
+                    addr,
+                    name: name.clone(),
+                    has_token: token.is_some(),
+                });
+                let mut res = ErrorResponse::new(Some("令牌错误".to_string()));
+                *res.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(res)
+            },
+        )
+        .await;
+        if let Some(remaining) = locked_out {
+            println!("已拒绝被锁定的 WebSocket 客户端: {addr} (剩余 {remaining:?})");
+        }
+        if let Some(rejected) = rejected {
+            println!("已拒绝令牌错误的 WebSocket 客户端: {addr}");
+            app.emit_all("on-client-rejected", rejected)?;
+        }
+        let wss = wss?;
+        let id = ConnectionId {
+            id: CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            addr,
+        };
+        let event = ConnectionEvent { conn: id, name };
+        println!(
+            "已连接 WebSocket 客户端: {addr} (ID {}, 名称 {:?}, 权限 {role:?})",
+            id.id, event.name
+        );
+        app.emit_all("on-client-connected", event.clone())?;
+        let connected_at = now_millis();
+        conn_infos.lock().unwrap().push(ConnectionInfo {
+            conn: id,
+            name: event.name.clone(),
+            version: None,
+            capabilities: 0,
+            connected_at,
+            last_seen_at: connected_at,
+            latency: None,
+            encoding: encoding.unwrap_or_default(),
+            topics: topics.unwrap_or(ws_protocol::topics::ALL),
+            role,
+        });
 
-        conns.lock().await.push(write);
+        let (write, mut read) = wss.split();
 
-        let mut read = read.try_filter(|x| future::ready(x.is_binary()));
+        conns.lock().await.push(ClientConnection {
+            id,
+            queue: OutgoingQueue::new(write, move |err| {
+                println!("WebSocket 客户端 {id:?} 发送失败: {err:?}");
+            }),
+            encoding: encoding.unwrap_or_default(),
+            topics: topics.unwrap_or(ws_protocol::topics::ALL),
+        });
+        async_std::task::spawn(Self::heartbeat(id, conns.clone()));
 
-        while let Some(Ok(data)) = read.next().await {
-            if let Ok(body) = ws_protocol::parse_body(&data.into_data()) {
-                app.emit_all("on-client-body", body)?;
+        let mut covers = CoverAssembler::default();
+        let mut timed_out = false;
+        loop {
+            let data = match async_std::future::timeout(CLIENT_TIMEOUT, read.next()).await {
+                Ok(Some(Ok(data))) => data,
+                Ok(_) => break,
+                Err(_) => {
+                    timed_out = true;
+                    break;
+                }
+            };
+            Self::on_activity(&conn_infos, id, &data);
+            let bytes = data.len();
+            let Some((body, frame_encoding)) = decode_body(data) else {
+                continue;
+            };
+            if let Ok(body) = &body {
+                stats.on_received(id, body, bytes);
+            }
+            // 没有在握手时指定编码方式的客户端，以其发送信息的编码方式作为回应的编码方式
+            if encoding.is_none() {
+                encoding = Some(frame_encoding);
+                Self::set_encoding(&conns, &conn_infos, id, frame_encoding).await;
+            }
+            match body.and_then(|body| covers.on_body(body)) {
+                Ok(None) => {}
+                Ok(Some(body)) => {
+                    if body.is_control() && !Self::role(&conn_infos, id).can_control() {
+                        println!(
+                            "已忽略只读 WebSocket 客户端 {addr} 发送的控制指令: {}",
+                            body.type_name()
+                        );
+                        if let Err(err) =
+                            app.emit_all("on-client-forbidden", ClientMessage { from: id, body })
+                        {
+                            println!("WebSocket 客户端事件发送失败: {err:?}");
+                        }
+                        continue;
+                    }
+                    if let ws_protocol::Body::Hello {
+                        client_name,
+                        version,
+                        capabilities,
+                    } = &body
+                    {
+                        let info = Self::on_hello(
+                            &conn_infos,
+                            id,
+                            client_name.to_string(),
+                            version.to_string(),
+                            *capabilities,
+                        );
+                        if let Some(info) = info {
+                            if let Err(err) = app.emit_all("on-client-hello", info) {
+                                println!("WebSocket 客户端事件发送失败: {err:?}");
+                            }
+                        }
+                        Self::send_hello(&conns, &stats, id).await;
+                    }
+                    if let ws_protocol::Body::Subscribe { topics } = &body {
+                        Self::set_topics(&conns, &conn_infos, id, *topics).await;
+                    }
+                    crate::on_client_body(&app, &body);
+                    // 前端改用二进制通道后不再通过事件发送高频信息，
+                    // 事件发送失败时只记录错误，不能因此断开播放源的连接
+                    if crate::stream::should_emit(&app, &body) {
+                        let result = app.emit_all("on-client-body", body.clone()).and_then(|_| {
+                            app.emit_all("on-ws-client-message", ClientMessage { from: id, body })
+                        });
+                        if let Err(err) = result {
+                            println!("WebSocket 客户端信息事件发送失败: {err:?}");
+                        }
+                    }
+                }
+                Err(err) => {
+                    println!("WebSocket 客户端 {addr} 发送的信息处理失败: {err:?}");
+                }
             }
         }
 
+        // 移除连接时会丢弃发送队列并关闭写入端，超时的客户端也会因此被断开
+        let mut conns = conns.lock().await;
+        conns.retain(|x| x.id != id);
+        // 播放源全部断开后不会再收到暂停的信息，需要主动释放电源锁并停止卡住检测
+        if conns.is_empty() {
+            error::lock(&app.state::<std::sync::Mutex<PowerInhibitor>>()).release();
+            error::lock(&app.state::<std::sync::Mutex<Watchdog>>()).reset();
+        }
+        drop(conns);
+        conn_infos.lock().unwrap().retain(|x| x.conn != id);
+        stats.remove_client(id);
+        if timed_out {
+            println!("WebSocket 客户端 {addr} 超时");
+            if let Err(err) = app.emit_all("on-client-timeout", event.clone()) {
+                println!("WebSocket 客户端事件发送失败: {err:?}");
+            }
+        }
         println!("已断开 WebSocket 客户端: {addr}");
-        app.emit_all("on-client-disconnected", addr)?;
-        conn_addrs.lock().unwrap().remove(&addr);
+        app.emit_all("on-client-disconnected", event)?;
         Ok(())
     }
 }
+
+/// 从握手请求的 URL 参数或者请求头中获取值，URL 参数优先
+fn request_param(req: &Request, key: &str, header: &str) -> Option<String> {
+    let from_query = req.uri().query().and_then(|query| {
+        url::form_urlencoded::parse(query.as_bytes())
+            .find(|(x, _)| x == key)
+            .map(|(_, value)| value.into_owned())
+    });
+    from_query
+        .or_else(|| {
+            req.headers()
+                .get(header)
+                .and_then(|x| x.to_str().ok())
+                .map(str::to_string)
+        })
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+}
+
+/// 从握手请求中获取客户端的名称，可以通过 URL 中的 `name` 参数或者 `X-AMLL-Client-Name` 请求头提供
+fn client_name(req: &Request) -> Option<String> {
+    request_param(req, "name", "X-AMLL-Client-Name")
+}
+
+/// 从握手请求中获取客户端希望使用的编码方式，可以通过 URL 中的 `encoding` 参数或者 `X-AMLL-Encoding` 请求头提供，
+/// 值为 `binary` 或者 `json`
+fn client_encoding(req: &Request) -> Option<BodyEncoding> {
+    request_param(req, "encoding", "X-AMLL-Encoding").and_then(|x| x.parse().ok())
+}
+
+/// 从握手请求中获取客户端订阅的信息类别，可以通过 URL 中的 `topics` 参数或者 `X-AMLL-Topics` 请求头
+/// 以逗号分隔的类别名称提供，例如 `lyric,progress`
+fn client_topics(req: &Request) -> Option<u32> {
+    request_param(req, "topics", "X-AMLL-Topics").map(|x| ws_protocol::topics::parse(&x))
+}
+
+/// 从握手请求中获取客户端要求的权限，可以通过 URL 中的 `role` 参数或者 `X-AMLL-Role` 请求头提供，
+/// 值为 `readonly` 或者 `control`，无法识别时忽略
+fn client_role(req: &Request) -> Option<ClientRole> {
+    request_param(req, "role", "X-AMLL-Role").and_then(|x| x.parse().ok())
+}
+
+/// 从握手请求中获取客户端的令牌，可以通过 URL 中的 `token` 参数或者 `X-AMLL-Token` 请求头提供
+fn client_token(req: &Request) -> Option<String> {
+    request_param(req, "token", "X-AMLL-Token")
+}
@@ -0,0 +1,180 @@
+//! Windows 系统媒体传输控件（SMTC）集成
+//!
+//! 将 WebSocket 客户端发送过来的歌曲信息、专辑封面和播放进度同步到 SMTC，
+//! 使音量浮窗和锁屏界面可以显示当前播放的歌曲。
+//! 媒体键和 SMTC 上的播放、暂停、切歌和调整进度操作会被转换成对应的控制指令广播给播放源。
+//!
+//! 桌面应用无法直接获取 SMTC，这里借用一个不播放任何内容的 [`MediaPlayer`] 的 SMTC，
+//! 并关闭其自带的命令处理。
+use std::time::{Duration, Instant};
+
+use tauri::AppHandle;
+use windows::{
+    core::HSTRING,
+    Foundation::{TimeSpan, TypedEventHandler, Uri},
+    Media::{
+        MediaPlaybackStatus, MediaPlaybackType, Playback::MediaPlayer,
+        PlaybackPositionChangeRequestedEventArgs, SystemMediaTransportControls,
+        SystemMediaTransportControlsButton, SystemMediaTransportControlsButtonPressedEventArgs,
+        SystemMediaTransportControlsTimelineProperties,
+    },
+    Storage::Streams::{DataWriter, InMemoryRandomAccessStream, RandomAccessStreamReference},
+};
+use ws_protocol::Body;
+
+/// 更新 SMTC 播放进度的最小间隔，播放进度信息的频率很高，没有必要每次都更新
+const POSITION_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 将毫秒转换为以 100 纳秒为单位的 [`TimeSpan`]
+fn time_span(millis: f64) -> TimeSpan {
+    TimeSpan {
+        Duration: (millis * 10_000.0) as i64,
+    }
+}
+
+pub struct Smtc {
+    /// 必须保持 [`MediaPlayer`] 存活，否则 SMTC 会被系统移除
+    _player: MediaPlayer,
+    controls: SystemMediaTransportControls,
+    /// 歌曲的总时长，单位为毫秒
+    duration: u64,
+    position_updated_at: Option<Instant>,
+}
+
+impl Smtc {
+    pub fn new(app: AppHandle) -> windows::core::Result<Self> {
+        let player = MediaPlayer::new()?;
+        player.CommandManager()?.SetIsEnabled(false)?;
+        let controls = player.SystemMediaTransportControls()?;
+        controls.SetIsEnabled(true)?;
+        controls.SetIsPlayEnabled(true)?;
+        controls.SetIsPauseEnabled(true)?;
+        controls.SetIsNextEnabled(true)?;
+        controls.SetIsPreviousEnabled(true)?;
+        controls.SetPlaybackStatus(MediaPlaybackStatus::Closed)?;
+        controls
+            .DisplayUpdater()?
+            .SetType(MediaPlaybackType::Music)?;
+
+        let button_app = app.clone();
+        controls.ButtonPressed(&TypedEventHandler::<
+            SystemMediaTransportControls,
+            SystemMediaTransportControlsButtonPressedEventArgs,
+        >::new(move |_, args| {
+            let Some(args) = args else {
+                return Ok(());
+            };
+            let body = match args.Button()? {
+                SystemMediaTransportControlsButton::Play => Body::Resume,
+                SystemMediaTransportControlsButton::Pause => Body::Pause,
+                SystemMediaTransportControlsButton::Next => Body::ForwardSong,
+                SystemMediaTransportControlsButton::Previous => Body::BackwardSong,
+                _ => return Ok(()),
+            };
+            crate::send_control(&button_app, body);
+            Ok(())
+        }))?;
+        controls.PlaybackPositionChangeRequested(&TypedEventHandler::<
+            SystemMediaTransportControls,
+            PlaybackPositionChangeRequestedEventArgs,
+        >::new(move |_, args| {
+            if let Some(args) = args {
+                let position = args.RequestedPlaybackPosition()?;
+                let progress = position.Duration as f64 / 10_000.0;
+                crate::send_control(&app, Body::SetPlayProgress { progress });
+            }
+            Ok(())
+        }))?;
+
+        Ok(Self {
+            _player: player,
+            controls,
+            duration: 0,
+            position_updated_at: None,
+        })
+    }
+
+    pub fn on_body(&mut self, body: &Body) {
+        if let Err(err) = self.update(body) {
+            println!("SMTC 更新失败: {err:?}");
+        }
+    }
+
+    fn update(&mut self, body: &Body) -> windows::core::Result<()> {
+        let updater = self.controls.DisplayUpdater()?;
+        match body {
+            Body::SetMusicId { name, duration, .. } => {
+                self.duration = *duration;
+                self.position_updated_at = None;
+                updater
+                    .MusicProperties()?
+                    .SetTitle(&HSTRING::from(name.to_string()))?;
+                updater.Update()?;
+                self.update_position(0.0)?;
+            }
+            Body::SetMusicAlbum { name, .. } => {
+                updater
+                    .MusicProperties()?
+                    .SetAlbumTitle(&HSTRING::from(name.to_string()))?;
+                updater.Update()?;
+            }
+            Body::SetMusicArtists { artists } => {
+                let artists: Vec<String> = artists.iter().map(|x| x.name.to_string()).collect();
+                updater
+                    .MusicProperties()?
+                    .SetArtist(&HSTRING::from(artists.join(" / ")))?;
+                updater.Update()?;
+            }
+            Body::SetMusicAlbumCoverImageURL { img_url } => {
+                let img_url = img_url.to_string();
+                // 自定义协议和 data URL 无法被系统读取，此时等待客户端发送图片数据
+                if img_url.starts_with("http://") || img_url.starts_with("https://") {
+                    let uri = Uri::CreateUri(&HSTRING::from(img_url))?;
+                    updater.SetThumbnail(&RandomAccessStreamReference::CreateFromUri(&uri)?)?;
+                    updater.Update()?;
+                }
+            }
+            Body::SetMusicAlbumCoverImageData { data } if !data.is_empty() => {
+                let stream = InMemoryRandomAccessStream::new()?;
+                let writer = DataWriter::CreateDataWriter(&stream)?;
+                writer.WriteBytes(data)?;
+                writer.StoreAsync()?.get()?;
+                writer.DetachStream()?;
+                stream.Seek(0)?;
+                updater.SetThumbnail(&RandomAccessStreamReference::CreateFromStream(&stream)?)?;
+                updater.Update()?;
+            }
+            Body::OnPlayProgress { progress } => {
+                let outdated = self
+                    .position_updated_at
+                    .map(|x| x.elapsed() >= POSITION_UPDATE_INTERVAL)
+                    .unwrap_or(true);
+                if outdated {
+                    self.update_position(*progress)?;
+                }
+            }
+            Body::OnPaused => {
+                self.controls
+                    .SetPlaybackStatus(MediaPlaybackStatus::Paused)?;
+            }
+            Body::OnResumed => {
+                self.controls
+                    .SetPlaybackStatus(MediaPlaybackStatus::Playing)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn update_position(&mut self, position: f64) -> windows::core::Result<()> {
+        let timeline = SystemMediaTransportControlsTimelineProperties::new()?;
+        timeline.SetStartTime(time_span(0.0))?;
+        timeline.SetEndTime(time_span(self.duration as f64))?;
+        timeline.SetMinSeekTime(time_span(0.0))?;
+        timeline.SetMaxSeekTime(time_span(self.duration as f64))?;
+        timeline.SetPosition(time_span(position))?;
+        self.controls.UpdateTimelineProperties(&timeline)?;
+        self.position_updated_at = Some(Instant::now());
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+//! 高频数据的二进制通道
+//!
+//! 播放进度和音频数据每秒会收到数十次，每次都以 JSON 事件发送给前端会带来大量的序列化开销，
+//! 音频数据尤其如此（每个字节都会被序列化成一个数字）。
+//! 因此这些数据也会被缓存起来，前端可以在需要绘制时通过自定义协议 `amll-stream` 以二进制的形式读取，
+//! 窗口隐藏时前端不会发起请求，也就没有任何开销。
+//!
+//! `amll-stream://localhost/frame` 返回的数据均为小端序：
+//!
+//! - `u64`：已接收的音频数据的总字节数，可以用来检测丢失的数据
+//! - `f64`：当前的播放进度，单位为毫秒
+//! - `u8`：是否暂停
+//! - 剩余部分：自上次读取以来收到的音频数据
+//!
+//! 原有的 `on-client-body` 等事件默认仍然会发送高频信息以保持兼容，
+//! 前端改用二进制通道后可以通过 [`stream_set_high_rate_events`] 关闭。
+//!
+//! 播放进度事件还可以通过 [`stream_set_progress_interval`] 限制发送频率，
+//! 间隔内收到的播放进度只保留最新的一条，在暂停、切歌等状态变化前补发，
+//! 所有窗口都隐藏时也不会发送播放进度事件。
+//! 加载进度不在二进制通道中，关闭高频事件后仍然会每 500 毫秒发送一次。
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tauri::{
+    http::{Request, Response, ResponseBuilder},
+    AppHandle, Manager,
+};
+use ws_protocol::Body;
+
+use crate::now_playing::NowPlaying;
+
+/// 自定义协议的名称
+pub const STREAM_PROTOCOL: &str = "amll-stream";
+/// 缓存的音频数据上限，前端长时间没有读取时只保留最新的数据
+const MAX_BUFFERED_AUDIO: usize = 1024 * 1024;
+
+/// 是否仍然通过事件发送高频信息
+static HIGH_RATE_EVENTS: AtomicBool = AtomicBool::new(true);
+/// 两次播放进度事件之间的最小间隔，单位为毫秒，为 0 时不限制
+static PROGRESS_INTERVAL_MS: AtomicU64 = AtomicU64::new(0);
+/// 关闭高频事件后加载进度事件的最小间隔，加载进度只用于绘制进度条上的缓冲区域，不需要很高的频率
+const LOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+static LAST_LOAD_PROGRESS: Mutex<Option<Instant>> = Mutex::new(None);
+static PROGRESS_THROTTLE: Mutex<ProgressThrottle> = Mutex::new(ProgressThrottle {
+    last_emit: None,
+    pending: None,
+});
+
+struct ProgressThrottle {
+    last_emit: Option<Instant>,
+    /// 因为发送频率限制而没有发送的最新一条播放进度
+    pending: Option<Body>,
+}
+
+fn any_window_visible(app: &AppHandle) -> bool {
+    app.windows()
+        .values()
+        .any(|x| x.is_visible().unwrap_or(false))
+}
+
+/// 信息是否需要通过事件发送给前端
+pub fn should_emit(app: &AppHandle, body: &Body) -> bool {
+    let high_rate_events = HIGH_RATE_EVENTS.load(Ordering::Relaxed);
+    match body {
+        Body::OnPlayProgress { .. } => {
+            if !high_rate_events {
+                return false;
+            }
+            let mut throttle = PROGRESS_THROTTLE.lock().unwrap();
+            let interval = Duration::from_millis(PROGRESS_INTERVAL_MS.load(Ordering::Relaxed));
+            let due = !matches!(throttle.last_emit, Some(x) if x.elapsed() < interval);
+            if due && any_window_visible(app) {
+                throttle.last_emit = Some(Instant::now());
+                throttle.pending = None;
+                true
+            } else {
+                throttle.pending = Some(body.clone());
+                false
+            }
+        }
+        // 状态变化总是会发送，并且会先补发被合并掉的最新播放进度
+        Body::SetMusicId { .. }
+        | Body::OnPaused
+        | Body::OnResumed
+        | Body::SetPlayProgress { .. } => {
+            let pending = {
+                let mut throttle = PROGRESS_THROTTLE.lock().unwrap();
+                throttle.last_emit = None;
+                throttle.pending.take()
+            };
+            if let Some(pending) = pending.filter(|_| !matches!(body, Body::SetMusicId { .. })) {
+                if let Err(err) = app.emit_all("on-client-body", pending) {
+                    println!("播放进度事件发送失败: {err:?}");
+                }
+            }
+            true
+        }
+        // 加载进度不在二进制通道中，关闭高频事件后仍然会以较低的频率发送
+        Body::OnLoadProgress { .. } => {
+            let mut last = LAST_LOAD_PROGRESS.lock().unwrap();
+            if high_rate_events || !matches!(*last, Some(x) if x.elapsed() < LOAD_PROGRESS_INTERVAL)
+            {
+                *last = Some(Instant::now());
+                true
+            } else {
+                false
+            }
+        }
+        _ => high_rate_events || !crate::ws_queue::is_droppable(body),
+    }
+}
+
+#[derive(Default)]
+pub struct StreamBuffer {
+    total_audio: u64,
+    audio: Vec<u8>,
+}
+
+impl StreamBuffer {
+    pub fn on_body(&mut self, body: &Body) {
+        if let Body::OnAudioData { data } = body {
+            self.total_audio += data.len() as u64;
+            self.audio.extend_from_slice(data);
+            if self.audio.len() > MAX_BUFFERED_AUDIO {
+                let excess = self.audio.len() - MAX_BUFFERED_AUDIO;
+                self.audio.drain(..excess);
+            }
+        }
+    }
+
+    /// 取出一帧数据，包括当前的播放状态和自上次读取以来收到的音频数据
+    fn take_frame(&mut self, position: f64, paused: bool) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(17 + self.audio.len());
+        frame.extend_from_slice(&self.total_audio.to_le_bytes());
+        frame.extend_from_slice(&position.to_le_bytes());
+        frame.push(paused as u8);
+        frame.append(&mut self.audio);
+        frame
+    }
+}
+
+pub fn handle_stream_protocol(
+    app: &AppHandle,
+    request: &Request,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    let path = request
+        .uri()
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    if !path.ends_with("/frame") {
+        return ResponseBuilder::new().status(404).body(Vec::new());
+    }
+    let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+    let frame = app
+        .state::<Mutex<StreamBuffer>>()
+        .lock()
+        .unwrap()
+        .take_frame(status.position, status.paused);
+    ResponseBuilder::new()
+        .status(200)
+        .mimetype("application/octet-stream")
+        .header("Cache-Control", "no-store")
+        .header("Access-Control-Allow-Origin", "*")
+        .body(frame)
+}
+
+/// 设置是否仍然通过事件发送播放进度和音频数据等高频信息
+#[tauri::command]
+pub fn stream_set_high_rate_events(enabled: bool) {
+    HIGH_RATE_EVENTS.store(enabled, Ordering::Relaxed);
+}
+
+/// 设置两次播放进度事件之间的最小间隔，单位为毫秒，为 0 时每次收到播放进度都会发送
+#[tauri::command]
+pub fn stream_set_progress_interval(interval_ms: u64) {
+    PROGRESS_INTERVAL_MS.store(interval_ms, Ordering::Relaxed);
+}
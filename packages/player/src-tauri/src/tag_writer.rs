@@ -0,0 +1,130 @@
+//! 音频文件标签写入模块
+//!
+//! 使用 lofty 修改音频文件中的标签，支持 MP3、FLAC、M4A、OGG 等常见格式。
+//! 写入时会先复制出一个临时文件，在临时文件上修改完成后再替换原文件，
+//! 避免写入过程中出错导致原文件损坏。
+//! 下载到的歌词和封面图片也可以直接嵌入到音频文件中，使其随文件一起复制到其它设备上。
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use lofty::{Accessor, ItemKey, Picture, PictureType, Probe, Tag, TagExt, TaggedFileExt};
+use serde::Deserialize;
+
+use crate::error::{CommandError, CommandResult, ErrorCode};
+
+/// 需要修改的标签，值为 `None` 的字段保持不变，值为空字符串的字段会被移除
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MusicMetadataChanges {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub lyric: Option<String>,
+    /// Base64 编码的封面图片数据
+    pub cover: Option<String>,
+}
+
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.amll-tmp"))
+}
+
+fn apply_changes(tag: &mut Tag, changes: &MusicMetadataChanges) -> anyhow::Result<()> {
+    match changes.title.as_deref() {
+        Some("") => tag.remove_title(),
+        Some(title) => tag.set_title(title.to_string()),
+        None => {}
+    }
+    match changes.artist.as_deref() {
+        Some("") => tag.remove_artist(),
+        Some(artist) => tag.set_artist(artist.to_string()),
+        None => {}
+    }
+    match changes.album.as_deref() {
+        Some("") => tag.remove_album(),
+        Some(album) => tag.set_album(album.to_string()),
+        None => {}
+    }
+    match changes.lyric.as_deref() {
+        Some("") => tag.remove_key(&ItemKey::Lyrics),
+        Some(lyric) => {
+            if !tag.insert_text(ItemKey::Lyrics, lyric.to_string()) {
+                anyhow::bail!("该文件的标签格式不支持写入歌词");
+            }
+        }
+        None => {}
+    }
+    if let Some(cover) = &changes.cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        if !cover.is_empty() {
+            let data = BASE64.decode(cover)?;
+            let mut picture = Picture::from_reader(&mut data.as_slice())?;
+            picture.set_pic_type(PictureType::CoverFront);
+            tag.push_picture(picture);
+        }
+    }
+    Ok(())
+}
+
+/// 修改音频文件的标签，会优先修改文件格式的主要标签，文件中没有标签时会新建一个
+pub fn write_tags(path: &Path, changes: &MusicMetadataChanges) -> anyhow::Result<()> {
+    let mut tagged_file = Probe::open(path)?.guess_file_type()?.read()?;
+    let tag_type = tagged_file.primary_tag_type();
+    if tagged_file.tag(tag_type).is_none() {
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+    let Some(tag) = tagged_file.tag_mut(tag_type) else {
+        anyhow::bail!("无法为该文件创建标签");
+    };
+    apply_changes(tag, changes)?;
+
+    let temp = temp_path(path);
+    let result = std::fs::copy(path, &temp)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| tag.save_to_path(&temp).map_err(anyhow::Error::from))
+        .and_then(|_| std::fs::rename(&temp, path).map_err(anyhow::Error::from));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+#[tauri::command]
+pub async fn write_music_metadata(
+    path: PathBuf,
+    changes: MusicMetadataChanges,
+) -> CommandResult<()> {
+    Ok(tauri::async_runtime::spawn_blocking(move || write_tags(&path, &changes)).await??)
+}
+
+/// 将歌词嵌入到音频文件的歌词标签中，歌词内容（LRC、TTML 等）会原样写入
+#[tauri::command]
+pub async fn embed_music_lyric(path: PathBuf, lyric: String) -> CommandResult<()> {
+    if lyric.trim().is_empty() {
+        return Err(CommandError::new(ErrorCode::InvalidInput, "歌词内容为空"));
+    }
+    let changes = MusicMetadataChanges {
+        lyric: Some(lyric),
+        ..Default::default()
+    };
+    write_music_metadata(path, changes).await
+}
+
+/// 将 Base64 编码的图片作为封面嵌入到音频文件中，会替换原有的封面图片
+#[tauri::command]
+pub async fn embed_music_cover(path: PathBuf, cover: String) -> CommandResult<()> {
+    if cover.is_empty() {
+        return Err(CommandError::new(
+            ErrorCode::InvalidInput,
+            "封面图片数据为空",
+        ));
+    }
+    let changes = MusicMetadataChanges {
+        cover: Some(cover),
+        ..Default::default()
+    };
+    write_music_metadata(path, changes).await
+}
@@ -0,0 +1,294 @@
+//! Windows 任务栏集成
+//!
+//! 在任务栏图标上显示播放进度（暂停时显示为黄色），
+//! 并在缩略图工具栏中添加上一首、播放/暂停和下一首按钮。
+//! 任务栏的 COM 接口只能在创建窗口的主线程上使用，因此所有操作都会被派发到主线程执行。
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use tauri::{AppHandle, Manager};
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::{BOOL, HWND, LPARAM, LRESULT, WPARAM},
+        Graphics::Gdi::{CreateBitmap, DeleteObject},
+        System::Com::{CoCreateInstance, CLSCTX_ALL},
+        UI::{
+            Shell::{
+                DefSubclassProc, ITaskbarList3, SetWindowSubclass, TaskbarList, TBPFLAG,
+                TBPF_NOPROGRESS, TBPF_NORMAL, TBPF_PAUSED, THBF_ENABLED, THBN_CLICKED, THB_FLAGS,
+                THB_ICON, THB_TOOLTIP, THUMBBUTTON,
+            },
+            WindowsAndMessaging::{
+                CreateIconIndirect, GetSystemMetrics, RegisterWindowMessageW, HICON, ICONINFO,
+                SM_CXSMICON, WM_COMMAND,
+            },
+        },
+    },
+};
+use ws_protocol::Body;
+
+use crate::{hotkeys::HotkeyAction, now_playing::NowPlaying};
+
+const PREVIOUS_BUTTON: u32 = 1;
+const PLAY_PAUSE_BUTTON: u32 = 2;
+const NEXT_BUTTON: u32 = 3;
+/// 进度条的精度，进度变化不足一个单位时不会更新
+const PROGRESS_STEPS: u32 = 1000;
+
+thread_local! {
+    static TASKBAR: RefCell<Option<Taskbar>> = RefCell::new(None);
+}
+
+/// 最近一次更新的进度，用于在派发到主线程之前过滤掉没有变化的更新
+static LAST_PROGRESS: AtomicU32 = AtomicU32::new(u32::MAX);
+
+/// 按钮图标的形状，坐标为相对图标大小的比例，每个形状都是凸多边形
+type Shape = &'static [(f32, f32)];
+
+const PLAY_ICON: &[Shape] = &[&[(0.25, 0.15), (0.85, 0.5), (0.25, 0.85)]];
+const PAUSE_ICON: &[Shape] = &[
+    &[(0.22, 0.15), (0.42, 0.15), (0.42, 0.85), (0.22, 0.85)],
+    &[(0.58, 0.15), (0.78, 0.15), (0.78, 0.85), (0.58, 0.85)],
+];
+const NEXT_ICON: &[Shape] = &[
+    &[(0.15, 0.2), (0.65, 0.5), (0.15, 0.8)],
+    &[(0.68, 0.2), (0.82, 0.2), (0.82, 0.8), (0.68, 0.8)],
+];
+const PREVIOUS_ICON: &[Shape] = &[
+    &[(0.85, 0.2), (0.85, 0.8), (0.35, 0.5)],
+    &[(0.18, 0.2), (0.32, 0.2), (0.32, 0.8), (0.18, 0.8)],
+];
+
+/// 点是否在凸多边形内，多边形的顶点可以是顺时针或者逆时针排列
+fn contains(shape: Shape, x: f32, y: f32) -> bool {
+    let mut sign = 0.0f32;
+    for (i, &(x1, y1)) in shape.iter().enumerate() {
+        let (x2, y2) = shape[(i + 1) % shape.len()];
+        let cross = (x2 - x1) * (y - y1) - (y2 - y1) * (x - x1);
+        if cross != 0.0 {
+            if sign != 0.0 && cross.signum() != sign {
+                return false;
+            }
+            sign = cross.signum();
+        }
+    }
+    true
+}
+
+/// 绘制白色的图标，每个像素采样 4x4 次以实现抗锯齿
+fn create_icon(shapes: &[Shape]) -> windows::core::Result<HICON> {
+    const SAMPLES: usize = 4;
+    let size = unsafe { GetSystemMetrics(SM_CXSMICON) }.max(16) as usize;
+    let mut pixels = vec![0u8; size * size * 4];
+    for y in 0..size {
+        for x in 0..size {
+            let mut covered = 0;
+            for sy in 0..SAMPLES {
+                for sx in 0..SAMPLES {
+                    let px = (x as f32 + (sx as f32 + 0.5) / SAMPLES as f32) / size as f32;
+                    let py = (y as f32 + (sy as f32 + 0.5) / SAMPLES as f32) / size as f32;
+                    if shapes.iter().any(|shape| contains(shape, px, py)) {
+                        covered += 1;
+                    }
+                }
+            }
+            let alpha = (covered * 255 / (SAMPLES * SAMPLES)) as u8;
+            // 图标使用预乘透明度的 BGRA 格式
+            pixels[(y * size + x) * 4..][..4].copy_from_slice(&[alpha, alpha, alpha, alpha]);
+        }
+    }
+    // 有透明通道时掩码不起作用，但仍然需要提供，单色位图的每行需要对齐到 2 字节
+    let mask = vec![0u8; (size + 15) / 16 * 2 * size];
+    unsafe {
+        let color = CreateBitmap(size as i32, size as i32, 1, 32, Some(pixels.as_ptr() as _));
+        let mask = CreateBitmap(size as i32, size as i32, 1, 1, Some(mask.as_ptr() as _));
+        let icon = CreateIconIndirect(&ICONINFO {
+            fIcon: BOOL(1),
+            xHotspot: 0,
+            yHotspot: 0,
+            hbmMask: mask,
+            hbmColor: color,
+        });
+        DeleteObject(color);
+        DeleteObject(mask);
+        icon
+    }
+}
+
+fn thumb_button(id: u32, icon: HICON, tip: &str) -> THUMBBUTTON {
+    let mut button = THUMBBUTTON {
+        dwMask: THB_ICON | THB_TOOLTIP | THB_FLAGS,
+        iId: id,
+        hIcon: icon,
+        dwFlags: THBF_ENABLED,
+        ..Default::default()
+    };
+    for (dst, src) in button.szTip.iter_mut().zip(tip.encode_utf16().take(259)) {
+        *dst = src;
+    }
+    button
+}
+
+struct Taskbar {
+    hwnd: HWND,
+    list: ITaskbarList3,
+    previous_icon: HICON,
+    play_icon: HICON,
+    pause_icon: HICON,
+    next_icon: HICON,
+    buttons_added: bool,
+    paused: bool,
+}
+
+impl Taskbar {
+    fn play_pause_button(&self) -> THUMBBUTTON {
+        if self.paused {
+            thumb_button(PLAY_PAUSE_BUTTON, self.play_icon, "播放")
+        } else {
+            thumb_button(PLAY_PAUSE_BUTTON, self.pause_icon, "暂停")
+        }
+    }
+
+    /// 添加缩略图工具栏按钮，只能在任务栏按钮创建后添加一次
+    fn add_buttons(&mut self) {
+        if self.buttons_added {
+            return;
+        }
+        let buttons = [
+            thumb_button(PREVIOUS_BUTTON, self.previous_icon, "上一首"),
+            self.play_pause_button(),
+            thumb_button(NEXT_BUTTON, self.next_icon, "下一首"),
+        ];
+        match unsafe { self.list.ThumbBarAddButtons(self.hwnd, &buttons) } {
+            Ok(()) => self.buttons_added = true,
+            Err(err) => println!("任务栏缩略图按钮添加失败: {err:?}"),
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) -> windows::core::Result<()> {
+        self.paused = paused;
+        if self.buttons_added {
+            unsafe {
+                self.list
+                    .ThumbBarUpdateButtons(self.hwnd, &[self.play_pause_button()])?;
+            }
+        }
+        self.set_state(if paused { TBPF_PAUSED } else { TBPF_NORMAL })
+    }
+
+    fn set_state(&self, state: TBPFLAG) -> windows::core::Result<()> {
+        unsafe { self.list.SetProgressState(self.hwnd, state) }
+    }
+
+    fn set_progress(&self, progress: u32) -> windows::core::Result<()> {
+        unsafe {
+            self.list
+                .SetProgressValue(self.hwnd, progress as u64, PROGRESS_STEPS as u64)
+        }
+    }
+}
+
+/// 处理缩略图按钮的点击和任务栏按钮的重新创建
+unsafe extern "system" fn subclass_proc(
+    hwnd: HWND,
+    msg: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _id: usize,
+    data: usize,
+) -> LRESULT {
+    let app = &*(data as *const AppHandle);
+    if msg == WM_COMMAND && (wparam.0 >> 16) as u32 & 0xffff == THBN_CLICKED {
+        let action = match wparam.0 as u32 & 0xffff {
+            PREVIOUS_BUTTON => Some(HotkeyAction::Previous),
+            PLAY_PAUSE_BUTTON => Some(HotkeyAction::PlayPause),
+            NEXT_BUTTON => Some(HotkeyAction::Next),
+            _ => None,
+        };
+        if let Some(action) = action {
+            crate::hotkeys::perform(app, action);
+            return LRESULT(0);
+        }
+    }
+    // 资源管理器重启后任务栏按钮会被重新创建，需要重新添加缩略图按钮
+    if msg == RegisterWindowMessageW(w!("TaskbarButtonCreated")) {
+        TASKBAR.with(|taskbar| {
+            if let Some(taskbar) = taskbar.borrow_mut().as_mut() {
+                taskbar.buttons_added = false;
+                taskbar.add_buttons();
+            }
+        });
+    }
+    DefSubclassProc(hwnd, msg, wparam, lparam)
+}
+
+/// 初始化任务栏集成，必须在主线程上调用
+pub fn init(app: &AppHandle) -> anyhow::Result<()> {
+    let Some(window) = app.get_window("main") else {
+        anyhow::bail!("主窗口不存在");
+    };
+    let hwnd = HWND(window.hwnd()?.0);
+    let list: ITaskbarList3 = unsafe { CoCreateInstance(&TaskbarList, None, CLSCTX_ALL)? };
+    unsafe { list.HrInit()? };
+    let mut taskbar = Taskbar {
+        hwnd,
+        list,
+        previous_icon: create_icon(PREVIOUS_ICON)?,
+        play_icon: create_icon(PLAY_ICON)?,
+        pause_icon: create_icon(PAUSE_ICON)?,
+        next_icon: create_icon(NEXT_ICON)?,
+        buttons_added: false,
+        paused: true,
+    };
+    // 窗口已经显示时任务栏按钮已经创建，不会再收到创建的消息
+    taskbar.add_buttons();
+    TASKBAR.with(|x| *x.borrow_mut() = Some(taskbar));
+    let data = Box::into_raw(Box::new(app.clone())) as usize;
+    unsafe { SetWindowSubclass(hwnd, Some(subclass_proc), 1, data) };
+    Ok(())
+}
+
+/// 播放进度或者播放状态变化时更新任务栏，需要在 [`NowPlaying`] 处理信息之后调用
+pub fn on_body(app: &AppHandle, body: &Body) {
+    let status = || app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+    let update: Box<dyn FnOnce(&mut Taskbar) -> windows::core::Result<()> + Send> = match body {
+        Body::OnPaused => Box::new(|x| x.set_paused(true)),
+        Body::OnResumed => Box::new(|x| x.set_paused(false)),
+        Body::OnPlayProgress { .. } | Body::SetMusicId { .. } => {
+            let status = status();
+            if status.music_id.is_empty() {
+                LAST_PROGRESS.store(u32::MAX, Ordering::Relaxed);
+                Box::new(|x| x.set_state(TBPF_NOPROGRESS))
+            } else {
+                if status.duration == 0 {
+                    return;
+                }
+                let progress = ((status.position / status.duration as f64).clamp(0.0, 1.0)
+                    * PROGRESS_STEPS as f64) as u32;
+                if LAST_PROGRESS.swap(progress, Ordering::Relaxed) == progress {
+                    return;
+                }
+                Box::new(move |x| x.set_progress(progress))
+            }
+        }
+        _ => return,
+    };
+    let result = app.run_on_main_thread(move || {
+        TASKBAR.with(|taskbar| {
+            if let Some(taskbar) = taskbar.borrow_mut().as_mut() {
+                if let Err(err) = update(taskbar) {
+                    println!("任务栏更新失败: {err:?}");
+                }
+            }
+        })
+    });
+    if let Err(err) = result {
+        println!("任务栏更新失败: {err:?}");
+    }
+}
@@ -0,0 +1,98 @@
+//! 系统托盘
+//!
+//! 托盘图标的提示文字和菜单会显示当前播放的歌曲，菜单中可以控制播放、切换桌面歌词和退出程序，
+//! 点击托盘图标会显示主窗口。
+use std::sync::Mutex;
+
+use tauri::{
+    AppHandle, CustomMenuItem, Manager, SystemTray, SystemTrayEvent, SystemTrayMenu,
+    SystemTrayMenuItem,
+};
+use ws_protocol::Body;
+
+use crate::{hotkeys::HotkeyAction, now_playing::NowPlaying};
+
+const NOW_PLAYING_ITEM: &str = "now-playing";
+const PLAY_PAUSE_ITEM: &str = "play-pause";
+const NEXT_ITEM: &str = "next";
+const PREVIOUS_ITEM: &str = "previous";
+const DESKTOP_LYRICS_ITEM: &str = "toggle-desktop-lyrics";
+const SHOW_WINDOW_ITEM: &str = "show-window";
+const QUIT_ITEM: &str = "quit";
+
+const IDLE_TITLE: &str = "未在播放";
+
+pub fn system_tray() -> SystemTray {
+    let menu = SystemTrayMenu::new()
+        .add_item(CustomMenuItem::new(NOW_PLAYING_ITEM, IDLE_TITLE).disabled())
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(PLAY_PAUSE_ITEM, "播放"))
+        .add_item(CustomMenuItem::new(PREVIOUS_ITEM, "上一首"))
+        .add_item(CustomMenuItem::new(NEXT_ITEM, "下一首"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(DESKTOP_LYRICS_ITEM, "桌面歌词"))
+        .add_item(CustomMenuItem::new(SHOW_WINDOW_ITEM, "显示主窗口"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(QUIT_ITEM, "退出"));
+    SystemTray::new()
+        .with_menu(menu)
+        .with_tooltip("AMLL Player")
+}
+
+fn show_window(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        if let Err(err) = window
+            .unminimize()
+            .and_then(|_| window.show())
+            .and_then(|_| window.set_focus())
+        {
+            println!("主窗口显示失败: {err:?}");
+        }
+    }
+}
+
+pub fn on_tray_event(app: &AppHandle, event: SystemTrayEvent) {
+    match event {
+        SystemTrayEvent::LeftClick { .. } => show_window(app),
+        SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
+            PLAY_PAUSE_ITEM => crate::hotkeys::perform(app, HotkeyAction::PlayPause),
+            NEXT_ITEM => crate::hotkeys::perform(app, HotkeyAction::Next),
+            PREVIOUS_ITEM => crate::hotkeys::perform(app, HotkeyAction::Previous),
+            DESKTOP_LYRICS_ITEM => crate::hotkeys::perform(app, HotkeyAction::ToggleDesktopLyrics),
+            SHOW_WINDOW_ITEM => show_window(app),
+            QUIT_ITEM => app.exit(0),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+/// 歌曲或者播放状态变化时更新托盘，需要在 [`NowPlaying`] 处理信息之后调用
+pub fn on_body(app: &AppHandle, body: &Body) {
+    if !matches!(
+        body,
+        Body::SetMusicId { .. } | Body::SetMusicArtists { .. } | Body::OnPaused | Body::OnResumed
+    ) {
+        return;
+    }
+    let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+    let title = match (status.music_name.is_empty(), status.artists.is_empty()) {
+        (true, _) => IDLE_TITLE.to_string(),
+        (false, true) => status.music_name,
+        (false, false) => format!("{} - {}", status.music_name, status.artists.join(" / ")),
+    };
+    let tray = app.tray_handle();
+    let result = tray
+        .set_tooltip(&format!("AMLL Player\n{title}"))
+        .and_then(|_| tray.get_item(NOW_PLAYING_ITEM).set_title(&title))
+        .and_then(|_| {
+            tray.get_item(PLAY_PAUSE_ITEM).set_title(if status.paused {
+                "播放"
+            } else {
+                "暂停"
+            })
+        });
+    if let Err(err) = result {
+        println!("托盘更新失败: {err:?}");
+    }
+}
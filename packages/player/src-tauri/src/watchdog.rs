@@ -0,0 +1,138 @@
+//! 播放卡住检测
+//!
+//! 播放源处于播放状态但长时间没有发送播放进度（例如网络音源卡住或者解码器死锁），
+//! 或者之前一直在发送音频数据但突然停止发送时，视为播放卡住。
+//! 此时会通过 `on-playback-stalled` 事件通知前端，并要求播放源跳转到最后的播放进度以重新开始播放，
+//! 之后收到新的播放进度时会发送 `on-playback-recovered` 事件。
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use ws_protocol::Body;
+
+use crate::now_playing::NowPlaying;
+
+/// 检查的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// 超过此时间没有收到播放进度或者音频数据时视为卡住
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum StallReason {
+    /// 没有收到新的播放进度
+    NoProgress,
+    /// 之前有音频数据，但已经停止发送
+    NoAudio,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackStalled {
+    pub reason: StallReason,
+    pub music_id: String,
+    /// 最后收到的播放进度，单位为毫秒，会要求播放源从此处重新开始播放
+    pub position: f64,
+    /// 距离最后一次收到数据的时间，单位为毫秒
+    pub stalled_for: u64,
+}
+
+#[derive(Default)]
+pub struct Watchdog {
+    last_position: f64,
+    last_progress_at: Option<Instant>,
+    last_audio_at: Option<Instant>,
+    stalled: bool,
+}
+
+impl Watchdog {
+    pub fn new(app: AppHandle) -> Self {
+        std::thread::spawn(move || Self::run(app));
+        Self::default()
+    }
+
+    fn run(app: AppHandle) {
+        loop {
+            std::thread::sleep(CHECK_INTERVAL);
+            // 线程启动时状态可能还没有交给 Tauri 管理
+            let Some(watchdog) = app.try_state::<Mutex<Watchdog>>() else {
+                continue;
+            };
+            let status = app.state::<Mutex<NowPlaying>>().lock().unwrap().status();
+            if status.paused || status.music_id.is_empty() {
+                continue;
+            }
+            let Some(stalled) = watchdog.lock().unwrap().check(status.music_id) else {
+                continue;
+            };
+            println!(
+                "播放卡住 ({:?})，已经 {} 毫秒没有收到数据，尝试从 {} 毫秒处重新开始播放",
+                stalled.reason, stalled.stalled_for, stalled.position
+            );
+            let position = stalled.position;
+            if let Err(err) = app.emit_all("on-playback-stalled", stalled) {
+                println!("播放卡住事件发送失败: {err:?}");
+            }
+            crate::send_control(&app, Body::SetPlayProgress { progress: position });
+        }
+    }
+
+    /// 检查是否刚刚卡住，同一次卡住只会返回一次
+    fn check(&mut self, music_id: String) -> Option<PlaybackStalled> {
+        if self.stalled {
+            return None;
+        }
+        let (reason, at) = match (self.last_progress_at, self.last_audio_at) {
+            (Some(at), _) if at.elapsed() >= STALL_TIMEOUT => (StallReason::NoProgress, at),
+            (_, Some(at)) if at.elapsed() >= STALL_TIMEOUT => (StallReason::NoAudio, at),
+            _ => return None,
+        };
+        self.stalled = true;
+        Some(PlaybackStalled {
+            reason,
+            music_id,
+            position: self.last_position,
+            stalled_for: at.elapsed().as_millis() as u64,
+        })
+    }
+
+    fn recover(&mut self, app: &AppHandle) {
+        if self.stalled {
+            self.stalled = false;
+            if let Err(err) = app.emit_all("on-playback-recovered", ()) {
+                println!("播放恢复事件发送失败: {err:?}");
+            }
+        }
+    }
+
+    pub fn on_body(&mut self, app: &AppHandle, body: &Body) {
+        match body {
+            // 只有在收到过播放进度或者音频数据之后才会检测，因为不是所有播放源都会发送这些信息
+            Body::SetMusicId { .. } => self.reset(),
+            Body::OnPlayProgress { progress } => {
+                self.last_position = *progress;
+                self.last_progress_at = Some(Instant::now());
+                self.recover(app);
+            }
+            Body::OnAudioData { .. } => {
+                self.last_audio_at = Some(Instant::now());
+                self.recover(app);
+            }
+            // 暂停期间不会收到数据，恢复播放时重新开始计时
+            Body::OnResumed => {
+                let now = Instant::now();
+                self.last_progress_at = self.last_progress_at.map(|_| now);
+                self.last_audio_at = self.last_audio_at.map(|_| now);
+            }
+            _ => {}
+        }
+    }
+
+    /// 播放源断开连接时停止检测
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
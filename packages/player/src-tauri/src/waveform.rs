@@ -0,0 +1,238 @@
+//! 波形概览模块
+//!
+//! 解码整个音频文件，按时间平均分成若干段，计算每段的最小值、最大值和均方根，
+//! 用于在进度条上绘制波形。
+//! 结果以文件内容的哈希和分段数量为键缓存到缓存文件夹中，同一个文件只需要解码一次。
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+use tauri::State;
+
+use crate::error::CommandResult;
+
+/// 解码时先按此帧数统计，最后再合并成需要的分段数量，这样不需要预先知道音频的总长度
+const CHUNK_FRAMES: usize = 1024;
+/// 最多允许的分段数量
+const MAX_BUCKETS: usize = 65536;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct WaveformBucket {
+    pub min: f32,
+    pub max: f32,
+    pub rms: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Waveform {
+    /// 音频的时长，单位为秒
+    pub duration: f64,
+    pub buckets: Vec<WaveformBucket>,
+}
+
+/// 一段采样的统计数据，所有声道混合在一起统计
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    min: f32,
+    max: f32,
+    square_sum: f64,
+    samples: u64,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self {
+            min: f32::MAX,
+            max: f32::MIN,
+            square_sum: 0.0,
+            samples: 0,
+        }
+    }
+}
+
+impl Chunk {
+    fn push(&mut self, sample: f32) {
+        self.min = self.min.min(sample);
+        self.max = self.max.max(sample);
+        self.square_sum += (sample * sample) as f64;
+        self.samples += 1;
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.square_sum += other.square_sum;
+        self.samples += other.samples;
+    }
+
+    fn to_bucket(self) -> WaveformBucket {
+        if self.samples == 0 {
+            return WaveformBucket::default();
+        }
+        WaveformBucket {
+            min: self.min,
+            max: self.max,
+            rms: (self.square_sum / self.samples as f64).sqrt() as f32,
+        }
+    }
+}
+
+pub fn generate_waveform_file(path: &Path, buckets: usize) -> anyhow::Result<Waveform> {
+    let mss = MediaSourceStream::new(crate::media_source::open_mapped(path)?, Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|x| x.to_str()) {
+        hint.with_extension(ext);
+    }
+    let mut probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let track = crate::media_source::audio_track(probed.format.as_ref())
+        .ok_or_else(|| anyhow::anyhow!("文件中没有音轨"))?;
+    let track_id = track.id;
+    let params = track.codec_params.clone();
+    let sample_rate = params
+        .sample_rate
+        .ok_or_else(|| anyhow::anyhow!("无法获取音频的采样率"))?;
+    let mut decoder = symphonia::default::get_codecs().make(&params, &DecoderOptions::default())?;
+
+    let mut chunks = Vec::new();
+    let mut current = Chunk::default();
+    let mut current_frames = 0;
+    let mut decoded_frames = 0u64;
+    let mut sample_buf: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // 损坏的数据包直接跳过
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let channels = decoded.spec().channels.count().max(1);
+        let buf = sample_buf
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        buf.copy_interleaved_ref(decoded);
+        for frame in buf.samples().chunks_exact(channels) {
+            for &sample in frame {
+                current.push(sample);
+            }
+            current_frames += 1;
+            decoded_frames += 1;
+            if current_frames == CHUNK_FRAMES {
+                chunks.push(std::mem::take(&mut current));
+                current_frames = 0;
+            }
+        }
+    }
+    if current_frames > 0 {
+        chunks.push(current);
+    }
+
+    // 将统计数据按比例合并到各个分段中，分段比统计数据多时会有分段重复使用同一段数据
+    let buckets = buckets.clamp(1, MAX_BUCKETS);
+    let result = (0..buckets)
+        .map(|i| {
+            if chunks.is_empty() {
+                return WaveformBucket::default();
+            }
+            let start = i * chunks.len() / buckets;
+            let end = ((i + 1) * chunks.len() / buckets).max(start + 1);
+            let mut merged = Chunk::default();
+            for chunk in &chunks[start..end.min(chunks.len())] {
+                merged.merge(chunk);
+            }
+            merged.to_bucket()
+        })
+        .collect();
+
+    Ok(Waveform {
+        duration: decoded_frames as f64 / sample_rate as f64,
+        buckets: result,
+    })
+}
+
+#[derive(Clone)]
+pub struct WaveformCache {
+    dir: PathBuf,
+}
+
+impl WaveformCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path_of(&self, path: &Path, buckets: usize) -> anyhow::Result<PathBuf> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = file.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+        }
+        Ok(self
+            .dir
+            .join(format!("{:x}-{buckets}.json", hasher.finalize())))
+    }
+
+    /// 读取缓存的波形，没有缓存时解码文件生成并写入缓存
+    pub fn get_or_generate(&self, path: &Path, buckets: usize) -> anyhow::Result<Waveform> {
+        let buckets = buckets.clamp(1, MAX_BUCKETS);
+        let cache_path = self.path_of(path, buckets)?;
+        if let Some(waveform) = std::fs::read(&cache_path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+        {
+            return Ok(waveform);
+        }
+        let waveform = generate_waveform_file(path, buckets)?;
+        let _ = std::fs::create_dir_all(&self.dir);
+        match serde_json::to_vec(&waveform) {
+            Ok(data) => {
+                if let Err(err) = std::fs::write(&cache_path, data) {
+                    println!("波形缓存保存失败: {err:?}");
+                }
+            }
+            Err(err) => println!("波形缓存序列化失败: {err:?}"),
+        }
+        Ok(waveform)
+    }
+}
+
+/// 生成音频文件的波形概览，返回指定数量的分段，结果会被缓存
+#[tauri::command]
+pub async fn generate_waveform(
+    cache: State<'_, WaveformCache>,
+    path: PathBuf,
+    buckets: usize,
+) -> CommandResult<Waveform> {
+    let cache = cache.inner().clone();
+    Ok(
+        tauri::async_runtime::spawn_blocking(move || cache.get_or_generate(&path, buckets))
+            .await??,
+    )
+}
@@ -0,0 +1,210 @@
+//! WebSocket 服务器的身份验证
+//!
+//! 设置了令牌后，客户端需要在握手时通过 URL 中的 `token` 参数或者 `X-AMLL-Token` 请求头
+//! 提供相同的令牌才能连接，否则握手会以 401 状态码被拒绝。
+//! 令牌可以是用户自定义的字符串，也可以生成随机的六位数字配对码，并会持久化保存到应用数据文件夹中。
+//!
+//! 另外可以设置只读令牌，使用只读令牌连接的客户端只能接收信息，发送的控制指令会被忽略，
+//! 适合分享给公开展示的歌词显示器。
+//!
+//! 六位数字的配对码很容易被穷举，所以同一个地址连续多次提供错误的令牌后，
+//! 会在一段时间内拒绝该地址的所有连接，每次锁定的时长都会翻倍。
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use ws_protocol::ClientRole;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct WsAuthConfig {
+    token: Option<String>,
+    #[serde(default)]
+    read_only_token: Option<String>,
+}
+
+/// 同一个地址连续提供错误令牌达到此次数后会被暂时锁定
+const MAX_FAILURES: u32 = 5;
+/// 第一次锁定的时长，之后每次锁定的时长都会翻倍
+const MIN_LOCKOUT: Duration = Duration::from_secs(30);
+/// 锁定的最大时长
+const MAX_LOCKOUT: Duration = Duration::from_secs(60 * 60);
+/// 超过此时间没有再提供错误的令牌时，清除该地址的失败记录
+const FAILURE_RESET: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// 单个地址提供错误令牌的记录
+struct FailureRecord {
+    /// 上次锁定之后连续失败的次数
+    failures: u32,
+    /// 已经被锁定的次数，用于计算下次锁定的时长
+    lockouts: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+pub struct WsAuth {
+    path: Option<PathBuf>,
+    token: RwLock<Option<String>>,
+    read_only_token: RwLock<Option<String>>,
+    failures: Mutex<HashMap<IpAddr, FailureRecord>>,
+}
+
+/// 去除令牌两端的空白，空字符串视为没有令牌
+fn normalize_token(token: Option<String>) -> Option<String> {
+    token
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+}
+
+/// 比较两个字符串，比较所需的时间与字符串的内容无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 生成随机的六位数字配对码
+fn generate_pin() -> String {
+    format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+}
+
+impl WsAuth {
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let config: WsAuthConfig =
+            crate::config::load_json(path.as_deref(), "WebSocket 身份验证配置").unwrap_or_default();
+        Self {
+            path,
+            token: RwLock::new(config.token),
+            read_only_token: RwLock::new(config.read_only_token),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn save(&self) {
+        let config = WsAuthConfig {
+            token: self.token(),
+            read_only_token: self.read_only_token(),
+        };
+        crate::config::save_json(self.path.as_deref(), &config, "WebSocket 身份验证配置");
+    }
+
+    pub fn token(&self) -> Option<String> {
+        self.token.read().unwrap().clone()
+    }
+
+    /// 设置令牌，传入空值或者空字符串时关闭身份验证
+    pub fn set_token(&self, token: Option<String>) {
+        *self.token.write().unwrap() = normalize_token(token);
+        self.save();
+    }
+
+    /// 生成新的配对码作为令牌并返回
+    pub fn rotate_token(&self) -> String {
+        let token = generate_pin();
+        self.set_token(Some(token.clone()));
+        token
+    }
+
+    pub fn read_only_token(&self) -> Option<String> {
+        self.read_only_token.read().unwrap().clone()
+    }
+
+    /// 设置只读令牌，传入空值或者空字符串时取消只读令牌
+    pub fn set_read_only_token(&self, token: Option<String>) {
+        *self.read_only_token.write().unwrap() = normalize_token(token);
+        self.save();
+    }
+
+    /// 生成新的配对码作为只读令牌并返回
+    pub fn rotate_read_only_token(&self) -> String {
+        let token = generate_pin();
+        self.set_read_only_token(Some(token.clone()));
+        token
+    }
+
+    /// 返回该地址剩余的锁定时长，没有被锁定时返回空值
+    ///
+    /// 被锁定的地址即使提供了正确的令牌也无法连接，应当在调用 [`WsAuth::verify`] 之前检查
+    pub fn locked_out(&self, ip: IpAddr) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+        let locked_until = failures.get(&ip)?.locked_until?;
+        locked_until.checked_duration_since(Instant::now())
+    }
+
+    /// 记录一次错误的令牌，连续失败次数达到上限时锁定该地址
+    fn on_failure(&self, ip: IpAddr) {
+        let now = Instant::now();
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, x| now.duration_since(x.last_failure) < FAILURE_RESET);
+        let record = failures.entry(ip).or_insert(FailureRecord {
+            failures: 0,
+            lockouts: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+        record.failures += 1;
+        record.last_failure = now;
+        if record.failures >= MAX_FAILURES {
+            let lockout = MIN_LOCKOUT
+                .saturating_mul(1 << record.lockouts.min(16))
+                .min(MAX_LOCKOUT);
+            println!(
+                "{ip} 连续提供了 {} 次错误的令牌，锁定 {lockout:?}",
+                record.failures
+            );
+            record.failures = 0;
+            record.lockouts += 1;
+            record.locked_until = Some(now + lockout);
+        }
+    }
+
+    /// 检查客户端提供的令牌并返回客户端的权限，令牌错误或者地址被锁定时返回空值
+    ///
+    /// 提供了只读令牌的客户端只有只读权限，两种令牌都没有设置时允许所有客户端以控制权限连接，
+    /// 只设置了只读令牌时客户端必须提供只读令牌。
+    /// 令牌错误时会记录失败次数，令牌正确时会清除该地址的失败记录
+    pub fn verify(&self, ip: IpAddr, token: Option<&str>) -> Option<ClientRole> {
+        if self.locked_out(ip).is_some() {
+            return None;
+        }
+        let matches = |expected: &Option<String>| match (token, expected) {
+            (Some(token), Some(expected)) => {
+                constant_time_eq(token.trim().as_bytes(), expected.as_bytes())
+            }
+            _ => false,
+        };
+        let role = {
+            let expected = self.token.read().unwrap();
+            let read_only = self.read_only_token.read().unwrap();
+            if (expected.is_none() && read_only.is_none()) || matches(&expected) {
+                Some(ClientRole::Control)
+            } else if matches(&read_only) {
+                Some(ClientRole::ReadOnly)
+            } else {
+                None
+            }
+        };
+        match role {
+            Some(_) => {
+                self.failures.lock().unwrap().remove(&ip);
+            }
+            None => self.on_failure(ip),
+        }
+        role
+    }
+}
+
+#[test]
+fn read_only_token_without_control_token() {
+    let ip = IpAddr::from([127, 0, 0, 1]);
+    let auth = WsAuth::load(None);
+    assert_eq!(auth.verify(ip, None), Some(ClientRole::Control));
+    auth.set_read_only_token(Some("123456".into()));
+    assert_eq!(auth.verify(ip, None), None);
+    assert_eq!(auth.verify(ip, Some("654321")), None);
+    assert_eq!(auth.verify(ip, Some("123456")), Some(ClientRole::ReadOnly));
+}
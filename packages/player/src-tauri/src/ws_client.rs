@@ -0,0 +1,190 @@
+//! WebSocket 客户端模式
+//!
+//! 与 [`crate::server::AMLLWebSocketServer`] 相反，此模式由播放器主动连接到远程的 AMLL WebSocket 服务器，
+//! 适用于播放源位于 NAT 之后、无法直接连接到播放器的情况。
+//! 从远程服务器接收到的信息会和来自 WS 客户端的信息一样分发给各个模块并转发给前端，
+//! 本地的播放状态也可以通过 [`ws_send_remote`] 发送给远程服务器。连接断开后会自动重连。
+use std::{sync::Arc, time::Duration};
+
+use async_std::sync::Mutex;
+use async_std::task::{block_on, JoinHandle};
+use async_tungstenite::async_std::ConnectStream;
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
+use futures::prelude::*;
+use futures::stream::SplitSink;
+use serde::Serialize;
+use tauri::{AppHandle, Manager, State};
+
+use crate::{cover_chunk::CoverAssembler, error::CommandResult};
+
+/// 连接失败或者断开后重新连接的间隔
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(3);
+
+type RemoteSink = Arc<Mutex<Option<SplitSink<WebSocketStream<ConnectStream>, Message>>>>;
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteStatus {
+    pub url: String,
+    pub connected: bool,
+}
+
+pub struct AMLLWebSocketClient {
+    app: AppHandle,
+    handle: Option<JoinHandle<()>>,
+    sink: RemoteSink,
+    url: Option<String>,
+}
+
+/// 没有协议前缀的地址会被当作 `ws://` 地址
+fn normalize_url(addr: &str) -> String {
+    let addr = addr.trim();
+    if addr.starts_with("ws://") || addr.starts_with("wss://") {
+        addr.to_string()
+    } else {
+        format!("ws://{addr}")
+    }
+}
+
+impl AMLLWebSocketClient {
+    pub fn new(app: AppHandle) -> Self {
+        Self {
+            app,
+            handle: None,
+            sink: Arc::new(Mutex::new(None)),
+            url: None,
+        }
+    }
+
+    /// 连接到远程服务器，已有的连接会被断开
+    pub fn connect(&mut self, addr: &str) {
+        self.disconnect();
+        let url = normalize_url(addr);
+        let app = self.app.clone();
+        let sink = self.sink.clone();
+        self.url = Some(url.clone());
+        self.handle = Some(async_std::task::spawn(async move {
+            loop {
+                println!("正在连接远程 WebSocket 服务器 {url}");
+                if let Err(err) = Self::run(&url, &app, &sink).await {
+                    println!("远程 WebSocket 服务器 {url} 连接失败: {err:?}");
+                }
+                sink.lock().await.take();
+                let _ = app.emit_all(
+                    "on-ws-remote-status",
+                    RemoteStatus {
+                        url: url.clone(),
+                        connected: false,
+                    },
+                );
+                async_std::task::sleep(RECONNECT_INTERVAL).await;
+            }
+        }));
+    }
+
+    async fn run(url: &str, app: &AppHandle, sink: &RemoteSink) -> anyhow::Result<()> {
+        let (wss, _) = async_tungstenite::async_std::connect_async(url).await?;
+        println!("已连接远程 WebSocket 服务器 {url}");
+        let (mut write, mut read) = wss.split();
+        let hello = ws_protocol::Body::Hello {
+            client_name: "AMLL Player".into(),
+            version: env!("CARGO_PKG_VERSION").into(),
+            capabilities: ws_protocol::capabilities::LYRIC | ws_protocol::capabilities::COVER_CHUNK,
+        };
+        write
+            .send(Message::Binary(ws_protocol::to_body(&hello)?))
+            .await?;
+        *sink.lock().await = Some(write);
+        app.emit_all(
+            "on-ws-remote-status",
+            RemoteStatus {
+                url: url.to_string(),
+                connected: true,
+            },
+        )?;
+
+        let mut covers = CoverAssembler::default();
+        while let Some(data) = read.next().await {
+            let body = match data? {
+                Message::Binary(data) => ws_protocol::parse_body(&data),
+                Message::Text(data) => ws_protocol::parse_body_json(&data),
+                _ => continue,
+            };
+            match body.and_then(|body| covers.on_body(body)) {
+                Ok(None) => {}
+                Ok(Some(body)) => {
+                    crate::on_client_body(app, &body);
+                    if crate::stream::should_emit(app, &body) {
+                        let result = app
+                            .emit_all("on-client-body", body.clone())
+                            .and_then(|_| app.emit_all("on-ws-remote-message", body));
+                        if let Err(err) = result {
+                            println!("远程 WebSocket 服务器信息事件发送失败: {err:?}");
+                        }
+                    }
+                }
+                Err(err) => {
+                    println!("远程 WebSocket 服务器发送的信息处理失败: {err:?}");
+                }
+            }
+        }
+        println!("已断开远程 WebSocket 服务器 {url}");
+        Ok(())
+    }
+
+    /// 断开与远程服务器的连接并停止重连
+    pub fn disconnect(&mut self) {
+        self.url = None;
+        block_on(async {
+            if let Some(task) = self.handle.take() {
+                task.cancel().await;
+            }
+            if let Some(mut sink) = self.sink.lock().await.take() {
+                let _ = sink.close().await;
+            }
+        });
+    }
+
+    pub fn status(&self) -> Option<RemoteStatus> {
+        let connected = block_on(self.sink.lock()).is_some();
+        self.url.clone().map(|url| RemoteStatus { url, connected })
+    }
+
+    /// 向远程服务器发送信息，没有连接时返回错误
+    pub async fn send(&self, data: ws_protocol::Body) -> anyhow::Result<()> {
+        let data = ws_protocol::to_body(&data)?;
+        match self.sink.lock().await.as_mut() {
+            Some(sink) => Ok(sink.send(Message::Binary(data)).await?),
+            None => anyhow::bail!("没有连接到远程 WebSocket 服务器"),
+        }
+    }
+}
+
+/// 连接到远程的 AMLL WebSocket 服务器，地址可以省略 `ws://` 前缀
+#[tauri::command]
+pub fn ws_connect_to(client: State<std::sync::Mutex<AMLLWebSocketClient>>, addr: String) {
+    client.lock().unwrap().connect(&addr);
+}
+
+#[tauri::command]
+pub fn ws_disconnect_remote(client: State<std::sync::Mutex<AMLLWebSocketClient>>) {
+    client.lock().unwrap().disconnect();
+}
+
+/// 获取远程连接的状态，没有连接到远程服务器时为空
+#[tauri::command]
+pub fn ws_get_remote_status(
+    client: State<std::sync::Mutex<AMLLWebSocketClient>>,
+) -> Option<RemoteStatus> {
+    client.lock().unwrap().status()
+}
+
+/// 向远程服务器发送信息，例如本地的播放进度
+#[tauri::command]
+pub fn ws_send_remote(
+    client: State<std::sync::Mutex<AMLLWebSocketClient>>,
+    data: ws_protocol::Body,
+) -> CommandResult<()> {
+    Ok(block_on(client.lock().unwrap().send(data))?)
+}
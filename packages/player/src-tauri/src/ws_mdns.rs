@@ -0,0 +1,157 @@
+//! 通过 mDNS 广播和发现 AMLL WebSocket 服务
+//!
+//! WebSocket 服务器监听局域网地址时会以 `_amll._tcp` 服务类型广播自身，
+//! 伴侣应用可以直接发现播放器而无需手动输入 IP 地址和端口。
+//! 只监听本地回环地址时不会进行广播。
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Serialize;
+use tauri::State;
+
+use crate::error::CommandResult;
+
+/// mDNS 服务类型
+const SERVICE_TYPE: &str = "_amll._tcp.local.";
+/// 广播时使用的主机名
+const HOST_NAME: &str = "amll-player.local.";
+/// 发现服务时默认的等待时间
+const DEFAULT_DISCOVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveredService {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub addresses: Vec<String>,
+    /// 服务的 TXT 记录，包含版本等信息
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Default)]
+pub struct MdnsService {
+    daemon: Option<ServiceDaemon>,
+    /// 已经广播的服务的完整名称
+    registered: Option<String>,
+}
+
+/// 从 `主机:端口` 形式的地址中解析出主机和端口
+fn split_addr(addr: &str) -> Option<(&str, u16)> {
+    let (host, port) = addr.trim().rsplit_once(':')?;
+    Some((host.trim_matches(['[', ']']), port.parse().ok()?))
+}
+
+fn is_loopback(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<IpAddr>()
+            .map(|x| x.is_loopback())
+            .unwrap_or(false)
+}
+
+impl MdnsService {
+    fn daemon(&mut self) -> anyhow::Result<&ServiceDaemon> {
+        if self.daemon.is_none() {
+            self.daemon = Some(ServiceDaemon::new()?);
+        }
+        Ok(self.daemon.as_ref().unwrap())
+    }
+
+    /// 停止广播服务
+    pub fn unregister(&mut self) {
+        if let (Some(daemon), Some(fullname)) = (&self.daemon, self.registered.take()) {
+            if let Err(err) = daemon.unregister(&fullname) {
+                println!("mDNS 服务 {fullname} 取消广播失败: {err:?}");
+            }
+        }
+    }
+
+    /// 根据 WebSocket 服务器监听的地址广播服务，会使用第一个非本地回环地址的端口，
+    /// 只监听本地回环地址时停止广播，
+    /// `tls` 和 `auth` 表示服务器是否开启了 TLS 和身份验证，会写入 TXT 记录中
+    pub fn advertise(&mut self, addrs: &[String], tls: bool, auth: bool) -> anyhow::Result<()> {
+        self.unregister();
+        let Some(port) = addrs
+            .iter()
+            .filter_map(|addr| split_addr(addr))
+            .find(|(host, _)| !is_loopback(host))
+            .map(|(_, port)| port)
+        else {
+            return Ok(());
+        };
+        let instance = format!("AMLL Player {port}");
+        let properties = HashMap::from([
+            ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+            ("protocol".to_string(), "amll-ws".to_string()),
+            ("tls".to_string(), tls.to_string()),
+            ("auth".to_string(), auth.to_string()),
+        ]);
+        let info = ServiceInfo::new(SERVICE_TYPE, &instance, HOST_NAME, "", port, properties)?
+            .enable_addr_auto();
+        let fullname = info.get_fullname().to_string();
+        self.daemon()?.register(info)?;
+        println!("已通过 mDNS 广播 WebSocket 服务: {fullname}");
+        self.registered = Some(fullname);
+        Ok(())
+    }
+
+    /// 获取用于查找服务的守护进程和自身广播的服务名称，查找时无需持有锁
+    fn browser(&mut self) -> anyhow::Result<(ServiceDaemon, Option<String>)> {
+        Ok((self.daemon()?.clone(), self.registered.clone()))
+    }
+}
+
+/// 在局域网中查找 AMLL WebSocket 服务，会等待 `timeout` 时间后返回找到的所有服务，
+/// 名称为 `own` 的服务（即自身广播的服务）不会被包含在内
+fn discover(
+    daemon: ServiceDaemon,
+    own: Option<String>,
+    timeout: Duration,
+) -> anyhow::Result<Vec<DiscoveredService>> {
+    let receiver = daemon.browse(SERVICE_TYPE)?;
+    let deadline = Instant::now() + timeout;
+    let mut result: Vec<DiscoveredService> = Vec::new();
+    while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = receiver.recv_timeout(remaining) else {
+            break;
+        };
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        if own.as_deref() == Some(info.get_fullname()) {
+            continue;
+        }
+        result.retain(|x| x.name != info.get_fullname());
+        result.push(DiscoveredService {
+            name: info.get_fullname().to_string(),
+            host: info.get_hostname().to_string(),
+            port: info.get_port(),
+            addresses: info.get_addresses().iter().map(|x| x.to_string()).collect(),
+            properties: info
+                .get_properties()
+                .iter()
+                .map(|x| (x.key().to_string(), x.val_str().to_string()))
+                .collect(),
+        });
+    }
+    let _ = daemon.stop_browse(SERVICE_TYPE);
+    Ok(result)
+}
+
+/// 查找局域网中的 AMLL WebSocket 服务，`timeout` 为等待时间，单位为毫秒
+#[tauri::command]
+pub async fn ws_discover(
+    mdns: State<'_, std::sync::Mutex<MdnsService>>,
+    timeout: Option<u64>,
+) -> CommandResult<Vec<DiscoveredService>> {
+    let timeout = timeout
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_DISCOVER_TIMEOUT);
+    let (daemon, own) = mdns.lock().unwrap().browser()?;
+    Ok(tauri::async_runtime::spawn_blocking(move || discover(daemon, own, timeout)).await??)
+}
@@ -0,0 +1,199 @@
+//! WebSocket 客户端的发送队列
+//!
+//! 每个连接都有独立的发送队列和写入任务，广播信息时只需要将信息放入队列，
+//! 不会因为某个客户端网络较慢而阻塞其它客户端。
+//! 队列中的信息超过上限时，会丢弃最旧的高频信息（播放进度、音频数据和频谱数据）。
+//! 歌曲信息、歌词和专辑封面等表示当前状态的信息只有最新的一条有意义，
+//! 放入队列时会替换掉队列中尚未发送的同类信息。
+//! 其它信息不会被丢弃，但队列中的信息总数超过硬上限时会直接断开客户端，避免内存无限增长。
+use std::{
+    collections::VecDeque,
+    mem::Discriminant,
+    sync::{Arc, Mutex},
+};
+
+use async_std::channel::{Receiver, Sender};
+use async_tungstenite::tungstenite::Message;
+use futures::prelude::*;
+
+/// 队列中的信息数量上限，超过后会开始丢弃高频信息
+const MAX_QUEUED_MESSAGES: usize = 64;
+/// 队列中的信息总数上限，超过后会断开客户端
+const MAX_PENDING_MESSAGES: usize = 1024;
+/// 队列清空前累计丢弃的信息数量达到此值时视为客户端持续落后
+const LAG_WARNING_THRESHOLD: usize = 32;
+
+/// 信息是否为可以丢弃的高频信息，丢弃后下一条同类信息会包含最新的状态
+pub fn is_droppable(body: &ws_protocol::Body) -> bool {
+    matches!(
+        body,
+        ws_protocol::Body::OnPlayProgress { .. }
+            | ws_protocol::Body::OnLoadProgress { .. }
+            | ws_protocol::Body::OnAudioData { .. }
+            | ws_protocol::Body::OnFFTData { .. }
+    )
+}
+
+/// 信息在发送队列中的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// 队列超过上限时可以丢弃的高频信息
+    Droppable,
+    /// 表示当前状态的信息，放入队列时会替换掉同类的旧信息
+    State(Discriminant<ws_protocol::Body>),
+    /// 其它需要按顺序送达的信息
+    Reliable,
+}
+
+impl MessageKind {
+    pub fn of(body: &ws_protocol::Body) -> Self {
+        use ws_protocol::Body;
+        match body {
+            _ if is_droppable(body) => Self::Droppable,
+            Body::SetMusicId { .. }
+            | Body::SetMusicAlbum { .. }
+            | Body::SetMusicAlbumCoverImageURL { .. }
+            | Body::SetMusicAlbumCoverImageData { .. }
+            | Body::SetMusicArtists { .. }
+            | Body::SetLyric { .. }
+            | Body::SetVolume { .. } => Self::State(std::mem::discriminant(body)),
+            _ => Self::Reliable,
+        }
+    }
+}
+
+/// 放入信息后队列的异常状态
+#[derive(Debug, Clone, Copy)]
+pub enum QueueEvent {
+    /// 客户端持续落后，已经开始丢弃信息
+    Lagging(QueueLag),
+    /// 队列中的信息超过硬上限，队列已被清空并关闭，客户端会被断开
+    Overflowed,
+}
+
+#[derive(Default)]
+struct QueueState {
+    messages: VecDeque<(Message, MessageKind)>,
+    /// 自上次队列清空以来丢弃的信息数量
+    dropped: usize,
+    /// 自上次队列清空以来是否已经发出过落后警告
+    warned: bool,
+    /// 连接建立以来丢弃的信息总数
+    total_dropped: u64,
+    /// 队列是否已经因为超过硬上限而关闭
+    closed: bool,
+}
+
+/// 客户端持续落后时的队列状态
+#[derive(Debug, Clone, Copy)]
+pub struct QueueLag {
+    pub queued: usize,
+    pub dropped: usize,
+}
+
+pub struct OutgoingQueue {
+    state: Arc<Mutex<QueueState>>,
+    /// 用于唤醒写入任务，队列被丢弃时写入任务会关闭连接的写入端并结束
+    notify: Sender<()>,
+}
+
+impl OutgoingQueue {
+    /// 创建发送队列并启动写入任务，`on_error` 会在写入失败时被调用
+    pub fn new<S>(sink: S, on_error: impl FnOnce(S::Error) + Send + 'static) -> Self
+    where
+        S: Sink<Message> + Unpin + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(QueueState::default()));
+        let (notify, receiver) = async_std::channel::bounded(1);
+        async_std::task::spawn(Self::write(state.clone(), receiver, sink, on_error));
+        Self { state, notify }
+    }
+
+    async fn write<S>(
+        state: Arc<Mutex<QueueState>>,
+        receiver: Receiver<()>,
+        mut sink: S,
+        on_error: impl FnOnce(S::Error),
+    ) where
+        S: Sink<Message> + Unpin,
+    {
+        while receiver.recv().await.is_ok() {
+            loop {
+                let msg = {
+                    let mut state = state.lock().unwrap();
+                    if state.closed {
+                        break;
+                    }
+                    match state.messages.pop_front() {
+                        Some((msg, _)) => msg,
+                        None => {
+                            state.dropped = 0;
+                            state.warned = false;
+                            break;
+                        }
+                    }
+                };
+                if let Err(err) = sink.send(msg).await {
+                    on_error(err);
+                    return;
+                }
+            }
+            if state.lock().unwrap().closed {
+                break;
+            }
+        }
+        let _ = sink.close().await;
+    }
+
+    /// 将信息放入队列，客户端持续落后或者队列溢出时返回对应的事件
+    pub fn push(&self, msg: Message, kind: MessageKind) -> Option<QueueEvent> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return None;
+        }
+        if let MessageKind::State(_) = kind {
+            state.messages.retain(|(_, x)| *x != kind);
+        }
+        if kind == MessageKind::Droppable && state.messages.len() >= MAX_QUEUED_MESSAGES {
+            // 队列中全部是不可丢弃的信息时丢弃新的信息
+            if let Some(i) = state
+                .messages
+                .iter()
+                .position(|(_, x)| *x == MessageKind::Droppable)
+            {
+                state.messages.remove(i);
+                state.messages.push_back((msg, kind));
+            }
+            state.dropped += 1;
+            state.total_dropped += 1;
+        } else {
+            state.messages.push_back((msg, kind));
+        }
+        if state.messages.len() > MAX_PENDING_MESSAGES {
+            state.total_dropped += state.messages.len() as u64;
+            state.messages.clear();
+            state.closed = true;
+            let _ = self.notify.try_send(());
+            return Some(QueueEvent::Overflowed);
+        }
+        let _ = self.notify.try_send(());
+        if state.dropped >= LAG_WARNING_THRESHOLD && !state.warned {
+            state.warned = true;
+            return Some(QueueEvent::Lagging(QueueLag {
+                queued: state.messages.len(),
+                dropped: state.dropped,
+            }));
+        }
+        None
+    }
+
+    /// 队列中等待发送的信息数量
+    pub fn queued(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    /// 连接建立以来丢弃的信息总数
+    pub fn total_dropped(&self) -> u64 {
+        self.state.lock().unwrap().total_dropped
+    }
+}
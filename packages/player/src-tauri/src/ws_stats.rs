@@ -0,0 +1,131 @@
+//! WebSocket 服务器的统计信息
+//!
+//! 记录各类信息的收发数量和字节数，以及每个客户端的收发情况，
+//! 用于排查外部歌词显示设备卡顿等问题时确认瓶颈所在。
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use serde::Serialize;
+
+use crate::server::ConnectionId;
+
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStats {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+impl MessageStats {
+    fn add(&mut self, bytes: usize) {
+        self.count += 1;
+        self.bytes += bytes as u64;
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientStats {
+    #[serde(flatten)]
+    pub conn: ConnectionId,
+    pub sent: MessageStats,
+    pub received: MessageStats,
+    /// 发送队列中等待发送的信息数量
+    pub queued: usize,
+    /// 因为客户端落后而丢弃的信息数量
+    pub dropped: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStats {
+    /// 服务器的运行时间，单位为毫秒
+    pub uptime: u64,
+    pub sent: MessageStats,
+    pub received: MessageStats,
+    /// 按照信息类型统计的发送情况，键为信息的类型名称
+    pub sent_by_type: HashMap<&'static str, MessageStats>,
+    pub received_by_type: HashMap<&'static str, MessageStats>,
+    pub clients: Vec<ClientStats>,
+}
+
+#[derive(Default)]
+struct StatsState {
+    sent_by_type: HashMap<&'static str, MessageStats>,
+    received_by_type: HashMap<&'static str, MessageStats>,
+    /// 每个连接的发送和接收情况，键为连接的 ID
+    clients: HashMap<u64, (MessageStats, MessageStats)>,
+}
+
+pub struct WsStats {
+    started_at: Instant,
+    state: Mutex<StatsState>,
+}
+
+impl Default for WsStats {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            state: Mutex::new(StatsState::default()),
+        }
+    }
+}
+
+impl WsStats {
+    pub fn on_sent(&self, id: ConnectionId, body: &ws_protocol::Body, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .sent_by_type
+            .entry(body.type_name())
+            .or_default()
+            .add(bytes);
+        state.clients.entry(id.id).or_default().0.add(bytes);
+    }
+
+    pub fn on_received(&self, id: ConnectionId, body: &ws_protocol::Body, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state
+            .received_by_type
+            .entry(body.type_name())
+            .or_default()
+            .add(bytes);
+        state.clients.entry(id.id).or_default().1.add(bytes);
+    }
+
+    /// 客户端断开后移除其统计信息，按照类型统计的信息会被保留
+    pub fn remove_client(&self, id: ConnectionId) {
+        self.state.lock().unwrap().clients.remove(&id.id);
+    }
+
+    /// 生成统计信息，`clients` 为当前连接的客户端及其发送队列的状态
+    pub fn snapshot(&self, clients: Vec<(ConnectionId, usize, u64)>) -> ServerStats {
+        let state = self.state.lock().unwrap();
+        let total = |stats: &HashMap<&'static str, MessageStats>| {
+            stats
+                .values()
+                .fold(MessageStats::default(), |acc, x| MessageStats {
+                    count: acc.count + x.count,
+                    bytes: acc.bytes + x.bytes,
+                })
+        };
+        ServerStats {
+            uptime: self.started_at.elapsed().as_millis() as u64,
+            sent: total(&state.sent_by_type),
+            received: total(&state.received_by_type),
+            sent_by_type: state.sent_by_type.clone(),
+            received_by_type: state.received_by_type.clone(),
+            clients: clients
+                .into_iter()
+                .map(|(conn, queued, dropped)| {
+                    let (sent, received) = state.clients.get(&conn.id).copied().unwrap_or_default();
+                    ClientStats {
+                        conn,
+                        sent,
+                        received,
+                        queued,
+                        dropped,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
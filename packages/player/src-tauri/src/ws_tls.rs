@@ -0,0 +1,121 @@
+//! WebSocket 服务器的 TLS 支持
+//!
+//! 开启后 WebSocket 服务器只接受 `wss://` 连接，证书可以由用户提供 PEM 格式的证书和私钥文件，
+//! 也可以自动生成自签名证书。自签名证书会保存到应用数据文件夹中，使其指纹在重启后保持不变，
+//! 客户端可以通过比对证书的 SHA-256 指纹确认连接到的是正确的服务器。
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// 自签名证书包含的主机名
+const SELF_SIGNED_NAMES: &[&str] = &["localhost", "amll-player.local"];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum TlsSource {
+    /// 用户提供的 PEM 格式证书和私钥文件
+    #[serde(rename_all = "camelCase")]
+    Files {
+        cert_path: PathBuf,
+        key_path: PathBuf,
+    },
+    /// 自动生成的自签名证书
+    SelfSigned,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsInfo {
+    /// 证书的 SHA-256 指纹，以冒号分隔的大写十六进制表示
+    pub fingerprint: String,
+    pub self_signed: bool,
+}
+
+fn fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .map(|x| format!("{x:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn read_certs(path: &Path) -> anyhow::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(path)?))?;
+    if certs.is_empty() {
+        anyhow::bail!("证书文件 {} 中没有证书", path.display());
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &Path) -> anyhow::Result<PrivateKey> {
+    let mut reader = BufReader::new(File::open(path)?);
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key),
+            ) => return Ok(PrivateKey(key)),
+            Some(_) => {}
+            None => anyhow::bail!("私钥文件 {} 中没有私钥", path.display()),
+        }
+    }
+}
+
+/// 读取保存的自签名证书，不存在时生成新的证书并保存
+fn self_signed(dir: Option<&Path>) -> anyhow::Result<(Vec<Certificate>, PrivateKey)> {
+    if let Some(dir) = dir {
+        let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+        if cert_path.is_file() && key_path.is_file() {
+            match read_certs(&cert_path).and_then(|certs| Ok((certs, read_key(&key_path)?))) {
+                Ok(result) => return Ok(result),
+                Err(err) => println!("自签名证书读取失败，将重新生成: {err:?}"),
+            }
+        }
+    }
+    let cert = rcgen::generate_simple_self_signed(
+        SELF_SIGNED_NAMES
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>(),
+    )?;
+    if let Some(dir) = dir {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join("cert.pem"), cert.serialize_pem()?)?;
+        std::fs::write(dir.join("key.pem"), cert.serialize_private_key_pem())?;
+    }
+    Ok((
+        vec![Certificate(cert.serialize_der()?)],
+        PrivateKey(cert.serialize_private_key_der()),
+    ))
+}
+
+/// 根据证书来源创建 TLS 接受器，`dir` 为保存自签名证书的文件夹
+pub fn load_tls(source: &TlsSource, dir: Option<&Path>) -> anyhow::Result<(TlsAcceptor, TlsInfo)> {
+    let (certs, key) = match source {
+        TlsSource::Files {
+            cert_path,
+            key_path,
+        } => (read_certs(cert_path)?, read_key(key_path)?),
+        TlsSource::SelfSigned => self_signed(dir)?,
+    };
+    let info = TlsInfo {
+        fingerprint: fingerprint(&certs[0].0),
+        self_signed: matches!(source, TlsSource::SelfSigned),
+    };
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    Ok((TlsAcceptor::from(Arc::new(config)), info))
+}
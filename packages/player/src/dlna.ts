@@ -0,0 +1,83 @@
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+
+/**
+ * DLNA 渲染器通过 `on-dlna-command` 事件发送的播放指令，
+ * 播放器后端不播放音乐，投送过来的歌曲由这里的音频元素播放
+ */
+type DlnaCommand =
+	| { action: "setUri"; uri: string }
+	| { action: "play" }
+	| { action: "pause" }
+	| { action: "stop" }
+	| { action: "seek"; position: number }
+	| { action: "setVolume"; volume: number }
+	| { action: "setMute"; muted: boolean };
+
+/** 播放时汇报播放进度的间隔，单位为毫秒 */
+const REPORT_INTERVAL = 1000;
+
+// Windows 上网页运行在 HTTPS 源下，HTTP 地址会被当作混合内容拦截，此时会汇报为无法播放
+const audio = new Audio();
+audio.preload = "auto";
+
+function reportPosition(ended = false) {
+	if (!audio.src) return;
+	invoke("dlna_report_position", {
+		position: audio.currentTime * 1000,
+		paused: audio.paused,
+		ended,
+	}).catch((err) => {
+		console.warn("DLNA 播放进度汇报失败", err);
+	});
+}
+
+function play() {
+	audio.play().catch((err) => {
+		console.warn("DLNA 投送的歌曲播放失败", err);
+		reportPosition(true);
+	});
+}
+
+function onCommand(command: DlnaCommand) {
+	switch (command.action) {
+		case "setUri":
+			audio.src = command.uri;
+			audio.load();
+			break;
+		case "play":
+			play();
+			break;
+		case "pause":
+			audio.pause();
+			break;
+		case "stop":
+			audio.pause();
+			audio.currentTime = 0;
+			break;
+		case "seek":
+			audio.currentTime = command.position / 1000;
+			break;
+		case "setVolume":
+			audio.volume = Math.min(Math.max(command.volume, 0), 1);
+			break;
+		case "setMute":
+			audio.muted = command.muted;
+			break;
+	}
+}
+
+listen<DlnaCommand>("on-dlna-command", (event) => onCommand(event.payload));
+
+audio.addEventListener("playing", () => reportPosition());
+audio.addEventListener("pause", () => reportPosition());
+audio.addEventListener("seeked", () => reportPosition());
+audio.addEventListener("ended", () => reportPosition(true));
+audio.addEventListener("error", () => {
+	console.warn("DLNA 投送的歌曲加载失败", audio.error);
+	reportPosition(true);
+});
+
+setInterval(() => {
+	if (!audio.paused) reportPosition();
+}, REPORT_INTERVAL);
@@ -2,9 +2,11 @@ import React from "react";
 import { createRoot } from "react-dom/client";
 import App from "./App";
 import "./styles.sass";
+import "./dlna";
 import * as wsp from "@applemusic-like-lyrics/ws-protocol";
 import {invoke} from "@tauri-apps/api";
 import {listen} from "@tauri-apps/api/event";
+import {confirm} from "@tauri-apps/api/dialog";
 
 (window as any).wsp = wsp;
 
@@ -24,6 +26,15 @@ listen("on-client-disconnected", (event) => {
   console.log("已断开播放状态源", event);
 })
 
+// 连接服务器的链接可能来自任何网页，需要用户确认后才连接
+listen<{ action: string; ws?: string }>("on-deep-link", async (event) => {
+  const { action, ws } = event.payload;
+  if (action !== "connect" || !ws) return;
+  if (await confirm(`是否连接到 WebSocket 服务器 ${ws}？`, "连接服务器")) {
+    await invoke("ws_connect_to", { addr: ws });
+  }
+})
+
 createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
     <App />
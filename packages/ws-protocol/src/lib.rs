@@ -129,8 +129,240 @@ pub enum Body {
     #[brw(magic(17u16))]
     BackwardSong,
     #[brw(magic(18u16))]
-    SetVolume {
-        volume: f64,
+    SetVolume { volume: f64 },
+    /// 客户端连接后发送的第一条信息，用于告知服务端自身的名称、版本和支持的功能，
+    /// 服务端也会以此信息回应自身的信息
+    #[serde(rename_all = "camelCase")]
+    #[brw(magic(19u16))]
+    Hello {
+        client_name: NullString,
+        version: NullString,
+        /// 支持的功能，为 [`capabilities`] 中各个标志的组合
+        capabilities: u32,
+    },
+    /// 开始分块传输专辑封面图片数据，之后会发送若干个相同 ID 的 [`Body::SetMusicAlbumCoverImageDataChunk`]，
+    /// 接收方收到全部数据并校验哈希后视为收到了一个 [`Body::SetMusicAlbumCoverImageData`]
+    #[serde(rename_all = "camelCase")]
+    #[brw(magic(20u16))]
+    SetMusicAlbumCoverImageDataBegin {
+        id: u32,
+        /// 图片数据的总大小，单位为字节
+        total_size: u32,
+        /// 图片数据的 SHA-256 哈希，以小写十六进制表示
+        hash: NullString,
+    },
+    /// 分块传输的专辑封面图片数据的一部分
+    #[brw(magic(21u16))]
+    SetMusicAlbumCoverImageDataChunk {
+        id: u32,
+        /// 此部分数据在图片数据中的偏移，单位为字节
+        offset: u32,
+        #[bw(try_calc = u32::try_from(data.len()))]
+        size: u32,
+        #[br(count = size)]
+        #[serde(with = "serde_bytes")]
+        data: Vec<u8>,
+    },
+    /// 客户端订阅需要接收的信息类别，服务端广播时会跳过客户端没有订阅的类别，
+    /// 没有发送此信息的客户端会接收所有类别的信息
+    #[brw(magic(22u16))]
+    Subscribe {
+        /// 订阅的信息类别，为 [`topics`] 中各个标志的组合
+        topics: u32,
+    },
+    /// 服务端根据音频数据计算出的一帧频谱，每个值为一个频段的强度，
+    /// 只会发送给声明了 [`capabilities::SPECTRUM`] 的客户端，
+    /// 使电视、LED 点阵等外部的可视化设备不需要自己采集和分析音频
+    #[brw(magic(23u16))]
+    OnFFTData {
+        #[bw(try_calc = u32::try_from(data.len()))]
+        size: u32,
+        #[br(count = size)]
+        data: Vec<f32>,
+    },
+}
+
+impl Body {
+    /// 获取信息所属的类别，为 [`topics`] 中的一个标志，
+    /// 心跳、握手和订阅等与连接本身相关的信息为 0，不会被订阅过滤
+    pub fn topic(&self) -> u32 {
+        match self {
+            Body::Ping | Body::Pong | Body::Hello { .. } | Body::Subscribe { .. } => 0,
+            Body::SetMusicId { .. } | Body::SetMusicAlbum { .. } | Body::SetMusicArtists { .. } => {
+                topics::METADATA
+            }
+            Body::SetMusicAlbumCoverImageURL { .. }
+            | Body::SetMusicAlbumCoverImageData { .. }
+            | Body::SetMusicAlbumCoverImageDataBegin { .. }
+            | Body::SetMusicAlbumCoverImageDataChunk { .. } => topics::COVER,
+            Body::OnLoadProgress { .. }
+            | Body::OnPlayProgress { .. }
+            | Body::OnPaused
+            | Body::OnResumed
+            | Body::SetPlayProgress { .. } => topics::PROGRESS,
+            Body::OnAudioData { .. } => topics::AUDIO_DATA,
+            Body::OnFFTData { .. } => topics::SPECTRUM,
+            Body::SetLyric { .. } => topics::LYRIC,
+            Body::Pause
+            | Body::Resume
+            | Body::ForwardSong
+            | Body::BackwardSong
+            | Body::SetVolume { .. } => topics::CONTROL,
+        }
+    }
+
+    /// 信息是否为控制播放的指令，只有 [`ClientRole::Control`] 的客户端可以发送
+    pub fn is_control(&self) -> bool {
+        matches!(
+            self,
+            Body::Pause
+                | Body::Resume
+                | Body::ForwardSong
+                | Body::BackwardSong
+                | Body::SetPlayProgress { .. }
+                | Body::SetVolume { .. }
+        )
+    }
+
+    /// 获取信息的类型名称，与 serde 表示中的 `type` 字段相同
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Body::Ping => "ping",
+            Body::Pong => "pong",
+            Body::SetMusicId { .. } => "setMusicId",
+            Body::SetMusicAlbum { .. } => "setMusicAlbum",
+            Body::SetMusicAlbumCoverImageURL { .. } => "setMusicAlbumCoverImageURL",
+            Body::SetMusicAlbumCoverImageData { .. } => "setMusicAlbumCoverImageData",
+            Body::SetMusicArtists { .. } => "setMusicArtists",
+            Body::OnLoadProgress { .. } => "onLoadProgress",
+            Body::OnPlayProgress { .. } => "onPlayProgress",
+            Body::OnPaused => "onPaused",
+            Body::OnResumed => "onResumed",
+            Body::SetPlayProgress { .. } => "setPlayProgress",
+            Body::OnAudioData { .. } => "onAudioData",
+            Body::SetLyric { .. } => "setLyric",
+            Body::Pause => "pause",
+            Body::Resume => "resume",
+            Body::ForwardSong => "forwardSong",
+            Body::BackwardSong => "backwardSong",
+            Body::SetVolume { .. } => "setVolume",
+            Body::Hello { .. } => "hello",
+            Body::SetMusicAlbumCoverImageDataBegin { .. } => "setMusicAlbumCoverImageDataBegin",
+            Body::SetMusicAlbumCoverImageDataChunk { .. } => "setMusicAlbumCoverImageDataChunk",
+            Body::Subscribe { .. } => "subscribe",
+            Body::OnFFTData { .. } => "onFFTData",
+        }
+    }
+}
+
+/// [`Body::Subscribe`] 中的信息类别标志
+pub mod topics {
+    /// 歌词
+    pub const LYRIC: u32 = 1 << 0;
+    /// 歌曲名称、专辑和歌手等元数据
+    pub const METADATA: u32 = 1 << 1;
+    /// 专辑封面
+    pub const COVER: u32 = 1 << 2;
+    /// 加载和播放进度、暂停和继续播放
+    pub const PROGRESS: u32 = 1 << 3;
+    /// 音频数据，通常用于频谱等可视化效果
+    pub const AUDIO_DATA: u32 = 1 << 4;
+    /// 暂停、切歌、调整音量等控制指令
+    pub const CONTROL: u32 = 1 << 5;
+    /// 服务端计算出的频谱数据
+    pub const SPECTRUM: u32 = 1 << 6;
+    /// 所有类别
+    pub const ALL: u32 = u32::MAX;
+
+    /// 解析以逗号分隔的类别名称，例如 `lyric,progress`，无法识别的名称会被忽略
+    pub fn parse(names: &str) -> u32 {
+        names
+            .split(',')
+            .map(|x| match x.trim().to_ascii_lowercase().as_str() {
+                "lyric" | "lyrics" => LYRIC,
+                "metadata" => METADATA,
+                "cover" => COVER,
+                "progress" => PROGRESS,
+                "audio" | "audiodata" => AUDIO_DATA,
+                "control" => CONTROL,
+                "spectrum" | "fft" => SPECTRUM,
+                "all" => ALL,
+                _ => 0,
+            })
+            .fold(0, |acc, x| acc | x)
+    }
+}
+
+/// [`Body::Hello`] 中的功能标志
+pub mod capabilities {
+    /// 可以发送或显示歌词
+    pub const LYRIC: u32 = 1 << 0;
+    /// 可以发送或接收专辑封面图片数据
+    pub const COVER_DATA: u32 = 1 << 1;
+    /// 可以发送或接收音频数据
+    pub const AUDIO_DATA: u32 = 1 << 2;
+    /// 可以接收或发送暂停、切歌、调整音量等控制指令
+    pub const REMOTE_CONTROL: u32 = 1 << 3;
+    /// 可以接收分块传输的专辑封面图片数据
+    pub const COVER_CHUNK: u32 = 1 << 4;
+    /// 可以接收服务端计算出的频谱数据
+    pub const SPECTRUM: u32 = 1 << 5;
+}
+
+/// 客户端的权限
+///
+/// 客户端可以在握手时通过 URL 中的 `role` 参数或者 `X-AMLL-Role` 请求头声明自己的权限，
+/// 只读的客户端（例如公开展示的歌词显示器）发送的控制指令会被服务器忽略
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum ClientRole {
+    /// 只能接收信息
+    ReadOnly,
+    /// 可以发送暂停、切歌、调整进度和音量等控制指令
+    #[default]
+    Control,
+}
+
+impl ClientRole {
+    pub fn can_control(self) -> bool {
+        self == Self::Control
+    }
+}
+
+impl std::str::FromStr for ClientRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "readonly" | "read-only" | "read_only" | "viewer" => Ok(Self::ReadOnly),
+            "control" | "controller" => Ok(Self::Control),
+            _ => anyhow::bail!("未知的客户端权限 {s}"),
+        }
+    }
+}
+
+/// 信息主体的编码方式
+///
+/// 默认使用 binrw 实现的二进制编码，专辑封面和音频数据等字节数组会以原始字节传输；
+/// 无法使用本协议库的客户端（例如嵌入式设备上的歌词显示器）可以使用 JSON 编码，
+/// 此时字节数组会被编码为数字数组，体积会大得多，因此只建议用于低频的信息
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum BodyEncoding {
+    #[default]
+    Binary,
+    Json,
+}
+
+impl std::str::FromStr for BodyEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "binary" | "bin" => Ok(Self::Binary),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("未知的信息编码方式 {s}"),
+        }
     }
 }
 
@@ -166,6 +398,82 @@ pub fn to_body_js(body: JsValue) -> Result<Box<[u8]>, String> {
     }
 }
 
+/// 解析 JSON 编码的信息主体，格式与 [`Body`] 的 serde 表示一致
+pub fn parse_body_json(body: &str) -> anyhow::Result<Body> {
+    Ok(serde_json::from_str(body)?)
+}
+
+pub fn to_body_json(body: &Body) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(body)?)
+}
+
+#[test]
+fn binary_encoding_test() {
+    let body = Body::SetMusicAlbumCoverImageData {
+        data: (0..=255).cycle().take(64 * 1024).collect(),
+    };
+    let binary = to_body(&body).unwrap();
+    let json = to_body_json(&body).unwrap();
+    // 2 字节的类型标识 + 4 字节的长度 + 原始数据
+    assert_eq!(binary.len(), 6 + 64 * 1024);
+    assert!(json.len() > binary.len() * 3);
+    match parse_body(&binary).unwrap() {
+        Body::SetMusicAlbumCoverImageData { data } => assert_eq!(data.len(), 64 * 1024),
+        _ => panic!("信息主体类型错误"),
+    }
+    assert!(matches!(
+        parse_body_json(&json).unwrap(),
+        Body::SetMusicAlbumCoverImageData { .. }
+    ));
+}
+
+#[test]
+fn topics_test() {
+    assert_eq!(
+        topics::parse("lyric, Progress"),
+        topics::LYRIC | topics::PROGRESS
+    );
+    assert_eq!(topics::parse("fft,unknown"), topics::SPECTRUM);
+    assert_eq!(
+        Body::OnPlayProgress { progress: 0.0 }.topic(),
+        topics::PROGRESS
+    );
+    assert_eq!(Body::Ping.topic(), 0);
+}
+
+#[test]
+fn fft_data_test() {
+    let body = Body::OnFFTData {
+        data: vec![0.0, 0.5, 1.0],
+    };
+    let binary = to_body(&body).unwrap();
+    // 2 字节的类型标识 + 4 字节的长度 + 每个频段 4 字节
+    assert_eq!(binary.len(), 6 + 3 * 4);
+    match parse_body(&binary).unwrap() {
+        Body::OnFFTData { data } => assert_eq!(data, vec![0.0, 0.5, 1.0]),
+        _ => panic!("信息主体类型错误"),
+    }
+    assert_eq!(body.topic(), topics::SPECTRUM);
+    assert_eq!(topics::parse("spectrum"), topics::SPECTRUM);
+    assert!(!body.is_control());
+}
+
+#[test]
+fn client_role_test() {
+    assert_eq!(
+        "read-only".parse::<ClientRole>().unwrap(),
+        ClientRole::ReadOnly
+    );
+    assert_eq!(
+        "Control".parse::<ClientRole>().unwrap(),
+        ClientRole::Control
+    );
+    assert!("admin".parse::<ClientRole>().is_err());
+    assert!(ClientRole::ReadOnly < ClientRole::Control);
+    assert!(Body::SetPlayProgress { progress: 0.0 }.is_control());
+    assert!(!Body::OnPlayProgress { progress: 0.0 }.is_control());
+}
+
 #[wasm_bindgen]
 /// When the `console_error_panic_hook` feature is enabled, we can call the
 /// `set_panic_hook` function at least once during initialization, and then